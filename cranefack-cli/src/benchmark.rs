@@ -1,7 +1,8 @@
-use crate::utils::read_input;
+use crate::utils::{print_parser_errors, read_input};
 use cranefack::CompiledJitModule;
 use cranefack::{
-    optimize_with_config, parse, CraneFackError, Interpreter, OptimizeConfig, Program,
+    optimize_with_config, parse_bytes_with_recovery, Interpreter, OptimizeConfig, ParserOptions,
+    Program,
 };
 use std::error::Error;
 use std::ffi::OsStr;
@@ -17,11 +18,9 @@ pub fn benchmark_file(
 ) -> Result<(), Box<dyn Error>> {
     let source = read_input(path)?;
 
-    let program = match parse(&source) {
+    let program = match parse_bytes_with_recovery(&source, &ParserOptions::default()) {
         Ok(program) => program,
-        Err(err) => {
-            return err.pretty_print(&source, Some(&path.to_string_lossy()));
-        }
+        Err(errors) => return print_parser_errors(&errors, &source, path),
     };
 
     let mut o0 = OptimizeConfig::o0();
@@ -181,7 +180,7 @@ fn run_jit(
         input.set_position(0);
         output.clear();
 
-        module.execute(&mut input, &mut output);
+        module.execute(&mut input, &mut output)?;
     }
 
     Ok(ts.elapsed()?.as_nanos())
@@ -1,6 +1,8 @@
 use std::error::Error;
 use std::ffi::OsStr;
+use std::fs::File;
 use std::io::{stdout, Write};
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 use codespan_reporting::term::termcolor::{
@@ -8,28 +10,36 @@ use codespan_reporting::term::termcolor::{
 };
 
 use cranefack::{
-    analyze, compile_to_rust, optimize_with_config, parse, CraneFackError, OptimizeConfig, Program,
-    Warning,
+    analyze, compile_to_bf, compile_to_c_with_config, compile_to_executable, compile_to_object,
+    compile_to_rust_with_config, compile_to_wasm, compile_to_wat, IrFormat, OptimizeConfig,
+    ParserOptions, Program,
 };
 
 use crate::utils;
+use crate::utils::OptimizationOptions;
 use cranefack::CompiledJitModule;
 
 pub fn compile_file(
     opt_mode: OptimizeConfig,
+    parser_options: ParserOptions,
     verbose: bool,
     format: &str,
+    opt_options: OptimizationOptions,
     path: &OsStr,
+    output: Option<&OsStr>,
 ) -> Result<(), Box<dyn Error>> {
     let source = utils::read_input(path)?;
 
     let mut ts = SystemTime::now();
 
-    let mut program = match parse(&source) {
-        Ok(program) => program,
-        Err(err) => {
-            return err.pretty_print(&source, Some(&path.to_string_lossy()));
-        }
+    // Serialized programs are already optimized and have no matching source for warnings
+    let is_ir = Program::is_ir(&source);
+
+    let mut opt_mode = opt_mode;
+
+    let mut program = match utils::load_program(&source, &parser_options, &mut opt_mode, path)? {
+        Some(program) => program,
+        None => return Ok(()),
     };
 
     if verbose {
@@ -55,8 +65,8 @@ pub fn compile_file(
         ts = SystemTime::now();
     }
 
-    if opt_mode.optimize() {
-        let opt_loop_count = optimize_with_config(&mut program, &opt_mode);
+    if opt_mode.optimize() && !is_ir {
+        let report = utils::optimize_program(&mut program, &opt_mode, &opt_options, &source, path)?;
 
         if verbose {
             let mut writer = StandardStream::stderr(ColorChoice::Auto);
@@ -73,26 +83,79 @@ pub fn compile_file(
                      cloop_count,
                      if_count,
                      ts.elapsed()?.as_micros() as f32 / 1000.0,
-                     opt_loop_count
+                     report.iteration_count()
             )?;
             writer.reset()?;
         }
     }
 
-    let warnings = analyze(&program);
-    if !warnings.is_empty() {
-        Warning::pretty_print(&warnings, &source, Some(&path.to_string_lossy()))?;
-    }
+    utils::print_warnings(&analyze(&program), is_ir, &source, path)?;
 
     match format {
-        "rust" => println!("{}", compile_to_rust(&program)),
-        "clir" => println!("{}", build_clir(&program, &opt_mode)?),
-        _ => program.dump(stdout(), opt_mode.debug)?,
+        "object" => {
+            let object = compile_to_object(&program, &opt_mode)?;
+            std::fs::write(get_output_path(path, output, "o"), object)?;
+        }
+        "wasm" => {
+            let wasm = compile_to_wasm(&program, &opt_mode)?;
+            std::fs::write(get_output_path(path, output, "wasm"), wasm)?;
+        }
+        "ir" => {
+            let file = File::create(get_output_path(path, output, "cfir"))?;
+            program.save(file, IrFormat::Binary, &opt_mode)?;
+        }
+        "exe" => {
+            compile_to_executable(&program, &opt_mode, &get_output_path(path, output, ""))?;
+        }
+        _ => {
+            let mut writer: Box<dyn Write> = match output {
+                Some(output) => Box::new(File::create(output)?),
+                None => Box::new(stdout()),
+            };
+
+            match format {
+                "rust" => writeln!(
+                    writer,
+                    "{}",
+                    compile_to_rust_with_config(&program, &opt_mode)
+                )?,
+                "c" => writeln!(writer, "{}", compile_to_c_with_config(&program, &opt_mode))?,
+                "wat" => writeln!(writer, "{}", compile_to_wat(&program, &opt_mode))?,
+                "bf" => writeln!(writer, "{}", compile_to_bf(&program, &opt_mode)?)?,
+                "clir" => writeln!(writer, "{}", build_clir(&program, &opt_mode)?)?,
+                "ir-text" => program.save(writer, IrFormat::Text, &opt_mode)?,
+                _ => program.dump(writer, opt_mode.debug)?,
+            }
+        }
     }
 
     Ok(())
 }
 
+/// Use the output file if given or derive it from the source file name
+fn get_output_path(path: &OsStr, output: Option<&OsStr>, extension: &str) -> PathBuf {
+    if let Some(output) = output {
+        return PathBuf::from(output);
+    }
+
+    if path == "-" {
+        return if extension.is_empty() {
+            PathBuf::from("a.out")
+        } else {
+            Path::new("out").with_extension(extension)
+        };
+    }
+
+    let name = Path::new(Path::new(path).file_name().unwrap_or(path));
+
+    // Don't overwrite source files without extension
+    if name.extension().is_none() && extension.is_empty() {
+        name.with_extension("out")
+    } else {
+        name.with_extension(extension)
+    }
+}
+
 fn build_clir(program: &Program, opt_mode: &OptimizeConfig) -> Result<String, Box<dyn Error>> {
     let module = CompiledJitModule::new(program, opt_mode)?;
     Ok(module.get_clir())
@@ -8,6 +8,10 @@ pub enum CliError {
     SourceFileNotFound(OsString),
     SourceFilePermissionDenied(OsString),
     SourceReadError(OsString, std::io::Error),
+    TokenMapReadError(OsString, std::io::Error),
+    InvalidTokenMap(OsString, usize),
+    CheckInputReadError(OsString, std::io::Error),
+    OptimizationCheckFailed,
 }
 
 impl Error for CliError {}
@@ -27,6 +31,25 @@ impl Display for CliError {
                 path.to_string_lossy(),
                 error
             ),
+            CliError::TokenMapReadError(path, error) => write!(
+                f,
+                "Error reading token map {}: {}",
+                path.to_string_lossy(),
+                error
+            ),
+            CliError::InvalidTokenMap(path, line) => write!(
+                f,
+                "Invalid token map {} in line {}",
+                path.to_string_lossy(),
+                line
+            ),
+            CliError::CheckInputReadError(path, error) => write!(
+                f,
+                "Error reading check input {}: {}",
+                path.to_string_lossy(),
+                error
+            ),
+            CliError::OptimizationCheckFailed => write!(f, "Optimization check failed"),
         }
     }
 }
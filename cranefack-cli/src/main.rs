@@ -1,4 +1,5 @@
 use clap::{crate_description, crate_name, crate_version, App, Arg, ArgMatches, SubCommand};
+use cranefack::PassManager;
 use std::error::Error;
 
 mod benchmark;
@@ -10,7 +11,9 @@ mod utils;
 use crate::benchmark::benchmark_file;
 use crate::compile::compile_file;
 use crate::run::run_file;
-use crate::utils::get_optimize_config_from_args;
+use crate::utils::{
+    get_optimization_options_from_args, get_optimize_config_from_args, get_parser_options_from_args,
+};
 use std::process::exit;
 
 fn main() {
@@ -44,10 +47,40 @@ fn create_clap_app() -> App<'static, 'static> {
                         .long("jit")
                         .help("Use JIT compiler"),
                 )
+                .arg(
+                    Arg::with_name("FUEL")
+                        .long("fuel")
+                        .value_names(&["steps"])
+                        .validator(|value| {
+                            value
+                                .parse::<u64>()
+                                .map(|_| ())
+                                .map_err(|err| err.to_string())
+                        })
+                        .help("Abort execution after the given number of steps. The interpreter counts every executed op and loop iteration, the JIT only loop iterations"),
+                )
                 .arg(get_opt_mode_arg())
                 .arg(get_jit_level())
+                .arg(get_bounds_checks_arg())
+                .arg(get_cell_size_arg())
+                .arg(get_eof_arg())
+                .arg(get_printable_output_arg())
+                .arg(get_bidirectional_tape_arg())
+                .arg(get_heap_size_arg())
+                .arg(get_dialect_arg())
+                .arg(get_token_map_arg())
+                .arg(get_breakpoints_arg())
+                .arg(get_inline_input_arg())
+                .arg(get_max_loop_depth_arg())
                 .arg(get_wrapping_is_ub_arg())
+                .arg(get_enable_pass_arg())
+                .arg(get_disable_pass_arg())
                 .arg(get_debug_opt_arg())
+                .arg(get_opt_report_arg())
+                .arg(get_opt_remarks_arg())
+                .arg(get_check_passes_arg())
+                .arg(get_check_input_arg())
+                .arg(get_check_random_inputs_arg())
                 .arg(get_verbose_arg()),
         )
         .subcommand(
@@ -58,15 +91,38 @@ fn create_clap_app() -> App<'static, 'static> {
                     Arg::with_name("FORMAT")
                         .short("f")
                         .long("format")
-                        .possible_values(&["dump", "clir", "rust"])
+                        .possible_values(&["dump", "clir", "rust", "c", "wat", "wasm", "object", "exe", "ir", "ir-text", "bf"])
                         .value_names(&["format"])
                         .default_value("dump")
                         .help("Format of compiled code"),
                 )
+                .arg(
+                    Arg::with_name("OUTPUT")
+                        .short("o")
+                        .long("output")
+                        .value_names(&["file"])
+                        .help("Output file. Defaults to stdout or the source file name for binary formats"),
+                )
                 .arg(get_opt_mode_arg())
                 .arg(get_jit_level())
+                .arg(get_bounds_checks_arg())
+                .arg(get_cell_size_arg())
+                .arg(get_eof_arg())
+                .arg(get_printable_output_arg())
+                .arg(get_bidirectional_tape_arg())
+                .arg(get_heap_size_arg())
+                .arg(get_dialect_arg())
+                .arg(get_token_map_arg())
+                .arg(get_max_loop_depth_arg())
                 .arg(get_wrapping_is_ub_arg())
+                .arg(get_enable_pass_arg())
+                .arg(get_disable_pass_arg())
                 .arg(get_debug_opt_arg())
+                .arg(get_opt_report_arg())
+                .arg(get_opt_remarks_arg())
+                .arg(get_check_passes_arg())
+                .arg(get_check_input_arg())
+                .arg(get_check_random_inputs_arg())
                 .arg(get_verbose_arg()),
         )
         .subcommand(
@@ -116,6 +172,95 @@ fn get_jit_level<'a, 'b>() -> Arg<'a, 'b> {
         .help("Optimization level for JIT")
 }
 
+fn get_bounds_checks_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("BOUNDS_CHECKS")
+        .long("bounds-checks")
+        .help("Check heap bounds in JIT compiled code")
+}
+
+fn get_cell_size_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CELL_SIZE")
+        .long("cell-size")
+        .possible_values(&["8", "16", "32"])
+        .value_names(&["bits"])
+        .help("Width of heap cells in bits")
+}
+
+fn get_eof_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("EOF")
+        .long("eof")
+        .possible_values(&["0", "max", "unchanged"])
+        .value_names(&["mode"])
+        .help("Value stored in the cell when reading past the end of input")
+}
+
+fn get_printable_output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("PRINTABLE_OUTPUT")
+        .long("printable-output")
+        .help("Escape zero and non ASCII output bytes as \\0x..")
+}
+
+fn get_bidirectional_tape_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("BIDIRECTIONAL_TAPE")
+        .long("bidirectional-tape")
+        .help("Allow the heap pointer to move left of the start cell")
+}
+
+fn get_heap_size_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("HEAP_SIZE")
+        .long("heap-size")
+        .value_names(&["cells"])
+        .validator(|value| match value.parse::<usize>() {
+            Ok(0) => Err("Heap size must be at least one cell".to_owned()),
+            Ok(_) => Ok(()),
+            Err(err) => Err(err.to_string()),
+        })
+        .help("Number of heap cells right of the start cell. Defaults to 16777216")
+}
+
+fn get_dialect_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("DIALECT")
+        .long("dialect")
+        .possible_values(&["brainfuck", "ook", "blub", "custom"])
+        .value_names(&["dialect"])
+        .requires_if("custom", "TOKEN_MAP")
+        .help("Syntax of the source file")
+}
+
+fn get_token_map_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("TOKEN_MAP")
+        .long("token-map")
+        .value_names(&["file"])
+        .help(
+            "Tokens of the custom dialect. Each line contains a command char followed by its token",
+        )
+}
+
+fn get_breakpoints_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("BREAKPOINTS")
+        .long("breakpoints")
+        .help("Parse # as breakpoint that prints the tape state when run with the interpreter")
+}
+
+fn get_inline_input_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("INLINE_INPUT")
+        .long("inline-input")
+        .help("Use everything after the first ! in the source as input instead of stdin")
+}
+
+fn get_max_loop_depth_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("MAX_LOOP_DEPTH")
+        .long("max-loop-depth")
+        .value_names(&["depth"])
+        .validator(|value| {
+            value
+                .parse::<usize>()
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        })
+        .help("Maximum depth of nested loops. Defaults to 1024")
+}
+
 fn get_verbose_arg<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("VERBOSE").short("v").long("verbose")
 }
@@ -135,12 +280,88 @@ fn get_wrapping_is_ub_arg<'a, 'b>() -> Arg<'a, 'b> {
         .help("Wrapping overflows are undefined behavior during optimization")
 }
 
+fn get_enable_pass_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("ENABLE_PASS")
+        .long("enable-pass")
+        .value_names(&["pass"])
+        .multiple(true)
+        .number_of_values(1)
+        .validator(validate_pass_name)
+        .help("Run optimization pass even if the optimization mode doesn't use it")
+}
+
+fn get_disable_pass_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("DISABLE_PASS")
+        .long("disable-pass")
+        .value_names(&["pass"])
+        .multiple(true)
+        .number_of_values(1)
+        .validator(validate_pass_name)
+        .help("Skip optimization pass")
+}
+
+fn validate_pass_name(name: String) -> Result<(), String> {
+    if PassManager::builtin_pass(&name).is_some() {
+        Ok(())
+    } else {
+        Err(format!(
+            "Unknown optimization pass {}. Available passes: {}",
+            name,
+            PassManager::builtin_pass_names().join(", ")
+        ))
+    }
+}
+
 fn get_debug_opt_arg<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("DEBUG_OPT")
         .long("debug-optimizations")
         .help("Print statistics for optimization passes")
 }
 
+fn get_opt_report_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("OPT_REPORT")
+        .long("opt-report")
+        .possible_values(&["json"])
+        .value_names(&["format"])
+        .help("Print statistics and timing of each optimization pass to stderr")
+}
+
+fn get_opt_remarks_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("OPT_REMARKS")
+        .long("opt-remarks")
+        .help("Print remarks about the changes of optimization passes")
+}
+
+fn get_check_passes_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CHECK_PASSES")
+        .long("check-passes")
+        .help("Run the program before and after each optimization pass and report the first pass changing its behavior")
+}
+
+fn get_check_input_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CHECK_INPUT")
+        .long("check-input")
+        .value_names(&["file"])
+        .multiple(true)
+        .number_of_values(1)
+        .requires("CHECK_PASSES")
+        .help("Input file used to check optimization passes")
+}
+
+fn get_check_random_inputs_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("CHECK_RANDOM_INPUTS")
+        .long("check-random-inputs")
+        .value_names(&["count"])
+        .requires("CHECK_PASSES")
+        .validator(|value| {
+            value
+                .parse::<usize>()
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        })
+        .help("Number of random inputs used to check optimization passes. Defaults to 8")
+}
+
 fn is_verbose(matches: &ArgMatches) -> bool {
     matches.is_present("VERBOSE")
 }
@@ -149,18 +370,42 @@ fn run(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let path = matches.value_of_os("FILE").unwrap();
     let verbose = is_verbose(matches);
     let opt_mode = get_optimize_config_from_args(matches);
+    let parser_options = get_parser_options_from_args(matches)?;
     let jit = matches.is_present("JIT");
+    let fuel = matches
+        .value_of("FUEL")
+        .and_then(|value| value.parse().ok());
+    let opt_options = get_optimization_options_from_args(matches)?;
 
-    run_file(opt_mode, jit, verbose, path)
+    run_file(
+        opt_mode,
+        parser_options,
+        jit,
+        verbose,
+        fuel,
+        opt_options,
+        path,
+    )
 }
 
 fn compile(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
     let path = matches.value_of_os("FILE").unwrap();
     let verbose = is_verbose(matches);
     let opt_mode = get_optimize_config_from_args(matches);
+    let parser_options = get_parser_options_from_args(matches)?;
     let format = matches.value_of("FORMAT").unwrap_or("dump");
+    let output = matches.value_of_os("OUTPUT");
+    let opt_options = get_optimization_options_from_args(matches)?;
 
-    compile_file(opt_mode, verbose, format, path)
+    compile_file(
+        opt_mode,
+        parser_options,
+        verbose,
+        format,
+        opt_options,
+        path,
+        output,
+    )
 }
 
 fn benchmark(matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
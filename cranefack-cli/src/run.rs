@@ -1,6 +1,6 @@
 use std::error::Error;
 use std::ffi::OsStr;
-use std::io::{stdin, stdout, Write};
+use std::io::{stdin, stdout, Cursor, Read, Write};
 use std::time::SystemTime;
 
 use codespan_reporting::term::termcolor::{
@@ -8,26 +8,35 @@ use codespan_reporting::term::termcolor::{
 };
 use cranefack::CompiledJitModule;
 use cranefack::{
-    analyze, optimize_with_config, parse, CraneFackError, Interpreter, OptimizeConfig, Warning,
+    analyze, BreakpointInfo, CraneFackError, Interpreter, OptimizeConfig, ParserOptions, Program,
 };
 
-use crate::utils::read_input;
+use crate::utils::{
+    load_program, optimize_program, print_warnings, read_input, source_to_string,
+    OptimizationOptions,
+};
 
 pub fn run_file(
     opt_mode: OptimizeConfig,
+    parser_options: ParserOptions,
     jit: bool,
     verbose: bool,
+    fuel: Option<u64>,
+    opt_options: OptimizationOptions,
     path: &OsStr,
 ) -> Result<(), Box<dyn Error>> {
     let source = read_input(path)?;
 
     let mut ts = SystemTime::now();
 
-    let mut program = match parse(&source) {
-        Ok(program) => program,
-        Err(err) => {
-            return err.pretty_print(&source, Some(&path.to_string_lossy()));
-        }
+    // Serialized programs are already optimized and their spans don't point into the input
+    let is_ir = Program::is_ir(&source);
+
+    let mut opt_mode = opt_mode;
+
+    let mut program = match load_program(&source, &parser_options, &mut opt_mode, path)? {
+        Some(program) => program,
+        None => return Ok(()),
     };
 
     if verbose {
@@ -52,8 +61,8 @@ pub fn run_file(
         ts = SystemTime::now();
     }
 
-    if opt_mode.optimize() {
-        let opt_loop_count = optimize_with_config(&mut program, &opt_mode);
+    if opt_mode.optimize() && !is_ir {
+        let report = optimize_program(&mut program, &opt_mode, &opt_options, &source, path)?;
 
         if verbose {
             let mut writer = StandardStream::stderr(ColorChoice::Auto);
@@ -70,24 +79,27 @@ pub fn run_file(
                      cloop_count,
                      if_count,
                      ts.elapsed()?.as_micros() as f32 / 1000.0,
-                     opt_loop_count
+                     report.iteration_count()
             )?;
             writer.reset()?;
             ts = SystemTime::now();
         }
     }
 
-    let warnings = analyze(&program);
-    if !warnings.is_empty() {
-        Warning::pretty_print(&warnings, &source, Some(&path.to_string_lossy()))?;
-    }
+    print_warnings(&analyze(&program), is_ir, &source, path)?;
+
+    let input: Box<dyn Read> = match parser_options.split_source(&source) {
+        (_, Some(inline_input)) if !is_ir => Box::new(Cursor::new(inline_input.to_vec())),
+        _ => Box::new(stdin()),
+    };
 
     if jit {
-        let module = match CompiledJitModule::new(&program, &opt_mode) {
+        let mut opt_mode = opt_mode;
+        opt_mode.jit_fuel_checks = fuel.is_some();
+
+        let mut module = match CompiledJitModule::new(&program, &opt_mode) {
             Ok(module) => module,
-            Err(err) => {
-                return err.pretty_print(&source, Some(&path.to_string_lossy()));
-            }
+            Err(err) => return report_error(err, is_ir, &source, path),
         };
 
         if verbose {
@@ -102,12 +114,22 @@ pub fn run_file(
             ts = SystemTime::now();
         }
 
-        module.execute(stdin(), stdout());
+        module.set_fuel(fuel);
+
+        if let Err(err) = module.execute(input, stdout()) {
+            return report_error(err, is_ir, &source, path);
+        }
     } else {
-        let mut interpreter = Interpreter::new(stdin(), stdout());
+        let mut interpreter = Interpreter::new_with_config(input, stdout(), &opt_mode);
+        interpreter.set_fuel(fuel);
+
+        if parser_options.breakpoints {
+            let source = source_to_string(&source);
+            interpreter.set_breakpoint_callback(move |info| print_breakpoint(&source, info));
+        }
 
         if let Err(err) = interpreter.execute(&program) {
-            return err.pretty_print(&source, Some(&path.to_string_lossy()));
+            return report_error(err, is_ir, &source, path);
         }
     }
 
@@ -124,3 +146,43 @@ pub fn run_file(
 
     Ok(())
 }
+
+/// Pretty print error with its source location or return it if there is no source to point into
+fn report_error<E: CraneFackError + 'static>(
+    err: E,
+    is_ir: bool,
+    source: &[u8],
+    path: &OsStr,
+) -> Result<(), Box<dyn Error>> {
+    if is_ir {
+        Err(Box::new(err))
+    } else {
+        err.pretty_print(&source_to_string(source), Some(&path.to_string_lossy()))
+    }
+}
+
+fn print_breakpoint(source: &str, info: &BreakpointInfo) {
+    // Keep the program output in order with the breakpoints
+    let _ = stdout().flush();
+
+    let mut cells = String::new();
+
+    for (index, value) in info.cells.iter().enumerate() {
+        if info.cells_start + index as isize == info.pointer {
+            cells.push_str(&format!(" [{}]", value));
+        } else {
+            cells.push_str(&format!(" {}", value));
+        }
+    }
+
+    let (line, column) = Program::get_line_column(source, info.span.start).unwrap_or((0, 0));
+
+    let mut writer = StandardStream::stderr(ColorChoice::Auto);
+    let _ = writer.set_color(ColorSpec::new().set_fg(Some(Color::Cyan)));
+    let _ = writeln!(
+        writer,
+        "Breakpoint at {}:{} pointer: {} cells from {}:{}",
+        line, column, info.pointer, info.cells_start, cells
+    );
+    let _ = writer.reset();
+}
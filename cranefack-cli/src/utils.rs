@@ -1,16 +1,23 @@
 use crate::errors::CliError;
 use clap::ArgMatches;
-use cranefack::OptimizeConfig;
+use codespan_reporting::diagnostic::Diagnostic;
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+use cranefack::{
+    optimize_with_config, parse_bytes_with_recovery, CellSize, CheckConfig, CraneFackError,
+    Dialect, EofMode, OptimizationReport, OptimizeConfig, ParserError, ParserOptions, PassManager,
+    Program, Remark, TokenMap, Warning,
+};
 use std::error::Error;
 use std::ffi::OsStr;
 use std::fs::File;
-use std::io::{stdin, ErrorKind, Read};
+use std::io::{stderr, stdin, ErrorKind, Read};
 
-pub fn read_input(path: &OsStr) -> Result<String, Box<dyn Error>> {
+pub fn read_input(path: &OsStr) -> Result<Vec<u8>, Box<dyn Error>> {
     if path == "-" {
-        let mut source = "".to_owned();
+        let mut source = vec![];
         stdin()
-            .read_to_string(&mut source)
+            .read_to_end(&mut source)
             .map_err(|err| CliError::SourceReadError(path.to_owned(), err))?;
         Ok(source)
     } else {
@@ -24,13 +31,164 @@ pub fn read_input(path: &OsStr) -> Result<String, Box<dyn Error>> {
             }
         })?;
 
-        let mut source = "".to_owned();
-        file.read_to_string(&mut source)
+        let mut source = vec![];
+        file.read_to_end(&mut source)
             .map_err(|err| CliError::SourceReadError(path.to_owned(), err))?;
         Ok(source)
     }
 }
 
+/// Convert source bytes to a string for error reporting
+///
+/// Each invalid UTF-8 byte is replaced with a single `?` to keep the byte offsets of the spans.
+pub fn source_to_string(source: &[u8]) -> String {
+    let mut text = String::with_capacity(source.len());
+    let mut rest = source;
+
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return text;
+            }
+            Err(error) => {
+                let (valid, invalid) = rest.split_at(error.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).expect("Valid UTF-8"));
+
+                let invalid_len = error.error_len().unwrap_or(invalid.len());
+                for _ in 0..invalid_len {
+                    text.push('?');
+                }
+
+                rest = &invalid[invalid_len..];
+            }
+        }
+    }
+}
+
+/// Pretty print all parser errors of a source
+pub fn print_parser_errors(
+    errors: &[ParserError],
+    source: &[u8],
+    path: &OsStr,
+) -> Result<(), Box<dyn Error>> {
+    let source = source_to_string(source);
+
+    for err in errors {
+        err.pretty_print(&source, Some(&path.to_string_lossy()))?;
+    }
+
+    Ok(())
+}
+
+/// Parse source or load it as serialized program if it starts with an IR header
+///
+/// Serialized programs replace the cell size, EOF mode and wrapping behavior of the config
+/// with the settings they were optimized for. A warning is printed for each replaced setting.
+///
+/// Returns `None` if the source has parser errors, which are already printed.
+pub fn load_program(
+    source: &[u8],
+    parser_options: &ParserOptions,
+    opt_mode: &mut OptimizeConfig,
+    path: &OsStr,
+) -> Result<Option<Program>, Box<dyn Error>> {
+    if Program::is_ir(source) {
+        let (cell_size, eof_mode, wrapping_is_ub) = (
+            opt_mode.cell_size,
+            opt_mode.eof_mode,
+            opt_mode.wrapping_is_ub,
+        );
+
+        let program = Program::load(source, opt_mode)?;
+
+        if cell_size != opt_mode.cell_size {
+            print_warning(&format!(
+                "Using {} bit cells the serialized program was optimized for instead of {} bit",
+                opt_mode.cell_size.bits(),
+                cell_size.bits()
+            ))?;
+        }
+
+        if eof_mode != opt_mode.eof_mode {
+            print_warning(&format!(
+                "Using --eof {} the serialized program was optimized for instead of {}",
+                eof_mode_name(opt_mode.eof_mode),
+                eof_mode_name(eof_mode)
+            ))?;
+        }
+
+        if wrapping_is_ub != opt_mode.wrapping_is_ub {
+            print_warning(&format!(
+                "Using --wrapping-is-ub={} the serialized program was optimized with",
+                opt_mode.wrapping_is_ub
+            ))?;
+        }
+
+        return Ok(Some(program));
+    }
+
+    match parse_bytes_with_recovery(source, parser_options) {
+        Ok(program) => Ok(Some(program)),
+        Err(errors) => {
+            print_parser_errors(&errors, source, path)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Value of the --eof option for the mode
+fn eof_mode_name(eof_mode: EofMode) -> &'static str {
+    match eof_mode {
+        EofMode::Zero => "0",
+        EofMode::Max => "max",
+        EofMode::Unchanged => "unchanged",
+    }
+}
+
+/// Print warning without source location
+pub fn print_warning(message: &str) -> Result<(), Box<dyn Error>> {
+    let files = SimpleFiles::<&str, &str>::new();
+    let writer = StandardStream::stderr(ColorChoice::Always);
+    let config = codespan_reporting::term::Config::default();
+
+    codespan_reporting::term::emit(
+        &mut writer.lock(),
+        &config,
+        &files,
+        &Diagnostic::warning().with_message(message),
+    )?;
+
+    Ok(())
+}
+
+/// Print warnings of the analyzer
+///
+/// The spans of serialized programs point into their original source which isn't available
+/// so only their byte offsets are printed.
+pub fn print_warnings(
+    warnings: &[Warning],
+    is_ir: bool,
+    source: &[u8],
+    path: &OsStr,
+) -> Result<(), Box<dyn Error>> {
+    if is_ir {
+        for warning in warnings {
+            print_warning(&format!(
+                "{} at {}..{} of the original source",
+                warning.warning_type, warning.span.start, warning.span.end
+            ))?;
+        }
+        Ok(())
+    } else {
+        Warning::pretty_print(
+            warnings,
+            &source_to_string(source),
+            Some(&path.to_string_lossy()),
+        )
+    }
+}
+
 pub fn get_optimize_config_from_args(matches: &ArgMatches) -> OptimizeConfig {
     let mut cfg = match matches.value_of("OPT_MODE").unwrap_or("2") {
         "1" => OptimizeConfig::o1(),
@@ -45,13 +203,201 @@ pub fn get_optimize_config_from_args(matches: &ArgMatches) -> OptimizeConfig {
         cfg.jit_level = Some(jit_level.to_string());
     }
 
+    if matches.is_present("BOUNDS_CHECKS") {
+        cfg.jit_bounds_checks = true;
+    }
+
+    if let Some(cell_size) = matches
+        .value_of("CELL_SIZE")
+        .and_then(|bits| bits.parse().ok())
+        .and_then(CellSize::from_bits)
+    {
+        cfg.cell_size = cell_size;
+    }
+
+    match matches.value_of("EOF") {
+        Some("0") => cfg.eof_mode = EofMode::Zero,
+        Some("max") => cfg.eof_mode = EofMode::Max,
+        Some("unchanged") => cfg.eof_mode = EofMode::Unchanged,
+        _ => {}
+    }
+
+    if matches.is_present("PRINTABLE_OUTPUT") {
+        cfg.printable_output = true;
+    }
+
+    if matches.is_present("BIDIRECTIONAL_TAPE") {
+        cfg.bidirectional_tape = true;
+    }
+
+    if let Some(heap_size) = matches
+        .value_of("HEAP_SIZE")
+        .and_then(|value| value.parse().ok())
+    {
+        cfg.heap_size = heap_size;
+    }
+
     if matches.is_present("WRAPPING_IS_UB") {
         cfg.wrapping_is_ub = true;
     }
 
+    if let Some(names) = matches.values_of("ENABLE_PASS") {
+        cfg.enabled_passes = names.map(str::to_owned).collect();
+    }
+
+    if let Some(names) = matches.values_of("DISABLE_PASS") {
+        cfg.disabled_passes = names.map(str::to_owned).collect();
+    }
+
     if matches.is_present("DEBUG_OPT") {
         cfg.debug = true;
     }
 
+    if matches.is_present("OPT_REPORT") {
+        cfg.statistics = true;
+    }
+
     cfg
 }
+
+/// Output and checks of the optimizer requested on the command line
+pub struct OptimizationOptions {
+    /// Format of the per pass report printed to stderr
+    pub report: Option<String>,
+
+    /// Print remarks of the passes
+    pub remarks: bool,
+
+    /// Check each pass with the interpreter
+    pub check: Option<CheckConfig>,
+}
+
+pub fn get_optimization_options_from_args(
+    matches: &ArgMatches,
+) -> Result<OptimizationOptions, Box<dyn Error>> {
+    Ok(OptimizationOptions {
+        report: matches.value_of("OPT_REPORT").map(str::to_owned),
+        remarks: matches.is_present("OPT_REMARKS"),
+        check: get_check_config_from_args(matches)?,
+    })
+}
+
+fn get_check_config_from_args(matches: &ArgMatches) -> Result<Option<CheckConfig>, Box<dyn Error>> {
+    if !matches.is_present("CHECK_PASSES") {
+        return Ok(None);
+    }
+
+    let mut check = CheckConfig::default();
+
+    if let Some(paths) = matches.values_of_os("CHECK_INPUT") {
+        for path in paths {
+            let input = std::fs::read(path)
+                .map_err(|err| CliError::CheckInputReadError(path.to_owned(), err))?;
+            check.inputs.push(input);
+        }
+    }
+
+    if let Some(count) = matches
+        .value_of("CHECK_RANDOM_INPUTS")
+        .and_then(|value| value.parse().ok())
+    {
+        check.random_inputs = count;
+    }
+
+    Ok(Some(check))
+}
+
+pub fn get_parser_options_from_args(matches: &ArgMatches) -> Result<ParserOptions, Box<dyn Error>> {
+    let dialect = match matches.value_of("DIALECT") {
+        Some("custom") => {
+            let path = matches.value_of_os("TOKEN_MAP").unwrap();
+            Dialect::Custom(read_token_map(path)?)
+        }
+        Some(name) => Dialect::from_name(name).unwrap_or_default(),
+        None => Dialect::Brainfuck,
+    };
+
+    let mut options = ParserOptions {
+        dialect,
+        breakpoints: matches.is_present("BREAKPOINTS"),
+        inline_input: matches.is_present("INLINE_INPUT"),
+        ..ParserOptions::default()
+    };
+
+    if let Some(depth) = matches
+        .value_of("MAX_LOOP_DEPTH")
+        .and_then(|value| value.parse().ok())
+    {
+        options.max_loop_depth = depth;
+    }
+
+    Ok(options)
+}
+
+/// Read token map with a command char and its token separated by whitespace on each line
+fn read_token_map(path: &OsStr) -> Result<TokenMap, Box<dyn Error>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|err| CliError::TokenMapReadError(path.to_owned(), err))?;
+
+    let mut token_map = TokenMap::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut chars = line.chars();
+        let command = chars.next().unwrap_or_default();
+        let token = chars.as_str().trim();
+
+        if !"><+-.,[]#".contains(command) || token.is_empty() {
+            return Err(CliError::InvalidTokenMap(path.to_owned(), index + 1).into());
+        }
+
+        token_map.insert(token, command);
+    }
+
+    Ok(token_map)
+}
+
+/// Optimize program and print the requested report and remarks
+///
+/// With a check config each pass is checked and a pass changing the behavior of the program
+/// is reported with its minimized reproducer.
+pub fn optimize_program(
+    program: &mut Program,
+    opt_mode: &OptimizeConfig,
+    options: &OptimizationOptions,
+    source: &[u8],
+    path: &OsStr,
+) -> Result<OptimizationReport, Box<dyn Error>> {
+    let report = match &options.check {
+        None => optimize_with_config(program, opt_mode),
+        Some(check) => {
+            match PassManager::from_config(opt_mode).run_checked(program, opt_mode, check) {
+                Ok(report) => report,
+                Err(err) => {
+                    err.pretty_print(&source_to_string(source), Some(&path.to_string_lossy()))?;
+                    err.write_details(stderr())?;
+                    return Err(CliError::OptimizationCheckFailed.into());
+                }
+            }
+        }
+    };
+
+    if options.report.as_deref() == Some("json") {
+        eprintln!("{}", report.to_json());
+    }
+
+    if options.remarks {
+        Remark::pretty_print(
+            &report.remarks(),
+            &source_to_string(source),
+            Some(&path.to_string_lossy()),
+        )?;
+    }
+
+    Ok(report)
+}
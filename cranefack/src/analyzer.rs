@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::fmt::{Display, Formatter};
 use std::ops::Range;
 
 use codespan_reporting::diagnostic::{Diagnostic, Label};
@@ -6,6 +7,7 @@ use codespan_reporting::files::SimpleFiles;
 use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 
 use crate::ir::ops::{Op, OpType};
+use crate::ir::visitor::Visitor;
 use crate::parser::Program;
 
 /// WarningType found in a call to [analyze]
@@ -15,6 +17,14 @@ pub enum WarningType {
     InfiniteLoop,
 }
 
+impl Display for WarningType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WarningType::InfiniteLoop => write!(f, "Possible infinite loop"),
+        }
+    }
+}
+
 /// Warning found in a call to [analyze]
 #[derive(Debug, Clone, PartialEq)]
 pub struct Warning {
@@ -48,11 +58,9 @@ impl Warning {
         for warning in warnings {
             let span = warning.span.clone();
 
-            let diagnostic = match &warning.warning_type {
-                WarningType::InfiniteLoop => Diagnostic::warning()
-                    .with_message("Possible infinite loop")
-                    .with_labels(vec![Label::primary(file_id, span)]),
-            };
+            let diagnostic = Diagnostic::warning()
+                .with_message(warning.warning_type.to_string())
+                .with_labels(vec![Label::primary(file_id, span)]);
 
             codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)?;
         }
@@ -63,23 +71,22 @@ impl Warning {
 
 /// Analyze program for possible errors
 pub fn analyze(program: &Program) -> Vec<Warning> {
-    let mut warnings = vec![];
-
-    analyze_ops(&mut warnings, &program.ops);
-
-    warnings
-}
-
-fn analyze_ops(warnings: &mut Vec<Warning>, ops: &[Op]) {
-    for op in ops.windows(2) {
-        check_infinite_loop(warnings, &op[0], &op[1]);
+    struct Analyzer {
+        warnings: Vec<Warning>,
     }
 
-    for op in ops {
-        if let Some(children) = op.op_type.get_children() {
-            analyze_ops(warnings, children);
+    impl Visitor for Analyzer {
+        fn visit_ops(&mut self, ops: &[Op], _depth: usize) {
+            for op in ops.windows(2) {
+                check_infinite_loop(&mut self.warnings, &op[0], &op[1]);
+            }
         }
     }
+
+    let mut analyzer = Analyzer { warnings: vec![] };
+    program.visit(&mut analyzer);
+
+    analyzer.warnings
 }
 
 fn check_infinite_loop(warnings: &mut Vec<Warning>, op1: &Op, op2: &Op) {
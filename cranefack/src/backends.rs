@@ -1,3 +1,46 @@
+use std::io::Write;
+
+use crate::OptimizeConfig;
+
+pub mod bf;
+pub mod c;
 pub mod cranelift;
 pub mod interpreter;
+pub mod object;
 pub mod rust;
+pub mod wasm;
+
+/// Default number of heap cells right of the start cell
+///
+/// This matches the limit the interpreter always had, so heap errors are reported at the same
+/// pointer by every backend. The compiled backends allocate zeroed memory that the OS only maps
+/// once it's touched, so a program using few cells doesn't pay for the full heap.
+pub const DEFAULT_HEAP_SIZE: usize = 16 * 1024 * 1024;
+
+/// Number of cells left of the start cell
+///
+/// With a bidirectional tape the heap extends by the heap size in both directions.
+pub(crate) fn negative_cells(config: &OptimizeConfig) -> usize {
+    if config.bidirectional_tape {
+        config.heap_size
+    } else {
+        0
+    }
+}
+
+/// Write a single byte of program output
+///
+/// With printable output zero and non ASCII bytes are escaped as `\0x..`
+pub(crate) fn write_byte<W: Write + ?Sized>(
+    output: &mut W,
+    value: u8,
+    printable: bool,
+) -> std::io::Result<()> {
+    if !printable {
+        output.write_all(&[value])
+    } else if value != 0 && value.is_ascii() {
+        write!(output, "{}", value as char)
+    } else {
+        write!(output, "\\0x{:x}", value)
+    }
+}
@@ -0,0 +1,570 @@
+use std::slice::Iter;
+
+use crate::errors::CompilerError;
+use crate::ir::ops::{CellSize, LoopDecrement, Op, OpType};
+use crate::parser::Program;
+use crate::OptimizeConfig;
+
+/// Maximum length of the lines of the generated source
+const LINE_LENGTH: usize = 80;
+
+/// Compile program back into classic brainfuck
+///
+/// Cell values are wrapped at the cell size of the config, so the generated source has to be run
+/// with the same cell size. Ops that need a temporary cell like [OpType::Copy] use scratch cells
+/// interleaved with the program cells, which doubles all pointer movements.
+pub fn compile_to_bf(program: &Program, config: &OptimizeConfig) -> Result<String, CompilerError> {
+    let mut builder = BfBuilder {
+        code: String::new(),
+        cell_size: config.cell_size,
+        stride: if needs_scratch(&program.ops) { 2 } else { 1 },
+        pointer: 0,
+    };
+
+    // Blocks are emitted with an explicit stack to support deeply nested programs
+    let mut stack = vec![Block {
+        ops: program.ops.iter(),
+        op: None,
+        shift: Some(0),
+    }];
+
+    while let Some(block) = stack.last_mut() {
+        match block.ops.next() {
+            Some(op) => {
+                if let Some(children) = op.op_type.get_children() {
+                    builder.begin_block(op);
+
+                    stack.push(Block {
+                        ops: children.iter(),
+                        op: Some(op),
+                        shift: Some(0),
+                    });
+                } else {
+                    builder.append_op(op, &mut block.shift);
+                }
+            }
+            None => {
+                let block = stack.pop().expect("Block on stack");
+
+                if let (Some(op), Some(parent)) = (block.op, stack.last_mut()) {
+                    builder.end_block(op, block.shift, &mut parent.shift)?;
+                }
+            }
+        }
+    }
+
+    let lines: Vec<_> = builder
+        .code
+        .as_bytes()
+        .chunks(LINE_LENGTH)
+        .map(|line| String::from_utf8_lossy(line))
+        .collect();
+
+    Ok(lines.join("\n"))
+}
+
+/// Check if any op needs a temporary cell
+fn needs_scratch(ops: &[Op]) -> bool {
+    let mut stack = vec![ops.iter()];
+
+    while let Some(ops) = stack.last_mut() {
+        match ops.next() {
+            Some(op) => match &op.op_type {
+                OpType::NzAdd(..)
+                | OpType::NzSub(..)
+                | OpType::NzMul(..)
+                | OpType::Copy(..)
+                | OpType::PutString(..) => return true,
+                op_type => {
+                    if let Some(children) = op_type.get_children() {
+                        stack.push(children.iter());
+                    }
+                }
+            },
+            None => {
+                stack.pop();
+            }
+        }
+    }
+
+    false
+}
+
+struct Block<'o> {
+    ops: Iter<'o, Op>,
+    op: Option<&'o Op>,
+
+    /// Pointer movement since the start of the block if it's static
+    shift: Option<isize>,
+}
+
+/// Cell addressed relative to the current pointer
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Target {
+    Cell(isize),
+
+    /// Scratch cell next to the cell that is always zero between ops
+    Scratch(isize),
+}
+
+struct BfBuilder {
+    code: String,
+    cell_size: CellSize,
+
+    /// Distance of neighbouring program cells on the tape
+    stride: isize,
+
+    /// Offset of the tape pointer to the cell of the program pointer
+    ///
+    /// Pointer movements are emitted lazily when a cell gets accessed.
+    pointer: isize,
+}
+
+impl BfBuilder {
+    fn move_to(&mut self, target: Target) {
+        let position = match target {
+            Target::Cell(offset) => offset * self.stride,
+            Target::Scratch(offset) => offset * self.stride + 1,
+        };
+
+        let distance = position - self.pointer;
+        let c = if distance > 0 { '>' } else { '<' };
+
+        for _ in 0..distance.abs() {
+            self.code.push(c);
+        }
+
+        self.pointer = position;
+    }
+
+    /// Add value to the cell using the shorter direction of + and -
+    fn add(&mut self, target: Target, value: i64) {
+        self.move_to(target);
+
+        let size = 1i64 << self.cell_size.bits();
+        let value = value.rem_euclid(size);
+
+        if value <= size / 2 {
+            self.repeat('+', value);
+        } else {
+            self.repeat('-', size - value);
+        }
+    }
+
+    fn repeat(&mut self, c: char, count: i64) {
+        for _ in 0..count {
+            self.code.push(c);
+        }
+    }
+
+    fn clear(&mut self, target: Target) {
+        self.move_to(target);
+        self.code.push_str("[-]");
+    }
+
+    /// Add multiples of the cell value to all targets and clear the cell
+    fn transfer(&mut self, src: Target, targets: &[(Target, i64)]) {
+        self.move_to(src);
+        self.code.push_str("[-");
+
+        for (target, multi) in targets {
+            self.add(*target, *multi);
+        }
+
+        self.move_to(src);
+        self.code.push(']');
+    }
+
+    /// Add multiples of the cell value to the target keeping the source cell intact
+    fn add_preserving(&mut self, src: isize, dest: isize, multi: i64) {
+        let scratch = Target::Scratch(src);
+
+        if src == dest {
+            self.transfer(Target::Cell(src), &[(scratch, 1)]);
+            self.transfer(scratch, &[(Target::Cell(src), multi + 1)]);
+        } else {
+            self.transfer(
+                Target::Cell(src),
+                &[(Target::Cell(dest), multi), (scratch, 1)],
+            );
+            self.transfer(scratch, &[(Target::Cell(src), 1)]);
+        }
+    }
+
+    fn shift(&mut self, offset: isize, shift: &mut Option<isize>) {
+        self.pointer -= offset * self.stride;
+
+        if let Some(shift) = shift {
+            *shift += offset;
+        }
+    }
+
+    fn append_op(&mut self, op: &Op, shift: &mut Option<isize>) {
+        match &op.op_type {
+            OpType::Start => {
+                // ignore
+            }
+            OpType::IncPtr(count) => self.shift(*count as isize, shift),
+            OpType::DecPtr(count) => self.shift(-(*count as isize), shift),
+            OpType::Inc(offset, value) => self.add(Target::Cell(*offset), *value as i64),
+            OpType::Dec(offset, value) => self.add(Target::Cell(*offset), -(*value as i64)),
+            OpType::Set(offset, value) => {
+                self.clear(Target::Cell(*offset));
+                self.add(Target::Cell(*offset), *value as i64);
+            }
+            OpType::Add(src, dest, multi) | OpType::Sub(src, dest, multi) => {
+                let multi = match &op.op_type {
+                    OpType::Add(..) => *multi as i64,
+                    _ => -(*multi as i64),
+                };
+
+                if src == dest {
+                    self.clear(Target::Cell(*src));
+                } else {
+                    self.transfer(Target::Cell(*src), &[(Target::Cell(*dest), multi)]);
+                }
+            }
+            OpType::NzAdd(src, dest, multi) => self.add_preserving(*src, *dest, *multi as i64),
+            OpType::NzSub(src, dest, multi) => self.add_preserving(*src, *dest, -(*multi as i64)),
+            OpType::CAdd(src, dest, value) => {
+                self.add(Target::Cell(*dest), *value as i64);
+                self.clear(Target::Cell(*src));
+            }
+            OpType::CSub(src, dest, value) => {
+                self.add(Target::Cell(*dest), -(*value as i64));
+                self.clear(Target::Cell(*src));
+            }
+            OpType::NzCAdd(_, dest, value) => self.add(Target::Cell(*dest), *value as i64),
+            OpType::NzCSub(_, dest, value) => self.add(Target::Cell(*dest), -(*value as i64)),
+            OpType::Mul(src, dest, multi) => {
+                self.clear(Target::Cell(*dest));
+
+                if src != dest {
+                    self.transfer(Target::Cell(*src), &[(Target::Cell(*dest), *multi as i64)]);
+                }
+            }
+            OpType::NzMul(src, dest, multi) => {
+                if src == dest {
+                    self.add_preserving(*src, *dest, *multi as i64 - 1);
+                } else {
+                    self.clear(Target::Cell(*dest));
+                    self.add_preserving(*src, *dest, *multi as i64);
+                }
+            }
+            OpType::Move(src, dest) => {
+                self.clear(Target::Cell(*dest));
+
+                if src != dest {
+                    self.transfer(Target::Cell(*src), &[(Target::Cell(*dest), 1)]);
+                }
+            }
+            OpType::Copy(src, dest) => {
+                if src != dest {
+                    self.clear(Target::Cell(*dest));
+                    self.add_preserving(*src, *dest, 1);
+                }
+            }
+            OpType::PutChar(offset) => {
+                self.move_to(Target::Cell(*offset));
+                self.code.push('.');
+            }
+            OpType::PutString(array) => {
+                let mut value = 0;
+
+                for c in array {
+                    self.add(Target::Scratch(0), *c as i64 - value);
+                    self.code.push('.');
+                    value = *c as i64;
+                }
+
+                self.add(Target::Scratch(0), -value);
+            }
+            OpType::GetChar(offset) => {
+                self.move_to(Target::Cell(*offset));
+                self.code.push(',');
+            }
+            OpType::SearchZero(step, _) => {
+                self.move_to(Target::Cell(0));
+                self.code.push('[');
+
+                let c = if *step > 0 { '>' } else { '<' };
+                self.repeat(c, (step.abs() * self.stride) as i64);
+
+                self.code.push(']');
+                *shift = None;
+            }
+            OpType::Breakpoint => {
+                self.move_to(Target::Cell(0));
+                self.code.push('#');
+            }
+            OpType::DLoop(..)
+            | OpType::LLoop(..)
+            | OpType::ILoop(..)
+            | OpType::CLoop(..)
+            | OpType::TNz(..)
+            | OpType::DTNz(..) => unreachable!("Blocks are handled by begin and end block"),
+        }
+    }
+
+    fn begin_block(&mut self, op: &Op) {
+        let counter = Target::Cell(0);
+
+        if let OpType::CLoop(_, iterations, ..) = &op.op_type {
+            self.clear(counter);
+            self.add(counter, *iterations as i64);
+        }
+
+        self.move_to(counter);
+        self.code.push('[');
+
+        match &op.op_type {
+            OpType::ILoop(_, step, LoopDecrement::Pre, _) => self.add(counter, -(*step as i64)),
+            OpType::CLoop(_, _, LoopDecrement::Pre, _) => self.add(counter, -1),
+            _ => {}
+        }
+    }
+
+    fn end_block(
+        &mut self,
+        op: &Op,
+        shift: Option<isize>,
+        parent_shift: &mut Option<isize>,
+    ) -> Result<(), CompilerError> {
+        let counter = Target::Cell(0);
+
+        match &op.op_type {
+            // The optimizer only creates DTNz blocks that end on a zero cell
+            OpType::DLoop(..) | OpType::DTNz(..) => {
+                if shift != Some(0) {
+                    *parent_shift = None;
+                }
+            }
+            _ => {
+                // Restore the pointer of the loop start
+                let shift = shift.ok_or_else(|| CompilerError::InternalCompilerError {
+                    message: format!(
+                        "Can't restore pointer of block at {}..{} after dynamic pointer movement",
+                        op.span.start, op.span.end
+                    ),
+                })?;
+
+                self.pointer += shift * self.stride;
+
+                match &op.op_type {
+                    OpType::ILoop(_, step, LoopDecrement::Post | LoopDecrement::Auto, _) => {
+                        self.add(counter, -(*step as i64))
+                    }
+                    OpType::CLoop(_, _, LoopDecrement::Post | LoopDecrement::Auto, _) => {
+                        self.add(counter, -1)
+                    }
+                    OpType::TNz(..) => self.clear(counter),
+                    _ => {}
+                }
+            }
+        }
+
+        self.move_to(counter);
+        self.code.push(']');
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backends::bf::compile_to_bf;
+    use crate::ir::ops::{CellSize, LoopDecrement, Op, OpType};
+    use crate::ir::opt_info::BlockInfo;
+    use crate::parser::Program;
+    use crate::{optimize_with_config, parse, Interpreter, OptimizeConfig};
+
+    fn run_interpreter(program: &Program, config: &OptimizeConfig, input: &[u8]) -> Vec<u8> {
+        let mut output = vec![];
+        let mut interpreter = Interpreter::new_with_config(input, &mut output, config);
+        interpreter.execute(program).unwrap();
+
+        output
+    }
+
+    fn diff_ops(ops: Vec<Op>, config: &OptimizeConfig) {
+        let program = Program { ops };
+        let source = compile_to_bf(&program, config).unwrap();
+
+        assert_eq!(
+            run_interpreter(&parse(&source).unwrap(), config, b""),
+            run_interpreter(&program, config, b""),
+            "{}",
+            source
+        );
+    }
+
+    fn diff_with_interpreter(source: &str, input: &[u8]) {
+        let reference = run_interpreter(&parse(source).unwrap(), &OptimizeConfig::o0(), input);
+
+        for config in [
+            OptimizeConfig::o1(),
+            OptimizeConfig::o2(),
+            OptimizeConfig::o3(),
+        ] {
+            let mut program = parse(source).unwrap();
+            optimize_with_config(&mut program, &config);
+
+            let lowered = compile_to_bf(&program, &config).unwrap();
+            let mut program = parse(&lowered).unwrap();
+            optimize_with_config(&mut program, &OptimizeConfig::o1());
+
+            assert_eq!(run_interpreter(&program, &config, input), reference);
+        }
+    }
+
+    #[test]
+    fn test_arithmetics() {
+        for cell_size in [CellSize::Bits8, CellSize::Bits16] {
+            let mut config = OptimizeConfig::o0();
+            config.cell_size = cell_size;
+
+            for op_type in [
+                OpType::Add(1, 2, 3),
+                OpType::Sub(1, 2, 3),
+                OpType::NzAdd(1, -1, 2),
+                OpType::NzAdd(1, 1, 2),
+                OpType::NzSub(1, 2, 200),
+                OpType::NzSub(1, 1, 2),
+                OpType::CAdd(1, 2, 3),
+                OpType::CSub(1, 1, 3),
+                OpType::NzCAdd(1, 2, 3),
+                OpType::NzCSub(1, 2, 300),
+                OpType::Mul(1, 2, 3),
+                OpType::Mul(1, 1, 3),
+                OpType::NzMul(1, 2, 3),
+                OpType::NzMul(1, 1, 3),
+                OpType::Move(1, 2),
+                OpType::Copy(1, 2),
+                OpType::Copy(1, 1),
+            ] {
+                diff_ops(
+                    vec![
+                        Op::set(0..1, 5),
+                        Op::set_with_offset(0..1, 1, 7),
+                        Op::set_with_offset(0..1, 2, 11),
+                        Op::inc_ptr(0..1, 1),
+                        Op {
+                            op_type,
+                            span: 0..1,
+                        },
+                        Op::put_char_with_offset(0..1, -1),
+                        Op::put_char(0..1),
+                        Op::put_char_with_offset(0..1, 1),
+                        Op::put_char_with_offset(0..1, 2),
+                    ],
+                    &config,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_put_string() {
+        let program = Program {
+            ops: vec![
+                Op::set(0..1, 65),
+                Op::put_string(0..1, b"Hello\n\0\xff".to_vec()),
+                Op::put_char(0..1),
+            ],
+        };
+
+        let source = compile_to_bf(&program, &OptimizeConfig::o0()).unwrap();
+        let output = run_interpreter(&parse(&source).unwrap(), &OptimizeConfig::o0(), b"");
+
+        assert_eq!(output, b"Hello\n\0\xffA");
+    }
+
+    #[test]
+    fn test_counting_loops() {
+        for decrement in [LoopDecrement::Pre, LoopDecrement::Post, LoopDecrement::Auto] {
+            diff_ops(
+                vec![
+                    Op::set(0..1, 6),
+                    Op::i_loop_with_decrement(
+                        0..1,
+                        vec![Op::inc_ptr(0..1, 1), Op::inc(0..1, 1), Op::dec_ptr(0..1, 1)],
+                        2,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::c_loop_with_decrement(
+                        0..1,
+                        vec![Op::inc_ptr(0..1, 2), Op::inc(0..1, 3), Op::dec_ptr(0..1, 2)],
+                        4,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(0..1),
+                    Op::put_char_with_offset(0..1, 1),
+                    Op::put_char_with_offset(0..1, 2),
+                ],
+                &OptimizeConfig::o0(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_tnz_and_search_zero() {
+        for value in [0, 1] {
+            diff_ops(
+                vec![
+                    Op::set(0..1, value),
+                    Op::t_nz(
+                        0..1,
+                        vec![Op::inc_ptr(0..1, 1), Op::set_with_offset(0..1, 1, 10)],
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(0..1),
+                    Op::put_char_with_offset(0..1, 2),
+                    Op::set_with_offset(0..1, 1, 1),
+                    Op::set_with_offset(0..1, 2, 2),
+                    Op::inc_ptr(0..1, 1),
+                    Op::search_zero(0..1, 1),
+                    Op::put_char_with_offset(0..1, -1),
+                ],
+                &OptimizeConfig::o0(),
+            );
+        }
+    }
+
+    #[test]
+    fn test_dynamic_pointer_in_local_loop() {
+        let program = Program {
+            ops: vec![Op::l_loop(
+                0..1,
+                vec![Op::search_zero(0..1, 1)],
+                BlockInfo::new_empty(),
+            )],
+        };
+
+        assert!(compile_to_bf(&program, &OptimizeConfig::o0()).is_err());
+    }
+
+    #[test]
+    fn test_hello_world() {
+        diff_with_interpreter(include_str!("../../../test_programs/hello_world.bf"), b"");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        diff_with_interpreter(include_str!("../../../test_programs/fizzbuzz.bf"), b"");
+    }
+
+    #[test]
+    fn test_bottles() {
+        diff_with_interpreter(include_str!("../../../test_programs/bottles.bf"), b"");
+    }
+
+    #[test]
+    fn test_factor() {
+        diff_with_interpreter(
+            include_str!("../../../test_programs/factor.bf"),
+            include_bytes!("../../../test_programs/factor.bf.in"),
+        );
+    }
+}
@@ -0,0 +1,728 @@
+use std::error::Error;
+use std::fmt::Write;
+use std::slice::Iter;
+
+use crate::backends::negative_cells;
+use crate::ir::ops::{CellSize, LoopDecrement, Op, OpType};
+use crate::parser::Program;
+use crate::OptimizeConfig;
+
+/// Compile program into a C file that can be compiled with gcc or clang
+pub fn compile_to_c(program: &Program) -> String {
+    compile_to_c_with_config(program, &OptimizeConfig::o0())
+}
+
+/// Compile program into a C file using the runtime settings of the optimization config
+pub fn compile_to_c_with_config(program: &Program, config: &OptimizeConfig) -> String {
+    let mut code = "".to_owned();
+
+    print_ops(&mut code, &program.ops, 1).expect("No io error");
+
+    let cell_type = match config.cell_size {
+        CellSize::Bits8 => "uint8_t",
+        CellSize::Bits16 => "uint16_t",
+        CellSize::Bits32 => "uint32_t",
+    };
+
+    let eof_value = config.eof_mode.value(config.cell_size);
+
+    include_str!("c.tpl")
+        .replace("{{CELL}}", cell_type)
+        .replace(
+            "{{HAS_EOF_VALUE}}",
+            &(eof_value.is_some() as u8).to_string(),
+        )
+        .replace("{{EOF_VALUE}}", &format!("{}u", eof_value.unwrap_or(0)))
+        .replace(
+            "{{PRINTABLE_OUTPUT}}",
+            &(config.printable_output as u8).to_string(),
+        )
+        .replace("{{HEAP_SIZE}}", &config.heap_size.to_string())
+        .replace("{{NEGATIVE_CELLS}}", &negative_cells(config).to_string())
+        .replace("{{CODE}}", &code)
+}
+
+/// Indentation stops growing at this depth to keep the code of deeply nested programs small
+const MAX_INDENT_DEPTH: usize = 32;
+
+struct Block<'o> {
+    ops: Iter<'o, Op>,
+    op: Option<&'o Op>,
+    depth: usize,
+}
+
+fn print_ops(out: &mut String, ops: &[Op], depth: usize) -> Result<(), Box<dyn Error>> {
+    // Blocks are printed with an explicit stack to support deeply nested programs
+    let mut stack = vec![Block {
+        ops: ops.iter(),
+        op: None,
+        depth,
+    }];
+
+    while let Some(block) = stack.last_mut() {
+        match block.ops.next() {
+            Some(op) => {
+                let indent = indent(block.depth);
+
+                if let Some(children) = op.op_type.get_children() {
+                    let depth = block.depth + begin_block(out, op, &indent)?;
+
+                    stack.push(Block {
+                        ops: children.iter(),
+                        op: Some(op),
+                        depth,
+                    });
+                } else {
+                    print_op(out, op, &indent)?;
+                }
+            }
+            None => {
+                let block = stack.pop().expect("Block on stack");
+
+                if let (Some(op), Some(parent)) = (block.op, stack.last()) {
+                    end_block(out, op, &indent(parent.depth))?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn indent(depth: usize) -> String {
+    "    ".repeat(depth.min(MAX_INDENT_DEPTH))
+}
+
+fn print_op(out: &mut String, op: &Op, indent: &str) -> Result<(), Box<dyn Error>> {
+    match &op.op_type {
+        OpType::Start | OpType::Breakpoint => {
+            // ignore
+        }
+        OpType::IncPtr(count) => writeln!(out, "{}inc_ptr({}u);", indent, count)?,
+        OpType::DecPtr(count) => writeln!(out, "{}dec_ptr({}u);", indent, count)?,
+        OpType::Inc(offset, count) => writeln!(out, "{}inc({}, {}u);", indent, offset, count)?,
+        OpType::Dec(offset, count) => writeln!(out, "{}dec({}, {}u);", indent, offset, count)?,
+        OpType::Set(offset, value) => writeln!(out, "{}set({}, {}u);", indent, offset, value)?,
+        OpType::Add(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}add({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::NzAdd(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}nz_add({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::CAdd(src_offset, dest_offset, value) => writeln!(
+            out,
+            "{}c_add({}, {}, {}u);",
+            indent, src_offset, dest_offset, value
+        )?,
+        OpType::NzCAdd(_src_offset, dest_offset, value) => {
+            writeln!(out, "{}nz_c_add({}, {}u);", indent, dest_offset, value)?
+        }
+        OpType::Sub(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}sub({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::NzSub(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}nz_sub({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::CSub(src_offset, dest_offset, value) => writeln!(
+            out,
+            "{}c_sub({}, {}, {}u);",
+            indent, src_offset, dest_offset, value
+        )?,
+        OpType::NzCSub(_src_offset, dest_offset, value) => {
+            writeln!(out, "{}nz_c_sub({}, {}u);", indent, dest_offset, value)?
+        }
+        OpType::Mul(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}mul({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::NzMul(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "{}nz_mul({}, {}, {}u);",
+            indent, src_offset, dest_offset, multi
+        )?,
+        OpType::Move(src_offset, dest_offset) => {
+            writeln!(out, "{}move({}, {});", indent, src_offset, dest_offset)?
+        }
+        OpType::Copy(src_offset, dest_offset) => {
+            writeln!(out, "{}copy({}, {});", indent, src_offset, dest_offset)?
+        }
+        OpType::GetChar(offset) => writeln!(out, "{}get_char({});", indent, offset)?,
+        OpType::PutString(array) => {
+            write!(out, "{}put_string(\"", indent)?;
+            for value in array {
+                write!(out, "\\x{:02x}", value)?;
+            }
+            writeln!(out, "\", {});", array.len())?;
+        }
+        OpType::PutChar(offset) => writeln!(out, "{}put_char({});", indent, offset)?,
+        OpType::SearchZero(step, _) => writeln!(out, "{}search_zero({});", indent, step)?,
+        OpType::DLoop(..)
+        | OpType::LLoop(..)
+        | OpType::ILoop(..)
+        | OpType::CLoop(..)
+        | OpType::TNz(..)
+        | OpType::DTNz(..) => unreachable!("Blocks are printed by print_ops"),
+    }
+
+    Ok(())
+}
+
+/// Print the code in front of the children of a block and return their additional depth
+fn begin_block(out: &mut String, op: &Op, indent: &str) -> Result<usize, Box<dyn Error>> {
+    match &op.op_type {
+        OpType::DLoop(..) => {
+            writeln!(out, "{}while (*heap_value() != 0) {{", indent)?;
+            Ok(1)
+        }
+        OpType::LLoop(..) => {
+            writeln!(out, "{}{{", indent)?;
+            writeln!(out, "{}    size_t heap_pointer = pointer;", indent)?;
+            writeln!(out, "{}    while (*heap_value() != 0) {{", indent)?;
+            Ok(2)
+        }
+        OpType::ILoop(_, step, decrement, _) => {
+            writeln!(out, "{}{{", indent)?;
+            writeln!(out, "{}    size_t heap_pointer = pointer;", indent)?;
+            writeln!(out, "{}    Cell left = *heap_value();", indent)?;
+            writeln!(out, "{}    while (left != 0) {{", indent)?;
+
+            if *decrement == LoopDecrement::Pre {
+                writeln!(out, "{}        left = (Cell) (left - {}u);", indent, step)?;
+                writeln!(out, "{}        *heap_value() = left;", indent)?;
+            }
+
+            Ok(2)
+        }
+        OpType::CLoop(_, iterations, decrement, _) => {
+            writeln!(out, "{}{{", indent)?;
+            writeln!(out, "{}    size_t heap_pointer = pointer;", indent)?;
+
+            match decrement {
+                LoopDecrement::Pre => {
+                    writeln!(out, "{}    Cell left = (Cell) {}u;", indent, iterations)?;
+                    writeln!(out, "{}    while (left != 0) {{", indent)?;
+                    writeln!(out, "{}        left = (Cell) (left - 1u);", indent)?;
+                    writeln!(out, "{}        *heap_value() = left;", indent)?;
+                }
+                LoopDecrement::Post => {
+                    writeln!(out, "{}    Cell left = (Cell) {}u;", indent, iterations)?;
+                    writeln!(out, "{}    *heap_value() = left;", indent)?;
+                    writeln!(out, "{}    while (left != 0) {{", indent)?;
+                }
+                LoopDecrement::Auto => {
+                    writeln!(
+                        out,
+                        "{}    for (uint32_t i = 0; i < {}u; i++) {{",
+                        indent, iterations
+                    )?;
+                }
+            }
+
+            Ok(2)
+        }
+        OpType::TNz(..) => {
+            writeln!(out, "{}if (*heap_value() != 0) {{", indent)?;
+            writeln!(out, "{}    size_t heap_pointer = pointer;", indent)?;
+            Ok(1)
+        }
+        OpType::DTNz(..) => {
+            writeln!(out, "{}if (*heap_value() != 0) {{", indent)?;
+            Ok(1)
+        }
+        op_type => unreachable!("Op {:?} is not a block", op_type),
+    }
+}
+
+/// Print the code after the children of a block
+fn end_block(out: &mut String, op: &Op, indent: &str) -> Result<(), Box<dyn Error>> {
+    match &op.op_type {
+        OpType::DLoop(..) | OpType::DTNz(..) => {
+            writeln!(out, "{}}}", indent)?;
+        }
+        OpType::LLoop(..) => {
+            writeln!(out, "{}        pointer = heap_pointer;", indent)?;
+            writeln!(out, "{}    }}", indent)?;
+            writeln!(out, "{}}}", indent)?;
+        }
+        OpType::ILoop(_, step, decrement, _) => {
+            match decrement {
+                LoopDecrement::Pre => {
+                    writeln!(out, "{}        pointer = heap_pointer;", indent)?;
+                }
+                LoopDecrement::Post => {
+                    writeln!(out, "{}        pointer = heap_pointer;", indent)?;
+                    writeln!(out, "{}        left = (Cell) (left - {}u);", indent, step)?;
+                    writeln!(out, "{}        *heap_value() = left;", indent)?;
+                }
+                LoopDecrement::Auto => {
+                    writeln!(out, "{}        left = (Cell) (left - {}u);", indent, step)?;
+                    writeln!(out, "{}        pointer = heap_pointer;", indent)?;
+                }
+            }
+
+            writeln!(out, "{}    }}", indent)?;
+            writeln!(out, "{}    *heap_value() = 0;", indent)?;
+            writeln!(out, "{}}}", indent)?;
+        }
+        OpType::CLoop(_, _, decrement, _) => {
+            writeln!(out, "{}        pointer = heap_pointer;", indent)?;
+
+            if *decrement == LoopDecrement::Post {
+                writeln!(out, "{}        left = (Cell) (left - 1u);", indent)?;
+                writeln!(out, "{}        *heap_value() = left;", indent)?;
+            }
+
+            writeln!(out, "{}    }}", indent)?;
+            writeln!(out, "{}    *heap_value() = 0;", indent)?;
+            writeln!(out, "{}}}", indent)?;
+        }
+        OpType::TNz(..) => {
+            writeln!(out, "{}    pointer = heap_pointer;", indent)?;
+            writeln!(out, "{}    *heap_value() = 0;", indent)?;
+            writeln!(out, "{}}}", indent)?;
+        }
+        op_type => unreachable!("Op {:?} is not a block", op_type),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Cursor;
+    use std::io::Write;
+    use std::process::{Command, Stdio};
+
+    use super::*;
+    use crate::ir::ops::EofMode;
+    use crate::ir::opt_info::BlockInfo;
+    use crate::{
+        optimize_with_config, parse, parse_bytes_with_options, Interpreter, ParserOptions,
+    };
+
+    fn run_c(code: &str, name: &str, input: &[u8]) -> Vec<u8> {
+        let build_dir =
+            std::env::temp_dir().join(format!("cranefack-c-test-{}-{}", std::process::id(), name));
+        fs::create_dir_all(&build_dir).unwrap();
+
+        let source = build_dir.join("program.c");
+        let binary = build_dir.join("program");
+        fs::write(&source, code).unwrap();
+
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        let status = Command::new(cc)
+            .arg("-O1")
+            .arg(&source)
+            .arg("-o")
+            .arg(&binary)
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        let mut child = Command::new(&binary)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let output = child.wait_with_output().unwrap();
+
+        fs::remove_dir_all(&build_dir).unwrap();
+
+        output.stdout
+    }
+
+    fn run_interpreter(program: &Program, config: &OptimizeConfig, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, config)
+            .execute(program)
+            .unwrap();
+
+        output
+    }
+
+    fn diff_program(name: &str, program: &Program) {
+        let config = OptimizeConfig::o0();
+
+        let expected = run_interpreter(program, &config, b"");
+        let output = run_c(&compile_to_c_with_config(program, &config), name, b"");
+
+        assert_eq!(output, expected);
+    }
+
+    fn diff_with_interpreter(name: &str, source: &str, input: &[u8]) {
+        let configs = [
+            ("o0", OptimizeConfig::o0()),
+            ("o1", OptimizeConfig::o1()),
+            ("o2", OptimizeConfig::o2()),
+            ("o3", OptimizeConfig::o3()),
+        ];
+
+        for (level, config) in configs {
+            let mut program = parse(source).unwrap();
+            optimize_with_config(&mut program, &config);
+
+            let expected = run_interpreter(&program, &config, input);
+            let output = run_c(
+                &compile_to_c_with_config(&program, &config),
+                &format!("{}-{}", name, level),
+                input,
+            );
+
+            assert_eq!(output, expected, "{} at {}", name, level);
+        }
+    }
+
+    #[test]
+    fn test_hello_world() {
+        diff_with_interpreter(
+            "hello_world",
+            include_str!("../../../test_programs/hello_world.bf"),
+            b"",
+        );
+    }
+
+    #[test]
+    fn test_fizz() {
+        diff_with_interpreter("fizz", include_str!("../../../test_programs/fizz.bf"), b"");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        diff_with_interpreter(
+            "fizzbuzz",
+            include_str!("../../../test_programs/fizzbuzz.bf"),
+            b"",
+        );
+    }
+
+    #[test]
+    fn test_bottles() {
+        diff_with_interpreter(
+            "bottles",
+            include_str!("../../../test_programs/bottles.bf"),
+            b"",
+        );
+    }
+
+    #[test]
+    fn test_factor() {
+        diff_with_interpreter(
+            "factor",
+            include_str!("../../../test_programs/factor.bf"),
+            include_bytes!("../../../test_programs/factor.bf.in"),
+        );
+    }
+
+    #[test]
+    fn test_life() {
+        diff_with_interpreter(
+            "life",
+            include_str!("../../../test_programs/life.bf"),
+            include_bytes!("../../../test_programs/life.bf.in"),
+        );
+    }
+
+    #[test]
+    fn test_cell_size() {
+        for cell_size in [CellSize::Bits8, CellSize::Bits16, CellSize::Bits32] {
+            let mut config = OptimizeConfig::o2();
+            config.cell_size = cell_size;
+
+            let mut program = parse(include_str!("../../../test_programs/cell_size.bf")).unwrap();
+            optimize_with_config(&mut program, &config);
+
+            let expected = run_interpreter(&program, &config, b"");
+            let output = run_c(
+                &compile_to_c_with_config(&program, &config),
+                &format!("cell_size-{}", cell_size.bits()),
+                b"",
+            );
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_eof_modes() {
+        for eof_mode in [EofMode::Zero, EofMode::Max, EofMode::Unchanged] {
+            let mut config = OptimizeConfig::o0();
+            config.eof_mode = eof_mode;
+
+            let program = parse("+++,.").unwrap();
+
+            let expected = run_interpreter(&program, &config, b"");
+            let output = run_c(
+                &compile_to_c_with_config(&program, &config),
+                &format!("eof-{:?}", eof_mode),
+                b"",
+            );
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let program = Program {
+            ops: vec![
+                Op::set(0..1, 7),
+                Op::set_with_offset(0..1, 1, 250),
+                Op::nz_add(0..1, 1, 3),
+                Op::put_char_with_offset(0..1, 1),
+                Op::nz_sub(0..1, 1, 5),
+                Op::put_char_with_offset(0..1, 1),
+                Op::nz_mul(0..1, 2, 40),
+                Op::put_char_with_offset(0..1, 2),
+                Op::nz_c_add(0..1, 2, 10),
+                Op::nz_c_sub(0..1, 1, 20),
+                Op::copy(0..1, 0, 3),
+                Op::put_char_with_offset(0..1, 3),
+                Op::add(0..1, 2, 2),
+                Op::put_char(0..1),
+                Op::put_char_with_offset(0..1, 2),
+                Op::set(0..1, 3),
+                Op::sub(0..1, 1, 200),
+                Op::put_char_with_offset(0..1, 1),
+                Op::set(0..1, 3),
+                Op::mul(0..1, 4, 100),
+                Op::put_char_with_offset(0..1, 4),
+                Op::set(0..1, 3),
+                Op::c_add(0..1, 3, 255),
+                Op::set(0..1, 3),
+                Op::c_sub(0..1, 4, 2),
+                Op::_move(0..1, 4, 5),
+                Op::put_char_with_offset(0..1, 3),
+                Op::put_char_with_offset(0..1, 4),
+                Op::put_char_with_offset(0..1, 5),
+                Op::put_char(0..1),
+                Op::dec(0..1, 3),
+                Op::put_char(0..1),
+            ],
+        };
+
+        diff_program("arithmetic", &program);
+    }
+
+    #[test]
+    fn test_put_string() {
+        let program = Program {
+            ops: vec![Op::put_string(0..1, b"a\"\x00\xff0\\".to_vec())],
+        };
+
+        diff_program("put_string", &program);
+    }
+
+    #[test]
+    fn test_i_loop() {
+        for decrement in [LoopDecrement::Pre, LoopDecrement::Post, LoopDecrement::Auto] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, 10),
+                    Op::i_loop_with_decrement(
+                        1..4,
+                        vec![
+                            Op::inc_ptr(1..2, 1),
+                            Op::inc(1..2, 1),
+                            Op::dec_ptr(1..2, 1),
+                            Op::put_char(1..2),
+                        ],
+                        2,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 1),
+                ],
+            };
+
+            diff_program(&format!("i_loop-{:?}", decrement), &program);
+        }
+    }
+
+    #[test]
+    fn test_c_loop() {
+        for decrement in [LoopDecrement::Pre, LoopDecrement::Post, LoopDecrement::Auto] {
+            let program = Program {
+                ops: vec![
+                    Op::c_loop_with_decrement(
+                        1..4,
+                        vec![
+                            Op::inc_ptr(1..2, 1),
+                            Op::inc(1..2, 1),
+                            Op::dec_ptr(1..2, 1),
+                            Op::put_char(1..2),
+                        ],
+                        5,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 1),
+                ],
+            };
+
+            diff_program(&format!("c_loop-{:?}", decrement), &program);
+        }
+    }
+
+    #[test]
+    fn test_l_loop() {
+        let program = Program {
+            ops: vec![
+                Op::set(0..1, 3),
+                Op::l_loop(
+                    1..4,
+                    vec![
+                        Op::inc_ptr(1..2, 1),
+                        Op::inc(1..2, 1),
+                        Op::put_char(1..2),
+                        Op::dec_ptr(1..2, 1),
+                        Op::dec(1..2, 1),
+                    ],
+                    BlockInfo::new_empty(),
+                ),
+            ],
+        };
+
+        diff_program("l_loop", &program);
+    }
+
+    #[test]
+    fn test_tnz() {
+        for value in [0, 1] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, value),
+                    Op::t_nz(
+                        1..4,
+                        vec![Op::set_with_offset(1..2, 2, 10)],
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 2),
+                ],
+            };
+
+            diff_program(&format!("tnz-{}", value), &program);
+        }
+    }
+
+    #[test]
+    fn test_dtnz() {
+        for value in [0, 1] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, value),
+                    Op::d_t_nz(
+                        1..4,
+                        vec![Op::inc_ptr(1..2, 2), Op::set(1..2, 10)],
+                        Some(2),
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                ],
+            };
+
+            diff_program(&format!("dtnz-{}", value), &program);
+        }
+    }
+
+    #[test]
+    fn test_search_zero() {
+        let program = Program {
+            ops: vec![
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 1),
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 2),
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 3),
+                Op::search_zero(0..1, -1),
+                Op::set(1..2, 4),
+                Op::put_char(1..2),
+                Op::put_char_with_offset(1..2, 1),
+                Op::put_char_with_offset(1..2, 3),
+            ],
+        };
+
+        diff_program("search_zero", &program);
+    }
+
+    #[test]
+    fn test_heap_underflow() {
+        let program = parse("+.<+.").unwrap();
+
+        let output = run_c(&compile_to_c(&program), "heap_underflow", b"");
+
+        assert_eq!(output, b"\x01");
+    }
+
+    #[test]
+    fn test_bidirectional_tape() {
+        let mut config = OptimizeConfig::o0();
+        config.bidirectional_tape = true;
+
+        let program = parse("+<<+++[->>++<<]>>.<+<+<+>>[<]+.").unwrap();
+
+        let expected = run_interpreter(&program, &config, b"");
+        let output = run_c(
+            &compile_to_c_with_config(&program, &config),
+            "bidirectional_tape",
+            b"",
+        );
+
+        assert_eq!(output, expected);
+        assert_eq!(output, b"\x07\x01");
+    }
+
+    #[test]
+    fn test_awib() {
+        let mut program = parse(include_str!("../../../test_programs/awib.bf")).unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o3());
+
+        let output = run_c(
+            &compile_to_c_with_config(&program, &OptimizeConfig::o3()),
+            "awib",
+            include_bytes!("../../../test_programs/mandelbrot.bf"),
+        );
+
+        assert_eq!(
+            output,
+            include_bytes!("../../../test_programs/mandelbrot.c")
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        let depth = 20_000;
+        let source = format!("+{}[-]{}", "[>+".repeat(depth), "<-]".repeat(depth));
+        let options = ParserOptions {
+            max_loop_depth: depth + 1,
+            ..ParserOptions::default()
+        };
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+
+        let code = compile_to_c(&program);
+
+        assert_eq!(
+            code.matches("while (*heap_value() != 0)").count(),
+            depth + 1
+        );
+        assert!(code
+            .lines()
+            .all(|line| line.len() - line.trim_start().len() <= 4 * MAX_INDENT_DEPTH));
+    }
+}
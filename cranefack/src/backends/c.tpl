@@ -0,0 +1,239 @@
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+#define MAX_HEAP_SIZE {{HEAP_SIZE}}
+
+typedef {{CELL}} Cell;
+
+#define HAS_EOF_VALUE {{HAS_EOF_VALUE}}
+
+#define EOF_VALUE {{EOF_VALUE}}
+
+#define PRINTABLE_OUTPUT {{PRINTABLE_OUTPUT}}
+
+#define NEGATIVE_CELLS {{NEGATIVE_CELLS}}
+
+static Cell *heap_memory;
+static Cell *heap;
+static size_t pointer = 0;
+
+static void heap_size_error(size_t required) {
+    fflush(stdout);
+    fprintf(stderr, "Max heap size reached: %zu\n", required);
+    exit(1);
+}
+
+static void heap_underflow_error(void) {
+    fflush(stdout);
+    fprintf(stderr, "Heap pointer moved left of the start of the tape\n");
+    exit(1);
+}
+
+static inline Cell *heap_value_at(ptrdiff_t position) {
+    if (position < -(ptrdiff_t) NEGATIVE_CELLS) {
+        heap_underflow_error();
+    }
+
+    if (position >= (ptrdiff_t) MAX_HEAP_SIZE) {
+        heap_size_error((size_t) position);
+    }
+
+    return &heap[position];
+}
+
+static inline Cell *heap_value(void) {
+    return heap_value_at((ptrdiff_t) pointer);
+}
+
+static inline Cell *heap_value_at_offset(ptrdiff_t ptr_offset) {
+    return heap_value_at((ptrdiff_t) pointer + ptr_offset);
+}
+
+static inline void inc_ptr(size_t count) {
+    pointer += count;
+}
+
+static inline void dec_ptr(size_t count) {
+    pointer -= count;
+}
+
+static inline void inc(ptrdiff_t offset, uint32_t count) {
+    Cell *value = heap_value_at_offset(offset);
+    *value = (Cell) (*value + count);
+}
+
+static inline void dec(ptrdiff_t offset, uint32_t count) {
+    Cell *value = heap_value_at_offset(offset);
+    *value = (Cell) (*value - count);
+}
+
+static inline void set(ptrdiff_t offset, uint32_t value) {
+    *heap_value_at_offset(offset) = (Cell) value;
+}
+
+static inline void add(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    uint32_t source = *heap_value_at_offset(src_offset);
+    if (source != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target + source * multi);
+    }
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+static inline void nz_add(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    uint32_t source = *heap_value_at_offset(src_offset);
+    if (source != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target + source * multi);
+    }
+}
+
+static inline void nz_c_add(ptrdiff_t dest_offset, uint32_t value) {
+    /* The loop didn't run if the constant is zero */
+    if (value != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target + value);
+    }
+}
+
+static inline void c_add(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t value) {
+    nz_c_add(dest_offset, value);
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+static inline void sub(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    uint32_t source = *heap_value_at_offset(src_offset);
+    if (source != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target - source * multi);
+    }
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+static inline void nz_sub(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    uint32_t source = *heap_value_at_offset(src_offset);
+    if (source != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target - source * multi);
+    }
+}
+
+static inline void nz_c_sub(ptrdiff_t dest_offset, uint32_t value) {
+    /* The loop didn't run if the constant is zero */
+    if (value != 0) {
+        Cell *target = heap_value_at_offset(dest_offset);
+        *target = (Cell) (*target - value);
+    }
+}
+
+static inline void c_sub(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t value) {
+    nz_c_sub(dest_offset, value);
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+/* The loop an op came from didn't run if the source is zero, so a target outside of the heap is skipped */
+static inline void set_target(Cell source, ptrdiff_t dest_offset, Cell value) {
+    ptrdiff_t position = (ptrdiff_t) pointer + dest_offset;
+
+    if (source == 0 && (position < -(ptrdiff_t) NEGATIVE_CELLS || position >= (ptrdiff_t) MAX_HEAP_SIZE)) {
+        return;
+    }
+
+    *heap_value_at(position) = value;
+}
+
+static inline void mul(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    Cell source = *heap_value_at_offset(src_offset);
+    set_target(source, dest_offset, (Cell) (source * multi));
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+static inline void nz_mul(ptrdiff_t src_offset, ptrdiff_t dest_offset, uint32_t multi) {
+    Cell source = *heap_value_at_offset(src_offset);
+    set_target(source, dest_offset, (Cell) (source * multi));
+}
+
+static inline void move(ptrdiff_t src_offset, ptrdiff_t dest_offset) {
+    Cell source = *heap_value_at_offset(src_offset);
+    set_target(source, dest_offset, source);
+    *heap_value_at_offset(src_offset) = 0;
+}
+
+static inline void copy(ptrdiff_t src_offset, ptrdiff_t dest_offset) {
+    Cell source = *heap_value_at_offset(src_offset);
+    set_target(source, dest_offset, source);
+}
+
+static inline void search_zero(ptrdiff_t step) {
+    ptrdiff_t position = (ptrdiff_t) pointer;
+
+    while (*heap_value_at(position) != 0) {
+        position += step;
+    }
+
+    pointer = (size_t) position;
+}
+
+static inline void write_byte(uint8_t value) {
+    int result;
+
+    if (!PRINTABLE_OUTPUT || (value != 0 && value < 0x80)) {
+        result = putchar(value);
+    } else {
+        result = printf("\\0x%x", value);
+    }
+
+    if (result < 0) {
+        perror("Output error");
+        exit(1);
+    }
+}
+
+static inline void get_char(ptrdiff_t offset) {
+    Cell *cell = heap_value_at_offset(offset);
+
+    int value = getchar();
+
+    if (value == EOF) {
+        if (ferror(stdin)) {
+            perror("Input error");
+            exit(1);
+        }
+
+        if (HAS_EOF_VALUE) {
+            *cell = EOF_VALUE;
+        }
+    } else {
+        *cell = (Cell) value;
+    }
+}
+
+static inline void put_char(ptrdiff_t offset) {
+    write_byte((uint8_t) *heap_value_at_offset(offset));
+}
+
+static inline void put_string(const char *array, size_t length) {
+    for (size_t i = 0; i < length; i++) {
+        write_byte((uint8_t) array[i]);
+    }
+}
+
+int main(void) {
+    heap_memory = calloc(NEGATIVE_CELLS + MAX_HEAP_SIZE, sizeof(Cell));
+
+    if (heap_memory == NULL) {
+        fprintf(stderr, "Failed to allocate heap\n");
+        return 1;
+    }
+
+    // Cells left of the start cell are stored before it
+    heap = heap_memory + NEGATIVE_CELLS;
+
+{{CODE}}
+    fflush(stdout);
+    free(heap_memory);
+
+    return 0;
+}
@@ -1,17 +1,80 @@
 use std::io::Write;
 use std::io::{ErrorKind, Read};
 use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
+use crate::backends::{write_byte, DEFAULT_HEAP_SIZE};
 use crate::errors::RuntimeError;
-use crate::ir::ops::{LoopDecrement, Op, OpType};
+use crate::ir::ops::{CellSize, EofMode, LoopDecrement, Op, OpType};
 use crate::parser::Program;
+use crate::OptimizeConfig;
+
+/// State of a program run with [Interpreter::start] or [Interpreter::resume]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExecutionStatus {
+    /// Program ran to the end
+    Finished,
+    /// Program is paused waiting for more input
+    NeedsInput,
+}
+
+/// Number of cells reported on each side of the heap pointer at a breakpoint
+const BREAKPOINT_CELLS: isize = 8;
+
+/// Tape state reported to the breakpoint callback of the [Interpreter]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakpointInfo {
+    /// Span of the breakpoint in the source
+    pub span: Range<usize>,
+
+    /// Position of the heap pointer
+    pub pointer: isize,
+
+    /// Position of the first cell in `cells`
+    pub cells_start: isize,
+
+    /// Values of the cells surrounding the heap pointer
+    pub cells: Vec<u32>,
+}
+
+type BreakpointCallback = Box<dyn FnMut(&BreakpointInfo)>;
+
+/// Execution state of a block
+#[derive(Default)]
+struct Frame {
+    /// Index of the current op in the block
+    index: usize,
+    /// Heap pointer at the start of the block
+    heap_pointer: usize,
+    /// Remaining iterations of counting loops
+    left: u32,
+}
 
-const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+impl Frame {
+    fn with_heap_pointer(heap_pointer: usize, left: u32) -> Frame {
+        Frame {
+            index: 0,
+            heap_pointer,
+            left,
+        }
+    }
+}
 
 /// Interpreter to execute a program
 pub struct Interpreter<R: Read, W: Write> {
     max_heap_size: usize,
-    pub(crate) heap: Vec<u8>,
+    cell_size: CellSize,
+    eof_mode: EofMode,
+    printable_output: bool,
+    bidirectional_tape: bool,
+    fuel: Option<u64>,
+    cancel_flag: Option<Arc<AtomicBool>>,
+    pause_on_eof: bool,
+    breakpoint_callback: Option<BreakpointCallback>,
+    frames: Vec<Frame>,
+    pub(crate) heap: Vec<u32>,
+    negative_heap: Vec<u32>,
     pointer: usize,
     input: R,
     output: W,
@@ -21,28 +84,381 @@ impl<R: Read, W: Write> Interpreter<R, W> {
     /// Create a default interpreter
     pub fn new(input: R, output: W) -> Interpreter<R, W> {
         Interpreter {
-            max_heap_size: MAX_HEAP_SIZE,
+            max_heap_size: DEFAULT_HEAP_SIZE,
+            cell_size: CellSize::Bits8,
+            eof_mode: EofMode::Zero,
+            printable_output: false,
+            bidirectional_tape: false,
+            fuel: None,
+            cancel_flag: None,
+            pause_on_eof: false,
+            breakpoint_callback: None,
+            frames: vec![],
             heap: vec![0; 1024],
+            negative_heap: vec![],
             pointer: 0,
             input,
             output,
         }
     }
 
+    /// Create an interpreter using the runtime settings of the optimization config
+    pub fn new_with_config(input: R, output: W, config: &OptimizeConfig) -> Interpreter<R, W> {
+        Interpreter {
+            max_heap_size: config.heap_size,
+            cell_size: config.cell_size,
+            eof_mode: config.eof_mode,
+            printable_output: config.printable_output,
+            bidirectional_tape: config.bidirectional_tape,
+            ..Self::new(input, output)
+        }
+    }
+
+    /// Limit the number of steps the interpreter executes
+    ///
+    /// Every executed op and every loop iteration consumes one unit of fuel.
+    /// The [CompiledJitModule](crate::CompiledJitModule) only counts loop iterations.
+    /// Execution stops with [RuntimeError::OutOfFuel] once the fuel is used up.
+    /// `None` disables the limit.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Get the remaining fuel
+    pub fn get_fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Set flag to cancel execution from another thread
+    ///
+    /// Execution stops with [RuntimeError::OutOfFuel] as soon as the flag is set to true.
+    pub fn set_cancel_flag(&mut self, cancel_flag: Arc<AtomicBool>) {
+        self.cancel_flag = Some(cancel_flag);
+    }
+
+    /// Pause execution instead of applying the eof mode when the input is exhausted
+    ///
+    /// This only has an effect on programs run with [Interpreter::start] and [Interpreter::resume].
+    /// More input can be provided with [Interpreter::input_mut] before resuming.
+    pub fn set_pause_on_eof(&mut self, pause_on_eof: bool) {
+        self.pause_on_eof = pause_on_eof;
+    }
+
+    /// Set callback that is invoked with the tape state whenever a breakpoint op is executed
+    ///
+    /// Breakpoints are ignored without a callback.
+    pub fn set_breakpoint_callback<F: FnMut(&BreakpointInfo) + 'static>(&mut self, callback: F) {
+        self.breakpoint_callback = Some(Box::new(callback));
+    }
+
+    /// Get mutable reference to the input
+    pub fn input_mut(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// Get mutable reference to the output
+    pub fn output_mut(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Check if the interpreter is waiting for more input
+    pub fn is_paused(&self) -> bool {
+        !self.frames.is_empty()
+    }
+
     /// Execute program
     pub fn execute(&mut self, program: &Program) -> Result<(), RuntimeError> {
-        self.execute_ops(&program.ops)
+        self.frames.clear();
+        self.frames.push(Frame::default());
+
+        self.run(program, false).map(|_| ())
+    }
+
+    /// Execute program until it finishes or needs more input
+    ///
+    /// Execution pauses with [ExecutionStatus::NeedsInput] when reading from the input would block
+    /// or, with [Interpreter::set_pause_on_eof], when the input is exhausted.
+    pub fn start(&mut self, program: &Program) -> Result<ExecutionStatus, RuntimeError> {
+        self.frames.clear();
+        self.frames.push(Frame::default());
+
+        self.run(program, true)
     }
 
-    fn execute_ops(&mut self, ops: &[Op]) -> Result<(), RuntimeError> {
-        for op in ops {
-            self.execute_op(op)?;
+    /// Continue a paused program at the op waiting for input
+    ///
+    /// The program needs to be the same as the one execution was started with.
+    /// Returns [ExecutionStatus::Finished] right away if no program is paused.
+    pub fn resume(&mut self, program: &Program) -> Result<ExecutionStatus, RuntimeError> {
+        if self.frames.is_empty() {
+            return Ok(ExecutionStatus::Finished);
         }
 
-        Ok(())
+        self.run(program, true)
+    }
+
+    fn run(&mut self, program: &Program, resumable: bool) -> Result<ExecutionStatus, RuntimeError> {
+        // Restore the blocks belonging to the stack frames
+        let mut blocks: Vec<&[Op]> = Vec::with_capacity(self.frames.len());
+        blocks.push(&program.ops);
+
+        for frame in &self.frames[..self.frames.len() - 1] {
+            let ops = blocks[blocks.len() - 1];
+            blocks.push(
+                ops[frame.index]
+                    .op_type
+                    .get_children()
+                    .expect("Paused program doesn't match"),
+            );
+        }
+
+        let result = self.run_blocks(&mut blocks, resumable);
+
+        if !matches!(result, Ok(ExecutionStatus::NeedsInput)) {
+            self.frames.clear();
+        }
+
+        result
+    }
+
+    fn run_blocks(
+        &mut self,
+        blocks: &mut Vec<&[Op]>,
+        resumable: bool,
+    ) -> Result<ExecutionStatus, RuntimeError> {
+        // The current block is kept out of the stacks
+        let mut ops = blocks.pop().expect("Missing block");
+        let mut frame = self.frames.pop().expect("Missing frame");
+
+        loop {
+            let op = match ops.get(frame.index) {
+                Some(op) => op,
+                None => {
+                    // End of block, either repeat loop body or continue after the op
+                    let op = match (blocks.last(), self.frames.last()) {
+                        (Some(parent_ops), Some(parent)) => &parent_ops[parent.index],
+                        _ => return Ok(ExecutionStatus::Finished),
+                    };
+
+                    if self.repeat_block(op, &mut frame)? {
+                        frame.index = 0;
+                    } else {
+                        ops = blocks.pop().expect("Missing block");
+                        frame = self.frames.pop().expect("Missing frame");
+                        frame.index += 1;
+                    }
+                    continue;
+                }
+            };
+
+            self.consume_fuel(&op.span)?;
+
+            let cell_size = self.cell_size;
+
+            let enter = match &op.op_type {
+                OpType::DLoop(..) => {
+                    if *self.heap_value(&op.span)? > 0 {
+                        self.consume_fuel(&op.span)?;
+                        Some(Frame::default())
+                    } else {
+                        None
+                    }
+                }
+                OpType::LLoop(..) => {
+                    if *self.heap_value(&op.span)? > 0 {
+                        self.consume_fuel(&op.span)?;
+                        Some(Frame::with_heap_pointer(self.pointer, 0))
+                    } else {
+                        None
+                    }
+                }
+                OpType::ILoop(_, step, decrement, _) => {
+                    let mut left = *self.heap_value(&op.span)?;
+
+                    if left > 0 {
+                        self.consume_fuel(&op.span)?;
+
+                        if *decrement == LoopDecrement::Pre {
+                            left = cell_size.wrapping_sub(left, *step);
+                            *self.heap_value(&op.span)? = left;
+                        }
+
+                        Some(Frame::with_heap_pointer(self.pointer, left))
+                    } else {
+                        *self.heap_value(&op.span)? = 0;
+                        None
+                    }
+                }
+                OpType::CLoop(_, iterations, decrement, _) => {
+                    let mut left = if *decrement == LoopDecrement::Auto {
+                        *iterations
+                    } else {
+                        *self.heap_value(&op.span)? = *iterations;
+                        *self.heap_value(&op.span)?
+                    };
+
+                    if left > 0 {
+                        self.consume_fuel(&op.span)?;
+
+                        match decrement {
+                            LoopDecrement::Pre => {
+                                left = cell_size.wrapping_sub(left, 1);
+                                *self.heap_value(&op.span)? = left;
+                            }
+                            LoopDecrement::Post => {}
+                            LoopDecrement::Auto => left -= 1,
+                        }
+
+                        Some(Frame::with_heap_pointer(self.pointer, left))
+                    } else {
+                        *self.heap_value(&op.span)? = 0;
+                        None
+                    }
+                }
+                OpType::TNz(..) => {
+                    if *self.heap_value(&op.span)? != 0 {
+                        Some(Frame::with_heap_pointer(self.pointer, 0))
+                    } else {
+                        None
+                    }
+                }
+                OpType::DTNz(..) => {
+                    if *self.heap_value(&op.span)? > 0 {
+                        Some(Frame::default())
+                    } else {
+                        None
+                    }
+                }
+                OpType::GetChar(offset) => {
+                    if !self.get_char(&op.span, *offset, resumable)? {
+                        // The op is executed again on resume
+                        if let Some(fuel) = &mut self.fuel {
+                            *fuel += 1;
+                        }
+
+                        self.frames.push(frame);
+                        return Ok(ExecutionStatus::NeedsInput);
+                    }
+                    None
+                }
+                _ => {
+                    self.execute_op(op)?;
+                    None
+                }
+            };
+
+            match enter {
+                Some(child_frame) => {
+                    blocks.push(ops);
+                    self.frames.push(std::mem::replace(&mut frame, child_frame));
+                    ops = op
+                        .op_type
+                        .get_children()
+                        .expect("Block op without children");
+                }
+                None => frame.index += 1,
+            }
+        }
+    }
+
+    /// Update loop state at the end of a block and check if it has to be executed again
+    fn repeat_block(&mut self, op: &Op, frame: &mut Frame) -> Result<bool, RuntimeError> {
+        let cell_size = self.cell_size;
+        let heap_pointer = frame.heap_pointer;
+
+        let repeat = match &op.op_type {
+            OpType::DLoop(..) => {
+                if *self.heap_value(&op.span)? > 0 {
+                    self.consume_fuel(&op.span)?;
+                    true
+                } else {
+                    false
+                }
+            }
+            OpType::LLoop(..) => {
+                self.pointer = heap_pointer;
+
+                if *self.heap_value(&op.span)? > 0 {
+                    self.consume_fuel(&op.span)?;
+                    true
+                } else {
+                    false
+                }
+            }
+            OpType::ILoop(_, step, decrement, _) => {
+                let mut left = frame.left;
+
+                match decrement {
+                    LoopDecrement::Pre => {
+                        self.pointer = heap_pointer;
+                    }
+                    LoopDecrement::Post => {
+                        left = cell_size.wrapping_sub(left, *step);
+                        *self.heap_value(&op.span)? = left;
+                        self.pointer = heap_pointer;
+                    }
+                    LoopDecrement::Auto => {
+                        left = cell_size.wrapping_sub(left, *step);
+                        self.pointer = heap_pointer;
+                    }
+                }
+
+                if left > 0 {
+                    self.consume_fuel(&op.span)?;
+
+                    if *decrement == LoopDecrement::Pre {
+                        left = cell_size.wrapping_sub(left, *step);
+                        *self.heap_value(&op.span)? = left;
+                    }
+                    frame.left = left;
+                    true
+                } else {
+                    *self.heap_value(&op.span)? = 0;
+                    false
+                }
+            }
+            OpType::CLoop(_, _, decrement, _) => {
+                let mut left = frame.left;
+                self.pointer = heap_pointer;
+
+                if *decrement == LoopDecrement::Post {
+                    left = cell_size.wrapping_sub(left, 1);
+                    *self.heap_value(&op.span)? = left;
+                }
+
+                if left > 0 {
+                    self.consume_fuel(&op.span)?;
+
+                    match decrement {
+                        LoopDecrement::Pre => {
+                            left = cell_size.wrapping_sub(left, 1);
+                            *self.heap_value(&op.span)? = left;
+                        }
+                        LoopDecrement::Post => {}
+                        LoopDecrement::Auto => left -= 1,
+                    }
+                    frame.left = left;
+                    true
+                } else {
+                    *self.heap_value(&op.span)? = 0;
+                    false
+                }
+            }
+            OpType::TNz(..) => {
+                self.pointer = heap_pointer;
+                *self.heap_value(&op.span)? = 0;
+                false
+            }
+            OpType::DTNz(..) => false,
+            _ => unreachable!("Op without block"),
+        };
+
+        Ok(repeat)
     }
 
     fn execute_op(&mut self, op: &Op) -> Result<(), RuntimeError> {
+        let cell_size = self.cell_size;
+
         match &op.op_type {
             OpType::Start => {
                 // ignore
@@ -51,188 +467,101 @@ impl<R: Read, W: Write> Interpreter<R, W> {
             OpType::DecPtr(count) => self.pointer = self.pointer.wrapping_sub(*count),
             OpType::Inc(offset, count) => {
                 let value = self.heap_value_at_offset(&op.span, *offset)?;
-                *value = value.wrapping_add(*count);
+                *value = cell_size.wrapping_add(*value, *count);
             }
             OpType::Dec(offset, count) => {
                 let value = self.heap_value_at_offset(&op.span, *offset)?;
-                *value = value.wrapping_sub(*count);
+                *value = cell_size.wrapping_sub(*value, *count);
             }
             OpType::Set(offset, value) => *self.heap_value_at_offset(&op.span, *offset)? = *value,
             OpType::Add(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_add(source.wrapping_mul(*multi));
+                if source != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target =
+                        cell_size.wrapping_add(*target, cell_size.wrapping_mul(source, *multi));
+                }
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::NzAdd(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_add(source.wrapping_mul(*multi));
+                if source != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target =
+                        cell_size.wrapping_add(*target, cell_size.wrapping_mul(source, *multi));
+                }
             }
             OpType::CAdd(src_offset, dest_offset, value) => {
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_add(*value);
+                if *value != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target = cell_size.wrapping_add(*target, *value);
+                }
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::NzCAdd(_src_offset, dest_offset, value) => {
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_add(*value);
+                if *value != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target = cell_size.wrapping_add(*target, *value);
+                }
             }
             OpType::Sub(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_sub(source.wrapping_mul(*multi));
+                if source != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target =
+                        cell_size.wrapping_sub(*target, cell_size.wrapping_mul(source, *multi));
+                }
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::NzSub(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_sub(source.wrapping_mul(*multi));
+                if source != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target =
+                        cell_size.wrapping_sub(*target, cell_size.wrapping_mul(source, *multi));
+                }
             }
             OpType::CSub(src_offset, dest_offset, value) => {
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_sub(*value);
+                if *value != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target = cell_size.wrapping_sub(*target, *value);
+                }
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::NzCSub(_src_offset, dest_offset, value) => {
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = target.wrapping_sub(*value);
+                if *value != 0 {
+                    let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
+                    *target = cell_size.wrapping_sub(*target, *value);
+                }
             }
             OpType::Mul(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = source.wrapping_mul(*multi);
+                let value = cell_size.wrapping_mul(source, *multi);
+                self.set_target(&op.span, source, *dest_offset, value)?;
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::NzMul(src_offset, dest_offset, multi) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = source.wrapping_mul(*multi);
+                let value = cell_size.wrapping_mul(source, *multi);
+                self.set_target(&op.span, source, *dest_offset, value)?;
             }
             OpType::Move(src_offset, dest_offset) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = source;
+                self.set_target(&op.span, source, *dest_offset, source)?;
                 *self.heap_value_at_offset(&op.span, *src_offset)? = 0;
             }
             OpType::Copy(src_offset, dest_offset) => {
                 let source = *self.heap_value_at_offset(&op.span, *src_offset)?;
-                let target = self.heap_value_at_offset(&op.span, *dest_offset)?;
-                *target = source;
+                self.set_target(&op.span, source, *dest_offset, source)?;
             }
-            OpType::GetChar(offset) => self.get_char(&op.span, *offset)?,
             OpType::PutChar(offset) => self.put_char(&op.span, *offset)?,
             OpType::PutString(array) => self.put_string(&op.span, array)?,
-            OpType::DLoop(ops, _) => {
-                while *self.heap_value(&op.span)? > 0 {
-                    self.execute_ops(ops)?;
-                }
-            }
-            OpType::LLoop(ops, _) => {
-                let heap_pointer = self.pointer;
-
-                while *self.heap_value(&op.span)? > 0 {
-                    self.execute_ops(ops)?;
-                    self.pointer = heap_pointer;
-                }
-            }
-            OpType::ILoop(ops, step, decrement, _) => match decrement {
-                LoopDecrement::Pre => {
-                    let heap_pointer = self.pointer;
-
-                    let mut left = *self.heap_value(&op.span)?;
-                    while left > 0 {
-                        left = left.wrapping_sub(*step);
-                        *self.heap_value(&op.span)? = left;
-                        self.execute_ops(ops)?;
-                        self.pointer = heap_pointer;
-                    }
-
-                    *self.heap_value(&op.span)? = 0;
-                }
-                LoopDecrement::Post => {
-                    let heap_pointer = self.pointer;
-
-                    let mut left = *self.heap_value(&op.span)?;
-                    while left > 0 {
-                        self.execute_ops(ops)?;
-                        left = left.wrapping_sub(*step);
-                        *self.heap_value(&op.span)? = left;
-                        self.pointer = heap_pointer;
-                    }
-
-                    *self.heap_value(&op.span)? = 0;
-                }
-                LoopDecrement::Auto => {
-                    let heap_pointer = self.pointer;
-
-                    let mut left = *self.heap_value(&op.span)?;
-                    while left > 0 {
-                        self.execute_ops(ops)?;
-                        left = left.wrapping_sub(*step);
-                        self.pointer = heap_pointer;
-                    }
-
-                    *self.heap_value(&op.span)? = 0;
-                }
-            },
-            OpType::CLoop(ops, iterations, decrement, _) => match decrement {
-                LoopDecrement::Pre => {
-                    let heap_pointer = self.pointer;
-
-                    *self.heap_value(&op.span)? = *iterations;
-                    let mut left = *self.heap_value(&op.span)?;
-                    while left > 0 {
-                        left = left.wrapping_sub(1);
-                        *self.heap_value(&op.span)? = left;
-                        self.execute_ops(ops)?;
-                        self.pointer = heap_pointer;
-                    }
-
-                    *self.heap_value(&op.span)? = 0;
-                }
-                LoopDecrement::Post => {
-                    let heap_pointer = self.pointer;
-
-                    *self.heap_value(&op.span)? = *iterations;
-                    let mut left = *self.heap_value(&op.span)?;
-                    while left > 0 {
-                        self.execute_ops(ops)?;
-                        self.pointer = heap_pointer;
-                        left = left.wrapping_sub(1);
-                        *self.heap_value(&op.span)? = left;
-                    }
-
-                    *self.heap_value(&op.span)? = 0;
-                }
-                LoopDecrement::Auto => {
-                    let heap_pointer = self.pointer;
-                    for _ in 0..*iterations {
-                        self.execute_ops(ops)?;
-                        self.pointer = heap_pointer;
-                    }
-                    *self.heap_value(&op.span)? = 0;
-                }
-            },
-            OpType::TNz(ops, _) => {
-                if *self.heap_value(&op.span)? != 0 {
-                    let heap_pointer = self.pointer;
-
-                    self.execute_ops(ops)?;
-
-                    self.pointer = heap_pointer;
-                    *self.heap_value(&op.span)? = 0;
-                }
-            }
-            OpType::DTNz(ops, _, _) => {
-                if *self.heap_value(&op.span)? > 0 {
-                    self.execute_ops(ops)?;
-                }
-            }
             OpType::SearchZero(step, _) => {
                 let mut pointer = self.pointer as isize;
 
                 loop {
+                    self.consume_fuel(&op.span)?;
+
                     let value = self.heap_value_at(&op.span, pointer)?;
 
                     if *value == 0 {
@@ -244,38 +573,127 @@ impl<R: Read, W: Write> Interpreter<R, W> {
 
                 self.pointer = pointer as usize;
             }
+            OpType::Breakpoint => self.breakpoint(&op.span),
+            OpType::GetChar(..)
+            | OpType::DLoop(..)
+            | OpType::LLoop(..)
+            | OpType::ILoop(..)
+            | OpType::CLoop(..)
+            | OpType::TNz(..)
+            | OpType::DTNz(..) => unreachable!("Op is handled by run loop"),
         }
 
         Ok(())
     }
 
-    fn heap_value(&mut self, span: &Range<usize>) -> Result<&mut u8, RuntimeError> {
-        if self.pointer >= self.max_heap_size {
-            return Err(RuntimeError::MaxHeapSizeReached {
-                span: span.clone(),
-                max_heap_size: self.max_heap_size,
-                required: self.pointer.saturating_add(1),
-            });
+    fn consume_fuel(&mut self, span: &Range<usize>) -> Result<(), RuntimeError> {
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(RuntimeError::OutOfFuel { span: span.clone() });
+            }
+            *fuel -= 1;
         }
 
-        while self.pointer > self.heap.len() - 1 {
-            self.heap.push(0);
+        if let Some(cancel_flag) = &self.cancel_flag {
+            if cancel_flag.load(Ordering::Relaxed) {
+                return Err(RuntimeError::OutOfFuel { span: span.clone() });
+            }
+        }
+
+        Ok(())
+    }
+
+    fn breakpoint(&mut self, span: &Range<usize>) {
+        if self.breakpoint_callback.is_none() {
+            return;
+        }
+
+        let pointer = self.pointer as isize;
+        let min_pointer = if self.bidirectional_tape {
+            -(self.max_heap_size as isize)
+        } else {
+            0
+        };
+
+        let cells_start = pointer.saturating_sub(BREAKPOINT_CELLS).max(min_pointer);
+        let cells_end = pointer
+            .saturating_add(BREAKPOINT_CELLS + 1)
+            .min(self.max_heap_size as isize);
+
+        let info = BreakpointInfo {
+            span: span.clone(),
+            pointer,
+            cells_start,
+            cells: (cells_start..cells_end)
+                .map(|position| self.peek_heap_value(position))
+                .collect(),
+        };
+
+        if let Some(callback) = &mut self.breakpoint_callback {
+            callback(&info);
+        }
+    }
+
+    /// Get cell value without growing the heap
+    fn peek_heap_value(&self, pointer: isize) -> u32 {
+        let value = if pointer < 0 {
+            self.negative_heap.get(pointer.unsigned_abs() - 1)
+        } else {
+            self.heap.get(pointer as usize)
+        };
+
+        value.copied().unwrap_or(0)
+    }
+
+    /// Get position of the first non zero cell and the cells up to the last non zero cell
+    pub(crate) fn get_tape(&self) -> (isize, Vec<u32>) {
+        let first = -(self.negative_heap.len() as isize);
+        let last = self.heap.len() as isize;
+
+        let start = (first..last).find(|pointer| self.peek_heap_value(*pointer) != 0);
+        let end = (first..last).rfind(|pointer| self.peek_heap_value(*pointer) != 0);
+
+        match (start, end) {
+            (Some(start), Some(end)) => (
+                start,
+                (start..=end)
+                    .map(|pointer| self.peek_heap_value(pointer))
+                    .collect(),
+            ),
+            _ => (0, vec![]),
         }
-        Ok(&mut self.heap[self.pointer])
+    }
+
+    fn heap_value(&mut self, span: &Range<usize>) -> Result<&mut u32, RuntimeError> {
+        self.heap_value_at(span, self.pointer as isize)
     }
 
     fn heap_value_at(
         &mut self,
         span: &Range<usize>,
         pointer: isize,
-    ) -> Result<&mut u8, RuntimeError> {
-        let pointer = pointer.max(0) as usize;
+    ) -> Result<&mut u32, RuntimeError> {
+        if pointer < 0 {
+            // Cell -1 is stored at index 0 of the negative heap
+            let index = pointer.unsigned_abs() - 1;
+
+            if !self.bidirectional_tape || index >= self.max_heap_size {
+                return Err(RuntimeError::HeapUnderflow { span: span.clone() });
+            }
+
+            while index >= self.negative_heap.len() {
+                self.negative_heap.push(0);
+            }
+            return Ok(&mut self.negative_heap[index]);
+        }
+
+        let pointer = pointer as usize;
 
         if pointer >= self.max_heap_size {
             return Err(RuntimeError::MaxHeapSizeReached {
                 span: span.clone(),
                 max_heap_size: self.max_heap_size,
-                required: self.pointer.saturating_add(1),
+                required: pointer + 1,
             });
         }
 
@@ -289,67 +707,84 @@ impl<R: Read, W: Write> Interpreter<R, W> {
         &mut self,
         span: &Range<usize>,
         ptr_offset: isize,
-    ) -> Result<&mut u8, RuntimeError> {
-        let pointer = self.pointer as isize + ptr_offset;
-
-        let pointer = pointer.max(0) as usize;
+    ) -> Result<&mut u32, RuntimeError> {
+        self.heap_value_at(span, (self.pointer as isize).wrapping_add(ptr_offset))
+    }
 
-        if pointer >= self.max_heap_size {
-            return Err(RuntimeError::MaxHeapSizeReached {
-                span: span.clone(),
-                max_heap_size: self.max_heap_size,
-                required: self.pointer.saturating_add(1),
-            });
+    /// Set the target cell of an op that replaced a loop
+    ///
+    /// The loop didn't run if the source is zero. A target outside of the heap is skipped
+    /// in that case instead of being reported as heap error.
+    fn set_target(
+        &mut self,
+        span: &Range<usize>,
+        source: u32,
+        dest_offset: isize,
+        value: u32,
+    ) -> Result<(), RuntimeError> {
+        match self.heap_value_at_offset(span, dest_offset) {
+            Ok(target) => *target = value,
+            Err(_) if source == 0 => {}
+            Err(error) => return Err(error),
         }
 
-        while pointer > self.heap.len() - 1 {
-            self.heap.push(0);
-        }
-        Ok(&mut self.heap[pointer])
+        Ok(())
     }
 
-    fn get_char(&mut self, span: &Range<usize>, offset: isize) -> Result<(), RuntimeError> {
+    /// Read input into cell
+    ///
+    /// Returns false without changing the cell if a resumable program has to wait for input.
+    fn get_char(
+        &mut self,
+        span: &Range<usize>,
+        offset: isize,
+        resumable: bool,
+    ) -> Result<bool, RuntimeError> {
         let mut buf = [0];
 
-        if let Err(error) = self.input.read_exact(&mut buf) {
-            // In case of EOF the system will read 0 as a fallback
-            if error.kind() != ErrorKind::UnexpectedEof {
+        let value = match self.input.read_exact(&mut buf) {
+            Ok(()) => Some(buf[0] as u32),
+            Err(error) if resumable && error.kind() == ErrorKind::WouldBlock => return Ok(false),
+            Err(error) if error.kind() == ErrorKind::UnexpectedEof => {
+                if resumable && self.pause_on_eof {
+                    return Ok(false);
+                }
+                self.eof_mode.value(self.cell_size)
+            }
+            Err(error) => {
                 return Err(RuntimeError::IoError {
                     span: span.clone(),
                     error,
-                });
+                })
             }
         };
 
-        *self.heap_value_at_offset(span, offset)? = buf[0];
+        let cell = self.heap_value_at_offset(span, offset)?;
+        if let Some(value) = value {
+            *cell = value;
+        }
 
-        Ok(())
+        Ok(true)
     }
 
     fn put_char(&mut self, span: &Range<usize>, offset: isize) -> Result<(), RuntimeError> {
-        let ch = *self.heap_value_at_offset(span, offset)?;
+        let ch = *self.heap_value_at_offset(span, offset)? as u8;
 
-        if ch.is_ascii() {
-            write!(self.output, "{}", ch as char)
-        } else {
-            write!(self.output, "\\0x{:x}", ch)
-        }
-        .map_err(|error| RuntimeError::IoError {
-            span: span.clone(),
-            error,
+        write_byte(&mut self.output, ch, self.printable_output).map_err(|error| {
+            RuntimeError::IoError {
+                span: span.clone(),
+                error,
+            }
         })
     }
 
     fn put_string(&mut self, span: &Range<usize>, array: &[u8]) -> Result<(), RuntimeError> {
         for &ch in array {
-            if ch.is_ascii() {
-                write!(self.output, "{}", ch as char)
-            } else {
-                write!(self.output, "\\0x{:x}", ch)
-            }
-            .map_err(|error| RuntimeError::IoError {
-                span: span.clone(),
-                error,
+            write_byte(&mut self.output, ch, self.printable_output).map_err(|error| {
+                RuntimeError::IoError {
+                    span: span.clone(),
+                    error,
+                }
             })?;
         }
 
@@ -359,12 +794,18 @@ impl<R: Read, W: Write> Interpreter<R, W> {
 
 #[cfg(test)]
 mod tests {
-    use std::io::Cursor;
-
-    use crate::backends::interpreter::Interpreter;
-    use crate::ir::ops::{LoopDecrement, Op};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::io::{Cursor, ErrorKind, Read};
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use crate::backends::interpreter::{BreakpointInfo, ExecutionStatus, Interpreter};
+    use crate::errors::RuntimeError;
+    use crate::ir::ops::{CellSize, EofMode, LoopDecrement, Op};
     use crate::ir::opt_info::BlockInfo;
-    use crate::parser::parse;
+    use crate::parser::{parse, parse_bytes_with_options, ParserOptions};
     use crate::{optimize_with_config, OptimizeConfig, Program};
 
     #[test]
@@ -383,24 +824,109 @@ mod tests {
     }
 
     #[test]
-    fn test_out_b() {
-        let mut program = parse(",++.").unwrap();
-        optimize_with_config(&mut program, &OptimizeConfig::o3());
+    fn test_out_raw() {
+        let program = parse("-.").unwrap();
 
-        let input = b"a";
+        let input = b"";
         let mut output = Vec::new();
 
         Interpreter::new(Cursor::new(input), &mut output)
             .execute(&program)
             .unwrap();
 
-        assert_eq!(output, b"c");
+        assert_eq!(output, b"\xff");
     }
 
     #[test]
-    fn test_loop() {
-        let mut program = parse("+++[>+<-]>.").unwrap();
-        optimize_with_config(&mut program, &OptimizeConfig::o3());
+    fn test_out_printable() {
+        let mut opt_mode = OptimizeConfig::o0();
+        opt_mode.printable_output = true;
+
+        let program = parse("+.-.-.").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, b"\x01\\0x0\\0xff");
+    }
+
+    #[test]
+    fn test_out_b() {
+        let mut program = parse(",++.").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o3());
+
+        let input = b"a";
+        let mut output = Vec::new();
+
+        Interpreter::new(Cursor::new(input), &mut output)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, b"c");
+    }
+
+    #[test]
+    fn test_eof_zero() {
+        let mut opt_mode = OptimizeConfig::o3();
+        opt_mode.eof_mode = EofMode::Zero;
+
+        let mut program = parse("+++,").unwrap();
+        optimize_with_config(&mut program, &opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter =
+            Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode);
+        interpreter.execute(&program).unwrap();
+
+        assert_eq!(interpreter.heap[0], 0);
+    }
+
+    #[test]
+    fn test_eof_max() {
+        let mut opt_mode = OptimizeConfig::o3();
+        opt_mode.eof_mode = EofMode::Max;
+
+        let mut program = parse("+++,").unwrap();
+        optimize_with_config(&mut program, &opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter =
+            Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode);
+        interpreter.execute(&program).unwrap();
+
+        assert_eq!(interpreter.heap[0], 255);
+    }
+
+    #[test]
+    fn test_eof_unchanged() {
+        let mut opt_mode = OptimizeConfig::o3();
+        opt_mode.eof_mode = EofMode::Unchanged;
+
+        let mut program = parse("+++,").unwrap();
+        optimize_with_config(&mut program, &opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter =
+            Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode);
+        interpreter.execute(&program).unwrap();
+
+        assert_eq!(interpreter.heap[0], 3);
+    }
+
+    #[test]
+    fn test_loop() {
+        let mut program = parse("+++[>+<-]>.").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o3());
 
         let input = b"";
         let mut output = Vec::new();
@@ -718,6 +1244,88 @@ mod tests {
         assert_eq!(output, b"8 bit cells\n");
     }
 
+    #[test]
+    fn test_cell_size_16() {
+        let mut opt_mode = OptimizeConfig::o3();
+        opt_mode.cell_size = CellSize::Bits16;
+
+        let mut program = parse(include_str!("../../../test_programs/cell_size.bf")).unwrap();
+        optimize_with_config(&mut program, &opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, b"16 bit cells\n");
+    }
+
+    #[test]
+    fn test_cell_size_32() {
+        let mut opt_mode = OptimizeConfig::o3();
+        opt_mode.cell_size = CellSize::Bits32;
+
+        let mut program = parse(include_str!("../../../test_programs/cell_size.bf")).unwrap();
+        optimize_with_config(&mut program, &opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, b"32 bit cells\n");
+    }
+
+    fn run_with_config(source: &str, opt_mode: &OptimizeConfig) -> Vec<u8> {
+        let mut program = parse(source).unwrap();
+        optimize_with_config(&mut program, opt_mode);
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, opt_mode)
+            .execute(&program)
+            .unwrap();
+
+        output
+    }
+
+    #[test]
+    fn test_cell_size_optimized() {
+        let sources = [
+            format!("{}[>{}<[-]]>.", "+".repeat(256), "+".repeat(65)),
+            format!("{}[.-----]", "+".repeat(25)),
+            format!("{}[>+<-]>.", "+".repeat(300)),
+            format!("{}[->+>{}<<]>>.<.", "+".repeat(300), "+".repeat(255)),
+            "+>[>>[-]]>[+[[.]]]".to_owned(),
+        ];
+
+        for cell_size in [CellSize::Bits16, CellSize::Bits32] {
+            let mut o0 = OptimizeConfig::o0();
+            o0.cell_size = cell_size;
+
+            for source in &sources {
+                let expected = run_with_config(source, &o0);
+
+                for mut opt_mode in [OptimizeConfig::o2(), OptimizeConfig::o3()] {
+                    opt_mode.cell_size = cell_size;
+
+                    assert_eq!(
+                        run_with_config(source, &opt_mode),
+                        expected,
+                        "{:?} {}",
+                        cell_size,
+                        source
+                    );
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_awib() {
         let mut program = parse(include_str!("../../../test_programs/awib.bf")).unwrap();
@@ -841,4 +1449,471 @@ mod tests {
         assert_eq!(interpreter.heap[0], 0);
         assert_eq!(&output, &[5, 4, 3, 2, 1]);
     }
+
+    #[test]
+    fn test_fuel() {
+        let program = parse("+++[>+<-]>.").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(Cursor::new(input), &mut output);
+        interpreter.set_fuel(Some(100));
+        interpreter.execute(&program).unwrap();
+
+        // 6 ops outside of the loop and 3 iterations with 5 ops each
+        assert_eq!(interpreter.get_fuel(), Some(100 - 6 - 3 * 5));
+        assert_eq!(&output, &[3]);
+    }
+
+    #[test]
+    fn test_out_of_fuel() {
+        let program = parse("+[>+<]").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(Cursor::new(input), &mut output);
+        interpreter.set_fuel(Some(1000));
+
+        match interpreter.execute(&program) {
+            Err(RuntimeError::OutOfFuel { span }) => assert!(span.start >= 1 && span.end <= 6),
+            result => panic!("Expected out of fuel error but got {:?}", result),
+        }
+
+        assert_eq!(interpreter.get_fuel(), Some(0));
+    }
+
+    #[test]
+    fn test_out_of_fuel_c_loop() {
+        let program = Program {
+            ops: vec![Op::c_loop(1..4, vec![], 200, BlockInfo::new_empty())],
+        };
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(Cursor::new(input), &mut output);
+        interpreter.set_fuel(Some(100));
+
+        match interpreter.execute(&program) {
+            Err(RuntimeError::OutOfFuel { span }) => assert_eq!(span, 1..4),
+            result => panic!("Expected out of fuel error but got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let mut program = parse("+[>+<]").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        let cancel_flag = Arc::new(AtomicBool::new(false));
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(Cursor::new(input), &mut output);
+        interpreter.set_cancel_flag(cancel_flag.clone());
+
+        // The flag is checked before the first op
+        cancel_flag.store(true, Ordering::Relaxed);
+
+        assert!(matches!(
+            interpreter.execute(&program),
+            Err(RuntimeError::OutOfFuel { .. })
+        ));
+    }
+
+    #[test]
+    fn test_resume_on_eof() {
+        let program = parse(",.,.").unwrap();
+
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(VecDeque::new(), &mut output);
+        interpreter.set_pause_on_eof(true);
+
+        assert_eq!(
+            interpreter.start(&program).unwrap(),
+            ExecutionStatus::NeedsInput
+        );
+        assert!(interpreter.is_paused());
+
+        interpreter.input_mut().push_back(b'a');
+        assert_eq!(
+            interpreter.resume(&program).unwrap(),
+            ExecutionStatus::NeedsInput
+        );
+
+        interpreter.input_mut().push_back(b'b');
+        assert_eq!(
+            interpreter.resume(&program).unwrap(),
+            ExecutionStatus::Finished
+        );
+        assert!(!interpreter.is_paused());
+
+        assert_eq!(output, b"ab");
+    }
+
+    #[test]
+    fn test_resume_in_loops() {
+        let mut program = parse(",[>++[<.>-]<,]").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(VecDeque::new(), &mut output);
+        interpreter.set_pause_on_eof(true);
+
+        let mut status = interpreter.start(&program).unwrap();
+
+        for chunk in [&b"ab"[..], b"", b"c", b"d\0"] {
+            assert_eq!(status, ExecutionStatus::NeedsInput);
+            interpreter.input_mut().extend(chunk);
+            status = interpreter.resume(&program).unwrap();
+        }
+
+        assert_eq!(status, ExecutionStatus::Finished);
+
+        assert_eq!(output, b"aabbccdd");
+    }
+
+    #[test]
+    fn test_resume_would_block() {
+        struct BlockingInput {
+            available: Option<u8>,
+        }
+
+        impl Read for BlockingInput {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.available.take() {
+                    Some(value) => {
+                        buf[0] = value;
+                        Ok(1)
+                    }
+                    None => Err(ErrorKind::WouldBlock.into()),
+                }
+            }
+        }
+
+        let program = parse(",+.,+.").unwrap();
+
+        let mut output = Vec::new();
+
+        let mut interpreter = Interpreter::new(BlockingInput { available: None }, &mut output);
+        interpreter.set_fuel(Some(6));
+
+        assert_eq!(
+            interpreter.start(&program).unwrap(),
+            ExecutionStatus::NeedsInput
+        );
+
+        interpreter.input_mut().available = Some(b'a');
+        assert_eq!(
+            interpreter.resume(&program).unwrap(),
+            ExecutionStatus::NeedsInput
+        );
+
+        interpreter.input_mut().available = Some(b'b');
+        assert_eq!(
+            interpreter.resume(&program).unwrap(),
+            ExecutionStatus::Finished
+        );
+
+        assert_eq!(interpreter.get_fuel(), Some(0));
+        assert_eq!(output, b"bc");
+    }
+
+    #[test]
+    fn test_execute_would_block() {
+        struct BlockingInput;
+
+        impl Read for BlockingInput {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::WouldBlock.into())
+            }
+        }
+
+        let program = parse(",").unwrap();
+
+        let mut output = Vec::new();
+
+        let result = Interpreter::new(BlockingInput, &mut output).execute(&program);
+
+        assert!(matches!(result, Err(RuntimeError::IoError { .. })));
+    }
+
+    #[test]
+    fn test_heap_underflow() {
+        let program = parse("+<<+>>.").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let result = Interpreter::new(Cursor::new(input), &mut output).execute(&program);
+
+        match result {
+            Err(RuntimeError::HeapUnderflow { span }) => assert_eq!(span, 3..4),
+            _ => panic!("Expected heap underflow"),
+        }
+    }
+
+    #[test]
+    fn test_heap_underflow_loop() {
+        let program = parse("+<[-]").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let result = Interpreter::new(Cursor::new(input), &mut output).execute(&program);
+
+        match result {
+            Err(RuntimeError::HeapUnderflow { span }) => assert_eq!(span, 2..5),
+            _ => panic!("Expected heap underflow"),
+        }
+    }
+
+    #[test]
+    fn test_add_zero_source_left_of_tape() {
+        // The loop never runs, so the cell left of the tape must not be touched either
+        let mut program = parse("+[>,]>,[-<<<<+>>>>]+++.").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new(Cursor::new(input), &mut output)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, vec![3]);
+    }
+
+    #[test]
+    fn test_move_zero_source_left_of_tape() {
+        let mut program = parse(",[-<+>]+.").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        let input = b"\0";
+        let mut output = Vec::new();
+
+        Interpreter::new(Cursor::new(input), &mut output)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, vec![1]);
+    }
+
+    #[test]
+    fn test_heap_size() {
+        let program = parse("+[>+]").unwrap();
+
+        let mut opt_mode = OptimizeConfig::o0();
+        opt_mode.heap_size = 100;
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let result = Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode)
+            .execute(&program);
+
+        match result {
+            Err(RuntimeError::MaxHeapSizeReached {
+                required,
+                max_heap_size,
+                ..
+            }) => {
+                assert_eq!(required, 101);
+                assert_eq!(max_heap_size, 100);
+            }
+            _ => panic!("Expected heap error"),
+        }
+    }
+
+    #[test]
+    fn test_bidirectional_tape() {
+        let mut opt_mode = OptimizeConfig::o0();
+        opt_mode.bidirectional_tape = true;
+
+        let program = parse("+<<+++[->>++<<]>>.<+<+<+>>[<]+.").unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        let mut interpreter =
+            Interpreter::new_with_config(Cursor::new(input), &mut output, &opt_mode);
+        interpreter.execute(&program).unwrap();
+
+        assert_eq!(interpreter.heap[0], 7);
+        assert_eq!(interpreter.negative_heap[0..4], [1, 1, 1, 1]);
+        assert_eq!(output, b"\x07\x01");
+    }
+
+    fn run_with_breakpoints(
+        source: &str,
+        opt_mode: &OptimizeConfig,
+    ) -> (Vec<BreakpointInfo>, Vec<u8>) {
+        let options = ParserOptions {
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+
+        let mut program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+        optimize_with_config(&mut program, opt_mode);
+
+        let breakpoints = Rc::new(RefCell::new(vec![]));
+        let mut output = Vec::new();
+
+        let mut interpreter =
+            Interpreter::new_with_config(Cursor::new(b"ab"), &mut output, opt_mode);
+
+        let reported = breakpoints.clone();
+        interpreter.set_breakpoint_callback(move |info| reported.borrow_mut().push(info.clone()));
+        interpreter.execute(&program).unwrap();
+
+        let breakpoints = breakpoints.borrow().clone();
+        (breakpoints, output)
+    }
+
+    #[test]
+    fn test_breakpoint() {
+        let (breakpoints, output) =
+            run_with_breakpoints("++>+++#<[->+<]>.#", &OptimizeConfig::o0());
+
+        assert_eq!(output, b"\x05");
+        assert_eq!(
+            breakpoints,
+            vec![
+                BreakpointInfo {
+                    span: 6..7,
+                    pointer: 1,
+                    cells_start: 0,
+                    cells: vec![2, 3, 0, 0, 0, 0, 0, 0, 0, 0],
+                },
+                BreakpointInfo {
+                    span: 16..17,
+                    pointer: 1,
+                    cells_start: 0,
+                    cells: vec![0, 5, 0, 0, 0, 0, 0, 0, 0, 0],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_breakpoint_window() {
+        let (breakpoints, _) = run_with_breakpoints(">>>>>>>>>>+#", &OptimizeConfig::o0());
+
+        assert_eq!(breakpoints[0].pointer, 10);
+        assert_eq!(breakpoints[0].cells_start, 2);
+        assert_eq!(breakpoints[0].cells.len(), 17);
+        assert_eq!(breakpoints[0].cells[8], 1);
+
+        let mut opt_mode = OptimizeConfig::o0();
+        opt_mode.bidirectional_tape = true;
+
+        let (breakpoints, _) = run_with_breakpoints("-<<+#", &opt_mode);
+
+        assert_eq!(breakpoints[0].pointer, -2);
+        assert_eq!(breakpoints[0].cells_start, -10);
+        assert_eq!(breakpoints[0].cells[6..11], [0, 0, 1, 0, 255]);
+    }
+
+    #[test]
+    fn test_breakpoint_optimization_barrier() {
+        let source = "++++[>++#>+++<<-]>#>[-<+>]#<<+++#[>.<-],#>[-]#>,.#";
+
+        let (expected_breakpoints, expected_output) =
+            run_with_breakpoints(source, &OptimizeConfig::o0());
+
+        assert_eq!(expected_breakpoints.len(), 10);
+
+        for opt_mode in [
+            OptimizeConfig::o1(),
+            OptimizeConfig::o2(),
+            OptimizeConfig::o3(),
+            OptimizeConfig::wtf(),
+        ] {
+            let (breakpoints, output) = run_with_breakpoints(source, &opt_mode);
+
+            assert_eq!(breakpoints, expected_breakpoints);
+            assert_eq!(output, expected_output);
+        }
+    }
+
+    #[test]
+    fn test_breakpoint_without_callback() {
+        let options = ParserOptions {
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_bytes_with_options(b"+#.", &options).unwrap();
+
+        let input = b"";
+        let mut output = Vec::new();
+
+        Interpreter::new(Cursor::new(input), &mut output)
+            .execute(&program)
+            .unwrap();
+
+        assert_eq!(output, b"\x01");
+    }
+
+    #[test]
+    fn test_unmodified_local_loop_in_shifted_block() {
+        let mut outputs = vec![];
+
+        for opt_mode in [OptimizeConfig::o0(), OptimizeConfig::o2()] {
+            let mut program = parse("++[->+++[>++[<<->>.-]<-]<]").unwrap();
+            optimize_with_config(&mut program, &opt_mode);
+
+            let mut output = Vec::new();
+
+            Interpreter::new(Cursor::new(b""), &mut output)
+                .execute(&program)
+                .unwrap();
+
+            outputs.push(output);
+        }
+
+        assert_eq!(outputs[0], outputs[1]);
+    }
+
+    fn parse_deeply_nested(depth: usize) -> Program {
+        let source = format!(
+            "+{}[-]{}{}.",
+            "[>+".repeat(depth),
+            "<-]".repeat(depth),
+            "+".repeat(65)
+        );
+
+        let options = ParserOptions {
+            max_loop_depth: depth + 1,
+            ..ParserOptions::default()
+        };
+
+        parse_bytes_with_options(source.as_bytes(), &options).unwrap()
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        for (depth, opt_mode) in [
+            (5000, OptimizeConfig::o0()),
+            (5000, OptimizeConfig::o1()),
+            (1024, OptimizeConfig::o2()),
+            (1024, OptimizeConfig::o3()),
+        ] {
+            let mut program = parse_deeply_nested(depth);
+            optimize_with_config(&mut program, &opt_mode);
+
+            let mut output = Vec::new();
+
+            Interpreter::new(Cursor::new(b""), &mut output)
+                .execute(&program)
+                .unwrap();
+
+            assert_eq!(output, b"A");
+        }
+    }
 }
@@ -0,0 +1,204 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use cranelift_module::{default_libcall_names, DataContext, Linkage, Module};
+use cranelift_object::{ObjectBuilder, ObjectModule};
+
+use crate::backends::cranelift::{build_isa, define_main};
+use crate::backends::negative_cells;
+use crate::errors::CompilerError;
+use crate::parser::Program;
+use crate::OptimizeConfig;
+
+/// Compile program into a native object file for the host
+///
+/// The object exports `cranefack_main` and expects the io functions of the runtime
+/// to be provided when linking.
+pub fn compile_to_object(
+    program: &Program,
+    opt_mode: &OptimizeConfig,
+) -> Result<Vec<u8>, CompilerError> {
+    let isa = build_isa(opt_mode, true)?;
+
+    let object_builder = ObjectBuilder::new(isa, "cranefack", default_libcall_names())?;
+    let mut module = ObjectModule::new(object_builder);
+
+    define_main(
+        &mut module,
+        program,
+        opt_mode,
+        "cranefack_main",
+        Linkage::Export,
+    )?;
+
+    define_data(
+        &mut module,
+        "cranefack_heap_size",
+        &opt_mode.heap_size.to_ne_bytes(),
+    )?;
+    define_data(
+        &mut module,
+        "cranefack_negative_cells",
+        &negative_cells(opt_mode).to_ne_bytes(),
+    )?;
+    define_data(
+        &mut module,
+        "cranefack_cell_bytes",
+        &[opt_mode.cell_size.bytes() as u8],
+    )?;
+    define_data(
+        &mut module,
+        "cranefack_printable_output",
+        &[opt_mode.printable_output as u8],
+    )?;
+
+    module
+        .finish()
+        .emit()
+        .map_err(|error| CompilerError::InternalCompilerError {
+            message: error.to_string(),
+        })
+}
+
+/// Compile program into a native executable
+///
+/// The object is linked together with a small C runtime using the system C compiler.
+/// The compiler can be changed with the `CC` environment variable.
+pub fn compile_to_executable(
+    program: &Program,
+    opt_mode: &OptimizeConfig,
+    output: &Path,
+) -> Result<(), CompilerError> {
+    let object = compile_to_object(program, opt_mode)?;
+
+    let build_dir = new_build_dir();
+
+    let result = link(&build_dir, &object, output);
+
+    let _ = fs::remove_dir_all(&build_dir);
+
+    result
+}
+
+/// Unique directory for the files of one linker run
+///
+/// The counter keeps concurrent compilations in the same process apart.
+fn new_build_dir() -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    std::env::temp_dir().join(format!("cranefack-{}-{}", std::process::id(), count))
+}
+
+fn link(build_dir: &Path, object: &[u8], output: &Path) -> Result<(), CompilerError> {
+    let object_file = build_dir.join("program.o");
+    let runtime_file = build_dir.join("runtime.c");
+
+    fs::create_dir_all(build_dir)
+        .and_then(|_| fs::write(&object_file, object))
+        .and_then(|_| fs::write(&runtime_file, include_str!("runtime.c")))
+        .map_err(|error| CompilerError::LinkerError {
+            message: error.to_string(),
+        })?;
+
+    let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+
+    let result = Command::new(&cc)
+        .arg("-O2")
+        .arg(&runtime_file)
+        .arg(&object_file)
+        .arg("-o")
+        .arg(output)
+        .output()
+        .map_err(|error| CompilerError::LinkerError {
+            message: format!("Failed to run {}: {}", cc, error),
+        })?;
+
+    if !result.status.success() {
+        return Err(CompilerError::LinkerError {
+            message: String::from_utf8_lossy(&result.stderr).into_owned(),
+        });
+    }
+
+    Ok(())
+}
+
+fn define_data(module: &mut ObjectModule, name: &str, value: &[u8]) -> Result<(), CompilerError> {
+    let data = module.declare_data(name, Linkage::Export, false, false)?;
+
+    let mut data_ctx = DataContext::new();
+    data_ctx.define(value.to_vec().into_boxed_slice());
+
+    module.define_data(data, &data_ctx)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Write};
+    use std::process::Stdio;
+
+    use super::*;
+    use crate::{parse, Interpreter};
+
+    #[test]
+    fn test_compile_to_object() {
+        let program = parse("++[>+<-]>.").unwrap();
+
+        let object = compile_to_object(&program, &OptimizeConfig::o0()).unwrap();
+
+        assert!(object.len() > 4);
+        if cfg!(target_os = "linux") {
+            assert_eq!(&object[0..4], b"\x7fELF");
+        }
+    }
+
+    #[test]
+    fn test_compile_to_object_optimized() {
+        let mut program = parse("++[>+<-]>.").unwrap();
+        crate::optimize_with_config(&mut program, &OptimizeConfig::o3());
+
+        let object = compile_to_object(&program, &OptimizeConfig::o3()).unwrap();
+
+        assert!(!object.is_empty());
+    }
+
+    #[test]
+    fn test_compile_to_executable() {
+        let cc = std::env::var("CC").unwrap_or_else(|_| "cc".to_owned());
+        if Command::new(&cc).arg("--version").output().is_err() {
+            eprintln!("Skipping test, no linker found");
+            return;
+        }
+
+        let mut program = parse(include_str!("../../../test_programs/factor.bf")).unwrap();
+        crate::optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        let input = include_bytes!("../../../test_programs/factor.bf.in");
+
+        let mut expected = Vec::new();
+        Interpreter::new(Cursor::new(input), &mut expected)
+            .execute(&program)
+            .unwrap();
+
+        let executable = new_build_dir().with_extension("exe");
+        compile_to_executable(&program, &OptimizeConfig::o2(), &executable).unwrap();
+
+        let mut child = Command::new(&executable)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child.stdin.take().unwrap().write_all(input).unwrap();
+        let result = child.wait_with_output().unwrap();
+
+        let _ = fs::remove_file(&executable);
+
+        assert!(result.status.success());
+        assert_eq!(result.stdout, expected);
+    }
+}
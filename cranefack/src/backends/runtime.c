@@ -0,0 +1,111 @@
+/*
+ * Runtime for programs compiled with `compile_to_object`
+ *
+ * Provides the io functions called by the generated code and a main function
+ * that allocates the heap and runs the program.
+ */
+
+#include <stddef.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+
+struct cranefack_env {
+    // Accessed by the generated code and needs to be the first field
+    uint64_t fuel;
+    int has_error;
+    size_t span_start;
+    size_t span_end;
+    ptrdiff_t index;
+};
+
+extern const size_t cranefack_heap_size;
+extern const size_t cranefack_negative_cells;
+extern const uint8_t cranefack_cell_bytes;
+extern const uint8_t cranefack_printable_output;
+
+extern void cranefack_main(void *heap, struct cranefack_env *env);
+
+int32_t cranefack_get_char(struct cranefack_env *env) {
+    (void) env;
+
+    int value = getchar();
+
+    if (value == EOF) {
+        // In case of EOF the generated code decides what to store based on the eof mode
+        if (ferror(stdin)) {
+            perror("Input error");
+            fprintf(stderr, "Terminating!!!\n");
+            exit(1);
+        }
+        return -1;
+    }
+
+    return value;
+}
+
+void cranefack_put_char(struct cranefack_env *env, uint8_t value) {
+    (void) env;
+
+    int result;
+
+    if (!cranefack_printable_output) {
+        result = putchar(value);
+    } else if (value != 0 && value < 0x80) {
+        result = putchar(value);
+    } else {
+        result = printf("\\0x%x", value);
+    }
+
+    if (result < 0) {
+        perror("Output error");
+        fprintf(stderr, "Terminating!!!\n");
+        exit(1);
+    }
+}
+
+void cranefack_heap_error(struct cranefack_env *env, size_t span_start, size_t span_end,
+                          ptrdiff_t index) {
+    env->has_error = 1;
+    env->span_start = span_start;
+    env->span_end = span_end;
+    env->index = index;
+}
+
+uint64_t cranefack_refuel(struct cranefack_env *env, size_t span_start, size_t span_end) {
+    (void) env;
+    (void) span_start;
+    (void) span_end;
+
+    // Standalone executables run without fuel limit
+    return UINT64_MAX;
+}
+
+int main(void) {
+    struct cranefack_env env = {0};
+
+    uint8_t *heap = calloc(cranefack_negative_cells + cranefack_heap_size, cranefack_cell_bytes);
+
+    if (heap == NULL) {
+        fprintf(stderr, "Failed to allocate heap\n");
+        return 1;
+    }
+
+    // The generated code expects the cells left of the start cell before the heap pointer
+    cranefack_main(heap + cranefack_negative_cells * cranefack_cell_bytes, &env);
+
+    fflush(stdout);
+    free(heap);
+
+    if (env.has_error) {
+        if (env.index < 0) {
+            fprintf(stderr, "Heap pointer moved left of the start of the tape\n");
+        } else {
+            fprintf(stderr, "Required heap size of 0x%zx exceeds limit of 0x%zx\n",
+                    (size_t) env.index + 1, cranefack_heap_size);
+        }
+        return 1;
+    }
+
+    return 0;
+}
@@ -1,194 +1,249 @@
 use std::error::Error;
 use std::fmt::Write;
+use std::slice::Iter;
 
-use crate::ir::ops::{LoopDecrement, Op, OpType};
+use crate::ir::ops::{CellSize, LoopDecrement, Op, OpType};
 use crate::parser::Program;
+use crate::OptimizeConfig;
 
 /// Compile program into a rust file that can be compiled with rustc
 pub fn compile_to_rust(program: &Program) -> String {
+    compile_to_rust_with_config(program, &OptimizeConfig::o0())
+}
+
+/// Compile program into a rust file using the runtime settings of the optimization config
+pub fn compile_to_rust_with_config(program: &Program, config: &OptimizeConfig) -> String {
     let mut code = "".to_owned();
 
     print_ops(&mut code, &program.ops).expect("No io error");
 
-    include_str!("rust.tpl").replace("{{CODE}}", &code)
+    let cell_type = match config.cell_size {
+        CellSize::Bits8 => "u8",
+        CellSize::Bits16 => "u16",
+        CellSize::Bits32 => "u32",
+    };
+
+    let eof_value = match config.eof_mode.value(config.cell_size) {
+        Some(value) => format!("Some({})", value),
+        None => "None".to_owned(),
+    };
+
+    include_str!("rust.tpl")
+        .replace("{{CELL}}", cell_type)
+        .replace("{{EOF_VALUE}}", &eof_value)
+        .replace("{{PRINTABLE_OUTPUT}}", &config.printable_output.to_string())
+        .replace(
+            "{{BIDIRECTIONAL_TAPE}}",
+            &config.bidirectional_tape.to_string(),
+        )
+        .replace("{{HEAP_SIZE}}", &config.heap_size.to_string())
+        .replace("{{CODE}}", &code)
+}
+
+struct Block<'o> {
+    ops: Iter<'o, Op>,
+    op: Option<&'o Op>,
 }
 
 fn print_ops(out: &mut String, ops: &[Op]) -> Result<(), Box<dyn Error>> {
-    for op in ops {
-        match &op.op_type {
-            OpType::Start => {
-                // ignore
-            }
-            OpType::IncPtr(count) => writeln!(out, "rt.inc_ptr({});", count)?,
-            OpType::DecPtr(count) => writeln!(out, "rt.dec_ptr({});", count)?,
-            OpType::Inc(offset, count) => writeln!(out, "rt.inc({}, {});", offset, count)?,
-            OpType::Dec(offset, count) => writeln!(out, "rt.dec({}, {});", offset, count)?,
-            OpType::Set(offset, value) => writeln!(out, "rt.set({}, {});", offset, value)?,
-            OpType::Add(src_offset, dest_offset, multi) => {
-                writeln!(out, "rt.add({}, {}, {});", src_offset, dest_offset, multi)?
-            }
-            OpType::NzAdd(src_offset, dest_offset, multi) => writeln!(
-                out,
-                "rt.nz_add({}, {}, {});",
-                src_offset, dest_offset, multi
-            )?,
-            OpType::CAdd(src_offset, dest_offset, count) => {
-                writeln!(out, "rt.c_add({}, {}, {});", src_offset, dest_offset, count)?
-            }
-            OpType::NzCAdd(src_offset, dest_offset, count) => writeln!(
-                out,
-                "rt.nz_c_add({}, {}, {});",
-                src_offset, dest_offset, count
-            )?,
-            OpType::Sub(src_offset, dest_offset, multi) => {
-                writeln!(out, "rt.sub({}, {}, {});", src_offset, dest_offset, multi)?
-            }
-            OpType::NzSub(src_offset, dest_offset, multi) => writeln!(
-                out,
-                "rt.nz_sub({}, {}, {});",
-                src_offset, dest_offset, multi
-            )?,
-            OpType::CSub(src_offset, dest_offset, count) => {
-                writeln!(out, "rt.c_sub({}, {}, {});", src_offset, dest_offset, count)?
-            }
-            OpType::NzCSub(src_offset, dest_offset, count) => writeln!(
-                out,
-                "rt.nz_c_sub({}, {}, {});",
-                src_offset, dest_offset, count
-            )?,
-            OpType::Move(src_offset, dest_offset) => {
-                writeln!(out, "rt.move({}, {});", src_offset, dest_offset)?
-            }
-            OpType::Copy(src_offset, dest_offset) => {
-                writeln!(out, "rt.copy({}, {});", src_offset, dest_offset)?
-            }
-            OpType::Mul(src_offset, dest_offset, multi) => {
-                writeln!(out, "rt.mul({}, {}, {});", src_offset, dest_offset, multi)?
+    // Blocks are printed with an explicit stack to support deeply nested programs
+    let mut stack = vec![Block {
+        ops: ops.iter(),
+        op: None,
+    }];
+
+    while let Some(block) = stack.last_mut() {
+        match block.ops.next() {
+            Some(op) => {
+                if let Some(children) = op.op_type.get_children() {
+                    begin_block(out, op)?;
+
+                    stack.push(Block {
+                        ops: children.iter(),
+                        op: Some(op),
+                    });
+                } else {
+                    print_op(out, op)?;
+                }
             }
-            OpType::NzMul(src_offset, dest_offset, multi) => writeln!(
-                out,
-                "rt.nz_mul({}, {}, {});",
-                src_offset, dest_offset, multi
-            )?,
-            OpType::GetChar(offset) => writeln!(out, "rt.get_char({});", offset)?,
-            OpType::PutString(array) => writeln!(out, "rt.put_string({:?});", array)?,
-            OpType::PutChar(offset) => writeln!(out, "rt.put_char({});", offset)?,
-            OpType::DLoop(children, _) => {
-                writeln!(out, "{{")?;
-
-                writeln!(out, " while *rt.heap_value() > 0 {{")?;
-                print_ops(out, children)?;
-                writeln!(out, "}}")?;
-
-                writeln!(out, "}}")?;
+            None => {
+                if let Some(op) = stack.pop().expect("Block on stack").op {
+                    end_block(out, op)?;
+                }
             }
-            OpType::LLoop(children, _) => {
-                writeln!(out, "{{")?;
+        }
+    }
 
-                writeln!(out, "let heap_pointer = rt.pointer;")?;
-                writeln!(out, "while *rt.heap_value() > 0 {{")?;
-                writeln!(out, "rt.pointer = heap_pointer;")?;
-                print_ops(out, children)?;
-                writeln!(out, "}}")?;
-                writeln!(out, "rt.pointer = heap_pointer;")?;
+    Ok(())
+}
 
-                writeln!(out, "}}")?;
-            }
-            OpType::ILoop(children, step, decrement, _) => {
-                writeln!(out, "{{")?;
-
-                match decrement {
-                    LoopDecrement::Pre => {
-                        writeln!(out, "let heap_pointer = rt.pointer;")?;
-                        writeln!(out, "let mut left = *rt.heap_value();")?;
-                        writeln!(out, "while left > 0 {{")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "left = left.wrapping_sub({});", step)?;
-                        print_ops(out, children)?;
-                        writeln!(out, "}}")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "*rt.heap_value() = 0;")?;
-                    }
-                    LoopDecrement::Post | LoopDecrement::Auto => {
-                        writeln!(out, "let heap_pointer = rt.pointer;")?;
-                        writeln!(out, "let mut left = *rt.heap_value();")?;
-                        writeln!(out, "while left > 0 {{")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        print_ops(out, children)?;
-                        writeln!(out, "left = left.wrapping_sub({});", step)?;
-                        writeln!(out, "}}")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "*rt.heap_value() = 0;")?;
-                    }
-                }
+fn print_op(out: &mut String, op: &Op) -> Result<(), Box<dyn Error>> {
+    match &op.op_type {
+        OpType::Start | OpType::Breakpoint => {
+            // ignore
+        }
+        OpType::IncPtr(count) => writeln!(out, "rt.inc_ptr({});", count)?,
+        OpType::DecPtr(count) => writeln!(out, "rt.dec_ptr({});", count)?,
+        OpType::Inc(offset, count) => writeln!(out, "rt.inc({}, {});", offset, count)?,
+        OpType::Dec(offset, count) => writeln!(out, "rt.dec({}, {});", offset, count)?,
+        OpType::Set(offset, value) => writeln!(out, "rt.set({}, {});", offset, value)?,
+        OpType::Add(src_offset, dest_offset, multi) => {
+            writeln!(out, "rt.add({}, {}, {});", src_offset, dest_offset, multi)?
+        }
+        OpType::NzAdd(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "rt.nz_add({}, {}, {});",
+            src_offset, dest_offset, multi
+        )?,
+        OpType::CAdd(src_offset, dest_offset, count) => {
+            writeln!(out, "rt.c_add({}, {}, {});", src_offset, dest_offset, count)?
+        }
+        OpType::NzCAdd(src_offset, dest_offset, count) => writeln!(
+            out,
+            "rt.nz_c_add({}, {}, {});",
+            src_offset, dest_offset, count
+        )?,
+        OpType::Sub(src_offset, dest_offset, multi) => {
+            writeln!(out, "rt.sub({}, {}, {});", src_offset, dest_offset, multi)?
+        }
+        OpType::NzSub(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "rt.nz_sub({}, {}, {});",
+            src_offset, dest_offset, multi
+        )?,
+        OpType::CSub(src_offset, dest_offset, count) => {
+            writeln!(out, "rt.c_sub({}, {}, {});", src_offset, dest_offset, count)?
+        }
+        OpType::NzCSub(src_offset, dest_offset, count) => writeln!(
+            out,
+            "rt.nz_c_sub({}, {}, {});",
+            src_offset, dest_offset, count
+        )?,
+        OpType::Move(src_offset, dest_offset) => {
+            writeln!(out, "rt._move({}, {});", src_offset, dest_offset)?
+        }
+        OpType::Copy(src_offset, dest_offset) => {
+            writeln!(out, "rt.copy({}, {});", src_offset, dest_offset)?
+        }
+        OpType::Mul(src_offset, dest_offset, multi) => {
+            writeln!(out, "rt.mul({}, {}, {});", src_offset, dest_offset, multi)?
+        }
+        OpType::NzMul(src_offset, dest_offset, multi) => writeln!(
+            out,
+            "rt.nz_mul({}, {}, {});",
+            src_offset, dest_offset, multi
+        )?,
+        OpType::GetChar(offset) => writeln!(out, "rt.get_char({});", offset)?,
+        OpType::PutString(array) => writeln!(out, "rt.put_string(&{:?});", array)?,
+        OpType::PutChar(offset) => writeln!(out, "rt.put_char({});", offset)?,
+        OpType::SearchZero(step, _) => writeln!(out, "rt.search_zero({});", step)?,
+        OpType::DLoop(..)
+        | OpType::LLoop(..)
+        | OpType::ILoop(..)
+        | OpType::CLoop(..)
+        | OpType::TNz(..)
+        | OpType::DTNz(..) => unreachable!("Blocks are printed by print_ops"),
+    }
 
-                writeln!(out, "}}")?;
+    Ok(())
+}
+
+/// Print the code in front of the children of a block
+fn begin_block(out: &mut String, op: &Op) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "{{")?;
+
+    match &op.op_type {
+        OpType::DLoop(..) => {
+            writeln!(out, " while *rt.heap_value() > 0 {{")?;
+        }
+        OpType::LLoop(..) => {
+            writeln!(out, "let heap_pointer = rt.pointer;")?;
+            writeln!(out, "while *rt.heap_value() > 0 {{")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+        }
+        OpType::ILoop(_, step, decrement, _) => {
+            writeln!(out, "let heap_pointer = rt.pointer;")?;
+            writeln!(out, "let mut left = *rt.heap_value();")?;
+            writeln!(out, "while left > 0 {{")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+
+            if *decrement == LoopDecrement::Pre {
+                writeln!(out, "left = left.wrapping_sub({});", step)?;
             }
-            OpType::CLoop(children, iterations, decrement, _) => {
-                writeln!(out, "{{")?;
-
-                match decrement {
-                    LoopDecrement::Pre => {
-                        writeln!(out, "let heap_pointer = rt.pointer;")?;
-                        writeln!(out, "*rt.heap_value() = {}", iterations)?;
-                        writeln!(out, "let mut left = *rt.heap_value();")?;
-                        writeln!(out, "while left > 0 {{")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "left = left.wrapping_sub(1);")?;
-                        print_ops(out, children)?;
-                        writeln!(out, "}}")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "*rt.heap_value() = 0;")?;
-                    }
-                    LoopDecrement::Post => {
-                        writeln!(out, "let heap_pointer = rt.pointer;")?;
-                        writeln!(out, "*rt.heap_value() = {}", iterations)?;
-                        writeln!(out, "let mut left = *rt.heap_value();")?;
-                        writeln!(out, "while left > 0 {{")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        print_ops(out, children)?;
-                        writeln!(out, "left = left.wrapping_sub(1);")?;
-                        writeln!(out, "}}")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "*rt.heap_value() = 0;")?;
-                    }
-                    LoopDecrement::Auto => {
-                        writeln!(out, "let heap_pointer = rt.pointer;")?;
-                        writeln!(out, "for _ in 0..{} {{", iterations)?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        print_ops(out, children)?;
-                        writeln!(out, "}}")?;
-                        writeln!(out, "rt.pointer = heap_pointer;")?;
-                        writeln!(out, "*rt.heap_value() = 0;")?;
-                    }
+        }
+        OpType::CLoop(_, iterations, decrement, _) => {
+            writeln!(out, "let heap_pointer = rt.pointer;")?;
+
+            match decrement {
+                LoopDecrement::Pre => {
+                    writeln!(out, "*rt.heap_value() = {}", iterations)?;
+                    writeln!(out, "let mut left = *rt.heap_value();")?;
+                    writeln!(out, "while left > 0 {{")?;
+                    writeln!(out, "rt.pointer = heap_pointer;")?;
+                    writeln!(out, "left = left.wrapping_sub(1);")?;
+                }
+                LoopDecrement::Post => {
+                    writeln!(out, "*rt.heap_value() = {}", iterations)?;
+                    writeln!(out, "let mut left = *rt.heap_value();")?;
+                    writeln!(out, "while left > 0 {{")?;
+                    writeln!(out, "rt.pointer = heap_pointer;")?;
+                }
+                LoopDecrement::Auto => {
+                    writeln!(out, "for _ in 0..{} {{", iterations)?;
+                    writeln!(out, "rt.pointer = heap_pointer;")?;
                 }
-
-                writeln!(out, "}}")?;
             }
-            OpType::TNz(children, _) => {
-                writeln!(out, "{{")?;
+        }
+        OpType::TNz(..) => {
+            writeln!(out, "if *rt.heap_value() != 0 {{")?;
+            writeln!(out, "let heap_pointer = self.pointer;")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+        }
+        OpType::DTNz(..) => {
+            writeln!(out, " if *rt.heap_value() > 0 {{")?;
+        }
+        op_type => unreachable!("Op {:?} is not a block", op_type),
+    }
 
-                writeln!(out, "if *rt.heap_value() != 0 {{")?;
-                writeln!(out, "let heap_pointer = self.pointer;")?;
-                writeln!(out, "rt.pointer = heap_pointer;")?;
-                print_ops(out, children)?;
-                writeln!(out, "rt.pointer = heap_pointer;")?;
-                writeln!(out, "*rt.heap_value() = 0;")?;
+    Ok(())
+}
 
-                writeln!(out, "}}")?;
+/// Print the code after the children of a block
+fn end_block(out: &mut String, op: &Op) -> Result<(), Box<dyn Error>> {
+    match &op.op_type {
+        OpType::DLoop(..) | OpType::DTNz(..) => {
+            writeln!(out, "}}")?;
+        }
+        OpType::LLoop(..) => {
+            writeln!(out, "}}")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+        }
+        OpType::ILoop(_, step, decrement, _) => {
+            if *decrement != LoopDecrement::Pre {
+                writeln!(out, "left = left.wrapping_sub({});", step)?;
             }
-            OpType::DTNz(children, _, _) => {
-                writeln!(out, "{{")?;
-
-                writeln!(out, " if *rt.heap_value() > 0 {{")?;
-                print_ops(out, children)?;
-                writeln!(out, "}}")?;
 
-                writeln!(out, "}}")?;
+            writeln!(out, "}}")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+            writeln!(out, "*rt.heap_value() = 0;")?;
+        }
+        OpType::CLoop(_, _, decrement, _) => {
+            if *decrement == LoopDecrement::Post {
+                writeln!(out, "left = left.wrapping_sub(1);")?;
             }
-            OpType::SearchZero(step, _) => writeln!(out, "rt.search_zero({});", step)?,
+
+            writeln!(out, "}}")?;
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+            writeln!(out, "*rt.heap_value() = 0;")?;
+        }
+        OpType::TNz(..) => {
+            writeln!(out, "rt.pointer = heap_pointer;")?;
+            writeln!(out, "*rt.heap_value() = 0;")?;
         }
+        op_type => unreachable!("Op {:?} is not a block", op_type),
     }
 
+    writeln!(out, "}}")?;
+
     Ok(())
 }
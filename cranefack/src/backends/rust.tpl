@@ -2,10 +2,19 @@
 use std::io::Read;
 use std::io::Write;
 
-const MAX_HEAP_SIZE: usize = 16 * 1024 * 1024;
+const MAX_HEAP_SIZE: usize = {{HEAP_SIZE}};
+
+type Cell = {{CELL}};
+
+const EOF_VALUE: Option<Cell> = {{EOF_VALUE}};
+
+const PRINTABLE_OUTPUT: bool = {{PRINTABLE_OUTPUT}};
+
+const BIDIRECTIONAL_TAPE: bool = {{BIDIRECTIONAL_TAPE}};
 
 pub struct Runtime {
-    heap: Vec<u8>,
+    heap: Vec<Cell>,
+    negative_heap: Vec<Cell>,
     pointer: usize,
 }
 
@@ -13,50 +22,46 @@ impl Runtime {
     pub fn new() -> Runtime {
         Runtime {
             heap: vec![0; 1024 * 1024],
+            negative_heap: vec![],
             pointer: 0,
         }
     }
 
-    fn heap_value(&mut self) -> &mut u8 {
-        if self.pointer >= MAX_HEAP_SIZE {
-            panic!("Max heap size reached: {}", self.pointer);
-        }
-
-        while self.pointer > self.heap.len() - 1 {
-            self.heap.push(0);
-        }
-        &mut self.heap[self.pointer]
+    fn heap_value(&mut self) -> &mut Cell {
+        self.heap_value_at(self.pointer as isize)
     }
 
-    fn heap_value_at(&mut self, pointer: isize) -> &mut u8 {
-        let pointer = pointer.max(0) as usize;
+    fn heap_value_at(&mut self, pointer: isize) -> &mut Cell {
+        if pointer < 0 {
+            // Cell -1 is stored at index 0 of the negative heap
+            let index = pointer.unsigned_abs() - 1;
 
-        if pointer >= MAX_HEAP_SIZE {
-            panic!("Max heap size reached: {}", self.pointer);
-        }
+            if !BIDIRECTIONAL_TAPE || index >= MAX_HEAP_SIZE {
+                panic!("Heap pointer moved left of the start of the tape");
+            }
 
-        while pointer > self.heap.len() - 1 {
-            self.heap.push(0);
+            while index >= self.negative_heap.len() {
+                self.negative_heap.push(0);
+            }
+            return &mut self.negative_heap[index];
         }
-        &mut self.heap[pointer]
-    }
 
-    fn heap_value_at_offset(&mut self, ptr_offset: isize) -> &mut u8 {
-        let pointer = self.pointer as isize + ptr_offset;
-
-        let pointer = pointer.max(0) as usize;
+        let pointer = pointer as usize;
 
         if pointer >= MAX_HEAP_SIZE {
-            panic!("Max heap size reached: {}", self.pointer);
+            panic!("Max heap size reached: {}", pointer);
         }
 
         while pointer > self.heap.len() - 1 {
             self.heap.push(0);
         }
-
         &mut self.heap[pointer]
     }
 
+    fn heap_value_at_offset(&mut self, ptr_offset: isize) -> &mut Cell {
+        self.heap_value_at((self.pointer as isize).wrapping_add(ptr_offset))
+    }
+
     fn inc_ptr(&mut self, count: usize) {
         self.pointer = self.pointer.wrapping_add(count)
     }
@@ -65,16 +70,16 @@ impl Runtime {
         self.pointer = self.pointer.wrapping_sub(count)
     }
 
-    fn inc(&mut self, offset: isize, count: u8) {
+    fn inc(&mut self, offset: isize, count: Cell) {
         let value = self.heap_value_at_offset(offset);
         *value = value.wrapping_add(count);
     }
 
-    fn set(&mut self, offset: isize, value: u8) {
+    fn set(&mut self, offset: isize, value: Cell) {
         *self.heap_value_at_offset(offset) = value;
     }
 
-    fn dec(&mut self, offset: isize, count: u8) {
+    fn dec(&mut self, offset: isize, count: Cell) {
         let value = self.heap_value_at_offset(offset);
         *value = value.wrapping_sub(count);
     }
@@ -82,83 +87,113 @@ impl Runtime {
     fn get_char(&mut self, offset: isize) {
         let mut buf = [0];
 
-        std::io::stdin().read_exact(&mut buf).unwrap();
+        let value = match std::io::stdin().read_exact(&mut buf) {
+            Ok(()) => Some(buf[0] as Cell),
+            Err(error) if error.kind() == std::io::ErrorKind::UnexpectedEof => EOF_VALUE,
+            Err(error) => panic!("Input error: {:?}", error),
+        };
 
-        *self.heap_value_at_offset(offset) = buf[0];
+        let cell = self.heap_value_at_offset(offset);
+        if let Some(value) = value {
+            *cell = value;
+        }
     }
 
-    fn add(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
+    fn add(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_add(source.wrapping_mul(multi));
+        if source != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_add(source.wrapping_mul(multi));
+        }
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
-    fn nz_add(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
+    fn nz_add(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_add(source.wrapping_mul(multi));
+        if source != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_add(source.wrapping_mul(multi));
+        }
     }
 
-    fn c_add(&mut self, src_offset: isize, dest_offset: isize, value: u8) {
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_add(value);
+    fn c_add(&mut self, src_offset: isize, dest_offset: isize, value: Cell) {
+        self.nz_c_add(src_offset, dest_offset, value);
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
-    fn nz_c_add(&mut self, src_offset: isize, dest_offset: isize, value: u8) {
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_add(value);
+    fn nz_c_add(&mut self, _src_offset: isize, dest_offset: isize, value: Cell) {
+        // The loop didn't run if the constant is zero
+        if value != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_add(value);
+        }
     }
 
-    fn sub(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
+    fn sub(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_sub(source.wrapping_mul(multi));
+        if source != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_sub(source.wrapping_mul(multi));
+        }
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
-    fn nz_sub(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
+    fn nz_sub(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_sub(source.wrapping_mul(multi));
+        if source != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_sub(source.wrapping_mul(multi));
+        }
     }
 
-    fn c_sub(&mut self, src_offset: isize, dest_offset: isize, value: u8) {
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_sub(value);
+    fn c_sub(&mut self, src_offset: isize, dest_offset: isize, value: Cell) {
+        self.nz_c_sub(src_offset, dest_offset, value);
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
-    fn nz_c_sub(&mut self, src_offset: isize, dest_offset: isize, value: u8) {
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = target.wrapping_sub(value);
+    fn nz_c_sub(&mut self, _src_offset: isize, dest_offset: isize, value: Cell) {
+        // The loop didn't run if the constant is zero
+        if value != 0 {
+            let target = self.heap_value_at_offset(dest_offset);
+            *target = target.wrapping_sub(value);
+        }
     }
 
-    fn mul(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
-        let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = source.wrapping_mul(multi);
+    fn mul(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
+        self.nz_mul(src_offset, dest_offset, multi);
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
-    fn nz_mul(&mut self, src_offset: isize, dest_offset: isize, multi: u8) {
+    fn nz_mul(&mut self, src_offset: isize, dest_offset: isize, multi: Cell) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = source.wrapping_mul(multi);
+        self.set_target(source, dest_offset, source.wrapping_mul(multi));
     }
 
-    fn move(&mut self, src_offset: isize, dest_offset: isize) {
-        let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = source;
+    fn _move(&mut self, src_offset: isize, dest_offset: isize) {
+        self.copy(src_offset, dest_offset);
         *self.heap_value_at_offset(src_offset) = 0;
     }
 
     fn copy(&mut self, src_offset: isize, dest_offset: isize) {
         let source = *self.heap_value_at_offset(src_offset);
-        let target = self.heap_value_at_offset(dest_offset);
-        *target = source;
+        self.set_target(source, dest_offset, source);
+    }
+
+    // The loop an op came from didn't run if the source is zero,
+    // so a target outside of the heap is skipped
+    fn set_target(&mut self, source: Cell, dest_offset: isize, value: Cell) {
+        let pointer = (self.pointer as isize).wrapping_add(dest_offset);
+        let min_pointer = if BIDIRECTIONAL_TAPE {
+            -(MAX_HEAP_SIZE as isize)
+        } else {
+            0
+        };
+
+        if source == 0 && (pointer < min_pointer || pointer >= MAX_HEAP_SIZE as isize) {
+            return;
+        }
+
+        *self.heap_value_at(pointer) = value;
     }
 
     fn search_zero(&mut self, step: isize) {
@@ -178,16 +213,30 @@ impl Runtime {
     }
 
     fn put_char(&mut self, offset: isize) {
-        let ch = *self.heap_value_at_offset(offset);
+        let ch = *self.heap_value_at_offset(offset) as u8;
 
-        if ch.is_ascii() {
-            write!(std::io::stdout(), "{}", ch as char)
-        } else {
-            write!(std::io::stdout(), "\\0x{:x}", ch)
-        }.unwrap()
+        write_byte(ch);
+    }
+
+    fn put_string(&mut self, array: &[u8]) {
+        for &ch in array {
+            write_byte(ch);
+        }
     }
 }
 
+fn write_byte(value: u8) {
+    let mut stdout = std::io::stdout();
+
+    if !PRINTABLE_OUTPUT {
+        stdout.write_all(&[value])
+    } else if value != 0 && value.is_ascii() {
+        write!(stdout, "{}", value as char)
+    } else {
+        write!(stdout, "\\0x{:x}", value)
+    }.unwrap()
+}
+
 fn main() {
     let mut rt = Runtime::new();
 
@@ -0,0 +1,944 @@
+use std::slice::Iter;
+
+use crate::backends::negative_cells;
+use crate::errors::CompilerError;
+use crate::ir::ops::{CellSize, EofMode, LoopDecrement, Op, OpType};
+use crate::parser::Program;
+use crate::OptimizeConfig;
+
+const WASM_PAGE_SIZE: usize = 64 * 1024;
+
+/// Indentation stops growing at this depth to keep the code of deeply nested programs small
+const MAX_INDENT_DEPTH: usize = 32;
+
+/// Compile program into a binary WebAssembly module
+///
+/// The module imports `env.get_char` returning the next input byte or -1 on end of file
+/// and `env.put_char` taking an output byte.
+/// The heap is exported as `memory` and the program is executed by calling `run`.
+/// The address of the start cell is exported as global `heap_start`.
+/// Accessing cells outside the heap traps.
+pub fn compile_to_wasm(
+    program: &Program,
+    opt_mode: &OptimizeConfig,
+) -> Result<Vec<u8>, CompilerError> {
+    wat::parse_str(compile_to_wat(program, opt_mode)).map_err(|error| {
+        CompilerError::InternalCompilerError {
+            message: error.to_string(),
+        }
+    })
+}
+
+/// Compile program into a WebAssembly module in text format
+///
+/// See [compile_to_wasm] for the interface of the module.
+pub fn compile_to_wat(program: &Program, opt_mode: &OptimizeConfig) -> String {
+    let negative_cells = negative_cells(opt_mode);
+    let heap_bytes = (negative_cells + opt_mode.heap_size) * opt_mode.cell_size.bytes();
+
+    let mut builder = Builder {
+        out: String::new(),
+        heap_bytes,
+        cell_size: opt_mode.cell_size,
+        eof_mode: opt_mode.eof_mode,
+        depth: 2,
+        loop_depth: 0,
+        max_loop_depth: 0,
+    };
+
+    builder.append_ops(&program.ops);
+
+    let mut locals = String::new();
+    for depth in 0..builder.max_loop_depth {
+        locals.push_str(&format!(
+            "    (local $heap_ptr{} i32)\n    (local $counter{} i32)\n",
+            depth, depth
+        ));
+    }
+
+    let pages = heap_bytes.div_ceil(WASM_PAGE_SIZE);
+
+    include_str!("wasm.tpl")
+        .replace("{{MEMORY_PAGES}}", &pages.to_string())
+        .replace(
+            "{{HEAP_START}}",
+            &(negative_cells * opt_mode.cell_size.bytes()).to_string(),
+        )
+        .replace(
+            "{{PRINTABLE_OUTPUT}}",
+            &(opt_mode.printable_output as u8).to_string(),
+        )
+        .replace("{{LOCALS}}", locals.trim_end())
+        .replace("{{CODE}}", &builder.out)
+}
+
+struct Block<'o> {
+    ops: Iter<'o, Op>,
+    op: Option<&'o Op>,
+}
+
+struct Builder {
+    out: String,
+    heap_bytes: usize,
+    cell_size: CellSize,
+    eof_mode: EofMode,
+    depth: usize,
+    loop_depth: usize,
+    max_loop_depth: usize,
+}
+
+impl Builder {
+    fn append_ops(&mut self, ops: &[Op]) {
+        // Blocks are emitted with an explicit stack to support deeply nested programs
+        let mut stack = vec![Block {
+            ops: ops.iter(),
+            op: None,
+        }];
+
+        while let Some(block) = stack.last_mut() {
+            match block.ops.next() {
+                Some(op) => {
+                    if let Some(children) = op.op_type.get_children() {
+                        self.begin_block(op);
+
+                        stack.push(Block {
+                            ops: children.iter(),
+                            op: Some(op),
+                        });
+                    } else {
+                        self.append_op(op);
+                    }
+                }
+                None => {
+                    if let Some(op) = stack.pop().expect("Block on stack").op {
+                        self.end_block(op);
+                    }
+                }
+            }
+        }
+    }
+
+    fn append_op(&mut self, op: &Op) {
+        match &op.op_type {
+            OpType::Start | OpType::Breakpoint => {
+                // ignore
+            }
+            OpType::IncPtr(value) => self.inc_ptr(*value as isize),
+            OpType::DecPtr(value) => self.inc_ptr(-(*value as isize)),
+            OpType::Inc(offset, value) => self.inc(*offset, *value, "i32.add"),
+            OpType::Dec(offset, value) => self.inc(*offset, *value, "i32.sub"),
+            OpType::Set(offset, value) => self.set(*offset, *value),
+            OpType::Add(src_offset, dest_offset, multi) => {
+                self.nz_add(*src_offset, *dest_offset, *multi, "i32.add");
+                self.set(*src_offset, 0);
+            }
+            OpType::NzAdd(src_offset, dest_offset, multi) => {
+                self.nz_add(*src_offset, *dest_offset, *multi, "i32.add")
+            }
+            OpType::CAdd(src_offset, dest_offset, value) => {
+                self.c_add(*dest_offset, *value, "i32.add");
+                self.set(*src_offset, 0);
+            }
+            OpType::NzCAdd(_src_offset, dest_offset, value) => {
+                self.c_add(*dest_offset, *value, "i32.add")
+            }
+            OpType::Sub(src_offset, dest_offset, multi) => {
+                self.nz_add(*src_offset, *dest_offset, *multi, "i32.sub");
+                self.set(*src_offset, 0);
+            }
+            OpType::NzSub(src_offset, dest_offset, multi) => {
+                self.nz_add(*src_offset, *dest_offset, *multi, "i32.sub")
+            }
+            OpType::CSub(src_offset, dest_offset, value) => {
+                self.c_add(*dest_offset, *value, "i32.sub");
+                self.set(*src_offset, 0);
+            }
+            OpType::NzCSub(_src_offset, dest_offset, value) => {
+                self.c_add(*dest_offset, *value, "i32.sub")
+            }
+            OpType::Mul(src_offset, dest_offset, multi) => {
+                self.nz_mul(*src_offset, *dest_offset, *multi);
+                self.set(*src_offset, 0);
+            }
+            OpType::NzMul(src_offset, dest_offset, multi) => {
+                self.nz_mul(*src_offset, *dest_offset, *multi)
+            }
+            OpType::Move(src_offset, dest_offset) => {
+                self.copy(*src_offset, *dest_offset);
+                self.set(*src_offset, 0);
+            }
+            OpType::Copy(src_offset, dest_offset) => self.copy(*src_offset, *dest_offset),
+            OpType::SearchZero(step, _) => self.search_zero(*step),
+            OpType::PutChar(offset) => self.put_char(*offset),
+            OpType::PutString(array) => self.put_string(array),
+            OpType::GetChar(offset) => self.get_char(*offset),
+            OpType::DLoop(..)
+            | OpType::LLoop(..)
+            | OpType::ILoop(..)
+            | OpType::CLoop(..)
+            | OpType::TNz(..)
+            | OpType::DTNz(..) => unreachable!("Blocks are emitted by append_ops"),
+        }
+    }
+
+    /// Emit the code in front of the children of a block
+    fn begin_block(&mut self, op: &Op) {
+        match &op.op_type {
+            OpType::DLoop(..) => self.loop_head(),
+            OpType::LLoop(..) => self.begin_l_loop(),
+            OpType::ILoop(_, step, decrement, _) => self.begin_i_loop(None, *step, *decrement),
+            OpType::CLoop(_, iterations, decrement, _) => {
+                self.begin_i_loop(Some(*iterations), 1, *decrement)
+            }
+            OpType::TNz(..) => self.begin_tnz(),
+            OpType::DTNz(..) => self.begin_d_tnz(),
+            op_type => unreachable!("Op {:?} is not a block", op_type),
+        }
+    }
+
+    /// Emit the code after the children of a block
+    fn end_block(&mut self, op: &Op) {
+        match &op.op_type {
+            OpType::DLoop(..) => self.loop_end(),
+            OpType::LLoop(..) => self.end_l_loop(),
+            OpType::ILoop(_, step, decrement, _) => self.end_i_loop(*step, *decrement),
+            OpType::CLoop(_, _, decrement, _) => self.end_i_loop(1, *decrement),
+            OpType::TNz(..) => self.end_tnz(),
+            OpType::DTNz(..) => self.end_d_tnz(),
+            op_type => unreachable!("Op {:?} is not a block", op_type),
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        for _ in 0..self.depth.min(MAX_INDENT_DEPTH) {
+            self.out.push_str("  ");
+        }
+        self.out.push_str(line);
+        self.out.push('\n');
+    }
+
+    /// Push address of the cell at offset and return the memory argument for load and store
+    ///
+    /// Cells left of the heap start wrap around to addresses beyond the memory and trap.
+    fn address(&mut self, offset: isize) -> String {
+        let offset = offset * self.cell_size.bytes() as isize;
+
+        self.line("local.get $ptr");
+
+        if offset > 0 {
+            format!(" offset={}", offset)
+        } else if offset == 0 {
+            "".to_owned()
+        } else {
+            self.line(&format!("i32.const {}", offset));
+            self.line("i32.add");
+            "".to_owned()
+        }
+    }
+
+    fn load(&mut self, offset: isize) {
+        let memarg = self.address(offset);
+
+        let instruction = match self.cell_size {
+            CellSize::Bits8 => "i32.load8_u",
+            CellSize::Bits16 => "i32.load16_u",
+            CellSize::Bits32 => "i32.load",
+        };
+
+        self.line(&format!("{}{}", instruction, memarg));
+    }
+
+    fn store(&mut self, memarg: &str) {
+        let instruction = match self.cell_size {
+            CellSize::Bits8 => "i32.store8",
+            CellSize::Bits16 => "i32.store16",
+            CellSize::Bits32 => "i32.store",
+        };
+
+        self.line(&format!("{}{}", instruction, memarg));
+    }
+
+    fn const_cell(&mut self, value: u32) {
+        self.line(&format!("i32.const {}", self.cell_size.wrap(value) as i32));
+    }
+
+    /// Wrap the value on the stack to the cell size
+    fn wrap(&mut self) {
+        if self.cell_size != CellSize::Bits32 {
+            self.line(&format!("i32.const {}", self.cell_size.max_value()));
+            self.line("i32.and");
+        }
+    }
+
+    fn inc_ptr(&mut self, value: isize) {
+        self.line("local.get $ptr");
+        self.line(&format!(
+            "i32.const {}",
+            value * self.cell_size.bytes() as isize
+        ));
+        self.line("i32.add");
+        self.line("local.set $ptr");
+    }
+
+    fn inc(&mut self, offset: isize, value: u32, instruction: &str) {
+        let memarg = self.address(offset);
+        self.load(offset);
+        self.const_cell(value);
+        self.line(instruction);
+        self.store(&memarg);
+    }
+
+    fn set(&mut self, offset: isize, value: u32) {
+        let memarg = self.address(offset);
+        self.const_cell(value);
+        self.store(&memarg);
+    }
+
+    /// Add multiple of the source cell to the target, which isn't touched if the source is zero
+    fn nz_add(&mut self, src_offset: isize, dest_offset: isize, multi: u32, instruction: &str) {
+        self.load(src_offset);
+        self.line("if");
+        self.depth += 1;
+
+        let memarg = self.address(dest_offset);
+        self.load(dest_offset);
+        self.load(src_offset);
+
+        if multi != 1 {
+            self.const_cell(multi);
+            self.line("i32.mul");
+        }
+
+        self.line(instruction);
+        self.store(&memarg);
+
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    /// Set the target to a multiple of the source, which isn't touched if the source is zero
+    /// Add constant to the target, which isn't touched if the constant is zero
+    fn c_add(&mut self, dest_offset: isize, value: u32, instruction: &str) {
+        // The loop didn't run if the constant is zero
+        if value != 0 {
+            self.inc(dest_offset, value, instruction);
+        }
+    }
+
+    /// Set the target to a multiple of the source cell
+    ///
+    /// The loop this came from didn't run if the source is zero. A target outside of the heap
+    /// is skipped in that case instead of trapping.
+    fn nz_mul(&mut self, src_offset: isize, dest_offset: isize, multi: u32) {
+        self.load(src_offset);
+        self.line("if");
+        self.depth += 1;
+
+        let memarg = self.address(dest_offset);
+        self.load(src_offset);
+
+        if multi != 1 {
+            self.const_cell(multi);
+            self.line("i32.mul");
+        }
+
+        self.store(&memarg);
+
+        self.depth -= 1;
+        self.line("else");
+        self.depth += 1;
+
+        self.line("local.get $ptr");
+        self.line(&format!(
+            "i32.const {}",
+            dest_offset * self.cell_size.bytes() as isize
+        ));
+        self.line("i32.add");
+        self.line(&format!("i32.const {}", self.heap_bytes));
+        self.line("i32.lt_u");
+        self.line("if");
+        self.depth += 1;
+
+        self.set(dest_offset, 0);
+
+        self.depth -= 1;
+        self.line("end");
+
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    fn copy(&mut self, src_offset: isize, dest_offset: isize) {
+        self.nz_mul(src_offset, dest_offset, 1);
+    }
+
+    fn get_char(&mut self, offset: isize) {
+        // The runtime returns -1 if the input reached end of file
+        self.line("call $get_char");
+        self.line("local.set $char");
+        self.line("local.get $char");
+        self.line("i32.const -1");
+        self.line("i32.ne");
+        self.line("if");
+        self.depth += 1;
+        let memarg = self.address(offset);
+        self.line("local.get $char");
+        self.store(&memarg);
+        self.depth -= 1;
+
+        if let Some(value) = self.eof_mode.value(self.cell_size) {
+            self.line("else");
+            self.depth += 1;
+            self.set(offset, value);
+            self.depth -= 1;
+        }
+
+        self.line("end");
+    }
+
+    fn put_char(&mut self, offset: isize) {
+        self.load(offset);
+
+        if self.cell_size != CellSize::Bits8 {
+            self.line("i32.const 0xff");
+            self.line("i32.and");
+        }
+
+        self.line("call $write_byte");
+    }
+
+    fn put_string(&mut self, array: &[u8]) {
+        for value in array {
+            self.line(&format!("i32.const {}", value));
+            self.line("call $write_byte");
+        }
+    }
+
+    /// Open block and loop and break out of both if the current cell is zero
+    fn loop_head(&mut self) {
+        self.line("block");
+        self.depth += 1;
+        self.line("loop");
+        self.depth += 1;
+        self.load(0);
+        self.line("i32.eqz");
+        self.line("br_if 1");
+    }
+
+    fn loop_end(&mut self) {
+        self.line("br 0");
+        self.depth -= 1;
+        self.line("end");
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    /// Reserve locals to save the heap pointer and the counter of a loop
+    fn enter_loop(&mut self) -> usize {
+        let depth = self.loop_depth;
+        self.loop_depth += 1;
+        self.max_loop_depth = self.max_loop_depth.max(self.loop_depth);
+        depth
+    }
+
+    /// Release the locals of the innermost loop and return their index
+    fn exit_loop(&mut self) -> usize {
+        self.loop_depth -= 1;
+        self.loop_depth
+    }
+
+    fn begin_d_tnz(&mut self) {
+        self.load(0);
+        self.line("if");
+        self.depth += 1;
+    }
+
+    fn end_d_tnz(&mut self) {
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    fn begin_l_loop(&mut self) {
+        let depth = self.enter_loop();
+
+        self.line("local.get $ptr");
+        self.line(&format!("local.set $heap_ptr{}", depth));
+
+        self.loop_head();
+    }
+
+    fn end_l_loop(&mut self) {
+        let depth = self.exit_loop();
+
+        self.line(&format!("local.get $heap_ptr{}", depth));
+        self.line("local.set $ptr");
+        self.loop_end();
+    }
+
+    /// Loop with a counter that is either read from the current cell or a constant
+    fn begin_i_loop(&mut self, iterations: Option<u32>, step: u32, decrement: LoopDecrement) {
+        let depth = self.enter_loop();
+
+        self.line("local.get $ptr");
+        self.line(&format!("local.set $heap_ptr{}", depth));
+
+        match iterations {
+            Some(iterations) => {
+                self.const_cell(iterations);
+                self.line(&format!("local.set $counter{}", depth));
+
+                if decrement == LoopDecrement::Post {
+                    self.set(0, iterations);
+                }
+            }
+            None => {
+                self.load(0);
+                self.line(&format!("local.set $counter{}", depth));
+            }
+        }
+
+        self.line("block");
+        self.depth += 1;
+        self.line("loop");
+        self.depth += 1;
+        self.line(&format!("local.get $counter{}", depth));
+        self.line("i32.eqz");
+        self.line("br_if 1");
+
+        if decrement == LoopDecrement::Pre {
+            self.decrement_counter(depth, step, true);
+        }
+    }
+
+    fn end_i_loop(&mut self, step: u32, decrement: LoopDecrement) {
+        let depth = self.exit_loop();
+
+        self.line(&format!("local.get $heap_ptr{}", depth));
+        self.line("local.set $ptr");
+
+        if decrement != LoopDecrement::Pre {
+            self.decrement_counter(depth, step, decrement == LoopDecrement::Post);
+        }
+
+        self.loop_end();
+
+        self.set(0, 0);
+    }
+
+    fn decrement_counter(&mut self, depth: usize, step: u32, store: bool) {
+        self.line(&format!("local.get $counter{}", depth));
+        self.const_cell(step);
+        self.line("i32.sub");
+        self.wrap();
+        self.line(&format!("local.set $counter{}", depth));
+
+        if store {
+            let memarg = self.address(0);
+            self.line(&format!("local.get $counter{}", depth));
+            self.store(&memarg);
+        }
+    }
+
+    fn begin_tnz(&mut self) {
+        let depth = self.enter_loop();
+
+        self.load(0);
+        self.line("if");
+        self.depth += 1;
+        self.line("local.get $ptr");
+        self.line(&format!("local.set $heap_ptr{}", depth));
+    }
+
+    fn end_tnz(&mut self) {
+        let depth = self.exit_loop();
+
+        self.line(&format!("local.get $heap_ptr{}", depth));
+        self.line("local.set $ptr");
+        self.set(0, 0);
+        self.depth -= 1;
+        self.line("end");
+    }
+
+    fn search_zero(&mut self, step: isize) {
+        self.loop_head();
+        self.inc_ptr(step);
+        self.loop_end();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use wasmi::{Caller, Engine, Linker, Module, Store};
+
+    use super::*;
+    use crate::backends::write_byte;
+    use crate::ir::opt_info::BlockInfo;
+    use crate::{
+        optimize_with_config, parse, parse_bytes_with_options, Interpreter, ParserOptions,
+    };
+
+    struct Env<'a> {
+        input: &'a [u8],
+        output: Vec<u8>,
+    }
+
+    fn run_wasm(program: &Program, config: &OptimizeConfig, input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+        try_run_wasm(program, config, input).unwrap()
+    }
+
+    fn try_run_wasm(
+        program: &Program,
+        config: &OptimizeConfig,
+        input: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>), wasmi::Error> {
+        let wasm = compile_to_wasm(program, config).unwrap();
+
+        let engine = Engine::default();
+        let module = Module::new(&engine, &wasm[..]).unwrap();
+
+        let mut store = Store::new(
+            &engine,
+            Env {
+                input,
+                output: Vec::new(),
+            },
+        );
+
+        let mut linker = <Linker<Env>>::new(&engine);
+        linker
+            .func_wrap("env", "get_char", |mut caller: Caller<'_, Env>| -> i32 {
+                let env = caller.data_mut();
+                match env.input.split_first() {
+                    Some((value, rest)) => {
+                        env.input = rest;
+                        *value as i32
+                    }
+                    None => -1,
+                }
+            })
+            .unwrap();
+        linker
+            .func_wrap(
+                "env",
+                "put_char",
+                |mut caller: Caller<'_, Env>, value: i32| {
+                    caller.data_mut().output.push(value as u8);
+                },
+            )
+            .unwrap();
+
+        let instance = linker.instantiate_and_start(&mut store, &module).unwrap();
+
+        instance
+            .get_typed_func::<(), ()>(&store, "run")
+            .unwrap()
+            .call(&mut store, ())?;
+
+        let heap_start = instance
+            .get_global(&store, "heap_start")
+            .unwrap()
+            .get(&store)
+            .i32()
+            .unwrap() as usize;
+
+        let memory = instance.get_memory(&store, "memory").unwrap();
+        let heap = memory.data(&store)[heap_start..heap_start + 16].to_vec();
+
+        Ok((store.into_data().output, heap))
+    }
+
+    fn run_interpreter(program: &Program, config: &OptimizeConfig, input: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+
+        Interpreter::new_with_config(Cursor::new(input), &mut output, config)
+            .execute(program)
+            .unwrap();
+
+        output
+    }
+
+    fn diff_with_interpreter(source: &str, input: &[u8]) {
+        for config in [
+            OptimizeConfig::o0(),
+            OptimizeConfig::o1(),
+            OptimizeConfig::o2(),
+            OptimizeConfig::o3(),
+        ] {
+            let mut program = parse(source).unwrap();
+            optimize_with_config(&mut program, &config);
+
+            let expected = run_interpreter(&program, &config, input);
+            let (output, _) = run_wasm(&program, &config, input);
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_out_1() {
+        let program = parse(">+.").unwrap();
+
+        let (output, heap) = run_wasm(&program, &OptimizeConfig::o0(), b"");
+
+        assert_eq!(output, b"\x01");
+        assert_eq!(&heap[0..2], &[0, 1]);
+    }
+
+    #[test]
+    fn test_put_char_printable() {
+        let mut config = OptimizeConfig::o0();
+        config.printable_output = true;
+
+        let program = parse("+.-.-.").unwrap();
+
+        let (output, _) = run_wasm(&program, &config, b"");
+
+        let mut expected = Vec::new();
+        for value in [1, 0, 255] {
+            write_byte(&mut expected, value, true).unwrap();
+        }
+
+        assert_eq!(output, expected);
+        assert_eq!(output, b"\x01\\0x0\\0xff");
+    }
+
+    #[test]
+    fn test_heap_underflow() {
+        let program = Program {
+            ops: vec![Op::set_with_offset(0..1, -2, 5), Op::put_char(0..1)],
+        };
+
+        assert!(try_run_wasm(&program, &OptimizeConfig::o0(), b"").is_err());
+    }
+
+    #[test]
+    fn test_bidirectional_tape() {
+        let mut config = OptimizeConfig::o0();
+        config.bidirectional_tape = true;
+
+        let program = parse("+<<+++[->>++<<]>>.").unwrap();
+
+        let (output, heap) = run_wasm(&program, &config, b"");
+
+        assert_eq!(output, b"\x07");
+        assert_eq!(heap[0], 7);
+    }
+
+    #[test]
+    fn test_eof() {
+        for (eof_mode, value) in [
+            (EofMode::Zero, 0),
+            (EofMode::Max, 255),
+            (EofMode::Unchanged, 3),
+        ] {
+            let mut config = OptimizeConfig::o0();
+            config.eof_mode = eof_mode;
+
+            let program = parse("+++,.").unwrap();
+
+            let (output, heap) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, [value]);
+            assert_eq!(heap[0], value);
+        }
+    }
+
+    #[test]
+    fn test_cell_size_16() {
+        let mut config = OptimizeConfig::o0();
+        config.cell_size = CellSize::Bits16;
+
+        let program = parse("-").unwrap();
+
+        let (_, heap) = run_wasm(&program, &config, b"");
+
+        assert_eq!(&heap[0..4], &[255, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_cell_size() {
+        for cell_size in [CellSize::Bits8, CellSize::Bits16, CellSize::Bits32] {
+            let mut config = OptimizeConfig::o2();
+            config.cell_size = cell_size;
+
+            let mut program = parse(include_str!("../../../test_programs/cell_size.bf")).unwrap();
+            optimize_with_config(&mut program, &config);
+
+            let expected = run_interpreter(&program, &config, b"");
+            let (output, _) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, expected);
+        }
+    }
+
+    #[test]
+    fn test_i_loop() {
+        for decrement in [LoopDecrement::Pre, LoopDecrement::Post, LoopDecrement::Auto] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, 10),
+                    Op::i_loop_with_decrement(
+                        1..4,
+                        vec![
+                            Op::inc_ptr(1..2, 1),
+                            Op::inc(1..2, 1),
+                            Op::dec_ptr(1..2, 1),
+                            Op::put_char(1..2),
+                        ],
+                        2,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 1),
+                ],
+            };
+
+            let config = OptimizeConfig::o0();
+            let (output, _) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, run_interpreter(&program, &config, b""));
+        }
+    }
+
+    #[test]
+    fn test_c_loop() {
+        for decrement in [LoopDecrement::Pre, LoopDecrement::Post, LoopDecrement::Auto] {
+            let program = Program {
+                ops: vec![
+                    Op::c_loop_with_decrement(
+                        1..4,
+                        vec![
+                            Op::inc_ptr(1..2, 1),
+                            Op::inc(1..2, 1),
+                            Op::dec_ptr(1..2, 1),
+                            Op::put_char(1..2),
+                        ],
+                        5,
+                        decrement,
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 1),
+                ],
+            };
+
+            let config = OptimizeConfig::o0();
+            let (output, _) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, run_interpreter(&program, &config, b""));
+        }
+    }
+
+    #[test]
+    fn test_tnz() {
+        for value in [0, 1] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, value),
+                    Op::t_nz(
+                        1..4,
+                        vec![Op::inc_ptr(1..2, 1), Op::set_with_offset(1..2, 1, 10)],
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                    Op::put_char_with_offset(1..2, 2),
+                ],
+            };
+
+            let config = OptimizeConfig::o0();
+            let (output, _) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, run_interpreter(&program, &config, b""));
+        }
+    }
+
+    #[test]
+    fn test_dtnz() {
+        for value in [0, 1] {
+            let program = Program {
+                ops: vec![
+                    Op::set(0..1, value),
+                    Op::d_t_nz(
+                        1..4,
+                        vec![Op::inc_ptr(1..2, 2), Op::set(1..2, 10)],
+                        Some(2),
+                        BlockInfo::new_empty(),
+                    ),
+                    Op::put_char(1..2),
+                ],
+            };
+
+            let config = OptimizeConfig::o0();
+            let (output, _) = run_wasm(&program, &config, b"");
+
+            assert_eq!(output, run_interpreter(&program, &config, b""));
+        }
+    }
+
+    #[test]
+    fn test_search_zero() {
+        let program = Program {
+            ops: vec![
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 1),
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 2),
+                Op::inc_ptr(0..1, 1),
+                Op::set(1..2, 3),
+                Op::search_zero(0..1, -1),
+                Op::set(1..2, 4),
+            ],
+        };
+
+        let (_, heap) = run_wasm(&program, &OptimizeConfig::o0(), b"");
+
+        assert_eq!(&heap[0..5], &[4, 1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_hello_world() {
+        diff_with_interpreter(include_str!("../../../test_programs/hello_world.bf"), b"");
+    }
+
+    #[test]
+    fn test_fizzbuzz() {
+        diff_with_interpreter(include_str!("../../../test_programs/fizzbuzz.bf"), b"");
+    }
+
+    #[test]
+    fn test_bottles() {
+        diff_with_interpreter(include_str!("../../../test_programs/bottles.bf"), b"");
+    }
+
+    #[test]
+    fn test_factor() {
+        diff_with_interpreter(
+            include_str!("../../../test_programs/factor.bf"),
+            include_bytes!("../../../test_programs/factor.bf.in"),
+        );
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        let depth = 20_000;
+        let source = format!("+{}[-]{}", "[>+".repeat(depth), "<-]".repeat(depth));
+        let options = ParserOptions {
+            max_loop_depth: depth + 1,
+            ..ParserOptions::default()
+        };
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+
+        let code = compile_to_wat(&program, &OptimizeConfig::o0());
+
+        assert_eq!(
+            code.lines().filter(|line| line.trim() == "loop").count(),
+            depth + 1
+        );
+        assert!(code
+            .lines()
+            .all(|line| line.len() - line.trim_start().len() <= 2 * MAX_INDENT_DEPTH));
+    }
+}
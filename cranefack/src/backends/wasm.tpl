@@ -0,0 +1,68 @@
+(module
+  (import "env" "get_char" (func $get_char (result i32)))
+  (import "env" "put_char" (func $put_char (param i32)))
+
+  (memory (export "memory") {{MEMORY_PAGES}} {{MEMORY_PAGES}})
+
+  ;; Cells left of the start cell are stored before it with a bidirectional tape
+  (global $heap_start (export "heap_start") i32 (i32.const {{HEAP_START}}))
+
+  ;; Write output byte and escape zero and non ASCII bytes as \0x.. with printable output
+  (func $write_byte (param $value i32)
+    i32.const {{PRINTABLE_OUTPUT}}
+    if
+      local.get $value
+      i32.eqz
+      local.get $value
+      i32.const 0x80
+      i32.ge_u
+      i32.or
+      if
+        i32.const 0x5c
+        call $put_char
+        i32.const 0x30
+        call $put_char
+        i32.const 0x78
+        call $put_char
+        local.get $value
+        i32.const 0x10
+        i32.ge_u
+        if
+          local.get $value
+          i32.const 4
+          i32.shr_u
+          call $write_hex_digit
+        end
+        local.get $value
+        i32.const 0xf
+        i32.and
+        call $write_hex_digit
+        return
+      end
+    end
+    local.get $value
+    call $put_char
+  )
+
+  (func $write_hex_digit (param $digit i32)
+    local.get $digit
+    i32.const 0x30
+    i32.add
+    local.get $digit
+    i32.const 0x57
+    i32.add
+    local.get $digit
+    i32.const 10
+    i32.lt_u
+    select
+    call $put_char
+  )
+
+  (func (export "run")
+    (local $ptr i32)
+    (local $char i32)
+{{LOCALS}}
+    global.get $heap_start
+    local.set $ptr
+{{CODE}}  )
+)
@@ -1,5 +1,6 @@
 use std::error::Error;
 use std::fmt::{Display, Formatter};
+use std::io::Write;
 
 use codespan_reporting::diagnostic::{Diagnostic, Label};
 use codespan_reporting::files::SimpleFiles;
@@ -7,11 +8,20 @@ use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
 use std::ops::Range;
 use std::option::Option::Some;
 
+use crate::ir::ops::OpType;
+use crate::optimizations::ExecutionOutcome;
+use crate::parser::Program;
+
 /// Trait all internal errors must implement
 pub trait CraneFackError: Error {
     /// Return error message with optional source position and optional source label
     fn get_message(&self) -> (Option<Range<usize>>, String, Option<String>);
 
+    /// Return additional source positions related to the error with their labels
+    fn get_secondary_labels(&self) -> Vec<(Range<usize>, String)> {
+        vec![]
+    }
+
     /// Print error to stderr with colors and other fancy stuff
     fn pretty_print(&self, source: &str, filename: Option<&str>) -> Result<(), Box<dyn Error>> {
         let mut files = SimpleFiles::new();
@@ -20,16 +30,23 @@ pub trait CraneFackError: Error {
 
         let (range, message, label_message) = self.get_message();
 
-        let diagnostic =
-            match range {
-                Some(range) => Diagnostic::error().with_message(message).with_labels(vec![
-                    match label_message {
-                        Some(message) => Label::primary(file_id, range).with_message(message),
-                        None => Label::primary(file_id, range),
-                    },
-                ]),
-                None => Diagnostic::error().with_message(message),
-            };
+        let diagnostic = match range {
+            Some(range) => {
+                let mut labels = vec![match label_message {
+                    Some(message) => Label::primary(file_id, range).with_message(message),
+                    None => Label::primary(file_id, range),
+                }];
+
+                for (range, message) in self.get_secondary_labels() {
+                    labels.push(Label::secondary(file_id, range).with_message(message));
+                }
+
+                Diagnostic::error()
+                    .with_message(message)
+                    .with_labels(labels)
+            }
+            None => Diagnostic::error().with_message(message),
+        };
 
         let writer = StandardStream::stderr(ColorChoice::Always);
         let config = codespan_reporting::term::Config::default();
@@ -47,13 +64,39 @@ pub enum ParserError {
     LoopStackOverflow { position: usize, max_depth: usize },
 
     /// A closing ] without matching [ was found
-    BadlyClosedLoop { position: usize },
+    ///
+    /// `likely_match` is the [ of the preceding loop on the same level if there is one.
+    BadlyClosedLoop {
+        position: usize,
+        likely_match: Option<usize>,
+    },
 
     /// There are still som unclosed loops at the end of the source
-    UnclosedLoop { position: usize },
+    ///
+    /// `likely_match` is the ] of the last loop nested directly inside the unclosed one if there is one.
+    UnclosedLoop {
+        position: usize,
+        likely_match: Option<usize>,
+    },
+
+    /// A token isn't a valid command of the dialect
+    InvalidToken { span: Range<usize> },
+
+    /// Reading the source failed
+    IoError {
+        position: usize,
+        error: std::io::Error,
+    },
 }
 
-impl Error for ParserError {}
+impl Error for ParserError {
+    fn cause(&self) -> Option<&dyn Error> {
+        match self {
+            ParserError::IoError { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
 
 impl Display for ParserError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -66,12 +109,18 @@ impl Display for ParserError {
                 "Maximum loop depth of {} reach at pos {}",
                 max_depth, position
             ),
-            ParserError::BadlyClosedLoop { position } => {
+            ParserError::BadlyClosedLoop { position, .. } => {
                 write!(f, "Badly closed loop at pos {}", position)
             }
-            ParserError::UnclosedLoop { position } => {
+            ParserError::UnclosedLoop { position, .. } => {
                 write!(f, "Unclosed loop at pos {}", position)
             }
+            ParserError::InvalidToken { span } => {
+                write!(f, "Invalid token at pos {}", span.start)
+            }
+            ParserError::IoError { position, error } => {
+                write!(f, "Failed to read source at pos {}: {}", position, error)
+            }
         }
     }
 }
@@ -92,6 +141,26 @@ impl CraneFackError for ParserError {
                 self.to_string(),
                 Some("Expected ] found end of file".to_owned()),
             ),
+            ParserError::InvalidToken { span } => (
+                Some(span.clone()),
+                self.to_string(),
+                Some("Not a command of the dialect".to_owned()),
+            ),
+            ParserError::IoError { .. } => (None, self.to_string(), None),
+        }
+    }
+
+    fn get_secondary_labels(&self) -> Vec<(Range<usize>, String)> {
+        match self {
+            ParserError::BadlyClosedLoop {
+                likely_match: Some(position),
+                ..
+            } => vec![(*position..position + 1, "Possibly matching [".to_owned())],
+            ParserError::UnclosedLoop {
+                likely_match: Some(position),
+                ..
+            } => vec![(*position..position + 1, "Possibly matching ]".to_owned())],
+            _ => vec![],
         }
     }
 }
@@ -106,11 +175,17 @@ pub enum RuntimeError {
         required: usize,
     },
 
+    /// The program tries to use a heap cell left of the start of the tape
+    HeapUnderflow { span: Range<usize> },
+
     /// Reading or writing with ops , or . failed
     IoError {
         span: Range<usize>,
         error: std::io::Error,
     },
+
+    /// Execution ran out of fuel or was cancelled
+    OutOfFuel { span: Range<usize> },
 }
 
 impl Error for RuntimeError {
@@ -134,7 +209,13 @@ impl Display for RuntimeError {
                 "Required heap size of 0x{:x} exceeds limit of 0x{:x}",
                 required, max_heap_size
             ),
+            RuntimeError::HeapUnderflow { .. } => {
+                write!(f, "Heap pointer moved left of the start of the tape")
+            }
             RuntimeError::IoError { error, .. } => std::fmt::Display::fmt(&error, f),
+            RuntimeError::OutOfFuel { .. } => {
+                write!(f, "Execution ran out of fuel or was cancelled")
+            }
         }
     }
 }
@@ -145,7 +226,9 @@ impl CraneFackError for RuntimeError {
             RuntimeError::MaxHeapSizeReached { span, .. } => {
                 (Some(span.clone()), self.to_string(), None)
             }
+            RuntimeError::HeapUnderflow { span } => (Some(span.clone()), self.to_string(), None),
             RuntimeError::IoError { span, .. } => (Some(span.clone()), self.to_string(), None),
+            RuntimeError::OutOfFuel { span } => (Some(span.clone()), self.to_string(), None),
         }
     }
 }
@@ -155,6 +238,9 @@ impl CraneFackError for RuntimeError {
 pub enum CompilerError {
     /// Some unknown and unexpected shit happened during compilation
     InternalCompilerError { message: String },
+
+    /// Linking the compiled object into an executable failed
+    LinkerError { message: String },
 }
 
 impl Error for CompilerError {}
@@ -165,6 +251,7 @@ impl Display for CompilerError {
             CompilerError::InternalCompilerError { message } => {
                 write!(f, "Internal compiler Error: {}", message)
             }
+            CompilerError::LinkerError { message } => write!(f, "Linker Error: {}", message),
         }
     }
 }
@@ -173,6 +260,141 @@ impl CraneFackError for CompilerError {
     fn get_message(&self) -> (Option<Range<usize>>, String, Option<String>) {
         match self {
             CompilerError::InternalCompilerError { message: _ } => (None, self.to_string(), None),
+            CompilerError::LinkerError { message: _ } => (None, self.to_string(), None),
+        }
+    }
+}
+
+/// Errors while loading or saving serialized programs
+#[derive(Debug)]
+pub enum IrError {
+    /// The data doesn't start with a known header
+    UnknownFormat,
+
+    /// The data was written with an incompatible format version
+    UnsupportedVersion(u32),
+
+    /// The binary data is malformed at the given byte offset
+    InvalidData { position: usize, message: String },
+
+    /// The text data is malformed at the given line
+    InvalidLine { line: usize, message: String },
+
+    /// Reading or writing the serialized program failed
+    IoError(std::io::Error),
+}
+
+impl Error for IrError {
+    fn cause(&self) -> Option<&dyn Error> {
+        match self {
+            IrError::IoError(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl Display for IrError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IrError::UnknownFormat => write!(f, "Data is not a serialized program"),
+            IrError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported IR format version {}", version)
+            }
+            IrError::InvalidData { position, message } => {
+                write!(f, "Invalid IR at offset {}: {}", position, message)
+            }
+            IrError::InvalidLine { line, message } => {
+                write!(f, "Invalid IR in line {}: {}", line, message)
+            }
+            IrError::IoError(error) => std::fmt::Display::fmt(&error, f),
         }
     }
 }
+
+impl CraneFackError for IrError {
+    fn get_message(&self) -> (Option<Range<usize>>, String, Option<String>) {
+        (None, self.to_string(), None)
+    }
+}
+
+/// Optimization pass changed the observable behavior of a program
+///
+/// Returned by [PassManager::run_checked](crate::PassManager::run_checked) with a minimized
+/// reproducer of the difference.
+#[derive(Debug)]
+pub struct ValidationError {
+    /// Name of the pass that changed the behavior
+    pub pass: String,
+
+    /// Iteration over all passes the change happened in
+    pub iteration: usize,
+
+    /// Minimized input the behavior differs for
+    pub input: Vec<u8>,
+
+    /// Outcome of the minimized program before the pass
+    pub expected: ExecutionOutcome,
+
+    /// Outcome of the minimized program after the pass
+    pub actual: ExecutionOutcome,
+
+    /// Minimized program before the pass
+    pub program: Program,
+
+    /// Minimized program after the pass
+    pub optimized: Program,
+}
+
+impl ValidationError {
+    /// Write input, outcomes and dumps of the minimized reproducer
+    pub fn write_details<W: Write>(&self, mut output: W) -> Result<(), Box<dyn Error>> {
+        writeln!(output, "Input: \"{}\"", self.input.escape_ascii())?;
+        writeln!(output, "Before the pass: {}", self.expected)?;
+        writeln!(output, "After the pass: {}", self.actual)?;
+        writeln!(output)?;
+        writeln!(output, "Program before the pass:")?;
+        self.program.dump(&mut output, false)?;
+        writeln!(output)?;
+        writeln!(output, "Program after the pass:")?;
+        self.optimized.dump(&mut output, false)?;
+
+        Ok(())
+    }
+}
+
+impl Error for ValidationError {}
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Optimization pass {} changed the behavior of the program in iteration {}",
+            self.pass, self.iteration
+        )
+    }
+}
+
+impl CraneFackError for ValidationError {
+    fn get_message(&self) -> (Option<Range<usize>>, String, Option<String>) {
+        // The start marker doesn't belong to the source
+        let ops = self
+            .program
+            .ops
+            .iter()
+            .filter(|op| op.op_type != OpType::Start);
+
+        let start = ops.clone().map(|op| op.span.start).min();
+        let end = ops.map(|op| op.span.end).max();
+
+        let span = match (start, end) {
+            (Some(start), Some(end)) => Some(start..end),
+            _ => None,
+        };
+
+        (
+            span,
+            self.to_string(),
+            Some("Minimized reproducer".to_owned()),
+        )
+    }
+}
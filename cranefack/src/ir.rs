@@ -1,2 +1,44 @@
+//! Intermediate representation of programs
+//!
+//! A [Program](crate::Program) is a list of [Op]s where loops and conditions hold the ops of
+//! their body. The parser creates the basic ops that map directly to brainfuck commands and the
+//! optimizer replaces them with more specialized ones.
+//!
+//! Programs can be inspected and modified with a [Visitor] or [VisitorMut]:
+//!
+//! ```
+//! # use std::error::Error;
+//! # fn main() -> Result<(), Box<dyn Error>> {
+//!     use cranefack::ir::{Op, OpType, Visitor};
+//!     use cranefack::parse;
+//!
+//!     struct CountOutput(usize);
+//!
+//!     impl Visitor for CountOutput {
+//!         fn visit_op(&mut self, op: &Op, _depth: usize) -> bool {
+//!             if let OpType::PutChar(_) = op.op_type {
+//!                 self.0 += 1;
+//!             }
+//!             true
+//!         }
+//!     }
+//!
+//!     let program = parse("+[.>[.]]")?;
+//!
+//!     let mut count = CountOutput(0);
+//!     program.visit(&mut count);
+//!
+//!     assert_eq!(count.0, 2);
+//! # Ok(())
+//! # }
+//! ```
+
+pub(crate) mod assembler;
 pub mod ops;
 pub mod opt_info;
+pub(crate) mod serialize;
+pub mod visitor;
+
+pub use ops::{CellSize, EofMode, LoopDecrement, Op, OpType};
+pub use opt_info::{BlockInfo, Cell, CellAccess};
+pub use visitor::{walk, walk_mut, Visitor, VisitorMut};
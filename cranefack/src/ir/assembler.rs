@@ -0,0 +1,653 @@
+use std::ops::Range;
+
+use crate::errors::IrError;
+use crate::ir::ops::{LoopDecrement, Op, OpType};
+use crate::ir::opt_info::{BlockInfo, Cell, CellAccess};
+use crate::ir::serialize::OpenBlocks;
+use crate::parser::Program;
+
+/// Parse program from the output of [Program::dump]
+///
+/// The children of blocks are detected by their indentation. Spans are optional and default
+/// to `0..1`. Without spans the indentation is two spaces per level, with spans the ops have
+/// to be aligned like in the dump.
+///
+/// Cell access infos of blocks can only be restored from dumps in debug mode. Other blocks
+/// with cell access infos are loaded without them.
+pub fn parse_dump(source: &str) -> Result<Program, IrError> {
+    let mut blocks = OpenBlocks::new();
+
+    // Ignore indentation shared by all lines
+    let margin = source
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.len() - line.trim_start().len())
+        .min()
+        .unwrap_or_default();
+
+    for (index, line) in source.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let line = Line::new(line, margin, index + 1)?;
+
+        while blocks.depth() > line.depth {
+            blocks.close();
+        }
+
+        if blocks.depth() < line.depth {
+            return Err(line.error("Unexpected indentation".to_owned()));
+        }
+
+        let op_type = line.parse_op()?;
+
+        blocks.push(Op {
+            op_type,
+            span: line.span.clone(),
+        });
+    }
+
+    while blocks.close() {}
+
+    Ok(blocks.finish())
+}
+
+struct Line<'a> {
+    number: usize,
+    span: Range<usize>,
+    depth: usize,
+    body: &'a str,
+}
+
+impl<'a> Line<'a> {
+    fn new(line: &'a str, margin: usize, number: usize) -> Result<Line<'a>, IrError> {
+        let mut line = Line {
+            number,
+            span: 0..1,
+            depth: 0,
+            body: line,
+        };
+
+        let mut rest = line.body;
+        let mut column = 0;
+        let mut base = margin;
+
+        if rest.trim_start().starts_with("0x") {
+            let start = rest.len() - rest.trim_start().len();
+            let len = rest[start..]
+                .find(char::is_whitespace)
+                .unwrap_or(rest.len() - start);
+
+            line.span = line.parse_span(&rest[start..start + len])?;
+
+            column = start + len;
+            base = start + 16.max(len + 1);
+            rest = &rest[column..];
+        }
+
+        let indent = rest.len() - rest.trim_start().len();
+        column += indent;
+        rest = &rest[indent..];
+
+        let first = rest.split_whitespace().next().unwrap_or_default();
+
+        if first.parse::<isize>().is_ok() {
+            // Debug dumps contain the pointer offset and mark each level with a |
+            rest = rest[first.len()..].trim_start();
+            line.depth = rest
+                .chars()
+                .take_while(|c| *c == '|' || *c == ' ')
+                .filter(|c| *c == '|')
+                .count();
+            rest = rest.trim_start_matches(['|', ' ']);
+        } else {
+            line.depth = column.saturating_sub(base) / 2;
+        }
+
+        line.body = rest.trim_end();
+
+        Ok(line)
+    }
+
+    fn error(&self, message: String) -> IrError {
+        IrError::InvalidLine {
+            line: self.number,
+            message,
+        }
+    }
+
+    fn parse_span(&self, text: &str) -> Result<Range<usize>, IrError> {
+        let parse = |value: &str| {
+            value
+                .strip_prefix("0x")
+                .and_then(|value| usize::from_str_radix(value, 16).ok())
+        };
+
+        match text.split_once("..") {
+            Some((start, end)) => match (parse(start), parse(end)) {
+                // Dumps contain the inclusive end of the span
+                (Some(start), Some(end)) if start <= end => Ok(start..end + 1),
+                _ => Err(self.error(format!("Invalid span {}", text))),
+            },
+            None => Err(self.error(format!("Invalid span {}", text))),
+        }
+    }
+
+    fn parse_op(&self) -> Result<OpType, IrError> {
+        let name_len = self
+            .body
+            .find(|c: char| !c.is_ascii_uppercase() && c != '_')
+            .unwrap_or(self.body.len());
+        let name = &self.body[..name_len];
+
+        if name == "PUT" && self.body[name_len..].trim_start().starts_with("STRING") {
+            return Ok(OpType::PutString(self.parse_string()?));
+        }
+
+        let mut tokens = Tokens {
+            line: self,
+            tokens: tokenize(&self.body[name_len..]),
+            index: 0,
+        };
+
+        let op_type = match name {
+            "START" => OpType::Start,
+            "INC_PTR" => OpType::IncPtr(tokens.number()?),
+            "DEC_PTR" => OpType::DecPtr(tokens.number()?),
+            "INC" => {
+                let value = tokens.number()?;
+                OpType::Inc(tokens.field("offset")?, value)
+            }
+            "DEC" => {
+                let value = tokens.number()?;
+                OpType::Dec(tokens.field("offset")?, value)
+            }
+            "SET" => {
+                let value = tokens.number()?;
+                OpType::Set(tokens.field("offset")?, value)
+            }
+            "ADD" | "NZ_ADD" | "SUB" | "NZ_SUB" | "MUL" | "NZ_MUL" => {
+                let src_offset = tokens.field("src_offset")?;
+                let dest_offset = tokens.field("dest_offset")?;
+                let multi = tokens.field("multiply")?;
+
+                match name {
+                    "ADD" => OpType::Add(src_offset, dest_offset, multi),
+                    "NZ_ADD" => OpType::NzAdd(src_offset, dest_offset, multi),
+                    "SUB" => OpType::Sub(src_offset, dest_offset, multi),
+                    "NZ_SUB" => OpType::NzSub(src_offset, dest_offset, multi),
+                    "MUL" => OpType::Mul(src_offset, dest_offset, multi),
+                    _ => OpType::NzMul(src_offset, dest_offset, multi),
+                }
+            }
+            "CADD" | "NZ_CADD" | "CSUB" | "NZ_CSUB" => {
+                let src_offset = tokens.field("src_offset")?;
+                let dest_offset = tokens.field("dest_offset")?;
+                let value = tokens.field("value")?;
+
+                match name {
+                    "CADD" => OpType::CAdd(src_offset, dest_offset, value),
+                    "NZ_CADD" => OpType::NzCAdd(src_offset, dest_offset, value),
+                    "CSUB" => OpType::CSub(src_offset, dest_offset, value),
+                    _ => OpType::NzCSub(src_offset, dest_offset, value),
+                }
+            }
+            "MOVE" => OpType::Move(tokens.field("src_offset")?, tokens.field("dest_offset")?),
+            "COPY" => OpType::Copy(tokens.field("src_offset")?, tokens.field("dest_offset")?),
+            "PUT" => OpType::PutChar(tokens.field("offset")?),
+            "GET" => OpType::GetChar(tokens.field("offset")?),
+            "BREAKPOINT" => OpType::Breakpoint,
+            "DLOOP" => OpType::DLoop(vec![], tokens.info()?),
+            "LLOOP" => OpType::LLoop(vec![], tokens.info()?),
+            "ILOOP" => {
+                let step = tokens.field("step")?;
+                let decrement = tokens.decrement()?;
+                OpType::ILoop(vec![], step, decrement, tokens.info()?)
+            }
+            "CLOOP" => {
+                let iterations = tokens.field("iterations")?;
+                let decrement = tokens.decrement()?;
+                OpType::CLoop(vec![], iterations, decrement, tokens.info()?)
+            }
+            "TNZ" => OpType::TNz(vec![], tokens.info()?),
+            "D_TNZ" => {
+                let info = tokens.info()?;
+
+                let end_offset = if tokens.peek().is_some() {
+                    tokens.expect("end")?;
+                    Some(tokens.field("offset")?)
+                } else {
+                    None
+                };
+
+                OpType::DTNz(vec![], end_offset, info)
+            }
+            "S_ZERO" => {
+                let step = tokens.number()?;
+
+                let always = tokens.peek() == Some("always");
+                if always {
+                    tokens.index += 1;
+                }
+
+                OpType::SearchZero(step, always)
+            }
+            "" => return Err(self.error(format!("Expected op instead of {}", self.body))),
+            _ => return Err(self.error(format!("Unknown op {}", name))),
+        };
+
+        if let Some(token) = tokens.peek() {
+            return Err(self.error(format!("Unexpected {}", token)));
+        }
+
+        Ok(op_type)
+    }
+
+    fn parse_string(&self) -> Result<Vec<u8>, IrError> {
+        let (start, end) = match (self.body.find('"'), self.body.rfind('"')) {
+            (Some(start), Some(end)) if start < end => (start, end),
+            _ => return Err(self.error("Expected quoted string".to_owned())),
+        };
+
+        if !self.body[end + 1..].trim().is_empty() {
+            return Err(self.error("Unexpected content after string".to_owned()));
+        }
+
+        let mut bytes = vec![];
+        let mut chars = self.body[start + 1..end].chars();
+
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                let mut buffer = [0; 4];
+                bytes.extend_from_slice(c.encode_utf8(&mut buffer).as_bytes());
+                continue;
+            }
+
+            match chars.next() {
+                Some('n') => bytes.push(b'\n'),
+                Some('r') => bytes.push(b'\r'),
+                Some('t') => bytes.push(b'\t'),
+                Some('\\') => bytes.push(b'\\'),
+                Some('0') if chars.next() == Some('x') => {
+                    let hex: String = chars.by_ref().take(2).collect();
+
+                    match u8::from_str_radix(&hex, 16) {
+                        Ok(value) if hex.len() == 2 => bytes.push(value),
+                        _ => return Err(self.error(format!("Invalid escape \\0x{}", hex))),
+                    }
+                }
+                _ => return Err(self.error("Invalid escape sequence".to_owned())),
+            }
+        }
+
+        Ok(bytes)
+    }
+}
+
+/// Split into words and single punctuation chars
+fn tokenize(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut start = None;
+
+    for (index, c) in text.char_indices() {
+        let punctuation = "[]{}(),:".contains(c);
+
+        if c.is_whitespace() || punctuation {
+            if let Some(start) = start.take() {
+                tokens.push(&text[start..index]);
+            }
+
+            if punctuation {
+                tokens.push(&text[index..index + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(index);
+        }
+    }
+
+    if let Some(start) = start {
+        tokens.push(&text[start..]);
+    }
+
+    tokens
+}
+
+struct Tokens<'a> {
+    line: &'a Line<'a>,
+    tokens: Vec<&'a str>,
+    index: usize,
+}
+
+impl<'a> Tokens<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.index).copied()
+    }
+
+    fn next(&mut self) -> Result<&'a str, IrError> {
+        match self.peek() {
+            Some(token) => {
+                self.index += 1;
+                Ok(token)
+            }
+            None => Err(self.line.error("Unexpected end of line".to_owned())),
+        }
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), IrError> {
+        let token = self.next()?;
+
+        if token == expected {
+            Ok(())
+        } else {
+            Err(self
+                .line
+                .error(format!("Expected {} instead of {}", expected, token)))
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&mut self) -> Result<T, IrError> {
+        let token = self.next()?;
+
+        token
+            .parse()
+            .map_err(|_| self.line.error(format!("Invalid number {}", token)))
+    }
+
+    fn bool(&mut self) -> Result<bool, IrError> {
+        match self.next()? {
+            "true" => Ok(true),
+            "false" => Ok(false),
+            token => Err(self.line.error(format!("Invalid bool {}", token))),
+        }
+    }
+
+    /// Parse value in the form `name: value`
+    fn field<T: std::str::FromStr>(&mut self, name: &str) -> Result<T, IrError> {
+        self.expect(name)?;
+        self.expect(":")?;
+        self.number()
+    }
+
+    fn decrement(&mut self) -> Result<LoopDecrement, IrError> {
+        self.expect("increment")?;
+        self.expect(":")?;
+
+        match self.next()? {
+            "Pre" => Ok(LoopDecrement::Pre),
+            "Post" => Ok(LoopDecrement::Post),
+            "Auto" => Ok(LoopDecrement::Auto),
+            token => Err(self.line.error(format!("Invalid loop decrement {}", token))),
+        }
+    }
+
+    /// Parse block info written by [BlockInfo::asm]
+    fn info(&mut self) -> Result<BlockInfo, IrError> {
+        self.expect("info")?;
+        self.expect(":")?;
+
+        let always_used = self.peek() == Some("always");
+        if always_used {
+            self.index += 1;
+        }
+
+        self.expect("access")?;
+        self.expect(":")?;
+
+        let mut info = match self.next()? {
+            "None" => BlockInfo::new_empty(),
+            "[" => {
+                let mut cell_access = vec![];
+
+                while self.peek() != Some("]") {
+                    if !cell_access.is_empty() {
+                        self.expect(",")?;
+                    }
+
+                    cell_access.push(self.cell_access()?);
+                }
+
+                self.index += 1;
+
+                BlockInfo::new_access(cell_access)
+            }
+            // Only the number of accessed cells is known without debug output
+            "0" => BlockInfo::new_access(vec![]),
+            token => {
+                if token.parse::<usize>().is_err() {
+                    return Err(self.line.error(format!("Invalid block info {}", token)));
+                }
+
+                BlockInfo::new_empty()
+            }
+        };
+
+        info.set_always_used(always_used);
+
+        Ok(info)
+    }
+
+    fn cell_access(&mut self) -> Result<CellAccess, IrError> {
+        self.expect("CellAccess")?;
+        self.expect("{")?;
+
+        let mut access = CellAccess::new_read(0, false, false);
+
+        loop {
+            match self.next()? {
+                "read_before_write" => {
+                    self.expect(":")?;
+                    access.read_before_write = self.bool()?;
+                }
+                "read_after_write" => {
+                    self.expect(":")?;
+                    access.read_after_write = self.bool()?;
+                }
+                "offset" => {
+                    self.expect(":")?;
+                    access.offset = self.number()?;
+                }
+                "value" => {
+                    self.expect(":")?;
+                    access.value = self.cell()?;
+                }
+                token => return Err(self.line.error(format!("Unexpected {}", token))),
+            }
+
+            match self.next()? {
+                "," => continue,
+                "}" => return Ok(access),
+                token => return Err(self.line.error(format!("Unexpected {}", token))),
+            }
+        }
+    }
+
+    fn cell(&mut self) -> Result<Cell, IrError> {
+        Ok(match self.next()? {
+            "Read" => Cell::Read,
+            "Write" => Cell::Write,
+            "NonZero" => Cell::NonZero,
+            "Bool" => Cell::Bool,
+            "Value" => {
+                self.expect("(")?;
+                let value = self.number()?;
+                self.expect(")")?;
+                Cell::Value(value)
+            }
+            "Range" => {
+                self.expect("(")?;
+                let start = self.number()?;
+                self.expect(",")?;
+                let end = self.number()?;
+                self.expect(")")?;
+                Cell::Range(start, end)
+            }
+            token => return Err(self.line.error(format!("Invalid cell value {}", token))),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::IrError;
+    use crate::ir::assembler::parse_dump;
+    use crate::ir::ops::{LoopDecrement, Op, OpType};
+    use crate::ir::opt_info::{BlockInfo, Cell, CellAccess};
+    use crate::parser::Program;
+    use crate::{optimize_with_config, parse, OptimizeConfig};
+
+    fn dump(program: &Program, debug: bool) -> String {
+        let mut output = vec![];
+        program.dump(&mut output, debug).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn round_trip(source: &str, config: &OptimizeConfig) {
+        let mut program = parse(source).unwrap();
+        optimize_with_config(&mut program, config);
+
+        let loaded = parse_dump(&dump(&program, true)).unwrap();
+        assert_eq!(loaded.ops, program.ops);
+
+        // Cell access infos are lost without debug output
+        let loaded = parse_dump(&dump(&program, false)).unwrap();
+        assert_eq!(loaded.get_statistics(), program.get_statistics());
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for source in [
+            include_str!("../../../test_programs/hello_world.bf"),
+            include_str!("../../../test_programs/bottles.bf"),
+            include_str!("../../../test_programs/factor.bf"),
+            include_str!("../../../test_programs/awib.bf"),
+        ] {
+            round_trip(source, &OptimizeConfig::o0());
+            round_trip(source, &OptimizeConfig::o1());
+            round_trip(source, &OptimizeConfig::o2());
+            round_trip(source, &OptimizeConfig::o3());
+        }
+    }
+
+    fn op(op_type: OpType) -> Op {
+        Op {
+            op_type,
+            span: 0..1,
+        }
+    }
+
+    #[test]
+    fn test_without_spans() {
+        let program = parse_dump(
+            "
+            START
+            GET offset: 0
+            ILOOP step: 2 increment: Pre info: always access: None
+              D_TNZ info: access: None end offset:-3
+                S_ZERO -3 always
+              PUT STRING \"a\\\\b\\0x00\\n\"
+            CLOOP iterations: 5 increment: Post info: access: 0
+              ADD src_offset: 0 dest_offset: -1 multiply: 3
+            S_ZERO 2
+            ",
+        )
+        .unwrap();
+
+        let mut info = BlockInfo::new_empty();
+        info.set_always_used(true);
+
+        assert_eq!(
+            program.ops,
+            vec![
+                op(OpType::Start),
+                op(OpType::GetChar(0)),
+                op(OpType::ILoop(
+                    vec![
+                        op(OpType::DTNz(
+                            vec![op(OpType::SearchZero(-3, true))],
+                            Some(-3),
+                            BlockInfo::new_empty()
+                        )),
+                        op(OpType::PutString(b"a\\b\0\n".to_vec())),
+                    ],
+                    2,
+                    LoopDecrement::Pre,
+                    info
+                )),
+                op(OpType::CLoop(
+                    vec![op(OpType::Add(0, -1, 3))],
+                    5,
+                    LoopDecrement::Post,
+                    BlockInfo::new_access(vec![])
+                )),
+                op(OpType::SearchZero(2, false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_debug_info() {
+        let program = parse_dump(
+            "0x1..0x4        0    DLOOP info: access: [CellAccess { read_before_write: true, read_after_write: false, offset: 0, value: Value(0) }, CellAccess { read_before_write: false, read_after_write: true, offset: 1, value: Range(1, 3) }]
+             0x2..0x2        0    | DEC 1 offset: 0",
+        )
+        .unwrap();
+
+        assert_eq!(
+            program.ops,
+            vec![Op {
+                op_type: OpType::DLoop(
+                    vec![Op {
+                        op_type: OpType::Dec(0, 1),
+                        span: 2..3,
+                    }],
+                    BlockInfo::new_access(vec![
+                        CellAccess::new_value(0, 0, true, false),
+                        CellAccess {
+                            read_before_write: false,
+                            read_after_write: true,
+                            offset: 1,
+                            value: Cell::Range(1, 3),
+                        },
+                    ])
+                ),
+                span: 1..5,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_long_span() {
+        let program = Program {
+            ops: vec![Op::d_loop(
+                0x1000000..0x2000000,
+                vec![Op::inc(0x1000001..0x1000002, 1)],
+                BlockInfo::new_empty(),
+            )],
+        };
+
+        assert_eq!(parse_dump(&dump(&program, false)).unwrap().ops, program.ops);
+    }
+
+    #[test]
+    fn test_errors() {
+        for (source, line) in [
+            ("INC 1", 1),
+            ("START\nFOO", 2),
+            ("START\n  INC 1 offset: 0", 2),
+            ("DLOOP info: access: None\n    INC 1 offset: 0", 2),
+            ("PUT STRING \"\\0x4\"", 1),
+            ("0x2..0x1        START", 1),
+            ("S_ZERO 1 never", 1),
+        ] {
+            match parse_dump(source) {
+                Err(IrError::InvalidLine {
+                    line: error_line, ..
+                }) => {
+                    assert_eq!(error_line, line, "{}", source)
+                }
+                _ => panic!("Expected error for {}", source),
+            }
+        }
+    }
+}
@@ -1,13 +1,125 @@
 use crate::ir::opt_info::BlockInfo;
 use std::ops::Range;
+use std::slice;
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Single operation of a program with the span of the source it was created from
+#[derive(Debug)]
 pub struct Op {
+    /// Kind of the operation and its arguments
     pub op_type: OpType,
+
+    /// Byte range of the source the op was created from
     pub span: Range<usize>,
 }
 
+// Clone, compare and drop use an explicit stack to support deeply nested programs
+impl Clone for Op {
+    fn clone(&self) -> Self {
+        let children = match self.op_type.get_children() {
+            Some(children) => children,
+            None => return self.clone_shallow(),
+        };
+
+        let mut stack = vec![(
+            children.iter(),
+            self.clone_shallow(),
+            Vec::with_capacity(children.len()),
+        )];
+
+        loop {
+            let (ops, _, cloned) = stack.last_mut().expect("Stack is not empty");
+
+            match ops.next() {
+                Some(op) => {
+                    if let Some(children) = op.op_type.get_children() {
+                        stack.push((
+                            children.iter(),
+                            op.clone_shallow(),
+                            Vec::with_capacity(children.len()),
+                        ));
+                    } else {
+                        cloned.push(op.clone_shallow());
+                    }
+                }
+                None => {
+                    let (_, mut block, cloned) = stack.pop().expect("Stack is not empty");
+                    *block
+                        .op_type
+                        .get_children_mut()
+                        .expect("Block has children") = cloned;
+
+                    match stack.last_mut() {
+                        Some((_, _, parent)) => parent.push(block),
+                        None => return block,
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl PartialEq for Op {
+    fn eq(&self, other: &Self) -> bool {
+        let mut stack = vec![(slice::from_ref(self).iter(), slice::from_ref(other).iter())];
+
+        while let Some((ops, other_ops)) = stack.last_mut() {
+            match (ops.next(), other_ops.next()) {
+                (Some(op), Some(other_op)) => {
+                    if op.span != other_op.span || !op.op_type.eq_shallow(&other_op.op_type) {
+                        return false;
+                    }
+
+                    if let (Some(children), Some(other_children)) =
+                        (op.op_type.get_children(), other_op.op_type.get_children())
+                    {
+                        stack.push((children.iter(), other_children.iter()));
+                    }
+                }
+                (None, None) => {
+                    stack.pop();
+                }
+                _ => return false,
+            }
+        }
+
+        true
+    }
+}
+
+impl Eq for Op {}
+
+/// Drop ops without recursing into the children of blocks
+pub(crate) fn drop_ops(mut ops: Vec<Op>) {
+    while let Some(mut op) = ops.pop() {
+        if let Some(children) = op.op_type.get_children_mut() {
+            ops.append(children);
+        }
+    }
+}
+
 impl Op {
+    /// Clone the op without the children of blocks
+    fn clone_shallow(&self) -> Op {
+        let op_type = match &self.op_type {
+            OpType::DLoop(_, info) => OpType::DLoop(vec![], info.clone()),
+            OpType::LLoop(_, info) => OpType::LLoop(vec![], info.clone()),
+            OpType::ILoop(_, step, decrement, info) => {
+                OpType::ILoop(vec![], *step, *decrement, info.clone())
+            }
+            OpType::CLoop(_, iterations, decrement, info) => {
+                OpType::CLoop(vec![], *iterations, *decrement, info.clone())
+            }
+            OpType::TNz(_, info) => OpType::TNz(vec![], info.clone()),
+            OpType::DTNz(_, offset, info) => OpType::DTNz(vec![], *offset, info.clone()),
+            op_type => op_type.clone(),
+        };
+
+        Op {
+            op_type,
+            span: self.span.clone(),
+        }
+    }
+    /// Create [OpType::Start] op marking the start of the program
     pub fn start() -> Op {
         Op {
             op_type: OpType::Start,
@@ -15,6 +127,7 @@ impl Op {
         }
     }
 
+    /// Create op moving the heap pointer right by count cells
     pub fn inc_ptr(span: Range<usize>, count: usize) -> Op {
         Op {
             op_type: OpType::IncPtr(count),
@@ -22,6 +135,7 @@ impl Op {
         }
     }
 
+    /// Create op moving the heap pointer left by count cells
     pub fn dec_ptr(span: Range<usize>, count: usize) -> Op {
         Op {
             op_type: OpType::DecPtr(count),
@@ -29,6 +143,7 @@ impl Op {
         }
     }
 
+    /// Create op moving the heap pointer by a signed offset
     pub fn ptr_offset(span: Range<usize>, offset: isize) -> Op {
         Op {
             op_type: OpType::new_ptr_offset(offset),
@@ -36,34 +151,39 @@ impl Op {
         }
     }
 
-    pub fn inc(span: Range<usize>, count: u8) -> Op {
+    /// Create op incrementing the current cell
+    pub fn inc(span: Range<usize>, count: u32) -> Op {
         Op {
             op_type: OpType::Inc(0, count),
             span,
         }
     }
 
-    pub fn inc_with_offset(span: Range<usize>, offset: isize, count: u8) -> Op {
+    /// Create op incrementing the cell at offset
+    pub fn inc_with_offset(span: Range<usize>, offset: isize, count: u32) -> Op {
         Op {
             op_type: OpType::Inc(offset, count),
             span,
         }
     }
 
-    pub fn dec(span: Range<usize>, count: u8) -> Op {
+    /// Create op decrementing the current cell
+    pub fn dec(span: Range<usize>, count: u32) -> Op {
         Op {
             op_type: OpType::Dec(0, count),
             span,
         }
     }
 
-    pub fn dec_with_offset(span: Range<usize>, offset: isize, count: u8) -> Op {
+    /// Create op decrementing the cell at offset
+    pub fn dec_with_offset(span: Range<usize>, offset: isize, count: u32) -> Op {
         Op {
             op_type: OpType::Dec(offset, count),
             span,
         }
     }
 
+    /// Create op copying the source cell into the destination cell
     pub fn copy(span: Range<usize>, src_offset: isize, dest_offset: isize) -> Op {
         Op {
             op_type: OpType::Copy(src_offset, dest_offset),
@@ -71,6 +191,7 @@ impl Op {
         }
     }
 
+    /// Create op moving the source cell into the destination cell
     pub fn _move(span: Range<usize>, src_offset: isize, dest_offset: isize) -> Op {
         Op {
             op_type: OpType::Move(src_offset, dest_offset),
@@ -78,6 +199,7 @@ impl Op {
         }
     }
 
+    /// Create op writing the current cell to the output
     pub fn put_char(span: Range<usize>) -> Op {
         Op {
             op_type: OpType::PutChar(0),
@@ -85,6 +207,7 @@ impl Op {
         }
     }
 
+    /// Create op writing the cell at offset to the output
     pub fn put_char_with_offset(span: Range<usize>, offset: isize) -> Op {
         Op {
             op_type: OpType::PutChar(offset),
@@ -92,6 +215,15 @@ impl Op {
         }
     }
 
+    /// Create op writing a constant string to the output
+    pub fn put_string(span: Range<usize>, value: Vec<u8>) -> Op {
+        Op {
+            op_type: OpType::PutString(value),
+            span,
+        }
+    }
+
+    /// Create op reading from the input into the current cell
     pub fn get_char(span: Range<usize>) -> Op {
         Op {
             op_type: OpType::GetChar(0),
@@ -99,6 +231,7 @@ impl Op {
         }
     }
 
+    /// Create dynamic loop as written in the source
     pub fn d_loop(span: Range<usize>, ops: Vec<Op>, info: BlockInfo) -> Op {
         Op {
             op_type: OpType::DLoop(ops, info),
@@ -106,6 +239,7 @@ impl Op {
         }
     }
 
+    /// Create loop that ends with the heap pointer at the same cell it started with
     pub fn l_loop(span: Range<usize>, ops: Vec<Op>, info: BlockInfo) -> Op {
         Op {
             op_type: OpType::LLoop(ops, info),
@@ -113,17 +247,19 @@ impl Op {
         }
     }
 
-    pub fn i_loop(span: Range<usize>, ops: Vec<Op>, step: u8, info: BlockInfo) -> Op {
+    /// Create loop decrementing the counter by step in each iteration
+    pub fn i_loop(span: Range<usize>, ops: Vec<Op>, step: u32, info: BlockInfo) -> Op {
         Op {
             op_type: OpType::ILoop(ops, step, LoopDecrement::Auto, info),
             span,
         }
     }
 
+    /// Create [Op::i_loop] with explicit decrement position
     pub fn i_loop_with_decrement(
         span: Range<usize>,
         ops: Vec<Op>,
-        step: u8,
+        step: u32,
         decrement: LoopDecrement,
         info: BlockInfo,
     ) -> Op {
@@ -133,17 +269,19 @@ impl Op {
         }
     }
 
-    pub fn c_loop(span: Range<usize>, ops: Vec<Op>, iterations: u8, info: BlockInfo) -> Op {
+    /// Create loop with a known number of iterations
+    pub fn c_loop(span: Range<usize>, ops: Vec<Op>, iterations: u32, info: BlockInfo) -> Op {
         Op {
             op_type: OpType::CLoop(ops, iterations, LoopDecrement::Auto, info),
             span,
         }
     }
 
+    /// Create [Op::c_loop] with explicit decrement position
     pub fn c_loop_with_decrement(
         span: Range<usize>,
         ops: Vec<Op>,
-        iterations: u8,
+        iterations: u32,
         decrement: LoopDecrement,
         info: BlockInfo,
     ) -> Op {
@@ -153,32 +291,36 @@ impl Op {
         }
     }
 
-    pub fn set(span: Range<usize>, value: u8) -> Op {
+    /// Create op setting the current cell
+    pub fn set(span: Range<usize>, value: u32) -> Op {
         Op {
             op_type: OpType::Set(0, value),
             span,
         }
     }
 
-    pub fn set_with_offset(span: Range<usize>, offset: isize, value: u8) -> Op {
+    /// Create op setting the cell at offset
+    pub fn set_with_offset(span: Range<usize>, offset: isize, value: u32) -> Op {
         Op {
             op_type: OpType::Set(offset, value),
             span,
         }
     }
 
-    pub fn add(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op adding multi times the current cell to the cell at offset and clearing it
+    pub fn add(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::Add(0, dest_offset, multi),
             span,
         }
     }
 
+    /// Create op adding multi times the source cell to the destination cell and clearing the source
     pub fn add_with_offset(
         span: Range<usize>,
         src_offset: isize,
         dest_offset: isize,
-        multi: u8,
+        multi: u32,
     ) -> Op {
         Op {
             op_type: OpType::Add(src_offset, dest_offset, multi),
@@ -186,32 +328,36 @@ impl Op {
         }
     }
 
-    pub fn nz_add(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op adding multi times the current cell to the cell at offset
+    pub fn nz_add(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::NzAdd(0, dest_offset, multi),
             span,
         }
     }
 
-    pub fn c_add(span: Range<usize>, dest_offset: isize, value: u8) -> Op {
+    /// Create op adding a constant to the cell at offset and clearing the current cell
+    pub fn c_add(span: Range<usize>, dest_offset: isize, value: u32) -> Op {
         Op {
             op_type: OpType::CAdd(0, dest_offset, value),
             span,
         }
     }
 
-    pub fn nz_c_add(span: Range<usize>, dest_offset: isize, value: u8) -> Op {
+    /// Create op adding a constant to the cell at offset
+    pub fn nz_c_add(span: Range<usize>, dest_offset: isize, value: u32) -> Op {
         Op {
             op_type: OpType::NzCAdd(0, dest_offset, value),
             span,
         }
     }
 
+    /// Create op adding a constant to the destination cell and clearing the source cell
     pub fn c_add_with_offset(
         span: Range<usize>,
         src_offset: isize,
         dest_offset: isize,
-        value: u8,
+        value: u32,
     ) -> Op {
         Op {
             op_type: OpType::CAdd(src_offset, dest_offset, value),
@@ -219,32 +365,36 @@ impl Op {
         }
     }
 
-    pub fn sub(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op subtracting multi times the current cell from the cell at offset and clearing it
+    pub fn sub(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::Sub(0, dest_offset, multi),
             span,
         }
     }
 
-    pub fn c_sub(span: Range<usize>, dest_offset: isize, value: u8) -> Op {
+    /// Create op subtracting a constant from the cell at offset and clearing the current cell
+    pub fn c_sub(span: Range<usize>, dest_offset: isize, value: u32) -> Op {
         Op {
             op_type: OpType::CSub(0, dest_offset, value),
             span,
         }
     }
 
-    pub fn nz_sub(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op subtracting multi times the current cell from the cell at offset
+    pub fn nz_sub(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::NzSub(0, dest_offset, multi),
             span,
         }
     }
 
+    /// Create op subtracting multi times the source cell from the destination cell
     pub fn nz_sub_with_offset(
         span: Range<usize>,
         src_offset: isize,
         dest_offset: isize,
-        multi: u8,
+        multi: u32,
     ) -> Op {
         Op {
             op_type: OpType::NzSub(src_offset, dest_offset, multi),
@@ -252,27 +402,31 @@ impl Op {
         }
     }
 
-    pub fn nz_c_sub(span: Range<usize>, dest_offset: isize, value: u8) -> Op {
+    /// Create op subtracting a constant from the cell at offset
+    pub fn nz_c_sub(span: Range<usize>, dest_offset: isize, value: u32) -> Op {
         Op {
             op_type: OpType::NzCSub(0, dest_offset, value),
             span,
         }
     }
 
-    pub fn mul(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op setting the cell at offset to multi times the current cell and clearing it
+    pub fn mul(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::Mul(0, dest_offset, multi),
             span,
         }
     }
 
-    pub fn nz_mul(span: Range<usize>, dest_offset: isize, multi: u8) -> Op {
+    /// Create op setting the cell at offset to multi times the current cell
+    pub fn nz_mul(span: Range<usize>, dest_offset: isize, multi: u32) -> Op {
         Op {
             op_type: OpType::NzMul(0, dest_offset, multi),
             span,
         }
     }
 
+    /// Create condition running the ops once if the current cell isn't zero
     pub fn t_nz(span: Range<usize>, ops: Vec<Op>, info: BlockInfo) -> Op {
         Op {
             op_type: OpType::TNz(ops, info),
@@ -280,6 +434,7 @@ impl Op {
         }
     }
 
+    /// Create [Op::t_nz] that may end at a different cell
     pub fn d_t_nz(
         span: Range<usize>,
         ops: Vec<Op>,
@@ -292,6 +447,15 @@ impl Op {
         }
     }
 
+    /// Create debug breakpoint
+    pub fn breakpoint(span: Range<usize>) -> Op {
+        Op {
+            op_type: OpType::Breakpoint,
+            span,
+        }
+    }
+
+    /// Create op moving the heap pointer by step until it points to a zero cell
     pub fn search_zero(span: Range<usize>, step: isize) -> Op {
         Op {
             op_type: OpType::SearchZero(step, false),
@@ -314,6 +478,108 @@ pub enum LoopDecrement {
     Auto,
 }
 
+/// Width of a single heap cell
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum CellSize {
+    /// 8 bit cells wrapping at 255
+    #[default]
+    Bits8,
+
+    /// 16 bit cells wrapping at 65535
+    Bits16,
+
+    /// 32 bit cells wrapping at 4294967295
+    Bits32,
+}
+
+impl CellSize {
+    /// Get cell size from number of bits
+    pub fn from_bits(bits: u32) -> Option<CellSize> {
+        match bits {
+            8 => Some(CellSize::Bits8),
+            16 => Some(CellSize::Bits16),
+            32 => Some(CellSize::Bits32),
+            _ => None,
+        }
+    }
+
+    /// Number of bits in a cell
+    pub fn bits(&self) -> u32 {
+        match self {
+            CellSize::Bits8 => 8,
+            CellSize::Bits16 => 16,
+            CellSize::Bits32 => 32,
+        }
+    }
+
+    /// Number of bytes a cell occupies on the heap
+    pub fn bytes(&self) -> usize {
+        self.bits() as usize / 8
+    }
+
+    /// Largest value a cell can hold
+    pub fn max_value(&self) -> u32 {
+        match self {
+            CellSize::Bits8 => u8::MAX as u32,
+            CellSize::Bits16 => u16::MAX as u32,
+            CellSize::Bits32 => u32::MAX,
+        }
+    }
+
+    /// Truncate value to the cell width
+    pub fn wrap(&self, value: u32) -> u32 {
+        value & self.max_value()
+    }
+
+    /// Add values wrapping around at the cell width
+    pub fn wrapping_add(&self, v1: u32, v2: u32) -> u32 {
+        self.wrap(v1.wrapping_add(v2))
+    }
+
+    /// Subtract values wrapping around at the cell width
+    pub fn wrapping_sub(&self, v1: u32, v2: u32) -> u32 {
+        self.wrap(v1.wrapping_sub(v2))
+    }
+
+    /// Multiply values wrapping around at the cell width
+    pub fn wrapping_mul(&self, v1: u32, v2: u32) -> u32 {
+        self.wrap(v1.wrapping_mul(v2))
+    }
+}
+
+/// Value stored by `,` when the input reached end of file
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum EofMode {
+    /// Leave the cell unchanged
+    Unchanged,
+
+    /// Set the cell to 0
+    #[default]
+    Zero,
+
+    /// Set the cell to the maximum cell value (255 for 8 bit cells)
+    Max,
+}
+
+impl EofMode {
+    /// Value written to the cell on end of file or None if the cell is left unchanged
+    pub fn value(&self, cell_size: CellSize) -> Option<u32> {
+        match self {
+            EofMode::Unchanged => None,
+            EofMode::Zero => Some(0),
+            EofMode::Max => Some(cell_size.max_value()),
+        }
+    }
+
+    /// Returns true if reading a char always overwrites the cell
+    pub fn overwrites_cell(&self) -> bool {
+        *self != EofMode::Unchanged
+    }
+}
+
+/// Kind of an op with its arguments
+///
+/// Offsets are relative to the heap pointer. Loops and conditions hold the ops of their body.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub enum OpType {
     /// Start of application
@@ -327,43 +593,43 @@ pub enum OpType {
     DecPtr(usize),
 
     /// Increment cell at offset
-    Inc(isize, u8),
+    Inc(isize, u32),
 
     /// Decrement cell at offset
-    Dec(isize, u8),
+    Dec(isize, u32),
 
     /// Set cell at offset
-    Set(isize, u8),
+    Set(isize, u32),
 
     /// Add current value to value at offset and reset current value to 0
-    Add(isize, isize, u8),
+    Add(isize, isize, u32),
 
     /// Add current value to value at offset without setting current value to 0
-    NzAdd(isize, isize, u8),
+    NzAdd(isize, isize, u32),
 
     /// Add constant value to value at offset and reset current value to 0
-    CAdd(isize, isize, u8),
+    CAdd(isize, isize, u32),
 
     /// Add constant value to value at offset without setting current value to 0
-    NzCAdd(isize, isize, u8),
+    NzCAdd(isize, isize, u32),
 
     /// Subtract current value to value at offset and reset current value to 0
-    Sub(isize, isize, u8),
+    Sub(isize, isize, u32),
 
     /// Subtract current value to value at offset without setting current value to 0
-    NzSub(isize, isize, u8),
+    NzSub(isize, isize, u32),
 
     /// Subtract constant value to value at offset and reset current value to 0
-    CSub(isize, isize, u8),
+    CSub(isize, isize, u32),
 
     /// Subtract constant value to value at offset without setting current value to 0
-    NzCSub(isize, isize, u8),
+    NzCSub(isize, isize, u32),
 
     /// Multiply current value to value at offset and reset current value to 0
-    Mul(isize, isize, u8),
+    Mul(isize, isize, u32),
 
     /// Multiply current value to value at offset without setting current value to 0
-    NzMul(isize, isize, u8),
+    NzMul(isize, isize, u32),
 
     /// Move value to value at offset and reset current value to 0
     Move(isize, isize),
@@ -387,10 +653,10 @@ pub enum OpType {
     LLoop(Vec<Op>, BlockInfo),
 
     /// Loop with an iterator variable and know steps per iteration
-    ILoop(Vec<Op>, u8, LoopDecrement, BlockInfo),
+    ILoop(Vec<Op>, u32, LoopDecrement, BlockInfo),
 
     /// Loop with compile time known iteration count
-    CLoop(Vec<Op>, u8, LoopDecrement, BlockInfo),
+    CLoop(Vec<Op>, u32, LoopDecrement, BlockInfo),
 
     /// Test if not zero.
     ///
@@ -405,6 +671,11 @@ pub enum OpType {
 
     /// Move heap pointer to first cell containing zero based on step
     SearchZero(isize, bool),
+
+    /// Debug breakpoint reporting the heap pointer and the surrounding cells
+    ///
+    /// Optimization passes treat it as a barrier that reads all cells and the heap pointer
+    Breakpoint,
 }
 
 impl OpType {
@@ -417,6 +688,7 @@ impl OpType {
         }
     }
 
+    /// Test for heap pointer increments and decrements
     pub fn is_ptr_inc_or_dec(&self) -> bool {
         matches!(self, OpType::DecPtr(_) | OpType::IncPtr(_))
     }
@@ -503,6 +775,28 @@ impl OpType {
         }
     }
 
+    /// Compare the op types without the children of blocks
+    fn eq_shallow(&self, other: &OpType) -> bool {
+        match (self, other) {
+            (OpType::DLoop(_, info), OpType::DLoop(_, other_info))
+            | (OpType::LLoop(_, info), OpType::LLoop(_, other_info))
+            | (OpType::TNz(_, info), OpType::TNz(_, other_info)) => info == other_info,
+            (
+                OpType::ILoop(_, value, decrement, info),
+                OpType::ILoop(_, other_value, other_decrement, other_info),
+            )
+            | (
+                OpType::CLoop(_, value, decrement, info),
+                OpType::CLoop(_, other_value, other_decrement, other_info),
+            ) => value == other_value && decrement == other_decrement && info == other_info,
+            (OpType::DTNz(_, offset, info), OpType::DTNz(_, other_offset, other_info)) => {
+                offset == other_offset && info == other_info
+            }
+            _ => self == other,
+        }
+    }
+
+    /// Get ops of the body for loops and conditions
     pub fn get_children(&self) -> Option<&Vec<Op>> {
         match self {
             OpType::DLoop(children, ..)
@@ -515,6 +809,7 @@ impl OpType {
         }
     }
 
+    /// Get mutable ops of the body for loops and conditions
     pub fn get_children_mut(&mut self) -> Option<&mut Vec<Op>> {
         match self {
             OpType::DLoop(children, ..)
@@ -527,6 +822,7 @@ impl OpType {
         }
     }
 
+    /// Get optimizer information for loops and conditions
     pub fn get_block_info(&self) -> Option<&BlockInfo> {
         match self {
             OpType::DLoop(_, info)
@@ -539,6 +835,7 @@ impl OpType {
         }
     }
 
+    /// Get mutable optimizer information for loops and conditions
     pub fn get_block_info_mut(&mut self) -> Option<&mut BlockInfo> {
         match self {
             OpType::DLoop(_, info)
@@ -551,6 +848,7 @@ impl OpType {
         }
     }
 
+    /// Test if the op might modify the cell at offset
     pub fn is_possible_write(&self, test_offset: isize) -> bool {
         match self {
             OpType::Inc(offset, _)
@@ -571,9 +869,11 @@ impl OpType {
             | OpType::Move(src_offset, dest_offset) => {
                 *src_offset == test_offset || *dest_offset == test_offset
             }
-            OpType::PutString(_) | OpType::PutChar(_) | OpType::IncPtr(_) | OpType::DecPtr(_) => {
-                false
-            }
+            OpType::PutString(_)
+            | OpType::PutChar(_)
+            | OpType::IncPtr(_)
+            | OpType::DecPtr(_)
+            | OpType::Breakpoint => false,
             OpType::Start | OpType::SearchZero(_, _) | OpType::DLoop(..) | OpType::DTNz(..) => true,
             OpType::LLoop(.., info)
             | OpType::ILoop(.., info)
@@ -1,3 +1,4 @@
+/// Information about a loop or condition collected by the optimizer
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BlockInfo {
     cell_access: Option<Vec<CellAccess>>,
@@ -5,6 +6,7 @@ pub struct BlockInfo {
 }
 
 impl BlockInfo {
+    /// Create info without known cell accesses
     pub fn new_empty() -> BlockInfo {
         BlockInfo {
             cell_access: None,
@@ -12,6 +14,7 @@ impl BlockInfo {
         }
     }
 
+    /// Create info with known cell accesses of the body
     pub fn new_access(cell_access: Vec<CellAccess>) -> BlockInfo {
         BlockInfo {
             cell_access: Some(cell_access),
@@ -19,26 +22,32 @@ impl BlockInfo {
         }
     }
 
+    /// Test if the cell accesses of the body are known
     pub fn has_cell_access(&self) -> bool {
         self.cell_access.is_some()
     }
 
+    /// Get the cell accesses of the body if they are known
     pub fn cell_access(&self) -> Option<&Vec<CellAccess>> {
         self.cell_access.as_ref()
     }
 
+    /// Set the known cell accesses of the body
     pub fn set_cell_access(&mut self, cell_access: Vec<CellAccess>) {
         self.cell_access = Some(cell_access);
     }
 
+    /// Test if the block is known to run at least once
     pub fn always_used(&self) -> bool {
         self.allways_used
     }
 
+    /// Mark the block as known to run at least once
     pub fn set_always_used(&mut self, always_used: bool) {
         self.allways_used = always_used;
     }
 
+    /// Get the inferred content of the cell at offset
     pub fn get_access_value(&self, offset: isize) -> Option<Cell> {
         if let Some(cell_access) = &self.cell_access {
             for cell in cell_access {
@@ -50,6 +59,7 @@ impl BlockInfo {
         None
     }
 
+    /// Get the access to the cell at offset
     pub fn get_access(&self, offset: isize) -> Option<&CellAccess> {
         if let Some(cell_access) = &self.cell_access {
             for cell in cell_access {
@@ -61,6 +71,7 @@ impl BlockInfo {
         None
     }
 
+    /// Test if the body might modify the cell at offset
     pub fn was_cell_written(&self, offset: isize) -> bool {
         if let Some(cell_access) = &self.cell_access {
             for cell in cell_access {
@@ -73,6 +84,7 @@ impl BlockInfo {
         false
     }
 
+    /// Test if the body accesses the cell at offset
     pub fn was_cell_accessed(&self, offset: isize) -> bool {
         if let Some(cell_access) = &self.cell_access {
             for cell in cell_access {
@@ -85,6 +97,7 @@ impl BlockInfo {
         false
     }
 
+    /// Format info for [crate::Program::dump]
     pub fn asm(&self, debug: bool) -> String {
         let cell_access = if let Some(cell_access) = self.cell_access() {
             if debug {
@@ -120,17 +133,19 @@ pub enum Cell {
     Bool,
 
     /// Cell value is known
-    Value(u8),
+    Value(u32),
 
     /// Range of cell value is known
-    Range(u8, u8),
+    Range(u32, u32),
 }
 
 impl Cell {
+    /// Test if the cell was only read
     pub fn is_read(&self) -> bool {
         matches!(self, Cell::Read)
     }
 
+    /// Test if the cell might have been modified
     pub fn is_write(&self) -> bool {
         !self.is_read()
     }
@@ -153,6 +168,7 @@ pub struct CellAccess {
 }
 
 impl CellAccess {
+    /// Create access that only reads the cell
     pub fn new_read(offset: isize, read_before_write: bool, read_after_write: bool) -> CellAccess {
         CellAccess {
             read_before_write,
@@ -162,6 +178,7 @@ impl CellAccess {
         }
     }
 
+    /// Create access that modifies the cell to an unknown value
     pub fn new_write(offset: isize, read_before_write: bool, read_after_write: bool) -> CellAccess {
         CellAccess {
             read_before_write,
@@ -171,9 +188,10 @@ impl CellAccess {
         }
     }
 
+    /// Create access that sets the cell to a known value
     pub fn new_value(
         offset: isize,
-        value: u8,
+        value: u32,
         read_before_write: bool,
         read_after_write: bool,
     ) -> CellAccess {
@@ -185,10 +203,12 @@ impl CellAccess {
         }
     }
 
+    /// Test if the cell was read at any point
     pub fn has_read(&self) -> bool {
         self.read_before_write || self.read_after_write || self.value.is_read()
     }
 
+    /// Record access to the cell at offset
     pub fn add(cells: &mut Vec<CellAccess>, offset: isize, value: Cell) {
         for exiting_cell in cells.iter_mut() {
             if exiting_cell.offset == offset {
@@ -211,6 +231,7 @@ impl CellAccess {
         });
     }
 
+    /// Record access to the cell at offset that also reads it
     pub fn add_with_read(cells: &mut Vec<CellAccess>, offset: isize, value: Cell) {
         for exiting_cell in cells.iter_mut() {
             if exiting_cell.offset == offset {
@@ -232,6 +253,7 @@ impl CellAccess {
         });
     }
 
+    /// Record access to the cell at offset that only happens on some paths
     pub fn add_conditional(
         cells: &mut Vec<CellAccess>,
         offset: isize,
@@ -367,6 +389,7 @@ impl CellAccess {
         });
     }
 
+    /// Record access to the cell at offset found while scanning backwards
     pub fn add_backward(cells: &mut Vec<CellAccess>, offset: isize, value: Cell) {
         for exiting_cell in cells.iter_mut() {
             if exiting_cell.offset == offset && !matches!(exiting_cell.value, Cell::Read) {
@@ -382,10 +405,12 @@ impl CellAccess {
         });
     }
 
+    /// Get the inferred content of the cell at offset
     pub fn get(cells: &[CellAccess], offset: isize) -> Option<Cell> {
         cells.iter().find(|c| c.offset == offset).map(|a| a.value)
     }
 
+    /// Test if the cell at offset might have been modified
     pub fn was_written(cells: &[CellAccess], offset: isize) -> bool {
         for cell in cells {
             if cell.offset == offset {
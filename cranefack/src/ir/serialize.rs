@@ -0,0 +1,1209 @@
+use std::fmt::Write as FmtWrite;
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::slice::Iter;
+
+use crate::errors::IrError;
+use crate::ir::ops::{CellSize, EofMode, LoopDecrement, Op, OpType};
+use crate::ir::opt_info::{BlockInfo, Cell, CellAccess};
+use crate::optimizations::OptimizeConfig;
+use crate::parser::Program;
+
+/// Version of the serialization format
+///
+/// Must be incremented on every incompatible change of the binary or text format.
+pub const IR_VERSION: u32 = 2;
+
+const BINARY_MAGIC: &[u8; 4] = b"CFIR";
+const TEXT_MAGIC: &str = "cranefack-ir";
+
+/// Marks the end of a block in the binary format
+const TAG_END: u8 = 0xff;
+
+/// Serialization format of a program
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum IrFormat {
+    /// Compact binary format
+    Binary,
+
+    /// Line based text format with one op per line
+    Text,
+}
+
+impl Program {
+    /// Serialize program including the optimization infos of all blocks
+    ///
+    /// The cell size, EOF mode and wrapping behavior of the config the program was optimized with
+    /// are stored in the header because the optimized ops are only valid for them.
+    pub fn save<W: Write>(
+        &self,
+        mut output: W,
+        format: IrFormat,
+        config: &OptimizeConfig,
+    ) -> Result<(), IrError> {
+        let data = match format {
+            IrFormat::Binary => encode_binary(&self.ops, config),
+            IrFormat::Text => encode_text(&self.ops, config).into_bytes(),
+        };
+
+        output.write_all(&data).map_err(IrError::IoError)
+    }
+
+    /// Load a program serialized with [Program::save]
+    ///
+    /// The format is detected automatically.
+    /// The cell size, EOF mode and wrapping behavior stored in the header replace the values
+    /// of the config so the program is executed with the settings it was optimized for.
+    pub fn load<R: Read>(mut input: R, config: &mut OptimizeConfig) -> Result<Program, IrError> {
+        let mut data = vec![];
+        input.read_to_end(&mut data).map_err(IrError::IoError)?;
+
+        if data.starts_with(BINARY_MAGIC) {
+            decode_binary(&data, config)
+        } else if data.starts_with(TEXT_MAGIC.as_bytes()) {
+            let text = std::str::from_utf8(&data).map_err(|_| IrError::InvalidLine {
+                line: 1,
+                message: "Text IR isn't valid UTF-8".to_owned(),
+            })?;
+            decode_text(text, config)
+        } else {
+            Err(IrError::UnknownFormat)
+        }
+    }
+
+    /// Check if data starts with the header of a serialized program
+    pub fn is_ir(data: &[u8]) -> bool {
+        data.starts_with(BINARY_MAGIC) || data.starts_with(TEXT_MAGIC.as_bytes())
+    }
+}
+
+fn op_tag(op_type: &OpType) -> u8 {
+    match op_type {
+        OpType::Start => 0,
+        OpType::IncPtr(_) => 1,
+        OpType::DecPtr(_) => 2,
+        OpType::Inc(..) => 3,
+        OpType::Dec(..) => 4,
+        OpType::Set(..) => 5,
+        OpType::Add(..) => 6,
+        OpType::NzAdd(..) => 7,
+        OpType::CAdd(..) => 8,
+        OpType::NzCAdd(..) => 9,
+        OpType::Sub(..) => 10,
+        OpType::NzSub(..) => 11,
+        OpType::CSub(..) => 12,
+        OpType::NzCSub(..) => 13,
+        OpType::Mul(..) => 14,
+        OpType::NzMul(..) => 15,
+        OpType::Move(..) => 16,
+        OpType::Copy(..) => 17,
+        OpType::PutChar(_) => 18,
+        OpType::PutString(_) => 19,
+        OpType::GetChar(_) => 20,
+        OpType::DLoop(..) => 21,
+        OpType::LLoop(..) => 22,
+        OpType::ILoop(..) => 23,
+        OpType::CLoop(..) => 24,
+        OpType::TNz(..) => 25,
+        OpType::DTNz(..) => 26,
+        OpType::SearchZero(..) => 27,
+        OpType::Breakpoint => 28,
+    }
+}
+
+/// Names of the ops in the text format indexed by their tag
+const TEXT_NAMES: [&str; 29] = [
+    "start",
+    "inc_ptr",
+    "dec_ptr",
+    "inc",
+    "dec",
+    "set",
+    "add",
+    "nz_add",
+    "c_add",
+    "nz_c_add",
+    "sub",
+    "nz_sub",
+    "c_sub",
+    "nz_c_sub",
+    "mul",
+    "nz_mul",
+    "move",
+    "copy",
+    "put_char",
+    "put_string",
+    "get_char",
+    "d_loop",
+    "l_loop",
+    "i_loop",
+    "c_loop",
+    "tnz",
+    "d_tnz",
+    "search_zero",
+    "breakpoint",
+];
+
+/// Arguments of an op without its children and block info
+enum Arg {
+    Usize(usize),
+    Isize(isize),
+    U32(u32),
+    Bool(bool),
+    Bytes(Vec<u8>),
+    Decrement(LoopDecrement),
+    OptionalIsize(Option<isize>),
+}
+
+fn op_args(op_type: &OpType) -> Vec<Arg> {
+    match op_type {
+        OpType::Start | OpType::Breakpoint => vec![],
+        OpType::IncPtr(count) | OpType::DecPtr(count) => vec![Arg::Usize(*count)],
+        OpType::Inc(offset, value) | OpType::Dec(offset, value) | OpType::Set(offset, value) => {
+            vec![Arg::Isize(*offset), Arg::U32(*value)]
+        }
+        OpType::Add(src, dest, value)
+        | OpType::NzAdd(src, dest, value)
+        | OpType::CAdd(src, dest, value)
+        | OpType::NzCAdd(src, dest, value)
+        | OpType::Sub(src, dest, value)
+        | OpType::NzSub(src, dest, value)
+        | OpType::CSub(src, dest, value)
+        | OpType::NzCSub(src, dest, value)
+        | OpType::Mul(src, dest, value)
+        | OpType::NzMul(src, dest, value) => {
+            vec![Arg::Isize(*src), Arg::Isize(*dest), Arg::U32(*value)]
+        }
+        OpType::Move(src, dest) | OpType::Copy(src, dest) => {
+            vec![Arg::Isize(*src), Arg::Isize(*dest)]
+        }
+        OpType::PutChar(offset) | OpType::GetChar(offset) => vec![Arg::Isize(*offset)],
+        OpType::PutString(array) => vec![Arg::Bytes(array.clone())],
+        OpType::DLoop(..) | OpType::LLoop(..) | OpType::TNz(..) => vec![],
+        OpType::ILoop(_, value, decrement, _) | OpType::CLoop(_, value, decrement, _) => {
+            vec![Arg::U32(*value), Arg::Decrement(*decrement)]
+        }
+        OpType::DTNz(_, end_offset, _) => vec![Arg::OptionalIsize(*end_offset)],
+        OpType::SearchZero(step, always) => vec![Arg::Isize(*step), Arg::Bool(*always)],
+    }
+}
+
+fn block_info(op_type: &OpType) -> Option<&BlockInfo> {
+    match op_type {
+        OpType::DLoop(_, info)
+        | OpType::LLoop(_, info)
+        | OpType::ILoop(_, _, _, info)
+        | OpType::CLoop(_, _, _, info)
+        | OpType::TNz(_, info)
+        | OpType::DTNz(_, _, info) => Some(info),
+        _ => None,
+    }
+}
+
+/// Build op type from its tag, arguments and block info
+///
+/// Children of blocks are added after they have been decoded.
+fn build_op_type<A: ArgReader>(tag: u8, args: &mut A) -> Result<OpType, IrError> {
+    Ok(match tag {
+        0 => OpType::Start,
+        1 => OpType::IncPtr(args.usize()?),
+        2 => OpType::DecPtr(args.usize()?),
+        3 => OpType::Inc(args.isize()?, args.u32()?),
+        4 => OpType::Dec(args.isize()?, args.u32()?),
+        5 => OpType::Set(args.isize()?, args.u32()?),
+        6 => OpType::Add(args.isize()?, args.isize()?, args.u32()?),
+        7 => OpType::NzAdd(args.isize()?, args.isize()?, args.u32()?),
+        8 => OpType::CAdd(args.isize()?, args.isize()?, args.u32()?),
+        9 => OpType::NzCAdd(args.isize()?, args.isize()?, args.u32()?),
+        10 => OpType::Sub(args.isize()?, args.isize()?, args.u32()?),
+        11 => OpType::NzSub(args.isize()?, args.isize()?, args.u32()?),
+        12 => OpType::CSub(args.isize()?, args.isize()?, args.u32()?),
+        13 => OpType::NzCSub(args.isize()?, args.isize()?, args.u32()?),
+        14 => OpType::Mul(args.isize()?, args.isize()?, args.u32()?),
+        15 => OpType::NzMul(args.isize()?, args.isize()?, args.u32()?),
+        16 => OpType::Move(args.isize()?, args.isize()?),
+        17 => OpType::Copy(args.isize()?, args.isize()?),
+        18 => OpType::PutChar(args.isize()?),
+        19 => OpType::PutString(args.bytes()?),
+        20 => OpType::GetChar(args.isize()?),
+        21 => OpType::DLoop(vec![], args.block_info()?),
+        22 => OpType::LLoop(vec![], args.block_info()?),
+        23 => OpType::ILoop(vec![], args.u32()?, args.decrement()?, args.block_info()?),
+        24 => OpType::CLoop(vec![], args.u32()?, args.decrement()?, args.block_info()?),
+        25 => OpType::TNz(vec![], args.block_info()?),
+        26 => OpType::DTNz(vec![], args.optional_isize()?, args.block_info()?),
+        27 => OpType::SearchZero(args.isize()?, args.bool()?),
+        28 => OpType::Breakpoint,
+        _ => return Err(args.error(format!("Unknown op {}", tag))),
+    })
+}
+
+/// Source of op arguments shared by the binary and text decoder
+trait ArgReader {
+    fn usize(&mut self) -> Result<usize, IrError>;
+    fn isize(&mut self) -> Result<isize, IrError>;
+    fn u32(&mut self) -> Result<u32, IrError>;
+    fn bool(&mut self) -> Result<bool, IrError>;
+    fn bytes(&mut self) -> Result<Vec<u8>, IrError>;
+    fn decrement(&mut self) -> Result<LoopDecrement, IrError>;
+    fn optional_isize(&mut self) -> Result<Option<isize>, IrError>;
+    fn block_info(&mut self) -> Result<BlockInfo, IrError>;
+    fn error(&self, message: String) -> IrError;
+}
+
+/// Decoded blocks that still wait for their closing marker
+pub(super) struct OpenBlocks {
+    stack: Vec<(Option<Op>, Vec<Op>)>,
+}
+
+impl OpenBlocks {
+    pub(super) fn new() -> OpenBlocks {
+        OpenBlocks {
+            stack: vec![(None, vec![])],
+        }
+    }
+
+    pub(super) fn push(&mut self, op: Op) {
+        if op.op_type.get_children().is_some() {
+            self.stack.push((Some(op), vec![]));
+        } else {
+            self.stack.last_mut().unwrap().1.push(op);
+        }
+    }
+
+    /// Close innermost block and return false if there is no open block left
+    pub(super) fn close(&mut self) -> bool {
+        if self.is_root() {
+            return false;
+        }
+
+        let (op, ops) = self.stack.pop().unwrap();
+        let mut op = op.unwrap();
+        *op.op_type.get_children_mut().unwrap() = ops;
+        self.stack.last_mut().unwrap().1.push(op);
+
+        true
+    }
+
+    /// Nesting depth of the ops added next
+    pub(super) fn depth(&self) -> usize {
+        self.stack.len() - 1
+    }
+
+    pub(super) fn is_root(&self) -> bool {
+        self.depth() == 0
+    }
+
+    pub(super) fn finish(mut self) -> Program {
+        Program {
+            ops: self.stack.pop().unwrap().1,
+        }
+    }
+}
+
+/// Walk ops in order together with their nesting depth
+///
+/// The end of each block is reported as `None` with the depth of the block op.
+fn walk_ops<F>(ops: &[Op], mut func: F)
+where
+    F: FnMut(Option<&Op>, usize),
+{
+    let mut stack: Vec<Iter<Op>> = vec![ops.iter()];
+
+    while let Some(ops) = stack.last_mut() {
+        match ops.next() {
+            Some(op) => {
+                func(Some(op), stack.len() - 1);
+
+                if let Some(children) = op.op_type.get_children() {
+                    stack.push(children.iter());
+                }
+            }
+            None => {
+                stack.pop();
+
+                if !stack.is_empty() {
+                    func(None, stack.len() - 1);
+                }
+            }
+        }
+    }
+}
+
+fn eof_mode_tag(eof_mode: EofMode) -> u8 {
+    match eof_mode {
+        EofMode::Unchanged => 0,
+        EofMode::Zero => 1,
+        EofMode::Max => 2,
+    }
+}
+
+/// Names of the EOF modes in the text format indexed by their tag
+const EOF_MODE_NAMES: [&str; 3] = ["unchanged", "0", "max"];
+
+fn eof_mode_from_tag(tag: u8) -> Option<EofMode> {
+    match tag {
+        0 => Some(EofMode::Unchanged),
+        1 => Some(EofMode::Zero),
+        2 => Some(EofMode::Max),
+        _ => None,
+    }
+}
+
+fn encode_binary(ops: &[Op], config: &OptimizeConfig) -> Vec<u8> {
+    let mut out = BINARY_MAGIC.to_vec();
+    out.extend_from_slice(&IR_VERSION.to_le_bytes());
+    out.push(config.cell_size.bits() as u8);
+    out.push(eof_mode_tag(config.eof_mode));
+    out.push(config.wrapping_is_ub as u8);
+
+    walk_ops(ops, |op, _| match op {
+        Some(op) => {
+            out.push(op_tag(&op.op_type));
+            write_varint(&mut out, op.span.start as u64);
+            write_varint(&mut out, (op.span.end - op.span.start) as u64);
+
+            for arg in op_args(&op.op_type) {
+                match arg {
+                    Arg::Usize(value) => write_varint(&mut out, value as u64),
+                    Arg::Isize(value) => write_signed_varint(&mut out, value as i64),
+                    Arg::U32(value) => write_varint(&mut out, value as u64),
+                    Arg::Bool(value) => out.push(value as u8),
+                    Arg::Bytes(bytes) => {
+                        write_varint(&mut out, bytes.len() as u64);
+                        out.extend_from_slice(&bytes);
+                    }
+                    Arg::Decrement(decrement) => out.push(match decrement {
+                        LoopDecrement::Pre => 0,
+                        LoopDecrement::Post => 1,
+                        LoopDecrement::Auto => 2,
+                    }),
+                    Arg::OptionalIsize(value) => match value {
+                        Some(value) => {
+                            out.push(1);
+                            write_signed_varint(&mut out, value as i64);
+                        }
+                        None => out.push(0),
+                    },
+                }
+            }
+
+            if let Some(info) = block_info(&op.op_type) {
+                encode_binary_block_info(&mut out, info);
+            }
+        }
+        None => out.push(TAG_END),
+    });
+
+    out.push(TAG_END);
+
+    out
+}
+
+fn encode_binary_block_info(out: &mut Vec<u8>, info: &BlockInfo) {
+    out.push(info.always_used() as u8 | (info.has_cell_access() as u8) << 1);
+
+    if let Some(cell_access) = info.cell_access() {
+        write_varint(out, cell_access.len() as u64);
+
+        for access in cell_access {
+            write_signed_varint(out, access.offset as i64);
+            out.push(access.read_before_write as u8 | (access.read_after_write as u8) << 1);
+
+            match access.value {
+                Cell::Read => out.push(0),
+                Cell::Write => out.push(1),
+                Cell::NonZero => out.push(2),
+                Cell::Bool => out.push(3),
+                Cell::Value(value) => {
+                    out.push(4);
+                    write_varint(out, value as u64);
+                }
+                Cell::Range(start, end) => {
+                    out.push(5);
+                    write_varint(out, start as u64);
+                    write_varint(out, end as u64);
+                }
+            }
+        }
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed_varint(out: &mut Vec<u8>, value: i64) {
+    write_varint(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct BinaryReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BinaryReader<'a> {
+    fn byte(&mut self) -> Result<u8, IrError> {
+        match self.data.get(self.position) {
+            Some(byte) => {
+                self.position += 1;
+                Ok(*byte)
+            }
+            None => Err(self.error("Unexpected end of data".to_owned())),
+        }
+    }
+
+    fn varint(&mut self) -> Result<u64, IrError> {
+        let mut value = 0;
+        let mut shift = 0;
+
+        loop {
+            let byte = self.byte()?;
+
+            if shift >= 64 {
+                return Err(self.error("Integer overflow".to_owned()));
+            }
+
+            value |= ((byte & 0x7f) as u64) << shift;
+            shift += 7;
+
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn signed_varint(&mut self) -> Result<i64, IrError> {
+        let value = self.varint()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn cell(&mut self) -> Result<Cell, IrError> {
+        Ok(match self.byte()? {
+            0 => Cell::Read,
+            1 => Cell::Write,
+            2 => Cell::NonZero,
+            3 => Cell::Bool,
+            4 => Cell::Value(self.u32()?),
+            5 => Cell::Range(self.u32()?, self.u32()?),
+            value => return Err(self.error(format!("Unknown cell value {}", value))),
+        })
+    }
+}
+
+impl<'a> ArgReader for BinaryReader<'a> {
+    fn usize(&mut self) -> Result<usize, IrError> {
+        let value = self.varint()?;
+        usize::try_from(value).map_err(|_| self.error(format!("Value {} out of range", value)))
+    }
+
+    fn isize(&mut self) -> Result<isize, IrError> {
+        let value = self.signed_varint()?;
+        isize::try_from(value).map_err(|_| self.error(format!("Value {} out of range", value)))
+    }
+
+    fn u32(&mut self) -> Result<u32, IrError> {
+        let value = self.varint()?;
+        u32::try_from(value).map_err(|_| self.error(format!("Value {} out of range", value)))
+    }
+
+    fn bool(&mut self) -> Result<bool, IrError> {
+        match self.byte()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(self.error(format!("Invalid bool {}", value))),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, IrError> {
+        let len = self.usize()?;
+
+        match self
+            .data
+            .get(self.position..self.position.saturating_add(len))
+        {
+            Some(bytes) => {
+                self.position += len;
+                Ok(bytes.to_vec())
+            }
+            None => Err(self.error("Unexpected end of data".to_owned())),
+        }
+    }
+
+    fn decrement(&mut self) -> Result<LoopDecrement, IrError> {
+        match self.byte()? {
+            0 => Ok(LoopDecrement::Pre),
+            1 => Ok(LoopDecrement::Post),
+            2 => Ok(LoopDecrement::Auto),
+            value => Err(self.error(format!("Invalid loop decrement {}", value))),
+        }
+    }
+
+    fn optional_isize(&mut self) -> Result<Option<isize>, IrError> {
+        if self.bool()? {
+            Ok(Some(self.isize()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn block_info(&mut self) -> Result<BlockInfo, IrError> {
+        let flags = self.byte()?;
+
+        let mut info = if flags & 2 != 0 {
+            let count = self.usize()?;
+            let mut cell_access = vec![];
+
+            for _ in 0..count {
+                let offset = self.isize()?;
+                let flags = self.byte()?;
+                let value = self.cell()?;
+
+                cell_access.push(CellAccess {
+                    read_before_write: flags & 1 != 0,
+                    read_after_write: flags & 2 != 0,
+                    offset,
+                    value,
+                });
+            }
+
+            BlockInfo::new_access(cell_access)
+        } else {
+            BlockInfo::new_empty()
+        };
+
+        info.set_always_used(flags & 1 != 0);
+
+        Ok(info)
+    }
+
+    fn error(&self, message: String) -> IrError {
+        IrError::InvalidData {
+            position: self.position,
+            message,
+        }
+    }
+}
+
+fn decode_binary(data: &[u8], config: &mut OptimizeConfig) -> Result<Program, IrError> {
+    let mut reader = BinaryReader {
+        data,
+        position: BINARY_MAGIC.len(),
+    };
+
+    let mut version = [0; 4];
+    for byte in &mut version {
+        *byte = reader.byte()?;
+    }
+
+    let version = u32::from_le_bytes(version);
+    if version != IR_VERSION {
+        return Err(IrError::UnsupportedVersion(version));
+    }
+
+    let bits = reader.byte()?;
+    let cell_size = CellSize::from_bits(bits as u32)
+        .ok_or_else(|| reader.error(format!("Invalid cell size {}", bits)))?;
+
+    let tag = reader.byte()?;
+    let eof_mode =
+        eof_mode_from_tag(tag).ok_or_else(|| reader.error(format!("Invalid EOF mode {}", tag)))?;
+
+    let wrapping_is_ub = reader.bool()?;
+
+    let mut blocks = OpenBlocks::new();
+
+    loop {
+        let tag = reader.byte()?;
+
+        if tag == TAG_END {
+            if !blocks.close() {
+                break;
+            }
+            continue;
+        }
+
+        let start = reader.usize()?;
+        let len = reader.usize()?;
+        let op_type = build_op_type(tag, &mut reader)?;
+
+        blocks.push(Op {
+            op_type,
+            span: start..start.saturating_add(len),
+        });
+    }
+
+    if reader.position != data.len() {
+        return Err(reader.error("Trailing data after end of program".to_owned()));
+    }
+
+    config.cell_size = cell_size;
+    config.eof_mode = eof_mode;
+    config.wrapping_is_ub = wrapping_is_ub;
+
+    Ok(blocks.finish())
+}
+
+fn encode_text(ops: &[Op], config: &OptimizeConfig) -> String {
+    let mut out = format!(
+        "{} {} cell-size:{} eof:{} wrapping-is-ub:{}\n",
+        TEXT_MAGIC,
+        IR_VERSION,
+        config.cell_size.bits(),
+        EOF_MODE_NAMES[eof_mode_tag(config.eof_mode) as usize],
+        config.wrapping_is_ub
+    );
+
+    walk_ops(ops, |op, depth| {
+        for _ in 0..depth {
+            out.push_str("  ");
+        }
+
+        let op = match op {
+            Some(op) => op,
+            None => {
+                out.push_str("}\n");
+                return;
+            }
+        };
+
+        write!(
+            out,
+            "{} {}..{}",
+            TEXT_NAMES[op_tag(&op.op_type) as usize],
+            op.span.start,
+            op.span.end
+        )
+        .unwrap();
+
+        for arg in op_args(&op.op_type) {
+            out.push(' ');
+
+            match arg {
+                Arg::Usize(value) => write!(out, "{}", value).unwrap(),
+                Arg::Isize(value) => write!(out, "{}", value).unwrap(),
+                Arg::U32(value) => write!(out, "{}", value).unwrap(),
+                Arg::Bool(value) => write!(out, "{}", value).unwrap(),
+                Arg::Bytes(bytes) => {
+                    out.push('"');
+                    for byte in bytes {
+                        write!(out, "{:02x}", byte).unwrap();
+                    }
+                    out.push('"');
+                }
+                Arg::Decrement(decrement) => out.push_str(match decrement {
+                    LoopDecrement::Pre => "pre",
+                    LoopDecrement::Post => "post",
+                    LoopDecrement::Auto => "auto",
+                }),
+                Arg::OptionalIsize(Some(value)) => write!(out, "{}", value).unwrap(),
+                Arg::OptionalIsize(None) => out.push('-'),
+            }
+        }
+
+        if let Some(info) = block_info(&op.op_type) {
+            if info.always_used() {
+                out.push_str(" always");
+            }
+
+            match info.cell_access() {
+                Some(cell_access) => {
+                    out.push_str(" access:[");
+
+                    for (index, access) in cell_access.iter().enumerate() {
+                        if index > 0 {
+                            out.push(',');
+                        }
+
+                        write!(out, "{}=", access.offset).unwrap();
+
+                        match access.value {
+                            Cell::Read => out.push('r'),
+                            Cell::Write => out.push('w'),
+                            Cell::NonZero => out.push_str("nz"),
+                            Cell::Bool => out.push_str("bool"),
+                            Cell::Value(value) => write!(out, "{}", value).unwrap(),
+                            Cell::Range(start, end) => write!(out, "{}-{}", start, end).unwrap(),
+                        }
+
+                        if access.read_before_write {
+                            out.push_str("/rb");
+                        }
+
+                        if access.read_after_write {
+                            out.push_str("/ra");
+                        }
+                    }
+
+                    out.push(']');
+                }
+                None => out.push_str(" access:none"),
+            }
+
+            out.push_str(" {");
+        }
+
+        out.push('\n');
+    });
+
+    out
+}
+
+struct TextReader<'a> {
+    tokens: Vec<&'a str>,
+    index: usize,
+    line: usize,
+}
+
+impl<'a> TextReader<'a> {
+    fn token(&mut self) -> Result<&'a str, IrError> {
+        match self.tokens.get(self.index) {
+            Some(token) => {
+                self.index += 1;
+                Ok(token)
+            }
+            None => Err(self.error("Missing argument".to_owned())),
+        }
+    }
+
+    fn number<T: std::str::FromStr>(&self, token: &str) -> Result<T, IrError> {
+        token
+            .parse()
+            .map_err(|_| self.error(format!("Invalid number {}", token)))
+    }
+
+    fn span(&mut self) -> Result<Range<usize>, IrError> {
+        let token = self.token()?;
+
+        match token.split_once("..") {
+            Some((start, end)) => Ok(self.number(start)?..self.number(end)?),
+            None => Err(self.error(format!("Invalid span {}", token))),
+        }
+    }
+
+    fn cell_access(&self, token: &str) -> Result<CellAccess, IrError> {
+        let (offset, value) = token
+            .split_once('=')
+            .ok_or_else(|| self.error(format!("Invalid cell access {}", token)))?;
+
+        let mut parts = value.split('/');
+
+        let value = match parts.next().unwrap_or_default() {
+            "r" => Cell::Read,
+            "w" => Cell::Write,
+            "nz" => Cell::NonZero,
+            "bool" => Cell::Bool,
+            value => match value.split_once('-') {
+                Some((start, end)) => Cell::Range(self.number(start)?, self.number(end)?),
+                None => Cell::Value(self.number(value)?),
+            },
+        };
+
+        let mut access = CellAccess {
+            read_before_write: false,
+            read_after_write: false,
+            offset: self.number(offset)?,
+            value,
+        };
+
+        for flag in parts {
+            match flag {
+                "rb" => access.read_before_write = true,
+                "ra" => access.read_after_write = true,
+                _ => return Err(self.error(format!("Invalid cell access flag {}", flag))),
+            }
+        }
+
+        Ok(access)
+    }
+
+    /// Get value of the next `name:value` token
+    fn setting(&mut self, name: &str) -> Result<&'a str, IrError> {
+        let token = self.token()?;
+
+        token
+            .strip_prefix(name)
+            .and_then(|token| token.strip_prefix(':'))
+            .ok_or_else(|| self.error(format!("Expected {} instead of {}", name, token)))
+    }
+}
+
+impl<'a> ArgReader for TextReader<'a> {
+    fn usize(&mut self) -> Result<usize, IrError> {
+        let token = self.token()?;
+        self.number(token)
+    }
+
+    fn isize(&mut self) -> Result<isize, IrError> {
+        let token = self.token()?;
+        self.number(token)
+    }
+
+    fn u32(&mut self) -> Result<u32, IrError> {
+        let token = self.token()?;
+        self.number(token)
+    }
+
+    fn bool(&mut self) -> Result<bool, IrError> {
+        let token = self.token()?;
+        self.number(token)
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, IrError> {
+        let token = self.token()?;
+
+        let hex = token
+            .strip_prefix('"')
+            .and_then(|token| token.strip_suffix('"'))
+            .filter(|hex| hex.len() % 2 == 0 && hex.is_ascii())
+            .ok_or_else(|| self.error(format!("Invalid string {}", token)))?;
+
+        (0..hex.len())
+            .step_by(2)
+            .map(|index| {
+                u8::from_str_radix(&hex[index..index + 2], 16)
+                    .map_err(|_| self.error(format!("Invalid string {}", token)))
+            })
+            .collect()
+    }
+
+    fn decrement(&mut self) -> Result<LoopDecrement, IrError> {
+        match self.token()? {
+            "pre" => Ok(LoopDecrement::Pre),
+            "post" => Ok(LoopDecrement::Post),
+            "auto" => Ok(LoopDecrement::Auto),
+            token => Err(self.error(format!("Invalid loop decrement {}", token))),
+        }
+    }
+
+    fn optional_isize(&mut self) -> Result<Option<isize>, IrError> {
+        match self.token()? {
+            "-" => Ok(None),
+            token => Ok(Some(self.number(token)?)),
+        }
+    }
+
+    fn block_info(&mut self) -> Result<BlockInfo, IrError> {
+        let mut token = self.token()?;
+
+        let always_used = token == "always";
+        if always_used {
+            token = self.token()?;
+        }
+
+        let mut info = match token {
+            "access:none" => BlockInfo::new_empty(),
+            _ => {
+                let list = token
+                    .strip_prefix("access:[")
+                    .and_then(|token| token.strip_suffix(']'))
+                    .ok_or_else(|| self.error(format!("Invalid block info {}", token)))?;
+
+                let cell_access = list
+                    .split(',')
+                    .filter(|access| !access.is_empty())
+                    .map(|access| self.cell_access(access))
+                    .collect::<Result<_, _>>()?;
+
+                BlockInfo::new_access(cell_access)
+            }
+        };
+
+        info.set_always_used(always_used);
+
+        if self.token()? != "{" {
+            return Err(self.error("Expected { after block".to_owned()));
+        }
+
+        Ok(info)
+    }
+
+    fn error(&self, message: String) -> IrError {
+        IrError::InvalidLine {
+            line: self.line,
+            message,
+        }
+    }
+}
+
+fn decode_text(text: &str, config: &mut OptimizeConfig) -> Result<Program, IrError> {
+    let mut lines = text.lines().enumerate();
+
+    let mut header = TextReader {
+        tokens: lines
+            .next()
+            .map(|(_, line)| line.split_whitespace().collect())
+            .unwrap_or_default(),
+        index: 0,
+        line: 1,
+    };
+
+    if header.tokens.first() != Some(&TEXT_MAGIC) {
+        return Err(IrError::UnknownFormat);
+    }
+
+    let version = header
+        .tokens
+        .get(1)
+        .and_then(|version| version.parse().ok())
+        .ok_or(IrError::UnknownFormat)?;
+
+    if version != IR_VERSION {
+        return Err(IrError::UnsupportedVersion(version));
+    }
+
+    header.index = 2;
+
+    let bits = header.setting("cell-size")?;
+    let cell_size = CellSize::from_bits(header.number(bits)?)
+        .ok_or_else(|| header.error(format!("Invalid cell size {}", bits)))?;
+
+    let name = header.setting("eof")?;
+    let eof_mode = EOF_MODE_NAMES
+        .iter()
+        .position(|&eof_name| eof_name == name)
+        .and_then(|tag| eof_mode_from_tag(tag as u8))
+        .ok_or_else(|| header.error(format!("Invalid EOF mode {}", name)))?;
+
+    let wrapping_is_ub = header.setting("wrapping-is-ub")?;
+    let wrapping_is_ub = header.number(wrapping_is_ub)?;
+
+    if header.index != header.tokens.len() {
+        return Err(header.error("Unexpected trailing header fields".to_owned()));
+    }
+
+    let mut blocks = OpenBlocks::new();
+
+    for (index, line) in lines {
+        let mut reader = TextReader {
+            tokens: line.split_whitespace().collect(),
+            index: 0,
+            line: index + 1,
+        };
+
+        let name = match reader.tokens.first() {
+            Some(&"}") if reader.tokens.len() == 1 => {
+                if !blocks.close() {
+                    return Err(reader.error("Unexpected }".to_owned()));
+                }
+                continue;
+            }
+            Some(name) => *name,
+            None => continue,
+        };
+
+        reader.index = 1;
+
+        let tag = TEXT_NAMES
+            .iter()
+            .position(|&text_name| text_name == name)
+            .map(|tag| tag as u8)
+            .ok_or_else(|| reader.error(format!("Unknown op {}", name)))?;
+
+        let span = reader.span()?;
+        let op_type = build_op_type(tag, &mut reader)?;
+
+        if reader.index != reader.tokens.len() {
+            return Err(reader.error("Unexpected trailing arguments".to_owned()));
+        }
+
+        blocks.push(Op { op_type, span });
+    }
+
+    if !blocks.is_root() {
+        return Err(IrError::InvalidLine {
+            line: text.lines().count(),
+            message: "Unclosed block at end of file".to_owned(),
+        });
+    }
+
+    config.cell_size = cell_size;
+    config.eof_mode = eof_mode;
+    config.wrapping_is_ub = wrapping_is_ub;
+
+    Ok(blocks.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::errors::IrError;
+    use crate::ir::ops::{CellSize, EofMode};
+    use crate::ir::serialize::IrFormat;
+    use crate::parser::Program;
+    use crate::{optimize_with_config, parse, OptimizeConfig};
+
+    fn round_trip(source: &str, config: &OptimizeConfig) {
+        let mut program = parse(source).unwrap();
+        optimize_with_config(&mut program, config);
+
+        for format in [IrFormat::Binary, IrFormat::Text] {
+            let mut data = vec![];
+            program.save(&mut data, format, config).unwrap();
+
+            assert!(Program::is_ir(&data));
+
+            let loaded = Program::load(&data[..], &mut OptimizeConfig::o0()).unwrap();
+            assert_eq!(loaded.ops, program.ops);
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for source in [
+            include_str!("../../../test_programs/hello_world.bf"),
+            include_str!("../../../test_programs/bottles.bf"),
+            include_str!("../../../test_programs/factor.bf"),
+            include_str!("../../../test_programs/awib.bf"),
+        ] {
+            round_trip(source, &OptimizeConfig::o0());
+            round_trip(source, &OptimizeConfig::o2());
+            round_trip(source, &OptimizeConfig::o3());
+        }
+    }
+
+    #[test]
+    fn test_text_format() {
+        let mut program = parse("++[->>+<<]>>.").unwrap();
+        optimize_with_config(&mut program, &OptimizeConfig::o1());
+
+        let mut data = vec![];
+        program
+            .save(&mut data, IrFormat::Text, &OptimizeConfig::o1())
+            .unwrap();
+
+        assert_eq!(
+            String::from_utf8(data).unwrap(),
+            "cranefack-ir 2 cell-size:8 eof:0 wrapping-is-ub:false\n\
+             set 0..2 0 2\n\
+             d_loop 2..10 access:none {\n  \
+               dec 3..4 0 1\n  \
+               inc 6..7 2 1\n\
+             }\n\
+             put_char 12..13 2\n"
+        );
+    }
+
+    #[test]
+    fn test_text_block() {
+        let text = "cranefack-ir 2 cell-size:16 eof:unchanged wrapping-is-ub:true\n\
+                    get_char 0..1 0\n\
+                    d_loop 1..6 always access:[0=r/rb,-1=3-5/ra] {\n  \
+                      put_string 2..3 \"4869\"\n  \
+                      d_tnz 3..5 - access:none {\n  \
+                      }\n\
+                    }\n";
+
+        let mut config = OptimizeConfig::o2();
+        let program = Program::load(text.as_bytes(), &mut config).unwrap();
+
+        let mut data = vec![];
+        program.save(&mut data, IrFormat::Text, &config).unwrap();
+
+        assert_eq!(String::from_utf8(data).unwrap(), text);
+    }
+
+    #[test]
+    fn test_settings() {
+        let mut program = parse(",[.,]").unwrap();
+        let mut config = OptimizeConfig::o2();
+        config.cell_size = CellSize::Bits16;
+        config.eof_mode = EofMode::Max;
+        config.wrapping_is_ub = true;
+        optimize_with_config(&mut program, &config);
+
+        for format in [IrFormat::Binary, IrFormat::Text] {
+            let mut data = vec![];
+            program.save(&mut data, format, &config).unwrap();
+
+            let mut loaded_config = OptimizeConfig::o0();
+            Program::load(&data[..], &mut loaded_config).unwrap();
+
+            assert_eq!(loaded_config.cell_size, CellSize::Bits16);
+            assert_eq!(loaded_config.eof_mode, EofMode::Max);
+            assert!(loaded_config.wrapping_is_ub);
+        }
+    }
+
+    #[test]
+    fn test_unsupported_version() {
+        let mut config = OptimizeConfig::o0();
+
+        assert!(matches!(
+            Program::load(&b"CFIR\x01\x00\x00\x00\xff"[..], &mut config),
+            Err(IrError::UnsupportedVersion(1))
+        ));
+        assert!(matches!(
+            Program::load(&b"cranefack-ir 7\n"[..], &mut config),
+            Err(IrError::UnsupportedVersion(7))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        let mut config = OptimizeConfig::o0();
+
+        assert!(matches!(
+            Program::load(&b"+++"[..], &mut config),
+            Err(IrError::UnknownFormat)
+        ));
+        assert!(matches!(
+            Program::load(
+                &b"CFIR\x02\x00\x00\x00\x08\x01\x00\x15\x00"[..],
+                &mut config
+            ),
+            Err(IrError::InvalidData { position: 13, .. })
+        ));
+        assert!(matches!(
+            Program::load(
+                &b"CFIR\x02\x00\x00\x00\x08\x01\x00\x63\x00\x00\xff"[..],
+                &mut config
+            ),
+            Err(IrError::InvalidData { .. })
+        ));
+        assert!(matches!(
+            Program::load(&b"CFIR\x02\x00\x00\x00\x0c\x01\x00\xff"[..], &mut config),
+            Err(IrError::InvalidData { position: 9, .. })
+        ));
+        assert!(matches!(
+            Program::load(
+                &b"cranefack-ir 2 cell-size:8 eof:0 wrapping-is-ub:false\ninc 0..1 0\n"[..],
+                &mut config
+            ),
+            Err(IrError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Program::load(&b"cranefack-ir 2 cell-size:8 eof:0 wrapping-is-ub:false\nl_loop 0..1 access:none {\n"[..], &mut config),
+            Err(IrError::InvalidLine { .. })
+        ));
+        assert!(matches!(
+            Program::load(
+                &b"cranefack-ir 2 cell-size:8 eof:0 wrapping-is-ub:false\n}\n"[..],
+                &mut config
+            ),
+            Err(IrError::InvalidLine { line: 2, .. })
+        ));
+        assert!(matches!(
+            Program::load(&b"cranefack-ir 2 cell-size:8\n"[..], &mut config),
+            Err(IrError::InvalidLine { line: 1, .. })
+        ));
+        assert!(matches!(
+            Program::load(
+                &b"cranefack-ir 2 cell-size:8 eof:1 wrapping-is-ub:false\n"[..],
+                &mut config
+            ),
+            Err(IrError::InvalidLine { line: 1, .. })
+        ));
+
+        assert_eq!(config.cell_size, CellSize::Bits8);
+    }
+
+    #[test]
+    fn test_deeply_nested() {
+        let source = format!("+{}{}", "[>".repeat(1000), "]".repeat(1000));
+        round_trip(&source, &OptimizeConfig::o0());
+    }
+}
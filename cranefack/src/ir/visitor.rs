@@ -0,0 +1,249 @@
+use std::mem;
+
+use crate::ir::ops::Op;
+
+/// Walk over ops and the bodies of all nested loops and conditions
+///
+/// Ops are visited in program order with the body of a block visited right after the block op.
+/// The depth is 0 for the top level ops and increases by one for each surrounding block.
+///
+/// Use [walk] or [crate::Program::visit] to run a visitor.
+/// Nested blocks are walked with an explicit stack, so deeply nested programs don't overflow the stack.
+pub trait Visitor {
+    /// Called for the ops of the program and of each block body before any of them is visited
+    fn visit_ops(&mut self, _ops: &[Op], _depth: usize) {}
+
+    /// Called for every op before the ops of its body
+    ///
+    /// Returning `false` skips the body of a block op.
+    fn visit_op(&mut self, _op: &Op, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called for a block op after all ops of its body were visited
+    fn leave_block(&mut self, _op: &Op, _depth: usize) {}
+}
+
+/// Walk over ops like [Visitor] with mutable access to all ops
+///
+/// Use [walk_mut] or [crate::Program::visit_mut] to run a visitor.
+/// Changes to an op are picked up before its body is visited, so a visitor can replace, insert
+/// or remove ops in [VisitorMut::visit_ops_mut] and change block ops in [VisitorMut::visit_op_mut].
+pub trait VisitorMut {
+    /// Called for the ops of the program and of each block body before any of them is visited
+    fn visit_ops_mut(&mut self, _ops: &mut Vec<Op>, _depth: usize) {}
+
+    /// Called for every op before the ops of its body
+    ///
+    /// Returning `false` skips the body of a block op.
+    fn visit_op_mut(&mut self, _op: &mut Op, _depth: usize) -> bool {
+        true
+    }
+
+    /// Called for a block op after all ops of its body were visited
+    fn leave_block_mut(&mut self, _op: &mut Op, _depth: usize) {}
+}
+
+/// Run visitor over ops and all nested blocks
+pub fn walk<V: Visitor + ?Sized>(visitor: &mut V, ops: &[Op]) {
+    visitor.visit_ops(ops, 0);
+
+    let mut stack: Vec<(Option<&Op>, std::slice::Iter<Op>)> = vec![(None, ops.iter())];
+
+    while let Some(depth) = stack.len().checked_sub(1) {
+        if let Some(op) = stack[depth].1.next() {
+            if visitor.visit_op(op, depth) {
+                if let Some(children) = op.op_type.get_children() {
+                    visitor.visit_ops(children, depth + 1);
+                    stack.push((Some(op), children.iter()));
+                }
+            }
+        } else if let Some((Some(block), _)) = stack.pop() {
+            visitor.leave_block(block, depth - 1);
+        }
+    }
+}
+
+/// Run mutable visitor over ops and all nested blocks
+///
+/// The body of the block that is currently visited is moved out of the block op and put back
+/// before [VisitorMut::leave_block_mut] is called.
+pub fn walk_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ops: &mut Vec<Op>) {
+    visitor.visit_ops_mut(ops, 0);
+
+    // Each entry holds the ops of a block and the index of the next op to visit
+    let mut stack = vec![(mem::take(ops), 0)];
+
+    while let Some(depth) = stack.len().checked_sub(1) {
+        let (current, index) = &mut stack[depth];
+
+        if *index < current.len() {
+            let op = &mut current[*index];
+            let enter = visitor.visit_op_mut(op, depth);
+
+            match op.op_type.get_children_mut() {
+                Some(children) if enter => {
+                    visitor.visit_ops_mut(children, depth + 1);
+                    let children = mem::take(children);
+                    stack.push((children, 0));
+                }
+                _ => *index += 1,
+            }
+        } else {
+            let (children, _) = stack.pop().expect("Stack is not empty");
+
+            if let Some((parent, index)) = stack.last_mut() {
+                let block = &mut parent[*index];
+                *block
+                    .op_type
+                    .get_children_mut()
+                    .expect("Op whose body is visited is a block") = children;
+
+                visitor.leave_block_mut(block, depth - 1);
+                *index += 1;
+            } else {
+                *ops = children;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::ops::{Op, OpType};
+    use crate::ir::opt_info::BlockInfo;
+    use crate::ir::visitor::{Visitor, VisitorMut};
+    use crate::parse;
+
+    #[derive(Default)]
+    struct Trace {
+        events: Vec<String>,
+    }
+
+    impl Visitor for Trace {
+        fn visit_ops(&mut self, ops: &[Op], depth: usize) {
+            self.events.push(format!("ops {} {}", ops.len(), depth));
+        }
+
+        fn visit_op(&mut self, op: &Op, depth: usize) -> bool {
+            self.events.push(format!("op {} {}", op.span.start, depth));
+            true
+        }
+
+        fn leave_block(&mut self, op: &Op, depth: usize) {
+            self.events
+                .push(format!("leave {} {}", op.span.start, depth));
+        }
+    }
+
+    #[test]
+    fn test_visit_order() {
+        let program = parse("+[>[-]]<").unwrap();
+
+        let mut trace = Trace::default();
+        program.visit(&mut trace);
+
+        assert_eq!(
+            trace.events,
+            vec![
+                "ops 3 0",
+                "op 0 0",
+                "op 1 0",
+                "ops 2 1",
+                "op 2 1",
+                "op 3 1",
+                "ops 1 2",
+                "op 4 2",
+                "leave 3 1",
+                "leave 1 0",
+                "op 7 0",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_visit_skip_body() {
+        struct SkipBlocks(usize);
+
+        impl Visitor for SkipBlocks {
+            fn visit_op(&mut self, op: &Op, _depth: usize) -> bool {
+                self.0 += 1;
+                op.op_type.get_children().is_none()
+            }
+
+            fn leave_block(&mut self, _op: &Op, _depth: usize) {
+                panic!("Skipped block must not be left");
+            }
+        }
+
+        let program = parse("+[>[-]]<").unwrap();
+
+        let mut visitor = SkipBlocks(0);
+        program.visit(&mut visitor);
+
+        assert_eq!(visitor.0, 3);
+    }
+
+    #[test]
+    fn test_visit_deep_nesting() {
+        struct MaxDepth(usize);
+
+        impl Visitor for MaxDepth {
+            fn visit_op(&mut self, _op: &Op, depth: usize) -> bool {
+                self.0 = self.0.max(depth);
+                true
+            }
+        }
+
+        let mut program = parse("+").unwrap();
+        for _ in 0..100_000 {
+            let ops = std::mem::take(&mut program.ops);
+            program.ops = vec![Op::d_loop(0..1, ops, BlockInfo::new_empty())];
+        }
+
+        let mut visitor = MaxDepth(0);
+        program.visit(&mut visitor);
+
+        assert_eq!(visitor.0, 100_000);
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        struct IncToDec;
+
+        impl VisitorMut for IncToDec {
+            fn visit_ops_mut(&mut self, ops: &mut Vec<Op>, _depth: usize) {
+                ops.retain(|op| !matches!(op.op_type, OpType::IncPtr(_)));
+            }
+
+            fn visit_op_mut(&mut self, op: &mut Op, _depth: usize) -> bool {
+                if let OpType::Inc(offset, value) = op.op_type {
+                    op.op_type = OpType::Dec(offset, value);
+                }
+                true
+            }
+
+            fn leave_block_mut(&mut self, op: &mut Op, _depth: usize) {
+                let children = op.op_type.get_children().unwrap();
+                if children.is_empty() {
+                    op.op_type = OpType::Breakpoint;
+                }
+            }
+        }
+
+        let mut program = parse("+[>+[>]]").unwrap();
+        program.visit_mut(&mut IncToDec);
+
+        assert_eq!(
+            program.ops,
+            vec![
+                Op::dec(0..1, 1),
+                Op::d_loop(
+                    1..8,
+                    vec![Op::dec(3..4, 1), Op::breakpoint(4..7)],
+                    BlockInfo::new_empty()
+                ),
+            ]
+        );
+    }
+}
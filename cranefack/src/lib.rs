@@ -43,7 +43,7 @@
 //!     let module = CompiledJitModule::new(&program, &opt_level)?;
 //!
 //!     // Execute compiled module reading from stdin and writing to stdout
-//!     module.execute(std::io::stdin(), std::io::stdout());
+//!     module.execute(std::io::stdin(), std::io::stdout())?;
 //!
 //! # Ok(())
 //! # }
@@ -54,14 +54,31 @@
 mod analyzer;
 mod backends;
 mod errors;
-mod ir;
+pub mod ir;
 mod optimizations;
 mod parser;
 
 pub use analyzer::{analyze, Warning, WarningType};
+pub use backends::bf::compile_to_bf;
+pub use backends::c::{compile_to_c, compile_to_c_with_config};
 pub use backends::cranelift::CompiledJitModule;
-pub use backends::interpreter::Interpreter;
-pub use backends::rust::compile_to_rust;
-pub use errors::{CompilerError, CraneFackError, ParserError, RuntimeError};
-pub use optimizations::{optimize, optimize_with_config, OptimizeConfig};
-pub use parser::{parse, Program};
+pub use backends::interpreter::{BreakpointInfo, ExecutionStatus, Interpreter};
+pub use backends::object::{compile_to_executable, compile_to_object};
+pub use backends::rust::{compile_to_rust, compile_to_rust_with_config};
+pub use backends::wasm::{compile_to_wasm, compile_to_wat};
+pub use backends::DEFAULT_HEAP_SIZE;
+pub use errors::{
+    CompilerError, CraneFackError, IrError, ParserError, RuntimeError, ValidationError,
+};
+pub use ir::assembler::parse_dump;
+pub use ir::ops::{CellSize, EofMode};
+pub use ir::serialize::{IrFormat, IR_VERSION};
+pub use optimizations::{
+    optimize, optimize_with_config, CheckConfig, ExecutionOutcome, IterationReport,
+    OptimizationPass, OptimizationReport, OptimizeConfig, PassContext, PassManager, PassReport,
+    ProgramStatistics, Remark, RemarkType,
+};
+pub use parser::{
+    parse, parse_bytes, parse_bytes_with_options, parse_bytes_with_recovery, parse_reader,
+    parse_reader_with_recovery, split_inline_input, Dialect, ParserOptions, Program, TokenMap,
+};
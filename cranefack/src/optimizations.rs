@@ -1,16 +1,19 @@
-use passes::*;
+pub use pass_manager::{OptimizationPass, PassContext, PassManager};
+pub use remarks::{Remark, RemarkType};
+pub use report::{IterationReport, OptimizationReport, PassReport, ProgramStatistics};
+pub use validation::{CheckConfig, ExecutionOutcome};
 
-use crate::ir::ops::{Op, OpType};
-use crate::optimizations::peephole::run_peephole_pass;
+use crate::backends::DEFAULT_HEAP_SIZE;
+use crate::ir::ops::{CellSize, EofMode};
 use crate::parser::Program;
-use codespan_reporting::term::termcolor::{
-    Color, ColorChoice, ColorSpec, StandardStream, WriteColor,
-};
-use std::io::Write;
 
+mod pass_manager;
 mod passes;
 mod peephole;
+mod remarks;
+mod report;
 mod utils;
+mod validation;
 
 /// Configuration to control optimization of the program
 pub struct OptimizeConfig {
@@ -40,8 +43,53 @@ pub struct OptimizeConfig {
     /// Allows some optimization to mark values as non zero if there is a known increment
     pub wrapping_is_ub: bool,
 
+    /// Width of the heap cells
+    ///
+    /// Arithmetic on cells wraps around at the maximum value of the cell
+    pub cell_size: CellSize,
+
+    /// Behavior of reading a char once the input reached end of file
+    pub eof_mode: EofMode,
+
+    /// Escape zero and non ASCII output bytes as `\0x..` instead of writing them raw
+    pub printable_output: bool,
+
+    /// Allow the heap pointer to move left of the start cell
+    ///
+    /// The cells left of the start are real storage instead of a heap underflow error
+    pub bidirectional_tape: bool,
+
+    /// Number of heap cells right of the start cell
+    ///
+    /// With a bidirectional tape the same number of cells is available left of the start cell.
+    /// The interpreter grows its heap on demand, the compiled backends allocate the whole
+    /// heap before the program starts.
+    pub heap_size: usize,
+
+    /// Emit bounds checks for heap accesses in jit compiled code
+    ///
+    /// Without them a program accessing cells beyond the heap corrupts memory
+    pub jit_bounds_checks: bool,
+
+    /// Emit fuel checks at loop back edges in jit compiled code
+    ///
+    /// Required to limit the execution with [CompiledJitModule::set_fuel](crate::CompiledJitModule::set_fuel)
+    /// or to cancel it with [CompiledJitModule::set_cancel_flag](crate::CompiledJitModule::set_cancel_flag)
+    pub jit_fuel_checks: bool,
+
+    /// Names of passes to run in addition to the ones of the optimization level
+    pub enabled_passes: Vec<String>,
+
+    /// Names of passes to skip
+    pub disabled_passes: Vec<String>,
+
     /// Print statistics after each pass
     pub debug: bool,
+
+    /// Collect statistics and timings of each pass for the [OptimizationReport]
+    ///
+    /// Without it the report only contains the progress and remarks of the passes.
+    pub statistics: bool,
 }
 
 impl OptimizeConfig {
@@ -55,7 +103,17 @@ impl OptimizeConfig {
             unroll_loop_limit: 0,
             partially_unroll_loops_limit: 0,
             wrapping_is_ub: false,
+            cell_size: CellSize::Bits8,
+            eof_mode: EofMode::Zero,
+            printable_output: false,
+            bidirectional_tape: false,
+            heap_size: DEFAULT_HEAP_SIZE,
+            jit_bounds_checks: false,
+            jit_fuel_checks: false,
+            enabled_passes: vec![],
+            disabled_passes: vec![],
             debug: false,
+            statistics: false,
         }
     }
 
@@ -112,7 +170,17 @@ impl OptimizeConfig {
             unroll_loop_limit: usize::MAX,
             partially_unroll_loops_limit: usize::MAX,
             wrapping_is_ub: false,
+            cell_size: CellSize::Bits8,
+            eof_mode: EofMode::Zero,
+            printable_output: false,
+            bidirectional_tape: false,
+            heap_size: DEFAULT_HEAP_SIZE,
+            jit_bounds_checks: false,
+            jit_fuel_checks: false,
+            enabled_passes: vec![],
+            disabled_passes: vec![],
             debug: false,
+            statistics: false,
         }
     }
 
@@ -123,200 +191,15 @@ impl OptimizeConfig {
 }
 
 /// Optimize program with level 2
-pub fn optimize(program: &mut Program) -> usize {
+pub fn optimize(program: &mut Program) -> OptimizationReport {
     optimize_with_config(program, &OptimizeConfig::o2())
 }
 
-/// Optimize program
-pub fn optimize_with_config(program: &mut Program, config: &OptimizeConfig) -> usize {
-    program.ops.insert(0, Op::start());
-
-    let mut progress = true;
-
-    let mut count = 0;
-
-    while count < config.max_loops && progress {
-        progress = false;
-        count += 1;
-
-        print_debug_iteration(config, count);
-
-        progress |= remove_dead_loops(&mut program.ops);
-        print_debug(program, config, "Remove dead loops");
-
-        progress |= optimize_zero_loops(&mut program.ops);
-        print_debug(program, config, "Optimize zero loops");
-
-        progress |= run_peephole_pass(&mut program.ops, optimize_arithmetics);
-        print_debug(program, config, "Optimize arithmetics");
-
-        progress |= remove_dead_stores_before_set(&mut program.ops);
-        print_debug(program, config, "Remove dead stores");
-
-        if config.complex_loops {
-            progress |= optimize_local_loops(&mut program.ops);
-            print_debug(program, config, "Detect local loops");
-
-            progress |= optimize_count_loops(&mut program.ops);
-            print_debug(program, config, "Detect iterating loops");
-
-            progress |= run_peephole_pass(&mut program.ops, optimize_arithmetic_loops);
-            print_debug(program, config, "Optimize arithmetic loops");
-
-            progress |= optimize_static_count_loops(&mut program.ops);
-            print_debug(program, config, "Detect constant loops");
-
-            progress |= optimize_constant_arithmetic_loop(&mut program.ops);
-            print_debug(program, config, "Optimize constant arithmetic loops");
-
-            progress |= optimize_conditional_loops(&mut program.ops);
-            print_debug(program, config, "Detect conditional loops");
-
-            progress |= optimize_search_zero(&mut program.ops);
-            print_debug(program, config, "Detect scanning loops");
-        }
-
-        progress |= run_peephole_pass(&mut program.ops, optimize_constant_arithmetics);
-        print_debug(program, config, "Optimize constant arithmetics");
-
-        progress |= optimize_offsets(&mut program.ops, 0);
-        print_debug(program, config, "Optimize offsets");
-
-        progress |= run_peephole_pass(&mut program.ops, optimize_arithmetic_offsets);
-        print_debug(program, config, "Optimize arithmetic offsets");
-
-        progress |= remove_trailing_pointer_ops(&mut program.ops, true);
-        print_debug(program, config, "Remove trailing pointer ops");
-
-        progress |= run_peephole_pass(&mut program.ops, remove_useless_copy);
-        print_debug(program, config, "Remove useless copy");
-
-        if config.non_local {
-            update_loop_access(&mut program.ops, config.wrapping_is_ub);
-
-            if optimize_non_local_arithmetics(&mut program.ops, config.wrapping_is_ub) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local arithmetics");
-
-            if optimize_non_local_conditional_loops(&mut program.ops, config.wrapping_is_ub) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local conditional loops");
-
-            if optimize_non_local_static_count_loops(&mut program.ops, config.wrapping_is_ub) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local constant loops");
-
-            if optimize_non_local_redundant_copies(&mut program.ops) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local redundant copies");
-
-            if optimize_non_local_dead_stores(&mut program.ops) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local dead stores");
-
-            if optimize_non_local_dead_block_stores(&mut program.ops) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Optimize non local dead blocks stores");
-
-            if remove_useless_loops(&mut program.ops) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Remove useless loops");
-
-            if remove_true_conditions(&mut program.ops, config.wrapping_is_ub) {
-                update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                progress = true;
-            }
-            print_debug(program, config, "Remove true conditions");
-
-            if config.unroll_loop_limit > 0 {
-                if unroll_constant_loops(&mut program.ops, config.unroll_loop_limit) {
-                    update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                    progress = true;
-                }
-                print_debug(program, config, "Unroll constant loops");
-
-                progress |= unroll_scanning_d_loops(
-                    &mut program.ops,
-                    config.unroll_loop_limit,
-                    config.wrapping_is_ub,
-                );
-                print_debug(program, config, "Unroll scanning dynamic loops");
-            }
-
-            if config.partially_unroll_loops_limit > 0 && (!progress || count > 10) {
-                if partially_unroll_loops(
-                    &mut program.ops,
-                    config.partially_unroll_loops_limit,
-                    config.wrapping_is_ub,
-                ) {
-                    non_local_remove_dead_loops(&mut program.ops, config.wrapping_is_ub);
-                    update_loop_access(&mut program.ops, config.wrapping_is_ub);
-                    progress = true;
-                }
-                print_debug(program, config, "Partially unroll dynamic loops");
-            }
-
-            progress |= non_local_remove_dead_loops(&mut program.ops, config.wrapping_is_ub);
-            print_debug(program, config, "Remove non local dead loops");
-        } else if config.complex_loops {
-            progress |= remove_useless_loops(&mut program.ops);
-            print_debug(program, config, "Remove useless loops");
-        }
-    }
-
-    match program.ops.remove(0).op_type {
-        OpType::Start => {
-            // Ignore
-        }
-        // Start marker MUST NOT be removed by any optimization pass
-        other => panic!("Start marker was removed: {:?}", other),
-    }
-
-    count
-}
-
-fn print_debug(program: &Program, config: &OptimizeConfig, pass: &str) {
-    if config.debug {
-        let mut writer = StandardStream::stderr(ColorChoice::Auto);
-        writer
-            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-            .unwrap();
-
-        let (op_count, dloop_count, lloop_count, iloop_count, cloop_count, if_count) =
-            program.get_statistics();
-
-        writeln!(
-            writer,
-            "Pass {} with {} instructions ({},{},{},{}) loops and {} ifs",
-            pass, op_count, dloop_count, lloop_count, iloop_count, cloop_count, if_count,
-        )
-        .unwrap();
-        writer.reset().unwrap();
-    }
-}
-
-fn print_debug_iteration(config: &OptimizeConfig, iteration: usize) {
-    if config.debug {
-        let mut writer = StandardStream::stderr(ColorChoice::Auto);
-        writer
-            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
-            .unwrap();
-
-        writeln!(writer, "Iteration {}", iteration).unwrap();
-        writer.reset().unwrap();
-    }
+/// Optimize program with the default pipeline of passes for the config
+///
+/// See [PassManager] to customize the passes.
+/// The returned [OptimizationReport] contains every pass that ran. Their statistics and timings
+/// are only collected with [OptimizeConfig::statistics].
+pub fn optimize_with_config(program: &mut Program, config: &OptimizeConfig) -> OptimizationReport {
+    PassManager::from_config(config).run(program, config)
 }
@@ -0,0 +1,790 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::ops::Range;
+use std::time::Instant;
+
+use codespan_reporting::term::termcolor::{
+    Color, ColorChoice, ColorSpec, StandardStream, WriteColor,
+};
+
+use crate::errors::ValidationError;
+use crate::ir::ops::{Op, OpType};
+use crate::optimizations::passes::*;
+use crate::optimizations::peephole::run_peephole_pass;
+use crate::optimizations::remarks::{Remark, RemarkType};
+use crate::optimizations::report::{
+    IterationReport, OptimizationReport, PassReport, ProgramStatistics,
+};
+use crate::optimizations::validation::{CheckConfig, Validator};
+use crate::optimizations::OptimizeConfig;
+use crate::parser::Program;
+
+/// State of the pipeline passed to each optimization pass
+pub struct PassContext<'a> {
+    /// Configuration the pipeline runs with
+    pub config: &'a OptimizeConfig,
+
+    /// Current iteration over all passes starting at 1
+    pub iteration: usize,
+
+    /// An earlier pass of the current iteration changed the program
+    pub progress: bool,
+
+    remarks: RefCell<Vec<Remark>>,
+}
+
+impl<'a> PassContext<'a> {
+    /// Add remark about a change of the current pass to the [OptimizationReport]
+    pub fn remark(&self, span: Range<usize>, remark_type: RemarkType) {
+        self.remarks
+            .borrow_mut()
+            .push(Remark::new(span, remark_type));
+    }
+}
+
+/// Single optimization pass of a [PassManager] pipeline
+///
+/// The ops passed to [OptimizationPass::run] start with an [OpType::Start] marker that must not
+/// be removed.
+pub trait OptimizationPass: Send + Sync {
+    /// Name used to enable or disable the pass
+    fn name(&self) -> &str;
+
+    /// Optimize the ops of the program and return true if they were changed
+    fn run(&self, ops: &mut Vec<Op>, context: &PassContext) -> bool;
+}
+
+/// Group of the builtin passes deciding which optimization config enables them
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PassGroup {
+    Simple,
+    ComplexLoops,
+    NonLocal,
+    ComplexLoopsOnly,
+}
+
+#[derive(Copy, Clone)]
+struct BuiltinPass {
+    name: &'static str,
+    group: PassGroup,
+    run: fn(&mut Vec<Op>, &PassContext) -> bool,
+}
+
+impl OptimizationPass for BuiltinPass {
+    fn name(&self) -> &str {
+        self.name
+    }
+
+    fn run(&self, ops: &mut Vec<Op>, context: &PassContext) -> bool {
+        (self.run)(ops, context)
+    }
+}
+
+/// Update cell access info of loops after a non local pass changed the ops
+fn update_access(ops: &mut Vec<Op>, context: &PassContext, progress: bool) -> bool {
+    if progress {
+        let config = context.config;
+        update_loop_access(
+            ops,
+            config.wrapping_is_ub,
+            config.cell_size,
+            config.eof_mode,
+        );
+    }
+
+    progress
+}
+
+/// Builtin passes in the order of the default pipeline
+const BUILTIN_PASSES: &[BuiltinPass] = &[
+    BuiltinPass {
+        name: "dead-loops",
+        group: PassGroup::Simple,
+        run: |ops, _| remove_dead_loops(ops),
+    },
+    BuiltinPass {
+        name: "zero-loops",
+        group: PassGroup::Simple,
+        run: |ops, context| optimize_zero_loops(ops, &mut context.remarks.borrow_mut()),
+    },
+    BuiltinPass {
+        name: "arithmetics",
+        group: PassGroup::Simple,
+        run: |ops, context| {
+            run_peephole_pass(ops, |ops| {
+                optimize_arithmetics(ops, context.config.cell_size)
+            })
+        },
+    },
+    BuiltinPass {
+        name: "dead-stores",
+        group: PassGroup::Simple,
+        run: |ops, context| {
+            remove_dead_stores_before_set(
+                ops,
+                context.config.eof_mode,
+                &mut context.remarks.borrow_mut(),
+            )
+        },
+    },
+    BuiltinPass {
+        name: "local-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, context| {
+            optimize_local_loops(ops, context.config.cell_size, context.config.eof_mode)
+        },
+    },
+    BuiltinPass {
+        name: "count-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, context| {
+            optimize_count_loops(
+                ops,
+                context.config.cell_size,
+                &mut context.remarks.borrow_mut(),
+            )
+        },
+    },
+    BuiltinPass {
+        name: "arithmetic-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, _| run_peephole_pass(ops, optimize_arithmetic_loops),
+    },
+    BuiltinPass {
+        name: "constant-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, context| optimize_static_count_loops(ops, &mut context.remarks.borrow_mut()),
+    },
+    BuiltinPass {
+        name: "constant-arithmetic-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, context| optimize_constant_arithmetic_loop(ops, context.config.cell_size),
+    },
+    BuiltinPass {
+        name: "conditional-loops",
+        group: PassGroup::ComplexLoops,
+        run: |ops, _| optimize_conditional_loops(ops),
+    },
+    BuiltinPass {
+        name: "search-zero",
+        group: PassGroup::ComplexLoops,
+        run: |ops, context| optimize_search_zero(ops, &mut context.remarks.borrow_mut()),
+    },
+    BuiltinPass {
+        name: "constant-arithmetics",
+        group: PassGroup::Simple,
+        run: |ops, context| {
+            run_peephole_pass(ops, |ops| {
+                optimize_constant_arithmetics(ops, context.config.cell_size)
+            })
+        },
+    },
+    BuiltinPass {
+        name: "offsets",
+        group: PassGroup::Simple,
+        run: |ops, _| optimize_offsets(ops, 0),
+    },
+    BuiltinPass {
+        name: "arithmetic-offsets",
+        group: PassGroup::Simple,
+        run: |ops, _| run_peephole_pass(ops, optimize_arithmetic_offsets),
+    },
+    BuiltinPass {
+        name: "trailing-pointer-ops",
+        group: PassGroup::Simple,
+        run: |ops, _| remove_trailing_pointer_ops(ops, true),
+    },
+    BuiltinPass {
+        name: "useless-copy",
+        group: PassGroup::Simple,
+        run: |ops, _| run_peephole_pass(ops, remove_useless_copy),
+    },
+    BuiltinPass {
+        name: "loop-access",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            update_access(ops, context, true);
+            false
+        },
+    },
+    BuiltinPass {
+        name: "non-local-arithmetics",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            let progress =
+                optimize_non_local_arithmetics(ops, config.wrapping_is_ub, config.cell_size);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "non-local-conditional-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            let progress =
+                optimize_non_local_conditional_loops(ops, config.wrapping_is_ub, config.cell_size);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "non-local-constant-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            let progress =
+                optimize_non_local_static_count_loops(ops, config.wrapping_is_ub, config.cell_size);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "non-local-redundant-copies",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let progress = optimize_non_local_redundant_copies(ops);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "non-local-dead-stores",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let progress = optimize_non_local_dead_stores(ops, context.config.eof_mode);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "non-local-dead-block-stores",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let progress = optimize_non_local_dead_block_stores(ops);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "useless-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let progress = remove_useless_loops(ops);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "true-conditions",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            let progress = remove_true_conditions(ops, config.wrapping_is_ub, config.cell_size);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "unroll-constant-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let limit = context.config.unroll_loop_limit;
+            let progress = limit > 0 && unroll_constant_loops(ops, limit);
+            update_access(ops, context, progress)
+        },
+    },
+    BuiltinPass {
+        name: "unroll-scanning-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            config.unroll_loop_limit > 0
+                && unroll_scanning_d_loops(
+                    ops,
+                    config.unroll_loop_limit,
+                    config.wrapping_is_ub,
+                    config.cell_size,
+                )
+        },
+    },
+    BuiltinPass {
+        name: "partially-unroll-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+
+            // Only unroll once the other passes are done or take too long
+            if config.partially_unroll_loops_limit == 0
+                || (context.progress && context.iteration <= 10)
+            {
+                return false;
+            }
+
+            if partially_unroll_loops(
+                ops,
+                config.partially_unroll_loops_limit,
+                config.wrapping_is_ub,
+                config.cell_size,
+            ) {
+                non_local_remove_dead_loops(ops, config.wrapping_is_ub, config.cell_size);
+                update_access(ops, context, true)
+            } else {
+                false
+            }
+        },
+    },
+    BuiltinPass {
+        name: "non-local-dead-loops",
+        group: PassGroup::NonLocal,
+        run: |ops, context| {
+            let config = context.config;
+            non_local_remove_dead_loops(ops, config.wrapping_is_ub, config.cell_size)
+        },
+    },
+    BuiltinPass {
+        name: "final-useless-loops",
+        group: PassGroup::ComplexLoopsOnly,
+        run: |ops, _| remove_useless_loops(ops),
+    },
+];
+
+struct PassEntry {
+    pass: Box<dyn OptimizationPass>,
+    enabled: bool,
+}
+
+/// Pipeline of optimization passes
+///
+/// All passes run in order until none of them changes the program anymore or the maximum number
+/// of iterations in [OptimizeConfig::max_loops] is reached.
+///
+/// ```
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     use cranefack::{parse, OptimizeConfig, PassManager};
+///
+///     let mut program = parse("+++[>++<-]>.")?;
+///     let config = OptimizeConfig::o2();
+///
+///     let mut passes = PassManager::from_config(&config);
+///     passes.set_enabled("count-loops", false);
+///     passes.run(&mut program, &config);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Default)]
+pub struct PassManager {
+    passes: Vec<PassEntry>,
+}
+
+impl PassManager {
+    /// Create empty pipeline
+    pub fn new() -> PassManager {
+        PassManager { passes: vec![] }
+    }
+
+    /// Create default pipeline with all builtin passes enabled by the config
+    ///
+    /// Passes named in [OptimizeConfig::enabled_passes] and [OptimizeConfig::disabled_passes]
+    /// are enabled or disabled afterwards.
+    /// Configs that don't optimize get an empty pipeline.
+    pub fn from_config(config: &OptimizeConfig) -> PassManager {
+        let mut manager = PassManager::new();
+
+        if !config.optimize() {
+            return manager;
+        }
+
+        for pass in BUILTIN_PASSES {
+            let enabled = match pass.group {
+                PassGroup::Simple => true,
+                PassGroup::ComplexLoops => config.complex_loops,
+                PassGroup::NonLocal => config.non_local,
+                PassGroup::ComplexLoopsOnly => config.complex_loops && !config.non_local,
+            };
+
+            manager.passes.push(PassEntry {
+                pass: Box::new(*pass),
+                enabled,
+            });
+        }
+
+        for name in &config.enabled_passes {
+            manager.set_enabled(name, true);
+        }
+
+        for name in &config.disabled_passes {
+            manager.set_enabled(name, false);
+        }
+
+        manager
+    }
+
+    /// Create the pipeline of a named optimization preset
+    ///
+    /// Supported names are `o0`, `o1`, `o2`, `o3`, `size` and `wtf` like the presets of [OptimizeConfig].
+    pub fn from_name(name: &str) -> Option<PassManager> {
+        let config = match name {
+            "o0" => OptimizeConfig::o0(),
+            "o1" => OptimizeConfig::o1(),
+            "o2" => OptimizeConfig::o2(),
+            "o3" => OptimizeConfig::o3(),
+            "size" => OptimizeConfig::size(),
+            "wtf" => OptimizeConfig::wtf(),
+            _ => return None,
+        };
+
+        Some(PassManager::from_config(&config))
+    }
+
+    /// Get builtin pass by name
+    ///
+    /// Use this to repeat builtin passes or to build a pipeline from scratch.
+    pub fn builtin_pass(name: &str) -> Option<Box<dyn OptimizationPass>> {
+        BUILTIN_PASSES
+            .iter()
+            .find(|pass| pass.name == name)
+            .map(|pass| Box::new(*pass) as Box<dyn OptimizationPass>)
+    }
+
+    /// Names of all builtin passes in the order of the default pipeline
+    pub fn builtin_pass_names() -> Vec<&'static str> {
+        BUILTIN_PASSES.iter().map(|pass| pass.name).collect()
+    }
+
+    /// Append pass to the end of the pipeline
+    pub fn add_pass(&mut self, pass: Box<dyn OptimizationPass>) {
+        self.insert_pass(self.passes.len(), pass);
+    }
+
+    /// Insert pass at position of the pipeline
+    ///
+    /// # Panics
+    ///
+    /// Panics if the index is greater than the number of passes.
+    pub fn insert_pass(&mut self, index: usize, pass: Box<dyn OptimizationPass>) {
+        self.passes.insert(
+            index,
+            PassEntry {
+                pass,
+                enabled: true,
+            },
+        );
+    }
+
+    /// Remove all passes with the name and return true if there were any
+    pub fn remove_pass(&mut self, name: &str) -> bool {
+        let len = self.passes.len();
+        self.passes.retain(|entry| entry.pass.name() != name);
+        self.passes.len() != len
+    }
+
+    /// Enable or disable all passes with the name and return true if there were any
+    pub fn set_enabled(&mut self, name: &str, enabled: bool) -> bool {
+        let mut found = false;
+
+        for entry in &mut self.passes {
+            if entry.pass.name() == name {
+                entry.enabled = enabled;
+                found = true;
+            }
+        }
+
+        found
+    }
+
+    /// Names of the enabled passes in the order they run
+    pub fn pass_names(&self) -> Vec<&str> {
+        self.passes
+            .iter()
+            .filter(|entry| entry.enabled)
+            .map(|entry| entry.pass.name())
+            .collect()
+    }
+
+    /// Optimize program and return a report of all passes that ran
+    pub fn run(&self, program: &mut Program, config: &OptimizeConfig) -> OptimizationReport {
+        match self.run_passes(program, config, None) {
+            Ok(report) => report,
+            Err(_) => unreachable!("Passes are only validated with a check config"),
+        }
+    }
+
+    /// Optimize program and check that no pass changes its behavior
+    ///
+    /// After each pass that changed the program the versions before and after the pass are run
+    /// with the [Interpreter](crate::Interpreter) for all inputs of the check config.
+    /// The first pass with a different output or final tape stops the optimization with a
+    /// [ValidationError] containing a minimized reproducer.
+    /// The program is left in the state before that pass.
+    ///
+    /// ```
+    /// # use std::error::Error;
+    /// # fn main() -> Result<(), Box<dyn Error>> {
+    ///     use cranefack::{parse, CheckConfig, OptimizeConfig, PassManager};
+    ///
+    ///     let mut program = parse(",[>+<-]>.")?;
+    ///     let config = OptimizeConfig::o2();
+    ///
+    ///     let passes = PassManager::from_config(&config);
+    ///     passes.run_checked(&mut program, &config, &CheckConfig::default())?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn run_checked(
+        &self,
+        program: &mut Program,
+        config: &OptimizeConfig,
+        check: &CheckConfig,
+    ) -> Result<OptimizationReport, Box<ValidationError>> {
+        self.run_passes(program, config, Some(check))
+    }
+
+    fn run_passes(
+        &self,
+        program: &mut Program,
+        config: &OptimizeConfig,
+        check: Option<&CheckConfig>,
+    ) -> Result<OptimizationReport, Box<ValidationError>> {
+        let mut report = OptimizationReport::default();
+
+        program.ops.insert(0, Op::start());
+
+        let mut context = PassContext {
+            config,
+            iteration: 0,
+            progress: true,
+            remarks: RefCell::new(vec![]),
+        };
+
+        let mut validator = check.map(|check| Validator::new(&program.ops, config, check));
+
+        // Statistics exclude the start marker
+        let collect_statistics = config.statistics || config.debug;
+        let mut statistics = ProgramStatistics::default();
+        if collect_statistics {
+            statistics = ProgramStatistics::from_ops(&program.ops[1..]);
+        }
+
+        while context.iteration < config.max_loops && context.progress {
+            context.progress = false;
+            context.iteration += 1;
+
+            print_debug_iteration(config, context.iteration);
+
+            let mut iteration = IterationReport::default();
+
+            for entry in self.passes.iter().filter(|entry| entry.enabled) {
+                let program_before = validator.as_ref().map(|_| program.clone());
+
+                let ts = config.statistics.then(Instant::now);
+                let progress = entry.pass.run(&mut program.ops, &context);
+                let duration = ts.map(|ts| ts.elapsed()).unwrap_or_default();
+
+                if let (Some(validator), Some(mut program_before)) =
+                    (&mut validator, program_before)
+                {
+                    if program_before.ops != program.ops {
+                        if let Err(error) = validator.validate(
+                            entry.pass.as_ref(),
+                            &context,
+                            &program_before.ops,
+                            &program.ops,
+                        ) {
+                            std::mem::swap(&mut program.ops, &mut program_before.ops);
+                            program.ops.remove(0);
+                            return Err(error);
+                        }
+                    }
+                }
+
+                let before = statistics;
+                if collect_statistics {
+                    statistics = ProgramStatistics::from_ops(&program.ops[1..]);
+                }
+
+                let mut remarks = context.remarks.take();
+                for remark in &mut remarks {
+                    remark.pass = entry.pass.name().to_owned();
+                }
+
+                print_debug(config, entry.pass.name(), &statistics);
+
+                context.progress |= progress;
+
+                iteration.passes.push(PassReport {
+                    name: entry.pass.name().to_owned(),
+                    before,
+                    after: statistics,
+                    duration,
+                    progress,
+                    remarks,
+                });
+            }
+
+            report.iterations.push(iteration);
+        }
+
+        match program.ops.remove(0).op_type {
+            OpType::Start => {
+                // Ignore
+            }
+            // Start marker MUST NOT be removed by any optimization pass
+            other => panic!("Start marker was removed: {:?}", other),
+        }
+
+        Ok(report)
+    }
+}
+
+fn print_debug(config: &OptimizeConfig, pass: &str, statistics: &ProgramStatistics) {
+    if config.debug {
+        let mut writer = StandardStream::stderr(ColorChoice::Auto);
+        writer
+            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
+            .unwrap();
+
+        writeln!(
+            writer,
+            "Pass {} with {} instructions ({},{},{},{}) loops and {} ifs",
+            pass,
+            statistics.op_count,
+            statistics.dloop_count,
+            statistics.lloop_count,
+            statistics.iloop_count,
+            statistics.cloop_count,
+            statistics.if_count,
+        )
+        .unwrap();
+        writer.reset().unwrap();
+    }
+}
+
+fn print_debug_iteration(config: &OptimizeConfig, iteration: usize) {
+    if config.debug {
+        let mut writer = StandardStream::stderr(ColorChoice::Auto);
+        writer
+            .set_color(ColorSpec::new().set_fg(Some(Color::Yellow)))
+            .unwrap();
+
+        writeln!(writer, "Iteration {}", iteration).unwrap();
+        writer.reset().unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::ir::ops::{Op, OpType};
+    use crate::ir::opt_info::BlockInfo;
+    use crate::optimizations::{OptimizationPass, PassContext, PassManager};
+    use crate::{optimize_with_config, parse, OptimizeConfig};
+
+    #[test]
+    fn test_default_pipeline() {
+        let source = "+++[>++<-]>[-]<[>+<-]++[>>[-]<<-].";
+        let config = OptimizeConfig::o2();
+
+        let mut expected = parse(source).unwrap();
+        optimize_with_config(&mut expected, &config);
+
+        let mut program = parse(source).unwrap();
+        PassManager::from_name("o2")
+            .unwrap()
+            .run(&mut program, &config);
+
+        assert_eq!(program.ops, expected.ops);
+        assert!(PassManager::from_name("o4").is_none());
+    }
+
+    #[test]
+    fn test_preset_passes() {
+        let o1 = PassManager::from_name("o1").unwrap();
+        assert!(o1.pass_names().contains(&"zero-loops"));
+        assert!(!o1.pass_names().contains(&"count-loops"));
+        assert!(!o1.pass_names().contains(&"non-local-arithmetics"));
+
+        let o2 = PassManager::from_name("o2").unwrap();
+        assert!(o2.pass_names().contains(&"count-loops"));
+        assert!(o2.pass_names().contains(&"non-local-arithmetics"));
+
+        assert!(PassManager::from_name("o0")
+            .unwrap()
+            .pass_names()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_unique_builtin_pass_names() {
+        let names = PassManager::builtin_pass_names();
+
+        for (index, name) in names.iter().enumerate() {
+            assert!(!names[..index].contains(name), "Duplicate pass {}", name);
+        }
+    }
+
+    #[test]
+    fn test_enable_disable_passes() {
+        let mut config = OptimizeConfig::o1();
+        config.disabled_passes = vec!["zero-loops".to_owned()];
+
+        let mut program = parse("+[-]").unwrap();
+        optimize_with_config(&mut program, &config);
+
+        assert_eq!(
+            program.ops,
+            vec![
+                Op::set(0..1, 1),
+                Op::d_loop(1..4, vec![Op::dec(2..3, 1)], BlockInfo::new_empty())
+            ]
+        );
+
+        config.disabled_passes.clear();
+        config.enabled_passes = vec!["search-zero".to_owned()];
+
+        let mut program = parse("+[>]").unwrap();
+        optimize_with_config(&mut program, &config);
+
+        assert_eq!(
+            program.ops,
+            vec![Op::set(0..1, 1), Op::search_zero(1..4, 1)]
+        );
+    }
+
+    struct CountRuns(AtomicUsize);
+
+    impl OptimizationPass for &'static CountRuns {
+        fn name(&self) -> &str {
+            "count-runs"
+        }
+
+        fn run(&self, ops: &mut Vec<Op>, context: &PassContext) -> bool {
+            assert_eq!(ops[0].op_type, OpType::Start);
+            self.0.fetch_add(1, Ordering::Relaxed);
+            context.iteration < 3
+        }
+    }
+
+    #[test]
+    fn test_custom_pipeline() {
+        static COUNT: CountRuns = CountRuns(AtomicUsize::new(0));
+
+        let mut manager = PassManager::new();
+        manager.add_pass(Box::new(&COUNT));
+        manager.add_pass(PassManager::builtin_pass("zero-loops").unwrap());
+        manager.insert_pass(0, Box::new(&COUNT));
+
+        assert_eq!(
+            manager.pass_names(),
+            vec!["count-runs", "count-runs", "zero-loops"]
+        );
+
+        let mut program = parse("+[-]").unwrap();
+        let report = manager.run(&mut program, &OptimizeConfig::o1());
+
+        assert_eq!(report.iteration_count(), 3);
+        assert_eq!(COUNT.0.load(Ordering::Relaxed), 6);
+        assert_eq!(program.ops, vec![Op::inc(0..1, 1), Op::set(1..3, 0)]);
+
+        assert!(manager.set_enabled("count-runs", false));
+        assert_eq!(manager.pass_names(), vec!["zero-loops"]);
+        assert!(manager.remove_pass("count-runs"));
+        assert!(!manager.remove_pass("count-runs"));
+        assert!(!manager.set_enabled("count-runs", true));
+    }
+}
@@ -1,15 +1,17 @@
 use std::cmp::Ordering;
 
-use crate::ir::ops::{LoopDecrement, Op, OpType};
+use crate::ir::ops::{CellSize, EofMode, LoopDecrement, Op, OpType};
 use crate::ir::opt_info::Cell::Value;
 use crate::ir::opt_info::{BlockInfo, Cell, CellAccess};
+use crate::ir::visitor::{walk_mut, VisitorMut};
 use crate::optimizations::peephole::run_peephole_pass;
+use crate::optimizations::remarks::{Remark, RemarkType};
 use crate::optimizations::utils;
 use crate::optimizations::utils::{
     count_ops_recursive, find_heap_value, find_last_accessing_inc_dec, find_last_put_string,
-    run_non_local_pass, CellValue, Change, OpCodes,
+    run_block_pass, run_block_pass_bottom_up, run_non_local_pass, CellValue, Change, OpCodes,
 };
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 pub fn remove_dead_loops(ops: &mut Vec<Op>) -> bool {
     run_peephole_pass(ops, remove_dead_loops_check)
@@ -35,7 +37,11 @@ fn remove_dead_loops_check(ops: [&Op; 2]) -> Change {
 
 // Replace '[-]' that decreases the current point to 0 with set
 // [+] can be optimized too assuming a wrapping overflow to 0
-pub fn optimize_zero_loops(ops: &mut Vec<Op>) -> bool {
+pub fn optimize_zero_loops(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
+    run_block_pass(ops, |ops| optimize_zero_loops_pass(ops, remarks))
+}
+
+fn optimize_zero_loops_pass(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
     let mut i = 0;
 
     let mut progress = false;
@@ -49,10 +55,9 @@ pub fn optimize_zero_loops(ops: &mut Vec<Op>) -> bool {
             }
 
             if optimized {
+                remarks.push(Remark::new(op.span.clone(), RemarkType::ZeroLoop));
                 ops[i] = Op::set(op.span.start..children[0].span.end, 0);
                 progress = true;
-            } else {
-                progress |= optimize_zero_loops(children);
             }
         }
 
@@ -203,14 +208,14 @@ pub fn optimize_arithmetic_loops(ops: [&Op; 1]) -> Change {
 }
 
 // Optimize loops that are known to use the same counting variable
-pub fn optimize_local_loops(ops: &mut Vec<Op>) -> bool {
-    let mut progress = false;
+pub fn optimize_local_loops(ops: &mut Vec<Op>, cell_size: CellSize, eof_mode: EofMode) -> bool {
+    run_block_pass_bottom_up(ops, |ops| {
+        optimize_local_loops_pass(ops, cell_size, eof_mode)
+    })
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_local_loops(children);
-        }
-    }
+fn optimize_local_loops_pass(ops: &mut Vec<Op>, cell_size: CellSize, eof_mode: EofMode) -> bool {
+    let mut progress = false;
 
     let mut i = 0;
 
@@ -237,16 +242,13 @@ pub fn optimize_local_loops(ops: &mut Vec<Op>) -> bool {
                             ignore = true;
                             break;
                         }
-                        OpType::LLoop(children, ..)
-                        | OpType::ILoop(children, ..)
-                        | OpType::CLoop(children, ..)
-                        | OpType::TNz(children, ..) => {
-                            if !is_ops_block_local(children, &[-ptr_offset]) {
-                                ignore = true;
-                                break;
-                            }
+                        OpType::LLoop(..)
+                        | OpType::ILoop(..)
+                        | OpType::CLoop(..)
+                        | OpType::TNz(..) => {
+                            // Only created from local loops, so all nested ops are local too
                         }
-                        OpType::SearchZero(..) => {
+                        OpType::SearchZero(..) | OpType::Breakpoint => {
                             ignore = true;
                             break;
                         }
@@ -290,7 +292,7 @@ pub fn optimize_local_loops(ops: &mut Vec<Op>) -> bool {
                     children.remove(children.len() - 1);
                 }
 
-                let access = get_loop_access(&children, false);
+                let access = get_loop_access(&children, false, cell_size, eof_mode);
 
                 ops.insert_or_push(i, Op::l_loop(span, children, BlockInfo::new_access(access)));
 
@@ -305,7 +307,12 @@ pub fn optimize_local_loops(ops: &mut Vec<Op>) -> bool {
     progress
 }
 
-fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
+fn get_loop_access(
+    ops: &[Op],
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+    eof_mode: EofMode,
+) -> Vec<CellAccess> {
     let mut access = vec![];
 
     let mut start_offset = 0;
@@ -323,7 +330,7 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 } else {
                     match CellAccess::get(&access, start_offset + offset) {
                         Some(Cell::Bool) => {
-                            if *v > 0 && *v < 254 {
+                            if *v > 0 && *v < cell_size.max_value() - 1 {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + offset,
@@ -338,7 +345,7 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                             }
                         }
                         Some(Cell::Range(start, end)) => {
-                            if 255 - start >= *v {
+                            if cell_size.max_value() - end >= *v {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + offset,
@@ -371,7 +378,7 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 } else {
                     match CellAccess::get(&access, start_offset + dest_offset) {
                         Some(Cell::Bool) => {
-                            if *v > 0 && *v < 254 {
+                            if *v > 0 && *v < cell_size.max_value() - 1 {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + dest_offset,
@@ -386,7 +393,7 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                             }
                         }
                         Some(Cell::Range(start, end)) => {
-                            if 255 - start >= *v {
+                            if cell_size.max_value() - end >= *v {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + dest_offset,
@@ -482,65 +489,19 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 CellAccess::add_with_read(&mut access, start_offset + dest_offset, Cell::Write);
             }
             OpType::LLoop(_, info) | OpType::ILoop(_, _, _, info) | OpType::TNz(_, info) => {
-                if let Some(cell_access) = info.cell_access() {
-                    for cell in cell_access {
-                        if info.always_used() {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            } else {
-                                CellAccess::add(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            }
-                        } else {
-                            CellAccess::add_conditional(
-                                &mut access,
-                                start_offset + cell.offset,
-                                cell.value,
-                                cell.has_read(),
-                            );
-                        }
-                    }
-                }
+                add_block_access(&mut access, start_offset, info);
                 CellAccess::add_with_read(&mut access, start_offset, Cell::Value(0));
             }
             OpType::CLoop(_, _, _, info) => {
-                if let Some(cell_access) = info.cell_access() {
-                    for cell in cell_access {
-                        if info.always_used() {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            } else {
-                                CellAccess::add(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            }
-                        } else {
-                            CellAccess::add_conditional(
-                                &mut access,
-                                start_offset + cell.offset,
-                                cell.value,
-                                cell.has_read(),
-                            );
-                        }
-                    }
-                }
+                add_block_access(&mut access, start_offset, info);
                 CellAccess::add(&mut access, start_offset, Cell::Value(0));
             }
             OpType::GetChar(offset) => {
-                CellAccess::add(&mut access, start_offset + offset, Cell::Write);
+                if eof_mode.overwrites_cell() {
+                    CellAccess::add(&mut access, start_offset + offset, Cell::Write);
+                } else {
+                    CellAccess::add_with_read(&mut access, start_offset + offset, Cell::Write);
+                }
             }
             OpType::PutChar(offset) => {
                 CellAccess::add_with_read(&mut access, start_offset + offset, Cell::Read);
@@ -551,6 +512,7 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
             OpType::SearchZero(_, _) => {
                 unreachable!("Must not be called with zero search in children")
             }
+            OpType::Breakpoint => unreachable!("Must not be called with breakpoints in children"),
             OpType::PutString(..) => {
                 // ignore
             }
@@ -560,7 +522,12 @@ fn get_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
     access
 }
 
-fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
+fn get_d_loop_access(
+    ops: &[Op],
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+    eof_mode: EofMode,
+) -> Vec<CellAccess> {
     let mut access = vec![];
 
     let mut start_offset = 0;
@@ -579,7 +546,7 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 } else {
                     match CellAccess::get(&access, start_offset + offset) {
                         Some(Cell::Bool) => {
-                            if *v > 0 && *v < 254 {
+                            if *v > 0 && *v < cell_size.max_value() - 1 {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + offset,
@@ -594,7 +561,7 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                             }
                         }
                         Some(Cell::Range(start, end)) => {
-                            if 255 - start >= *v {
+                            if cell_size.max_value() - end >= *v {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + offset,
@@ -627,7 +594,7 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 } else {
                     match CellAccess::get(&access, start_offset + dest_offset) {
                         Some(Cell::Bool) => {
-                            if *v > 0 && *v < 254 {
+                            if *v > 0 && *v < cell_size.max_value() - 1 {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + dest_offset,
@@ -642,7 +609,7 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                             }
                         }
                         Some(Cell::Range(start, end)) => {
-                            if 255 - start >= *v {
+                            if cell_size.max_value() - end >= *v {
                                 CellAccess::add_with_read(
                                     &mut access,
                                     start_offset + dest_offset,
@@ -738,65 +705,19 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                 CellAccess::add_with_read(&mut access, start_offset + dest_offset, Cell::Write);
             }
             OpType::LLoop(_, info) | OpType::ILoop(_, _, _, info) | OpType::TNz(_, info) => {
-                if let Some(cell_access) = info.cell_access() {
-                    for cell in cell_access {
-                        if info.always_used() {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            } else {
-                                CellAccess::add(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            }
-                        } else {
-                            CellAccess::add_conditional(
-                                &mut access,
-                                start_offset + cell.offset,
-                                cell.value,
-                                cell.has_read(),
-                            );
-                        }
-                    }
-                }
+                add_block_access(&mut access, start_offset, info);
                 CellAccess::add_with_read(&mut access, start_offset, Cell::Value(0));
             }
             OpType::CLoop(_, _, _, info) => {
-                if let Some(cell_access) = info.cell_access() {
-                    for cell in cell_access {
-                        if info.always_used() {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            } else {
-                                CellAccess::add(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            }
-                        } else {
-                            CellAccess::add_conditional(
-                                &mut access,
-                                start_offset + cell.offset,
-                                cell.value,
-                                cell.has_read(),
-                            );
-                        }
-                    }
-                }
+                add_block_access(&mut access, start_offset, info);
                 CellAccess::add(&mut access, start_offset, Cell::Value(0));
             }
             OpType::GetChar(offset) => {
-                CellAccess::add(&mut access, start_offset + offset, Cell::Write);
+                if eof_mode.overwrites_cell() {
+                    CellAccess::add(&mut access, start_offset + offset, Cell::Write);
+                } else {
+                    CellAccess::add_with_read(&mut access, start_offset + offset, Cell::Write);
+                }
             }
             OpType::PutChar(offset) => {
                 CellAccess::add_with_read(&mut access, start_offset + offset, Cell::Read);
@@ -809,47 +730,43 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                     access.clear();
 
                     if let Some(cell_access) = info.cell_access() {
-                        for cell in cell_access {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            } else {
-                                CellAccess::add(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    cell.value,
-                                );
-                            }
-                        }
+                        add_nested_access(
+                            &mut access,
+                            start_offset,
+                            cell_access,
+                            |access, offset, cell| {
+                                if cell.has_read() {
+                                    CellAccess::add_with_read(access, offset, cell.value);
+                                } else {
+                                    CellAccess::add(access, offset, cell.value);
+                                }
+                            },
+                        );
                     }
                 } else if let Some(cell_access) = info.cell_access() {
                     let old_access = access;
                     access = vec![];
 
-                    for cell in cell_access {
-                        if let Some(value) =
-                            CellAccess::get(&old_access, start_offset + cell.offset)
-                        {
-                            if cell.has_read() {
-                                CellAccess::add_with_read(
-                                    &mut access,
-                                    start_offset + cell.offset,
-                                    value,
+                    add_nested_access(
+                        &mut access,
+                        start_offset,
+                        cell_access,
+                        |access, offset, cell| {
+                            if let Some(value) = CellAccess::get(&old_access, offset) {
+                                if cell.has_read() {
+                                    CellAccess::add_with_read(access, offset, value);
+                                } else {
+                                    CellAccess::add(access, offset, value);
+                                }
+                                CellAccess::add_conditional(
+                                    access,
+                                    offset,
+                                    cell.value,
+                                    cell.has_read(),
                                 );
-                            } else {
-                                CellAccess::add(&mut access, start_offset + cell.offset, value);
                             }
-                            CellAccess::add_conditional(
-                                &mut access,
-                                start_offset + cell.offset,
-                                cell.value,
-                                cell.has_read(),
-                            );
-                        }
-                    }
+                        },
+                    );
                 } else {
                     access.clear();
                 }
@@ -864,7 +781,7 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
                     CellAccess::add_with_read(&mut access, -*step, Cell::NonZero);
                 }
             }
-            OpType::PutString(..) => {
+            OpType::PutString(..) | OpType::Breakpoint => {
                 // ignore
             }
         }
@@ -881,81 +798,75 @@ fn get_d_loop_access(ops: &[Op], wrapping_is_ub: bool) -> Vec<CellAccess> {
     access
 }
 
-fn is_ops_block_local(ops: &[Op], parent_offsets: &[isize]) -> bool {
-    let mut ptr_offset = 0_isize;
-
-    for op in ops {
-        match &op.op_type {
-            OpType::Start => {
-                // ignore
-            }
-            OpType::IncPtr(value) => {
-                ptr_offset += *value as isize;
-            }
-            OpType::DecPtr(value) => {
-                ptr_offset -= *value as isize;
-            }
-            OpType::LLoop(children, ..)
-            | OpType::ILoop(children, ..)
-            | OpType::CLoop(children, ..)
-            | OpType::TNz(children, ..) => {
-                let mut offsets = parent_offsets.to_vec();
-
-                offsets.push(-ptr_offset);
-
-                if !is_ops_block_local(children, &offsets) {
-                    return false;
+/// Add the cell access of a nested block to the cell access of the surrounding block
+fn add_block_access(access: &mut Vec<CellAccess>, start_offset: isize, info: &BlockInfo) {
+    if let Some(cell_access) = info.cell_access() {
+        add_nested_access(access, start_offset, cell_access, |access, offset, cell| {
+            if info.always_used() {
+                if cell.has_read() {
+                    CellAccess::add_with_read(access, offset, cell.value);
+                } else {
+                    CellAccess::add(access, offset, cell.value);
                 }
+            } else {
+                CellAccess::add_conditional(access, offset, cell.value, cell.has_read());
             }
-            OpType::DLoop(..) | OpType::DTNz(..) | OpType::SearchZero(..) => {
-                return false;
-            }
-            OpType::PutChar(..)
-            | OpType::PutString(..)
-            | OpType::GetChar(..)
-            | OpType::Inc(..)
-            | OpType::Dec(..)
-            | OpType::Set(..)
-            | OpType::Add(..)
-            | OpType::Sub(..)
-            | OpType::CAdd(..)
-            | OpType::CSub(..)
-            | OpType::Move(..)
-            | OpType::NzAdd(..)
-            | OpType::NzSub(..)
-            | OpType::NzCAdd(..)
-            | OpType::NzCSub(..)
-            | OpType::Mul(..)
-            | OpType::NzMul(..)
-            | OpType::Copy(..) => {
-                // ignore
-            }
-        }
+        });
     }
+}
 
-    true
+/// Add each cell of a nested block at the start offset with the add function
+///
+/// The cells of a block are unique, so only cells that were accessed before the nested block
+/// need to be searched. Otherwise blocks with many cells would take quadratic time for each
+/// level of nesting.
+fn add_nested_access<F>(
+    access: &mut Vec<CellAccess>,
+    start_offset: isize,
+    nested: &[CellAccess],
+    add: F,
+) where
+    F: Fn(&mut Vec<CellAccess>, isize, &CellAccess),
+{
+    let known: HashSet<isize> = access.iter().map(|cell| cell.offset).collect();
+    let mut added = vec![];
+
+    for cell in nested {
+        let offset = start_offset + cell.offset;
+
+        if known.contains(&offset) {
+            add(access, offset, cell);
+        } else {
+            add(&mut added, offset, cell);
+            access.append(&mut added);
+        }
+    }
 }
 
 // Optimize loops that are known to use the same counting variable
-pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
-    if ops.is_empty() {
-        return false;
-    }
+pub fn optimize_count_loops(
+    ops: &mut Vec<Op>,
+    cell_size: CellSize,
+    remarks: &mut Vec<Remark>,
+) -> bool {
+    run_block_pass_bottom_up(ops, |ops| {
+        optimize_count_loops_pass(ops, cell_size, remarks)
+    })
+}
 
+fn optimize_count_loops_pass(
+    ops: &mut Vec<Op>,
+    cell_size: CellSize,
+    remarks: &mut Vec<Remark>,
+) -> bool {
     let mut progress = false;
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_count_loops(children);
-        }
-    }
-
     let mut i = 0;
 
     while !ops.is_empty() && !ops.is_empty() && i < ops.len() {
         let replace = if let OpType::LLoop(children, _) = &mut ops[i].op_type {
             let mut ptr_offset = 0;
-            let mut ignore = false;
+            let mut ignore = None;
             let mut counter_decrements = vec![];
             let mut counter_reads = vec![];
 
@@ -977,12 +888,12 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                     | OpType::Mul(src_offset, dest_offset, _)
                     | OpType::Move(src_offset, dest_offset) => {
                         if ptr_offset + src_offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
 
                         if ptr_offset + dest_offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
                     }
@@ -991,7 +902,7 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                     | OpType::NzMul(src_offset, dest_offset, _)
                     | OpType::Copy(src_offset, dest_offset) => {
                         if ptr_offset + dest_offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
 
@@ -1001,7 +912,7 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                     }
                     OpType::NzCAdd(_, dest_offset, _) | OpType::NzCSub(_, dest_offset, _) => {
                         if ptr_offset + dest_offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
                     }
@@ -1014,13 +925,13 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
 
                     OpType::Inc(offset, _) | OpType::Set(offset, _) => {
                         if ptr_offset + offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
                     }
                     OpType::GetChar(offset) => {
                         if ptr_offset + offset == 0 {
-                            ignore = true;
+                            ignore = Some("counter cell is modified in the loop");
                             break;
                         }
                     }
@@ -1030,24 +941,24 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                         }
                     }
                     OpType::DLoop(..) | OpType::DTNz(..) | OpType::LLoop(..) => {
-                        ignore = true;
+                        ignore = Some("loop contains a nested loop with unknown iterations");
                         break;
                     }
                     OpType::ILoop(children, ..)
                     | OpType::CLoop(children, ..)
                     | OpType::TNz(children, ..) => {
                         if ptr_offset == 0 {
-                            ignore = true;
+                            ignore = Some("nested loop starts at the counter cell");
                             break;
                         }
 
                         if !is_ops_block_unmodified_local(children, &[-ptr_offset]) {
-                            ignore = true;
+                            ignore = Some("nested loop may modify the counter cell");
                             break;
                         }
                     }
-                    OpType::SearchZero(..) => {
-                        ignore = true;
+                    OpType::SearchZero(..) | OpType::Breakpoint => {
+                        ignore = Some("loop contains a search for zero or a breakpoint");
                         break;
                     }
                     OpType::PutString(..) => {
@@ -1057,7 +968,13 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
             }
 
             #[allow(clippy::manual_map)]
-            if !ignore {
+            if let Some(reason) = ignore {
+                remarks.push(Remark::new(
+                    ops[i].span.clone(),
+                    RemarkType::MissedCountLoop(reason),
+                ));
+                None
+            } else {
                 if !counter_reads.is_empty() {
                     let min_counter = counter_decrements
                         .iter()
@@ -1078,13 +995,17 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                     } else if min_counter > max_read {
                         Some((counter_decrements, LoopDecrement::Post))
                     } else {
+                        remarks.push(Remark::new(
+                            ops[i].span.clone(),
+                            RemarkType::MissedCountLoop(
+                                "counter cell is read between its decrements",
+                            ),
+                        ));
                         None
                     }
                 } else {
                     Some((counter_decrements, LoopDecrement::Auto))
                 }
-            } else {
-                None
             }
         } else {
             None
@@ -1098,7 +1019,7 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                 let mut step = 0;
 
                 for (index, amount) in counter_decrements.iter().rev() {
-                    step += *amount;
+                    step = cell_size.wrapping_add(step, *amount);
                     children.remove(*index);
                 }
 
@@ -1109,8 +1030,10 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
                 }
 
                 if step == 1 && children.is_empty() {
+                    remarks.push(Remark::new(span.clone(), RemarkType::ZeroLoop));
                     ops.insert_or_push(i, Op::set(span, 0));
                 } else {
+                    remarks.push(Remark::new(span.clone(), RemarkType::CountLoop { step }));
                     ops.insert_or_push(
                         i,
                         Op::i_loop_with_decrement(span, children, step, loop_decrement, info),
@@ -1129,18 +1052,47 @@ pub fn optimize_count_loops(ops: &mut Vec<Op>) -> bool {
 }
 
 fn is_ops_block_unmodified_local(ops: &[Op], parent_offsets: &[isize]) -> bool {
-    let mut ptr_offset = 0_isize;
+    // Cells that must not be modified relative to the start of the outermost block.
+    // The start cell of each block with a nested block is added while the nested block is checked.
+    let mut parent_cells: HashMap<isize, usize> = HashMap::new();
+
+    for offset in parent_offsets {
+        *parent_cells.entry(*offset).or_default() += 1;
+    }
+
+    // Each entry holds the remaining ops of a block, its start cell and the current pointer offset
+    let mut blocks = vec![(ops.iter(), 0_isize, 0_isize)];
+
+    while let Some((ops, start_offset, ptr_offset)) = blocks.last_mut() {
+        let op = if let Some(op) = ops.next() {
+            op
+        } else {
+            blocks.pop();
+
+            if let Some((_, start_offset, _)) = blocks.last() {
+                if let Some(count) = parent_cells.get_mut(start_offset) {
+                    *count -= 1;
+
+                    if *count == 0 {
+                        parent_cells.remove(start_offset);
+                    }
+                }
+            }
+
+            continue;
+        };
+
+        let offset = *start_offset + *ptr_offset;
 
-    for op in ops {
         match &op.op_type {
             OpType::Start => {
                 // ignore
             }
             OpType::IncPtr(value) => {
-                ptr_offset += *value as isize;
+                *ptr_offset += *value as isize;
             }
             OpType::DecPtr(value) => {
-                ptr_offset -= *value as isize;
+                *ptr_offset -= *value as isize;
             }
             OpType::Add(src_offset, dest_offset, _)
             | OpType::Sub(src_offset, dest_offset, _)
@@ -1148,16 +1100,10 @@ fn is_ops_block_unmodified_local(ops: &[Op], parent_offsets: &[isize]) -> bool {
             | OpType::CSub(src_offset, dest_offset, _)
             | OpType::Mul(src_offset, dest_offset, _)
             | OpType::Move(src_offset, dest_offset) => {
-                for parent_offset in parent_offsets {
-                    if ptr_offset + src_offset == *parent_offset {
-                        return false;
-                    }
-                }
-
-                for parent_offset in parent_offsets {
-                    if ptr_offset + dest_offset == *parent_offset {
-                        return false;
-                    }
+                if parent_cells.contains_key(&(offset + src_offset))
+                    || parent_cells.contains_key(&(offset + dest_offset))
+                {
+                    return false;
                 }
             }
             OpType::NzAdd(_, dest_offset, _)
@@ -1165,39 +1111,26 @@ fn is_ops_block_unmodified_local(ops: &[Op], parent_offsets: &[isize]) -> bool {
             | OpType::NzCAdd(_, dest_offset, _)
             | OpType::NzCSub(_, dest_offset, _)
             | OpType::NzMul(_, dest_offset, _)
-            | OpType::Copy(_, dest_offset) => {
-                for parent_offset in parent_offsets {
-                    if ptr_offset + dest_offset == *parent_offset {
-                        return false;
-                    }
-                }
-            }
-            OpType::Inc(offset, _) | OpType::Dec(offset, _) | OpType::Set(offset, _) => {
-                for parent_offset in parent_offsets {
-                    if ptr_offset + offset == *parent_offset {
-                        return false;
-                    }
-                }
-            }
-            OpType::GetChar(offset) => {
-                for parent_offset in parent_offsets {
-                    if ptr_offset + offset == *parent_offset {
-                        return false;
-                    }
+            | OpType::Copy(_, dest_offset)
+            | OpType::Inc(dest_offset, _)
+            | OpType::Dec(dest_offset, _)
+            | OpType::Set(dest_offset, _)
+            | OpType::GetChar(dest_offset) => {
+                if parent_cells.contains_key(&(offset + dest_offset)) {
+                    return false;
                 }
             }
             OpType::ILoop(children, ..)
             | OpType::CLoop(children, ..)
             | OpType::TNz(children, ..) => {
-                let mut offsets = parent_offsets.to_vec();
-
-                offsets.push(-ptr_offset);
-
-                if !is_ops_block_unmodified_local(children, &offsets) {
-                    return false;
-                }
+                *parent_cells.entry(*start_offset).or_default() += 1;
+                blocks.push((children.iter(), offset, 0));
             }
-            OpType::DLoop(..) | OpType::DTNz(..) | OpType::LLoop(..) | OpType::SearchZero(..) => {
+            OpType::DLoop(..)
+            | OpType::DTNz(..)
+            | OpType::LLoop(..)
+            | OpType::SearchZero(..)
+            | OpType::Breakpoint => {
                 return false;
             }
             OpType::PutChar(..) | OpType::PutString(..) => {
@@ -1209,18 +1142,18 @@ fn is_ops_block_unmodified_local(ops: &[Op], parent_offsets: &[isize]) -> bool {
     true
 }
 
-pub fn optimize_arithmetics(ops: [&Op; 2]) -> Change {
+pub fn optimize_arithmetics(ops: [&Op; 2], cell_size: CellSize) -> Change {
     match (&ops[0].op_type, &ops[1].op_type) {
         (OpType::Inc(offset, v1), OpType::Inc(offset2, v2)) => {
             if *offset == *offset2 {
-                Change::Replace(vec![OpType::Inc(*offset, v1.wrapping_add(*v2))])
+                Change::Replace(vec![OpType::Inc(*offset, cell_size.wrapping_add(*v1, *v2))])
             } else {
                 Change::Ignore
             }
         }
         (OpType::Dec(offset, v1), OpType::Dec(offset2, v2)) => {
             if *offset == *offset2 {
-                Change::Replace(vec![OpType::Dec(*offset, v1.wrapping_add(*v2))])
+                Change::Replace(vec![OpType::Dec(*offset, cell_size.wrapping_add(*v1, *v2))])
             } else {
                 Change::Ignore
             }
@@ -1265,21 +1198,25 @@ pub fn optimize_arithmetics(ops: [&Op; 2]) -> Change {
         },
         (OpType::Set(offset, v1), OpType::Inc(offset2, v2)) => {
             if *offset == *offset2 {
-                Change::Replace(vec![OpType::Set(*offset, v1.wrapping_add(*v2))])
+                Change::Replace(vec![OpType::Set(*offset, cell_size.wrapping_add(*v1, *v2))])
             } else {
                 Change::Ignore
             }
         }
         (OpType::Set(offset, v1), OpType::Dec(offset2, v2)) => {
             if *offset == *offset2 {
-                Change::Replace(vec![OpType::Set(*offset, v1.wrapping_sub(*v2))])
+                Change::Replace(vec![OpType::Set(*offset, cell_size.wrapping_sub(*v1, *v2))])
             } else {
                 Change::Ignore
             }
         }
         (OpType::Set(offset, v1), OpType::PutChar(offset2)) => {
             if *offset == *offset2 {
-                Change::ReplaceOffset(1, ops[1].span.clone(), vec![OpType::PutString(vec![*v1])])
+                Change::ReplaceOffset(
+                    1,
+                    ops[1].span.clone(),
+                    vec![OpType::PutString(vec![*v1 as u8])],
+                )
             } else {
                 Change::Ignore
             }
@@ -1301,7 +1238,7 @@ pub fn optimize_arithmetics(ops: [&Op; 2]) -> Change {
                 Change::ReplaceOffset(
                     1,
                     ops[1].span.clone(),
-                    vec![OpType::Set(*offset, 0u8.wrapping_sub(*v))],
+                    vec![OpType::Set(*offset, cell_size.wrapping_sub(0, *v))],
                 )
             } else {
                 Change::Ignore
@@ -1381,14 +1318,34 @@ pub fn optimize_arithmetics(ops: [&Op; 2]) -> Change {
     }
 }
 
-pub fn remove_dead_stores_before_set(ops: &mut Vec<Op>) -> bool {
+pub fn remove_dead_stores_before_set(
+    ops: &mut Vec<Op>,
+    eof_mode: EofMode,
+    remarks: &mut Vec<Remark>,
+) -> bool {
+    run_block_pass(ops, |ops| {
+        remove_dead_stores_before_set_pass(ops, eof_mode, remarks)
+    })
+}
+
+fn remove_dead_stores_before_set_pass(
+    ops: &mut Vec<Op>,
+    eof_mode: EofMode,
+    remarks: &mut Vec<Remark>,
+) -> bool {
     let mut i = 0;
 
     let mut progress = false;
 
     while !ops.is_empty() && i < ops.len() - 1 {
-        let dest_offset = ops[i + 1].op_type.get_overwriting_dest_offset();
-        let unread_zeroing_offset = ops[i + 1].op_type.get_unread_zeroing_src_offset();
+        let (dest_offset, unread_zeroing_offset) = match &ops[i + 1].op_type {
+            // The previous value is kept if the input reached end of file
+            OpType::GetChar(_) if !eof_mode.overwrites_cell() => (None, None),
+            op_type => (
+                op_type.get_overwriting_dest_offset(),
+                op_type.get_unread_zeroing_src_offset(),
+            ),
+        };
 
         let op1 = &mut ops[i];
 
@@ -1455,7 +1412,8 @@ pub fn remove_dead_stores_before_set(ops: &mut Vec<Op>) -> bool {
         };
 
         if remove {
-            ops.remove(i);
+            let op = ops.remove(i);
+            remarks.push(Remark::new(op.span, RemarkType::DeadStore));
             progress = true;
         } else {
             if let Some(unread_zeroing_offset) = unread_zeroing_offset {
@@ -1506,16 +1464,14 @@ pub fn remove_dead_stores_before_set(ops: &mut Vec<Op>) -> bool {
         }
     }
 
-    for op in ops {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= remove_dead_stores_before_set(children);
-        }
-    }
-
     progress
 }
 
-pub fn optimize_static_count_loops(ops: &mut Vec<Op>) -> bool {
+pub fn optimize_static_count_loops(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
+    run_block_pass(ops, |ops| optimize_static_count_loops_pass(ops, remarks))
+}
+
+fn optimize_static_count_loops_pass(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
     let mut i = 0;
 
     let mut progress = false;
@@ -1525,8 +1481,11 @@ pub fn optimize_static_count_loops(ops: &mut Vec<Op>) -> bool {
         let op2 = &ops[i + 1];
 
         let count = match (&op1.op_type, &op2.op_type) {
-            (OpType::Set(0, v), OpType::ILoop(_, step, ..)) => {
-                if *step != 0 && v % step == 0 {
+            (OpType::Set(0, v), OpType::ILoop(_, step, decrement, _)) => {
+                // The counter of a CLoop is decremented by one so the body must not see it
+                // unless the step matches
+                if *step != 0 && v % step == 0 && (*step == 1 || *decrement == LoopDecrement::Auto)
+                {
                     Some(v / step)
                 } else {
                     None
@@ -1536,11 +1495,18 @@ pub fn optimize_static_count_loops(ops: &mut Vec<Op>) -> bool {
         };
 
         if let Some(count) = count {
+            progress = true;
+
             let prev = ops.remove(i);
             if count == 0 {
-                ops.remove(i);
+                let loop_op = ops.remove(i);
+                remarks.push(Remark::new(loop_op.span, RemarkType::DeadLoop));
             } else {
                 let loop_op = ops.remove(i);
+                remarks.push(Remark::new(
+                    loop_op.span.clone(),
+                    RemarkType::ConstantLoop { iterations: count },
+                ));
                 let span = prev.span.start..loop_op.span.end;
 
                 if let OpType::ILoop(children, _, decrements, access) = loop_op.op_type {
@@ -1554,30 +1520,26 @@ pub fn optimize_static_count_loops(ops: &mut Vec<Op>) -> bool {
 
                 i += 1;
             }
-        } else if let Some(children) = ops[i].op_type.get_children_mut() {
-            progress |= optimize_static_count_loops(children);
-            i += 1;
         } else {
             i += 1;
         }
     }
 
-    if let Some(last) = ops.last_mut() {
-        if let Some(children) = last.op_type.get_children_mut() {
-            progress |= optimize_static_count_loops(children);
-        }
-    }
-
     progress
 }
 
-pub fn optimize_non_local_static_count_loops(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
+pub fn optimize_non_local_static_count_loops(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
         optimize_non_local_static_count_loops_pass,
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -1586,6 +1548,7 @@ pub fn optimize_non_local_static_count_loops_pass(
     zeroing: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool {
     let mut progress = false;
 
@@ -1595,7 +1558,9 @@ pub fn optimize_non_local_static_count_loops_pass(
         let op = &ops[i];
 
         let count = match &op.op_type {
-            OpType::ILoop(_, step, ..) => {
+            OpType::ILoop(_, step, decrement, _)
+                if *step == 1 || *decrement == LoopDecrement::Auto =>
+            {
                 if let CellValue::Value(v) = utils::find_heap_value(
                     ops,
                     0,
@@ -1603,6 +1568,7 @@ pub fn optimize_non_local_static_count_loops_pass(
                     zeroing,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
                     if *step != 0 && v % step == 0 {
@@ -1624,7 +1590,7 @@ pub fn optimize_non_local_static_count_loops_pass(
             if let OpType::ILoop(children, _, decrement, info) = loop_op.op_type {
                 if decrement == LoopDecrement::Auto && count == 1 {
                     ops.insert_or_push(i, Op::t_nz(span, children, info));
-                } else if count > 1 {
+                } else if count > 0 {
                     ops.insert_or_push(
                         i,
                         Op::c_loop_with_decrement(span, children, count, decrement, info),
@@ -1642,13 +1608,18 @@ pub fn optimize_non_local_static_count_loops_pass(
     progress
 }
 
-pub fn optimize_non_local_conditional_loops(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
+pub fn optimize_non_local_conditional_loops(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
         optimize_non_local_conditional_loops_pass,
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -1657,6 +1628,7 @@ fn optimize_non_local_conditional_loops_pass(
     zeroing: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool {
     let mut progress = false;
 
@@ -1675,6 +1647,7 @@ fn optimize_non_local_conditional_loops_pass(
                         zeroing,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
                     matches!(v, CellValue::Bool)
@@ -1682,9 +1655,15 @@ fn optimize_non_local_conditional_loops_pass(
                     false
                 }
             }
-            OpType::DLoop(children, _) => {
-                is_zeroing_d_loop(children, ops, i as isize, zeroing, inputs, wrapping_is_ub)
-            }
+            OpType::DLoop(children, _) => is_zeroing_d_loop(
+                children,
+                ops,
+                i as isize,
+                zeroing,
+                inputs,
+                wrapping_is_ub,
+                cell_size,
+            ),
             _ => false,
         };
 
@@ -1717,6 +1696,7 @@ pub fn is_zeroing_d_loop(
     zeroing: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool {
     let mut ptr_offset = 0;
 
@@ -1764,7 +1744,7 @@ pub fn is_zeroing_d_loop(
                     }
                 }
             }
-            OpType::Start | OpType::PutString(_) | OpType::PutChar(_) => {
+            OpType::Start | OpType::PutString(_) | OpType::PutChar(_) | OpType::Breakpoint => {
                 // Ignore
             }
             OpType::SearchZero(..) => {
@@ -1786,6 +1766,7 @@ pub fn is_zeroing_d_loop(
                         zeroing,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     ) == CellValue::Value(0)
                     {
@@ -1807,6 +1788,7 @@ pub fn is_zeroing_d_loop(
         zeroing,
         inputs,
         wrapping_is_ub,
+        cell_size,
         true,
     )
     .is_zero()
@@ -1814,13 +1796,11 @@ pub fn is_zeroing_d_loop(
 
 // Replace loops only containing constant sets with TNz
 pub fn optimize_conditional_loops(ops: &mut Vec<Op>) -> bool {
-    let mut progress = false;
+    run_block_pass_bottom_up(ops, optimize_conditional_loops_pass)
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_conditional_loops(children);
-        }
-    }
+fn optimize_conditional_loops_pass(ops: &mut Vec<Op>) -> bool {
+    let mut progress = false;
 
     let mut i = 0;
 
@@ -1916,7 +1896,11 @@ fn contains_only_constant_sets(ops: &[Op]) -> bool {
     true
 }
 
-pub fn optimize_search_zero(ops: &mut Vec<Op>) -> bool {
+pub fn optimize_search_zero(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
+    run_block_pass(ops, |ops| optimize_search_zero_pass(ops, remarks))
+}
+
+fn optimize_search_zero_pass(ops: &mut Vec<Op>, remarks: &mut Vec<Remark>) -> bool {
     let mut i = 0;
 
     let mut progress = false;
@@ -1942,11 +1926,10 @@ pub fn optimize_search_zero(ops: &mut Vec<Op>) -> bool {
         if let Some(step) = replace {
             let span = ops[i].span.clone();
 
+            remarks.push(Remark::new(span.clone(), RemarkType::SearchZero { step }));
             ops[i] = Op::search_zero(span, step);
 
             progress = true;
-        } else if let Some(children) = ops[i].op_type.get_children_mut() {
-            progress |= optimize_search_zero(children);
         }
 
         i += 1;
@@ -1955,7 +1938,13 @@ pub fn optimize_search_zero(ops: &mut Vec<Op>) -> bool {
     progress
 }
 
-pub fn optimize_constant_arithmetic_loop(ops: &mut Vec<Op>) -> bool {
+pub fn optimize_constant_arithmetic_loop(ops: &mut Vec<Op>, cell_size: CellSize) -> bool {
+    run_block_pass(ops, |ops| {
+        optimize_constant_arithmetic_loop_pass(ops, cell_size)
+    })
+}
+
+fn optimize_constant_arithmetic_loop_pass(ops: &mut Vec<Op>, cell_size: CellSize) -> bool {
     let mut i = 0;
 
     let mut progress = false;
@@ -1970,6 +1959,8 @@ pub fn optimize_constant_arithmetic_loop(ops: &mut Vec<Op>) -> bool {
         };
 
         if replace {
+            progress = true;
+
             let prev = ops.remove(i);
             let span = prev.span;
 
@@ -1997,7 +1988,7 @@ pub fn optimize_constant_arithmetic_loop(ops: &mut Vec<Op>) -> bool {
                     for mut child in children {
                         match &mut child.op_type {
                             OpType::Inc(_, v) | OpType::Dec(_, v) => {
-                                *v = v.wrapping_mul(iterations)
+                                *v = cell_size.wrapping_mul(*v, iterations)
                             }
                             OpType::IncPtr(v) => ptr_offset += *v as isize,
                             OpType::DecPtr(v) => ptr_offset -= *v as isize,
@@ -2017,25 +2008,21 @@ pub fn optimize_constant_arithmetic_loop(ops: &mut Vec<Op>) -> bool {
                             pos,
                             Op::dec_ptr(span.end - 1..span.end, ptr_offset as usize),
                         );
+                        pos += 1;
                     } else if ptr_offset < 0 {
                         ops.insert_or_push(
                             pos,
                             Op::inc_ptr(span.end - 1..span.end, -ptr_offset as usize),
                         );
+                        pos += 1;
                     }
 
-                    pos += 1;
-
                     ops.insert_or_push(pos, Op::set(span.end - 1..span.end, 0));
                 }
                 _ => unreachable!(),
             }
         }
 
-        if let Some(children) = ops[i].op_type.get_children_mut() {
-            progress |= optimize_constant_arithmetic_loop(children);
-        }
-
         i += 1;
     }
 
@@ -2051,14 +2038,14 @@ fn contains_only_simple_arithmetics(ops: &[Op]) -> bool {
     true
 }
 
-pub fn optimize_constant_arithmetics(ops: [&Op; 2]) -> Change {
+pub fn optimize_constant_arithmetics(ops: [&Op; 2], cell_size: CellSize) -> Change {
     match (&ops[0].op_type, &ops[1].op_type) {
         (OpType::Set(offset, value), OpType::Add(src_offset, dest_offset, multi)) => {
             if *offset == *src_offset {
                 Change::Replace(vec![OpType::CAdd(
                     *src_offset,
                     *dest_offset,
-                    value.wrapping_mul(*multi),
+                    cell_size.wrapping_mul(*value, *multi),
                 )])
             } else {
                 Change::Ignore
@@ -2069,7 +2056,7 @@ pub fn optimize_constant_arithmetics(ops: [&Op; 2]) -> Change {
                 Change::Replace(vec![OpType::CSub(
                     *src_offset,
                     *dest_offset,
-                    value.wrapping_mul(*multi),
+                    cell_size.wrapping_mul(*value, *multi),
                 )])
             } else {
                 Change::Ignore
@@ -2080,6 +2067,19 @@ pub fn optimize_constant_arithmetics(ops: [&Op; 2]) -> Change {
 }
 
 pub fn optimize_offsets(ops: &mut Vec<Op>, start_offset: isize) -> bool {
+    let mut start_offset = start_offset;
+
+    run_block_pass(ops, |ops| {
+        let progress = optimize_offsets_pass(ops, start_offset);
+
+        // The ops of nested blocks are relative to the start of the block
+        start_offset = 0;
+
+        progress
+    })
+}
+
+fn optimize_offsets_pass(ops: &mut Vec<Op>, start_offset: isize) -> bool {
     if ops.is_empty() {
         return false;
     }
@@ -2152,7 +2152,8 @@ pub fn optimize_offsets(ops: &mut Vec<Op>, start_offset: isize) -> bool {
                 | OpType::ILoop(..)
                 | OpType::CLoop(..)
                 | OpType::TNz(..)
-                | OpType::SearchZero(..) => {
+                | OpType::SearchZero(..)
+                | OpType::Breakpoint => {
                     break;
                 }
             };
@@ -2185,12 +2186,6 @@ pub fn optimize_offsets(ops: &mut Vec<Op>, start_offset: isize) -> bool {
         i += 1;
     }
 
-    for op in ops {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_offsets(children, 0);
-        }
-    }
-
     progress
 }
 
@@ -2289,32 +2284,44 @@ pub fn optimize_arithmetic_offsets(ops: [&Op; 3]) -> Change {
 }
 
 pub fn remove_trailing_pointer_ops(ops: &mut Vec<Op>, in_framed_block: bool) -> bool {
-    let mut progress = false;
-
-    if in_framed_block {
-        while !ops.is_empty() && ops[ops.len() - 1].op_type.is_ptr_inc_or_dec() {
-            ops.remove(ops.len() - 1);
-            progress = true;
-        }
+    struct TrailingPointerOps {
+        progress: bool,
     }
 
-    for op in ops {
-        match &mut op.op_type {
-            OpType::LLoop(children, ..)
-            | OpType::ILoop(children, ..)
-            | OpType::CLoop(children, ..)
-            | OpType::TNz(children, ..) => {
-                progress |= remove_trailing_pointer_ops(children, true);
-            }
-            OpType::DLoop(children, _) | OpType::DTNz(children, _, _) => {
-                progress |= remove_trailing_pointer_ops(children, false);
-            }
-            _ => {
-                //ignore
+    impl VisitorMut for TrailingPointerOps {
+        fn visit_op_mut(&mut self, op: &mut Op, _depth: usize) -> bool {
+            match &mut op.op_type {
+                OpType::LLoop(children, ..)
+                | OpType::ILoop(children, ..)
+                | OpType::CLoop(children, ..)
+                | OpType::TNz(children, ..) => {
+                    self.progress |= remove_framed_trailing_pointer_ops(children);
+                }
+                _ => {
+                    //ignore
+                }
             }
+
+            true
         }
     }
 
+    let mut visitor = TrailingPointerOps {
+        progress: in_framed_block && remove_framed_trailing_pointer_ops(ops),
+    };
+    walk_mut(&mut visitor, ops);
+
+    visitor.progress
+}
+
+fn remove_framed_trailing_pointer_ops(ops: &mut Vec<Op>) -> bool {
+    let mut progress = false;
+
+    while !ops.is_empty() && ops[ops.len() - 1].op_type.is_ptr_inc_or_dec() {
+        ops.remove(ops.len() - 1);
+        progress = true;
+    }
+
     progress
 }
 
@@ -2359,13 +2366,18 @@ pub fn remove_useless_loops_pass(ops: [&Op; 1]) -> Change {
 }
 
 /// Non local and slower version of arithmetic optimizations
-pub fn optimize_non_local_arithmetics(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
+pub fn optimize_non_local_arithmetics(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
         optimize_non_local_arithmetics_pass,
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -2374,6 +2386,7 @@ fn optimize_non_local_arithmetics_pass(
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool {
     let mut progress = false;
 
@@ -2391,24 +2404,28 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
-                    Change::Replace(vec![OpType::Set(*offset, v.wrapping_add(*value))])
+                    Change::Replace(vec![OpType::Set(
+                        *offset,
+                        cell_size.wrapping_add(v, *value),
+                    )])
                 } else if let Some((index, value2)) =
                     find_last_accessing_inc_dec(ops, *offset, i as isize - 1)
                 {
-                    let value = *value as i16 + value2;
+                    let value = *value as i64 + value2;
                     let remove_index = index - (i as isize);
 
                     if value >= 0 {
                         Change::RemoveAndReplace(
                             remove_index,
-                            vec![OpType::Inc(*offset, value as u8)],
+                            vec![OpType::Inc(*offset, cell_size.wrap(value as u32))],
                         )
                     } else {
                         Change::RemoveAndReplace(
                             remove_index,
-                            vec![OpType::Dec(*offset, (-value) as u8)],
+                            vec![OpType::Dec(*offset, cell_size.wrap((-value) as u32))],
                         )
                     }
                 } else {
@@ -2423,24 +2440,28 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
-                    Change::Replace(vec![OpType::Set(*offset, v.wrapping_sub(*value))])
+                    Change::Replace(vec![OpType::Set(
+                        *offset,
+                        cell_size.wrapping_sub(v, *value),
+                    )])
                 } else if let Some((index, value2)) =
                     find_last_accessing_inc_dec(ops, *offset, i as isize - 1)
                 {
-                    let value = value2 - *value as i16;
+                    let value = value2 - *value as i64;
                     let remove_index = index - (i as isize);
 
                     if value >= 0 {
                         Change::RemoveAndReplace(
                             remove_index,
-                            vec![OpType::Inc(*offset, value as u8)],
+                            vec![OpType::Inc(*offset, cell_size.wrap(value as u32))],
                         )
                     } else {
                         Change::RemoveAndReplace(
                             remove_index,
-                            vec![OpType::Dec(*offset, (-value) as u8)],
+                            vec![OpType::Dec(*offset, cell_size.wrap((-value) as u32))],
                         )
                     }
                 } else {
@@ -2455,6 +2476,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 ) {
                     if *value == v {
@@ -2474,9 +2496,10 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
-                    Change::Replace(vec![OpType::PutString(vec![v])])
+                    Change::Replace(vec![OpType::PutString(vec![v as u8])])
                 } else {
                     Change::Ignore
                 }
@@ -2500,6 +2523,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 );
                 let dest = utils::find_heap_value(
@@ -2509,13 +2533,15 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 );
 
                 match (src, dest) {
                     (CellValue::Value(0), CellValue::Value(_)) => Change::Remove,
                     (CellValue::Value(src), CellValue::Value(dest)) => {
-                        let value = dest.wrapping_add(src.wrapping_mul(*multi));
+                        let value =
+                            cell_size.wrapping_add(dest, cell_size.wrapping_mul(src, *multi));
 
                         Change::Replace(vec![
                             OpType::Set(*dest_offset, value),
@@ -2523,7 +2549,7 @@ fn optimize_non_local_arithmetics_pass(
                         ])
                     }
                     (CellValue::Value(src), _) => {
-                        let value = src.wrapping_mul(*multi);
+                        let value = cell_size.wrapping_mul(src, *multi);
 
                         Change::Replace(vec![OpType::CAdd(*src_offset, *dest_offset, value)])
                     }
@@ -2545,6 +2571,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 );
                 let dest = utils::find_heap_value(
@@ -2554,18 +2581,20 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 );
 
                 match (src, dest) {
                     (CellValue::Value(0), CellValue::Value(_)) => Change::Remove,
                     (CellValue::Value(src), CellValue::Value(dest)) => {
-                        let value = dest.wrapping_add(src.wrapping_mul(*multi));
+                        let value =
+                            cell_size.wrapping_add(dest, cell_size.wrapping_mul(src, *multi));
 
                         Change::Replace(vec![OpType::Set(*dest_offset, value)])
                     }
                     (CellValue::Value(src), _) => {
-                        let value = src.wrapping_mul(*multi);
+                        let value = cell_size.wrapping_mul(src, *multi);
 
                         Change::Replace(vec![OpType::NzCAdd(*src_offset, *dest_offset, value)])
                     }
@@ -2587,10 +2616,11 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
                     Change::Replace(vec![
-                        OpType::Set(*dest_offset, value2.wrapping_add(*value)),
+                        OpType::Set(*dest_offset, cell_size.wrapping_add(value2, *value)),
                         OpType::Set(*src_offset, 0),
                     ])
                 } else {
@@ -2605,9 +2635,13 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
-                    Change::Replace(vec![OpType::Set(*dest_offset, value2.wrapping_add(*value))])
+                    Change::Replace(vec![OpType::Set(
+                        *dest_offset,
+                        cell_size.wrapping_add(value2, *value),
+                    )])
                 } else {
                     Change::Ignore
                 }
@@ -2620,6 +2654,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 );
                 let dest = utils::find_heap_value(
@@ -2629,13 +2664,15 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 );
 
                 match (src, dest) {
                     (CellValue::Value(0), CellValue::Value(_)) => Change::Remove,
                     (CellValue::Value(src), CellValue::Value(dest)) => {
-                        let value = dest.wrapping_sub(src.wrapping_mul(*multi));
+                        let value =
+                            cell_size.wrapping_sub(dest, cell_size.wrapping_mul(src, *multi));
 
                         Change::Replace(vec![
                             OpType::Set(*dest_offset, value),
@@ -2643,7 +2680,7 @@ fn optimize_non_local_arithmetics_pass(
                         ])
                     }
                     (CellValue::Value(src), _) => {
-                        let value = src.wrapping_mul(*multi);
+                        let value = cell_size.wrapping_mul(src, *multi);
 
                         Change::Replace(vec![OpType::CSub(*src_offset, *dest_offset, value)])
                     }
@@ -2658,6 +2695,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 );
                 let dest = utils::find_heap_value(
@@ -2667,18 +2705,20 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 );
 
                 match (src, dest) {
                     (CellValue::Value(0), CellValue::Value(_)) => Change::Remove,
                     (CellValue::Value(src), CellValue::Value(dest)) => {
-                        let value = dest.wrapping_sub(src.wrapping_mul(*multi));
+                        let value =
+                            cell_size.wrapping_sub(dest, cell_size.wrapping_mul(src, *multi));
 
                         Change::Replace(vec![OpType::Set(*dest_offset, value)])
                     }
                     (CellValue::Value(src), _) => {
-                        let value = src.wrapping_mul(*multi);
+                        let value = cell_size.wrapping_mul(src, *multi);
 
                         Change::Replace(vec![OpType::NzCSub(*src_offset, *dest_offset, value)])
                     }
@@ -2693,10 +2733,11 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
                     Change::Replace(vec![
-                        OpType::Set(*dest_offset, value2.wrapping_sub(*value)),
+                        OpType::Set(*dest_offset, cell_size.wrapping_sub(value2, *value)),
                         OpType::Set(*src_offset, 0),
                     ])
                 } else {
@@ -2711,9 +2752,13 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
-                    Change::Replace(vec![OpType::Set(*dest_offset, value2.wrapping_sub(*value))])
+                    Change::Replace(vec![OpType::Set(
+                        *dest_offset,
+                        cell_size.wrapping_sub(value2, *value),
+                    )])
                 } else {
                     Change::Ignore
                 }
@@ -2726,13 +2771,14 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 ) {
                     if value == 0 {
                         Change::Remove
                     } else {
                         Change::Replace(vec![
-                            OpType::Set(*dest_offset, value.wrapping_mul(*multi)),
+                            OpType::Set(*dest_offset, cell_size.wrapping_mul(value, *multi)),
                             OpType::Set(*src_offset, 0),
                         ])
                     }
@@ -2748,12 +2794,16 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     false,
                 ) {
                     if value == 0 {
                         Change::Remove
                     } else {
-                        Change::Replace(vec![OpType::Set(*dest_offset, value.wrapping_mul(*multi))])
+                        Change::Replace(vec![OpType::Set(
+                            *dest_offset,
+                            cell_size.wrapping_mul(value, *multi),
+                        )])
                     }
                 } else {
                     Change::Ignore
@@ -2767,6 +2817,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
                     Change::Replace(vec![
@@ -2785,6 +2836,7 @@ fn optimize_non_local_arithmetics_pass(
                     zeroed,
                     inputs,
                     wrapping_is_ub,
+                    cell_size,
                     true,
                 ) {
                     Change::Replace(vec![OpType::Set(*dest_offset, value)])
@@ -2804,6 +2856,7 @@ fn optimize_non_local_arithmetics_pass(
                         zeroed,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
 
@@ -2851,14 +2904,14 @@ fn optimize_non_local_arithmetics_pass(
     progress
 }
 
-pub fn optimize_non_local_dead_stores(ops: &mut Vec<Op>) -> bool {
-    let mut progress = false;
+pub fn optimize_non_local_dead_stores(ops: &mut Vec<Op>, eof_mode: EofMode) -> bool {
+    run_block_pass_bottom_up(ops, |ops| {
+        optimize_non_local_dead_stores_pass(ops, eof_mode)
+    })
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_non_local_dead_stores(children);
-        }
-    }
+fn optimize_non_local_dead_stores_pass(ops: &mut Vec<Op>, eof_mode: EofMode) -> bool {
+    let mut progress = false;
 
     let mut i = 1;
 
@@ -2870,8 +2923,10 @@ pub fn optimize_non_local_dead_stores(ops: &mut Vec<Op>) -> bool {
             | OpType::Move(_, offset)
             | OpType::Copy(_, offset)
             | OpType::Mul(_, offset, _)
-            | OpType::NzMul(_, offset, _)
-            | OpType::GetChar(offset) => find_last_unread_set(ops, *offset, i - 1),
+            | OpType::NzMul(_, offset, _) => find_last_unread_set(ops, *offset, i - 1),
+            OpType::GetChar(offset) if eof_mode.overwrites_cell() => {
+                find_last_unread_set(ops, *offset, i - 1)
+            }
             OpType::CAdd(offset, ..) | OpType::CSub(offset, ..) => {
                 find_last_unread_set(ops, *offset, i - 1)
             }
@@ -2927,13 +2982,11 @@ fn get_write_only_indices(op: &Op) -> Vec<isize> {
 }
 
 pub fn optimize_non_local_dead_block_stores(ops: &mut Vec<Op>) -> bool {
-    let mut progress = false;
+    run_block_pass_bottom_up(ops, optimize_non_local_dead_block_stores_pass)
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_non_local_dead_block_stores(children);
-        }
-    }
+fn optimize_non_local_dead_block_stores_pass(ops: &mut Vec<Op>) -> bool {
+    let mut progress = false;
 
     let mut i = 0;
 
@@ -2951,124 +3004,135 @@ pub fn optimize_non_local_dead_block_stores(ops: &mut Vec<Op>) -> bool {
     progress
 }
 
-fn remove_dead_block_sets(ops: &mut Vec<Op>, mut ptr_offset: isize) -> bool {
-    let mut i = ops.len() as isize - 1;
+fn remove_dead_block_sets(mut ops: &mut Vec<Op>, mut ptr_offset: isize) -> bool {
+    // Continue in the body of blocks that overwrite the cell
+    loop {
+        let mut i = ops.len() as isize - 1;
+        let mut nested = None;
 
-    while !ops.is_empty() && i >= 0 {
-        let change = match &mut ops[i as usize].op_type {
-            OpType::IncPtr(offset) => {
-                ptr_offset += *offset as isize;
-                Change::Ignore
-            }
-            OpType::DecPtr(offset) => {
-                ptr_offset -= *offset as isize;
-                Change::Ignore
-            }
-            OpType::Set(offset, _)
-            | OpType::Inc(offset, _)
-            | OpType::Dec(offset, _)
-            | OpType::NzAdd(_, offset, _)
-            | OpType::NzSub(_, offset, _)
-            | OpType::NzMul(_, offset, _)
-            | OpType::NzCAdd(_, offset, _)
-            | OpType::NzCSub(_, offset, _)
-            | OpType::Copy(offset, _) => {
-                if *offset == ptr_offset {
-                    Change::Remove
-                } else {
-                    Change::Ignore
-                }
-            }
-            OpType::Add(src_offset, dest_offset, v) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::NzAdd(*src_offset, *dest_offset, *v)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
-                    Change::Ignore
-                }
-            }
-            OpType::Sub(src_offset, dest_offset, v) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::NzSub(*src_offset, *dest_offset, *v)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
+        while !ops.is_empty() && i >= 0 {
+            let change = match &mut ops[i as usize].op_type {
+                OpType::IncPtr(offset) => {
+                    ptr_offset += *offset as isize;
                     Change::Ignore
                 }
-            }
-            OpType::CAdd(src_offset, dest_offset, v) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::NzCAdd(*src_offset, *dest_offset, *v)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
+                OpType::DecPtr(offset) => {
+                    ptr_offset -= *offset as isize;
                     Change::Ignore
                 }
-            }
-            OpType::CSub(src_offset, dest_offset, v) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::NzCSub(*src_offset, *dest_offset, *v)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
-                    Change::Ignore
+                OpType::Set(offset, _)
+                | OpType::Inc(offset, _)
+                | OpType::Dec(offset, _)
+                | OpType::NzAdd(_, offset, _)
+                | OpType::NzSub(_, offset, _)
+                | OpType::NzMul(_, offset, _)
+                | OpType::NzCAdd(_, offset, _)
+                | OpType::NzCSub(_, offset, _)
+                | OpType::Copy(offset, _) => {
+                    if *offset == ptr_offset {
+                        Change::Remove
+                    } else {
+                        Change::Ignore
+                    }
                 }
-            }
-            OpType::Mul(src_offset, dest_offset, v) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::NzMul(*src_offset, *dest_offset, *v)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
-                    Change::Ignore
+                OpType::Add(src_offset, dest_offset, v) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::NzAdd(*src_offset, *dest_offset, *v)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
+                    } else {
+                        Change::Ignore
+                    }
                 }
-            }
-            OpType::Move(src_offset, dest_offset) => {
-                if *src_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Copy(*src_offset, *dest_offset)])
-                } else if *dest_offset == ptr_offset {
-                    Change::Replace(vec![OpType::Set(*src_offset, 0)])
-                } else {
-                    Change::Ignore
+                OpType::Sub(src_offset, dest_offset, v) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::NzSub(*src_offset, *dest_offset, *v)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
+                    } else {
+                        Change::Ignore
+                    }
                 }
-            }
-            OpType::TNz(children, info) | OpType::DTNz(children, _, info) => {
-                if let Some(value) = info.get_access(ptr_offset) {
-                    if value.value.is_write() && !value.read_after_write {
-                        return remove_dead_block_sets(children, ptr_offset);
+                OpType::CAdd(src_offset, dest_offset, v) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::NzCAdd(*src_offset, *dest_offset, *v)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
                     } else {
-                        return false;
+                        Change::Ignore
                     }
                 }
-                Change::Ignore
-            }
-            OpType::LLoop(children, info)
-            | OpType::ILoop(children, .., info)
-            | OpType::CLoop(children, .., info) => {
-                if let Some(value) = info.get_access(ptr_offset) {
-                    if value.value.is_write() && !value.has_read() {
-                        return remove_dead_block_sets(children, ptr_offset);
+                OpType::CSub(src_offset, dest_offset, v) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::NzCSub(*src_offset, *dest_offset, *v)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
                     } else {
-                        return false;
+                        Change::Ignore
                     }
                 }
-                Change::Ignore
-            }
-            OpType::Start | OpType::GetChar(_) | OpType::PutChar(_) | OpType::PutString(_) => {
-                Change::Ignore
+                OpType::Mul(src_offset, dest_offset, v) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::NzMul(*src_offset, *dest_offset, *v)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
+                    } else {
+                        Change::Ignore
+                    }
+                }
+                OpType::Move(src_offset, dest_offset) => {
+                    if *src_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Copy(*src_offset, *dest_offset)])
+                    } else if *dest_offset == ptr_offset {
+                        Change::Replace(vec![OpType::Set(*src_offset, 0)])
+                    } else {
+                        Change::Ignore
+                    }
+                }
+                OpType::TNz(_, info) | OpType::DTNz(_, _, info) => {
+                    if let Some(value) = info.get_access(ptr_offset) {
+                        if value.value.is_write() && !value.read_after_write {
+                            nested = Some(i as usize);
+                            break;
+                        } else {
+                            return false;
+                        }
+                    }
+                    Change::Ignore
+                }
+                OpType::LLoop(_, info) | OpType::ILoop(.., info) | OpType::CLoop(.., info) => {
+                    if let Some(value) = info.get_access(ptr_offset) {
+                        if value.value.is_write() && !value.has_read() {
+                            nested = Some(i as usize);
+                            break;
+                        } else {
+                            return false;
+                        }
+                    }
+                    Change::Ignore
+                }
+                OpType::Start | OpType::GetChar(_) | OpType::PutChar(_) | OpType::PutString(_) => {
+                    Change::Ignore
+                }
+                OpType::DLoop(..) | OpType::SearchZero(..) | OpType::Breakpoint => return false,
+            };
+
+            if change.apply(ops, i as usize, 1).0 {
+                return true;
+            } else {
+                i -= 1;
             }
-            OpType::DLoop(..) | OpType::SearchZero(..) => return false,
-        };
+        }
 
-        if change.apply(ops, i as usize, 1).0 {
-            return true;
+        if let Some(index) = nested {
+            ops = ops[index]
+                .op_type
+                .get_children_mut()
+                .expect("Blocks must have children");
         } else {
-            i -= 1;
+            return false;
         }
     }
-
-    false
 }
 
 fn has_unread_set(ops: &[Op], mut cell_offset: isize, start_index: usize) -> bool {
@@ -3172,13 +3236,11 @@ fn has_unread_set(ops: &[Op], mut cell_offset: isize, start_index: usize) -> boo
 }
 
 pub fn unroll_constant_loops(ops: &mut Vec<Op>, limit: usize) -> bool {
-    let mut progress = false;
+    run_block_pass_bottom_up(ops, |ops| unroll_constant_loops_pass(ops, limit))
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= unroll_constant_loops(children, limit);
-        }
-    }
+fn unroll_constant_loops_pass(ops: &mut Vec<Op>, limit: usize) -> bool {
+    let mut progress = false;
 
     let mut i = 0;
 
@@ -3367,8 +3429,22 @@ fn find_last_unread_set(
     None
 }
 
-pub fn update_loop_access(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
-    run_non_local_pass(ops, update_loop_access_pass, true, &[], wrapping_is_ub)
+pub fn update_loop_access(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+    eof_mode: EofMode,
+) -> bool {
+    run_non_local_pass(
+        ops,
+        |ops, zeroed, inputs, wrapping_is_ub, cell_size| {
+            update_loop_access_pass(ops, zeroed, inputs, wrapping_is_ub, cell_size, eof_mode)
+        },
+        true,
+        &[],
+        wrapping_is_ub,
+        cell_size,
+    )
 }
 
 fn update_loop_access_pass(
@@ -3376,17 +3452,29 @@ fn update_loop_access_pass(
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
+    eof_mode: EofMode,
 ) -> bool {
     for op in ops.iter_mut() {
         match &mut op.op_type {
             OpType::DLoop(children, info) | OpType::DTNz(children, _, info) => {
-                info.set_cell_access(get_d_loop_access(children, wrapping_is_ub));
+                info.set_cell_access(get_d_loop_access(
+                    children,
+                    wrapping_is_ub,
+                    cell_size,
+                    eof_mode,
+                ));
             }
             OpType::LLoop(children, info)
             | OpType::ILoop(children, _, _, info)
             | OpType::CLoop(children, _, _, info)
             | OpType::TNz(children, info) => {
-                info.set_cell_access(get_loop_access(children, wrapping_is_ub));
+                info.set_cell_access(get_loop_access(
+                    children,
+                    wrapping_is_ub,
+                    cell_size,
+                    eof_mode,
+                ));
             }
             _ => {
                 // Ignore
@@ -3405,16 +3493,34 @@ fn update_loop_access_pass(
             | OpType::CLoop(.., info)
             | OpType::TNz(.., info) => {
                 if !info.always_used()
-                    && find_heap_value(ops, 0, i as isize - 1, zeroed, inputs, wrapping_is_ub, true)
-                        .is_not_zero()
+                    && find_heap_value(
+                        ops,
+                        0,
+                        i as isize - 1,
+                        zeroed,
+                        inputs,
+                        wrapping_is_ub,
+                        cell_size,
+                        true,
+                    )
+                    .is_not_zero()
                 {
                     always_used.push(i);
                 }
             }
             OpType::SearchZero(_, always) => {
                 if !*always
-                    && find_heap_value(ops, 0, i as isize - 1, zeroed, inputs, wrapping_is_ub, true)
-                        .is_not_zero()
+                    && find_heap_value(
+                        ops,
+                        0,
+                        i as isize - 1,
+                        zeroed,
+                        inputs,
+                        wrapping_is_ub,
+                        cell_size,
+                        true,
+                    )
+                    .is_not_zero()
                 {
                     always_used.push(i);
                 }
@@ -3461,13 +3567,11 @@ fn update_loop_access_pass(
 /// SET 0 offset 1
 /// ```
 pub fn optimize_non_local_redundant_copies(ops: &mut Vec<Op>) -> bool {
-    let mut progress = false;
+    run_block_pass_bottom_up(ops, optimize_non_local_redundant_copies_pass)
+}
 
-    for op in ops.iter_mut() {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= optimize_non_local_redundant_copies(children);
-        }
-    }
+fn optimize_non_local_redundant_copies_pass(ops: &mut Vec<Op>) -> bool {
+    let mut progress = false;
 
     let mut i = 1;
 
@@ -3560,15 +3664,21 @@ pub fn optimize_non_local_redundant_copies(ops: &mut Vec<Op>) -> bool {
     progress
 }
 
-pub fn partially_unroll_loops(ops: &mut Vec<Op>, limit: usize, wrapping_is_ub: bool) -> bool {
+pub fn partially_unroll_loops(
+    ops: &mut Vec<Op>,
+    limit: usize,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
-        |ops, zeroing, inputs, wrapping_is_ub| {
-            partially_unroll_loops_pass(ops, zeroing, inputs, wrapping_is_ub, limit)
+        |ops, zeroing, inputs, wrapping_is_ub, cell_size| {
+            partially_unroll_loops_pass(ops, zeroing, inputs, wrapping_is_ub, cell_size, limit)
         },
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -3577,6 +3687,7 @@ fn partially_unroll_loops_pass(
     _zeroed: bool,
     _inputs: &[(isize, CellValue)],
     _wrapping_is_ub: bool,
+    _cell_size: CellSize,
     limit: usize,
 ) -> bool {
     let mut progress = false;
@@ -3699,13 +3810,18 @@ fn get_d_loop_ptr_end_offset(ops: &[Op]) -> Option<isize> {
     Some(ptr_offset)
 }
 
-pub fn non_local_remove_dead_loops(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
+pub fn non_local_remove_dead_loops(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
         non_local_remove_dead_loops_pass,
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -3714,6 +3830,7 @@ fn non_local_remove_dead_loops_pass(
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool {
     let mut progress = false;
 
@@ -3727,10 +3844,17 @@ fn non_local_remove_dead_loops_pass(
             | OpType::DTNz(..)
             | OpType::LLoop(..)
             | OpType::ILoop(..)
-            | OpType::TNz(..) => {
-                find_heap_value(ops, 0, i as isize - 1, zeroed, inputs, wrapping_is_ub, true)
-                    .is_zero()
-            }
+            | OpType::TNz(..) => find_heap_value(
+                ops,
+                0,
+                i as isize - 1,
+                zeroed,
+                inputs,
+                wrapping_is_ub,
+                cell_size,
+                true,
+            )
+            .is_zero(),
             _ => false,
         };
 
@@ -3745,8 +3869,19 @@ fn non_local_remove_dead_loops_pass(
     progress
 }
 
-pub fn remove_true_conditions(ops: &mut Vec<Op>, wrapping_is_ub: bool) -> bool {
-    run_non_local_pass(ops, remove_true_conditions_pass, true, &[], wrapping_is_ub)
+pub fn remove_true_conditions(
+    ops: &mut Vec<Op>,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
+    run_non_local_pass(
+        ops,
+        remove_true_conditions_pass,
+        true,
+        &[],
+        wrapping_is_ub,
+        cell_size,
+    )
 }
 
 fn remove_true_conditions_pass(
@@ -3754,6 +3889,7 @@ fn remove_true_conditions_pass(
     _zeroed: bool,
     _inputs: &[(isize, CellValue)],
     _wrapping_is_ub: bool,
+    _cell_size: CellSize,
 ) -> bool {
     let mut progress = false;
 
@@ -3783,33 +3919,17 @@ fn remove_true_conditions_pass(
                     }
 
                     for (index, op) in children.into_iter().enumerate() {
-                        let index = i + index;
-                        if index < ops.len() - 1 {
-                            ops.insert_or_push(index, op);
-                        } else {
-                            ops.push(op);
-                        }
+                        ops.insert_or_push(i + index, op);
                     }
 
                     let mut index = i + len;
 
                     if ptr_offset != 0 {
-                        if index < ops.len() - 1 {
-                            ops.insert_or_push(
-                                index,
-                                Op::ptr_offset(old.span.clone(), -ptr_offset),
-                            );
-                        } else {
-                            ops.push(Op::ptr_offset(old.span.clone(), -ptr_offset));
-                        }
+                        ops.insert_or_push(index, Op::ptr_offset(old.span.clone(), -ptr_offset));
                         index += 1;
                     }
 
-                    if index < ops.len() - 1 {
-                        ops.insert_or_push(index, Op::set(old.span, 0));
-                    } else {
-                        ops.push(Op::set(old.span, 0));
-                    }
+                    ops.insert_or_push(index, Op::set(old.span, 0));
 
                     progress = true;
                 }
@@ -3829,15 +3949,21 @@ fn remove_true_conditions_pass(
     progress
 }
 
-pub fn unroll_scanning_d_loops(ops: &mut Vec<Op>, limit: usize, wrapping_is_ub: bool) -> bool {
+pub fn unroll_scanning_d_loops(
+    ops: &mut Vec<Op>,
+    limit: usize,
+    wrapping_is_ub: bool,
+    cell_size: CellSize,
+) -> bool {
     run_non_local_pass(
         ops,
-        |ops, zeroing, inputs, wrapping_is_ub| {
-            unroll_scanning_d_loops_pass(ops, zeroing, inputs, wrapping_is_ub, limit)
+        |ops, zeroing, inputs, wrapping_is_ub, cell_size| {
+            unroll_scanning_d_loops_pass(ops, zeroing, inputs, wrapping_is_ub, cell_size, limit)
         },
         true,
         &[],
         wrapping_is_ub,
+        cell_size,
     )
 }
 
@@ -3846,6 +3972,7 @@ fn unroll_scanning_d_loops_pass(
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
     limit: usize,
 ) -> bool {
     let mut progress = false;
@@ -3870,6 +3997,7 @@ fn unroll_scanning_d_loops_pass(
                                 zeroed,
                                 inputs,
                                 wrapping_is_ub,
+                                cell_size,
                                 true,
                             );
 
@@ -3992,7 +4120,7 @@ fn get_scan_loop_step(ops: &[Op]) -> Option<isize> {
                 }
             }
             OpType::DLoop(..) | OpType::DTNz(..) | OpType::SearchZero(..) => return None,
-            OpType::PutChar(_) | OpType::PutString(_) | OpType::Start => {
+            OpType::PutChar(_) | OpType::PutString(_) | OpType::Start | OpType::Breakpoint => {
                 // Ignore
             }
         }
@@ -4003,6 +4131,7 @@ fn get_scan_loop_step(ops: &[Op]) -> Option<isize> {
 
 #[cfg(test)]
 mod tests {
+    use crate::ir::assembler::parse_dump;
     use crate::ir::ops::Op;
     use crate::optimizations::peephole::run_peephole_pass;
 
@@ -4033,7 +4162,7 @@ mod tests {
     fn test_optimize_inc() {
         let mut ops = vec![Op::inc(0..1, 1), Op::inc(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::inc(0..2, 3),])
     }
@@ -4042,7 +4171,7 @@ mod tests {
     fn test_optimize_dec() {
         let mut ops = vec![Op::dec(0..1, 1), Op::dec(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::dec(0..2, 3),])
     }
@@ -4051,7 +4180,7 @@ mod tests {
     fn test_optimize_inc_dec() {
         let mut ops = vec![Op::inc(0..1, 1), Op::dec(1..2, 2), Op::dec(2..3, 3)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::dec(0..3, 4),])
     }
@@ -4060,7 +4189,7 @@ mod tests {
     fn test_optimize_set_inc() {
         let mut ops = vec![Op::set(0..1, 5), Op::inc(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::set(0..2, 7),])
     }
@@ -4069,16 +4198,25 @@ mod tests {
     fn test_optimize_set_dec() {
         let mut ops = vec![Op::set(0..1, 5), Op::dec(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::set(0..2, 3),])
     }
 
+    #[test]
+    fn test_optimize_set_dec_16_bit() {
+        let mut ops = vec![Op::set(0..1, 0), Op::dec(1..2, 2)];
+
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits16));
+
+        assert_eq!(ops, vec![Op::set(0..2, 65534),])
+    }
+
     #[test]
     fn test_optimize_inc_dec_0() {
         let mut ops = vec![Op::inc(0..1, 1), Op::dec(1..2, 1), Op::inc_ptr(2..3, 3)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::inc_ptr(2..3, 3),])
     }
@@ -4087,7 +4225,7 @@ mod tests {
     fn test_optimize_inc_ptr() {
         let mut ops = vec![Op::inc_ptr(0..1, 1), Op::inc_ptr(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::inc_ptr(0..2, 3),])
     }
@@ -4096,7 +4234,7 @@ mod tests {
     fn test_optimize_dec_ptr() {
         let mut ops = vec![Op::dec_ptr(0..1, 1), Op::dec_ptr(1..2, 2)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::dec_ptr(0..2, 3),])
     }
@@ -4109,7 +4247,7 @@ mod tests {
             Op::dec_ptr(2..3, 3),
         ];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::dec_ptr(0..3, 4),])
     }
@@ -4118,7 +4256,7 @@ mod tests {
     fn test_optimize_inc_dec_ptr_0() {
         let mut ops = vec![Op::inc_ptr(0..1, 1), Op::dec_ptr(1..2, 1), Op::inc(2..3, 3)];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::inc(2..3, 3),])
     }
@@ -4130,7 +4268,7 @@ mod tests {
             Op::inc(2..3, 3),
         ];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(
             ops,
@@ -4149,7 +4287,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_zero_loops(&mut ops);
+        optimize_zero_loops(&mut ops, &mut vec![]);
 
         assert_eq!(ops, vec![Op::set(0..2, 0),])
     }
@@ -4162,7 +4300,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_zero_loops(&mut ops);
+        optimize_zero_loops(&mut ops, &mut vec![]);
 
         assert_eq!(ops, vec![Op::set(0..2, 0),])
     }
@@ -4180,9 +4318,9 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
         optimize_offsets(&mut ops, 1);
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
         run_peephole_pass(&mut ops, optimize_arithmetic_loops);
 
         assert_eq!(ops, vec![Op::add(0..5, 1, 1),])
@@ -4201,9 +4339,9 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
         optimize_offsets(&mut ops, 1);
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
         run_peephole_pass(&mut ops, optimize_arithmetic_loops);
 
         assert_eq!(ops, vec![Op::add(0..5, 2, 1),])
@@ -4222,9 +4360,9 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
         optimize_offsets(&mut ops, 1);
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
         run_peephole_pass(&mut ops, optimize_arithmetic_loops);
 
         assert_eq!(ops, vec![Op::sub(0..5, 1, 2),])
@@ -4243,9 +4381,9 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
         optimize_offsets(&mut ops, 1);
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
         run_peephole_pass(&mut ops, optimize_arithmetic_loops);
 
         assert_eq!(ops, vec![Op::sub(0..5, 1, 2),])
@@ -4267,7 +4405,7 @@ mod tests {
             ),
         ];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
 
         assert_eq!(
             ops,
@@ -4285,6 +4423,42 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_optimize_nested_local_loops() {
+        let mut ops = vec![Op::d_loop(
+            0..1,
+            vec![
+                Op::d_loop(
+                    1..2,
+                    vec![
+                        Op::d_loop(2..3, vec![Op::dec(3..4, 1)], BlockInfo::new_empty()),
+                        Op::dec(4..5, 1),
+                    ],
+                    BlockInfo::new_empty(),
+                ),
+                Op::d_loop(5..6, vec![Op::search_zero(6..7, 1)], BlockInfo::new_empty()),
+            ],
+            BlockInfo::new_empty(),
+        )];
+
+        assert!(optimize_local_loops(
+            &mut ops,
+            CellSize::Bits8,
+            EofMode::Zero
+        ));
+
+        let children = match &ops[0].op_type {
+            OpType::DLoop(children, _) => children,
+            op_type => panic!("Expected dynamic loop, got {:?}", op_type),
+        };
+
+        assert!(matches!(
+            &children[0].op_type,
+            OpType::LLoop(children, _) if matches!(children[0].op_type, OpType::LLoop(..))
+        ));
+        assert!(matches!(children[1].op_type, OpType::DLoop(..)));
+    }
+
     #[test]
     fn test_optimize_count_loops() {
         let mut ops = vec![
@@ -4301,8 +4475,8 @@ mod tests {
             ),
         ];
 
-        optimize_local_loops(&mut ops);
-        optimize_count_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
 
         assert_eq!(
             ops,
@@ -4337,8 +4511,8 @@ mod tests {
             ),
         ];
 
-        optimize_local_loops(&mut ops);
-        optimize_count_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
 
         assert_eq!(
             ops,
@@ -4373,10 +4547,10 @@ mod tests {
             ),
         ];
 
-        optimize_local_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
         optimize_offsets(&mut ops, 1);
-        optimize_count_loops(&mut ops);
-        optimize_static_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
+        assert!(optimize_static_count_loops(&mut ops, &mut vec![]));
 
         assert_eq!(
             ops,
@@ -4408,13 +4582,58 @@ mod tests {
             ),
         ];
 
-        optimize_local_loops(&mut ops);
-        optimize_count_loops(&mut ops);
-        optimize_static_count_loops(&mut ops);
+        optimize_local_loops(&mut ops, CellSize::Bits8, EofMode::Zero);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
+        assert!(optimize_static_count_loops(&mut ops, &mut vec![]));
 
         assert_eq!(ops, vec![])
     }
 
+    #[test]
+    fn test_optimize_static_count_loops_counter_read() {
+        let mut ops = vec![
+            Op::set(0..1, 25),
+            Op::i_loop_with_decrement(
+                1..2,
+                vec![Op::put_char(2..3)],
+                5,
+                LoopDecrement::Post,
+                BlockInfo::new_empty(),
+            ),
+        ];
+
+        let expected = ops.clone();
+
+        assert!(!optimize_static_count_loops(&mut ops, &mut vec![]));
+
+        assert_eq!(ops, expected)
+    }
+
+    #[test]
+    fn test_remove_true_conditions_order() {
+        let mut info = BlockInfo::new_empty();
+        info.set_always_used(true);
+
+        let mut ops = vec![
+            Op::set(0..1, 1),
+            Op::t_nz(1..4, vec![Op::put_char(2..3), Op::inc(3..4, 1)], info),
+            Op::put_char(4..5),
+        ];
+
+        remove_true_conditions(&mut ops, false, CellSize::Bits8);
+
+        assert_eq!(
+            ops,
+            vec![
+                Op::set(0..1, 1),
+                Op::put_char(2..3),
+                Op::inc(3..4, 1),
+                Op::set(1..4, 0),
+                Op::put_char(4..5),
+            ]
+        )
+    }
+
     #[test]
     fn test_optimize_tnz_set() {
         let mut ops = vec![Op::i_loop(
@@ -4440,7 +4659,7 @@ mod tests {
     fn test_dead_store_set() {
         let mut ops = vec![Op::inc(0..1, 1), Op::set(1..2, 2)];
 
-        remove_dead_stores_before_set(&mut ops);
+        remove_dead_stores_before_set(&mut ops, EofMode::Zero, &mut vec![]);
 
         assert_eq!(ops, vec![Op::set(1..2, 2),])
     }
@@ -4449,11 +4668,20 @@ mod tests {
     fn test_dead_store_get_char() {
         let mut ops = vec![Op::inc(0..1, 1), Op::get_char(1..2)];
 
-        remove_dead_stores_before_set(&mut ops);
+        remove_dead_stores_before_set(&mut ops, EofMode::Zero, &mut vec![]);
 
         assert_eq!(ops, vec![Op::get_char(1..2),])
     }
 
+    #[test]
+    fn test_dead_store_get_char_eof_unchanged() {
+        let mut ops = vec![Op::inc(0..1, 1), Op::get_char(1..2)];
+
+        remove_dead_stores_before_set(&mut ops, EofMode::Unchanged, &mut vec![]);
+
+        assert_eq!(ops, vec![Op::inc(0..1, 1), Op::get_char(1..2),])
+    }
+
     #[test]
     fn test_optimize_search_zero_inc() {
         let mut ops = vec![Op::d_loop(
@@ -4462,7 +4690,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_search_zero(&mut ops);
+        optimize_search_zero(&mut ops, &mut vec![]);
 
         assert_eq!(ops, vec![Op::search_zero(0..2, 8),])
     }
@@ -4479,7 +4707,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_search_zero(&mut ops);
+        optimize_search_zero(&mut ops, &mut vec![]);
 
         assert_eq!(
             ops,
@@ -4499,7 +4727,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_search_zero(&mut ops);
+        optimize_search_zero(&mut ops, &mut vec![]);
 
         assert_eq!(ops, vec![Op::search_zero(0..2, -8),])
     }
@@ -4516,7 +4744,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_search_zero(&mut ops);
+        optimize_search_zero(&mut ops, &mut vec![]);
 
         assert_eq!(
             ops,
@@ -4537,7 +4765,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_constant_arithmetic_loop(&mut ops);
+        assert!(optimize_constant_arithmetic_loop(&mut ops, CellSize::Bits8));
 
         assert_eq!(
             ops,
@@ -4550,11 +4778,54 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_optimize_constant_arithmetic_loop_16_bit() {
+        let mut ops = vec![Op::c_loop(
+            0..3,
+            vec![Op::inc_with_offset(1..2, 1, 100)],
+            5,
+            BlockInfo::new_empty(),
+        )];
+
+        optimize_constant_arithmetic_loop(&mut ops, CellSize::Bits16);
+
+        assert_eq!(
+            ops,
+            vec![Op::inc_with_offset(1..2, 1, 500), Op::set(2..3, 0),]
+        )
+    }
+
+    #[test]
+    fn test_optimize_constant_arithmetic_loop_keep_order() {
+        let mut ops = vec![
+            Op::c_loop(
+                0..3,
+                vec![Op::inc_with_offset(1..2, 1, 3)],
+                5,
+                BlockInfo::new_empty(),
+            ),
+            Op::inc(3..4, 1),
+        ];
+
+        optimize_constant_arithmetic_loop(&mut ops, CellSize::Bits8);
+
+        assert_eq!(
+            ops,
+            vec![
+                Op::inc_with_offset(1..2, 1, 15),
+                Op::set(2..3, 0),
+                Op::inc(3..4, 1),
+            ]
+        )
+    }
+
     #[test]
     fn test_optimize_constant_arithmetic_loop_add() {
         let mut ops = vec![Op::set(0..1, 3), Op::add(1..2, 1, 3)];
 
-        run_peephole_pass(&mut ops, optimize_constant_arithmetics);
+        run_peephole_pass(&mut ops, |ops| {
+            optimize_constant_arithmetics(ops, CellSize::Bits8)
+        });
 
         assert_eq!(ops, vec![Op::c_add(0..2, 1, 9),])
     }
@@ -4563,7 +4834,9 @@ mod tests {
     fn test_optimize_constant_arithmetic_loop_sub() {
         let mut ops = vec![Op::set(0..1, 3), Op::sub(1..2, 1, 2)];
 
-        run_peephole_pass(&mut ops, optimize_constant_arithmetics);
+        run_peephole_pass(&mut ops, |ops| {
+            optimize_constant_arithmetics(ops, CellSize::Bits8)
+        });
 
         assert_eq!(ops, vec![Op::c_sub(0..2, 1, 6),])
     }
@@ -4679,6 +4952,7 @@ mod tests {
             false,
             &[],
             false,
+            CellSize::Bits8,
         );
 
         assert_eq!(
@@ -4733,7 +5007,7 @@ mod tests {
             Op::dec_ptr(0..1, 11),
         ];
 
-        update_loop_access(&mut ops, false);
+        update_loop_access(&mut ops, false, CellSize::Bits8, EofMode::Zero);
 
         let inputs = ops.clone();
 
@@ -4743,6 +5017,7 @@ mod tests {
             false,
             &[],
             false,
+            CellSize::Bits8,
         );
 
         assert_eq!(ops, inputs)
@@ -4777,7 +5052,7 @@ mod tests {
             Op::set_with_offset(7..8, 2, 30),
         ];
 
-        optimize_non_local_dead_stores(&mut ops);
+        optimize_non_local_dead_stores(&mut ops, EofMode::Zero);
 
         assert_eq!(
             ops,
@@ -4806,7 +5081,7 @@ mod tests {
             Op::search_zero(8..9, -2),
         ];
 
-        optimize_non_local_arithmetics(&mut ops, false);
+        optimize_non_local_arithmetics(&mut ops, false, CellSize::Bits8);
 
         assert_eq!(
             ops,
@@ -4840,7 +5115,7 @@ mod tests {
             Op::dec_ptr(0..1, 1),
         ];
 
-        run_peephole_pass(&mut ops, optimize_arithmetics);
+        run_peephole_pass(&mut ops, |ops| optimize_arithmetics(ops, CellSize::Bits8));
 
         assert_eq!(ops, vec![Op::dec(0..1, 1),]);
     }
@@ -4853,7 +5128,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
 
         assert_eq!(ops, vec![Op::set(0..2, 0)]);
     }
@@ -4866,7 +5141,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        optimize_count_loops(&mut ops);
+        optimize_count_loops(&mut ops, CellSize::Bits8, &mut vec![]);
 
         assert_eq!(
             ops,
@@ -4882,7 +5157,7 @@ mod tests {
             BlockInfo::new_empty(),
         )];
 
-        update_loop_access(&mut ops, false);
+        update_loop_access(&mut ops, false, CellSize::Bits8, EofMode::Zero);
 
         assert_eq!(
             ops,
@@ -4893,4 +5168,87 @@ mod tests {
             ),]
         );
     }
+
+    #[test]
+    fn test_update_loop_access_range_overflow() {
+        let mut ops = vec![Op::l_loop(
+            0..8,
+            vec![
+                Op::set_with_offset(1..2, 1, 0),
+                Op::inc_ptr(2..3, 2),
+                Op::t_nz(
+                    3..5,
+                    vec![Op::set_with_offset(4..5, -1, 200)],
+                    BlockInfo::new_access(vec![CellAccess::new_value(-1, 200, false, false)]),
+                ),
+                Op::dec_ptr(5..6, 2),
+                Op::inc_with_offset(6..7, 1, 100),
+                Op::dec(7..8, 1),
+            ],
+            BlockInfo::new_empty(),
+        )];
+
+        update_loop_access(&mut ops, false, CellSize::Bits8, EofMode::Zero);
+
+        if let OpType::LLoop(_, info) = &ops[0].op_type {
+            assert_eq!(info.get_access_value(1), Some(Cell::Write));
+        } else {
+            unreachable!();
+        }
+    }
+
+    fn ir(source: &str) -> Vec<Op> {
+        std::mem::take(&mut parse_dump(source).unwrap().ops)
+    }
+
+    #[test]
+    fn test_search_zero_ir() {
+        let mut ops = ir("
+            GET offset: 0
+            TNZ info: access: None
+              DLOOP info: access: None
+                DEC_PTR 2
+            DLOOP info: access: None
+              INC_PTR 1
+              INC 1 offset: 0
+            ");
+
+        optimize_search_zero(&mut ops, &mut vec![]);
+
+        assert_eq!(
+            ops,
+            ir("
+            GET offset: 0
+            TNZ info: access: None
+              S_ZERO -2
+            DLOOP info: access: None
+              INC_PTR 1
+              INC 1 offset: 0
+            ")
+        );
+    }
+
+    #[test]
+    fn test_conditional_loops_ir() {
+        let mut ops = ir("
+            ILOOP step: 1 increment: Pre info: access: None
+              SET 0 offset: 0
+              SET 3 offset: 1
+            LLOOP info: access: None
+              SET 0 offset: 0
+            ");
+
+        optimize_conditional_loops(&mut ops);
+
+        assert_eq!(
+            ops,
+            ir("
+            TNZ info: access: None
+              SET 0 offset: 0
+              SET 3 offset: 1
+            TNZ info: access: None
+              SET 0 offset: 0
+            ")
+        );
+    }
 }
@@ -1,9 +1,38 @@
 use std::mem::MaybeUninit;
 
 use crate::ir::ops::Op;
+use crate::ir::visitor::{walk_mut, VisitorMut};
 use crate::optimizations::utils::Change;
 
-pub fn run_peephole_pass<F, const WINDOW: usize>(mut ops: &mut Vec<Op>, func: F) -> bool
+/// Run a peephole pass over the ops and all nested blocks
+pub fn run_peephole_pass<F, const WINDOW: usize>(ops: &mut Vec<Op>, func: F) -> bool
+where
+    F: Fn([&Op; WINDOW]) -> Change + Copy,
+{
+    struct Peephole<F, const WINDOW: usize> {
+        func: F,
+        progress: bool,
+    }
+
+    impl<F, const WINDOW: usize> VisitorMut for Peephole<F, WINDOW>
+    where
+        F: Fn([&Op; WINDOW]) -> Change + Copy,
+    {
+        fn visit_ops_mut(&mut self, ops: &mut Vec<Op>, _depth: usize) {
+            self.progress |= run_peephole_window(ops, self.func);
+        }
+    }
+
+    let mut peephole = Peephole::<F, WINDOW> {
+        func,
+        progress: false,
+    };
+    walk_mut(&mut peephole, ops);
+
+    peephole.progress
+}
+
+fn run_peephole_window<F, const WINDOW: usize>(ops: &mut Vec<Op>, func: F) -> bool
 where
     F: Fn([&Op; WINDOW]) -> Change + Copy,
 {
@@ -25,7 +54,7 @@ where
 
         let change = func(window);
 
-        let (changed, removed) = change.apply(&mut ops, i, WINDOW);
+        let (changed, removed) = change.apply(ops, i, WINDOW);
         i -= removed;
         if changed {
             progress = true;
@@ -34,11 +63,5 @@ where
         }
     }
 
-    for op in ops {
-        if let Some(children) = op.op_type.get_children_mut() {
-            progress |= run_peephole_pass(children, func);
-        }
-    }
-
     progress
 }
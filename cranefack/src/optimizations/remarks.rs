@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+use codespan_reporting::diagnostic::{Diagnostic, Label};
+use codespan_reporting::files::SimpleFiles;
+use codespan_reporting::term::termcolor::{ColorChoice, StandardStream};
+
+/// Type of a [Remark] emitted by an optimization pass
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RemarkType {
+    /// Loop clearing the current cell was replaced with a set to zero
+    ZeroLoop,
+
+    /// Loop was converted into a loop decrementing a counter cell by the step each iteration
+    CountLoop { step: u32 },
+
+    /// Loop was converted into a loop with a known number of iterations
+    ConstantLoop { iterations: u32 },
+
+    /// Loop was converted into a search for the next zero cell
+    SearchZero { step: isize },
+
+    /// Loop was removed because it is never entered
+    DeadLoop,
+
+    /// Store was removed because the cell is overwritten before it is read
+    DeadStore,
+
+    /// Loop was not converted into a counting loop for the given reason
+    MissedCountLoop(&'static str),
+}
+
+impl RemarkType {
+    /// Check if the remark explains why an optimization didn't happen
+    pub fn is_missed(&self) -> bool {
+        matches!(self, RemarkType::MissedCountLoop(..))
+    }
+}
+
+impl Display for RemarkType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RemarkType::ZeroLoop => write!(f, "Loop replaced with setting the cell to zero"),
+            RemarkType::CountLoop { step } => {
+                write!(f, "Loop converted to ILoop with step {}", step)
+            }
+            RemarkType::ConstantLoop { iterations } => {
+                write!(f, "Loop converted to CLoop with {} iterations", iterations)
+            }
+            RemarkType::SearchZero { step } => {
+                write!(f, "Loop converted to search for zero with step {}", step)
+            }
+            RemarkType::DeadLoop => write!(f, "Dead loop removed"),
+            RemarkType::DeadStore => write!(f, "Dead store removed"),
+            RemarkType::MissedCountLoop(reason) => {
+                write!(f, "Loop not converted to ILoop: {}", reason)
+            }
+        }
+    }
+}
+
+/// Note about a change made by an optimization pass
+///
+/// Remarks are collected per pass in the [OptimizationReport](crate::OptimizationReport).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Remark {
+    /// Name of the pass that emitted the remark
+    ///
+    /// Set by the [PassManager](crate::PassManager) after the pass ran.
+    pub pass: String,
+
+    /// Code position of the op the remark is about
+    pub span: Range<usize>,
+
+    /// Type of the remark
+    pub remark_type: RemarkType,
+}
+
+impl Remark {
+    pub fn new(span: Range<usize>, remark_type: RemarkType) -> Remark {
+        Remark {
+            pass: String::new(),
+            span,
+            remark_type,
+        }
+    }
+
+    pub fn pretty_print(
+        remarks: &[Remark],
+        source: &str,
+        filename: Option<&str>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut files = SimpleFiles::new();
+
+        let file_id = files.add(filename.unwrap_or(""), source);
+
+        let writer = StandardStream::stderr(ColorChoice::Always);
+        let config = codespan_reporting::term::Config::default();
+
+        for remark in remarks {
+            let diagnostic = Diagnostic::note()
+                .with_message(remark.remark_type.to_string())
+                .with_labels(vec![Label::primary(file_id, remark.span.clone())])
+                .with_notes(vec![format!("pass: {}", remark.pass)]);
+
+            codespan_reporting::term::emit(&mut writer.lock(), &config, &files, &diagnostic)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::ops::Op;
+    use crate::optimizations::{OptimizationPass, PassContext, PassManager};
+    use crate::{optimize_with_config, parse, OptimizeConfig, Remark, RemarkType};
+
+    fn remark(pass: &str, span: std::ops::Range<usize>, remark_type: RemarkType) -> Remark {
+        Remark {
+            pass: pass.to_owned(),
+            span,
+            remark_type,
+        }
+    }
+
+    #[test]
+    fn test_loop_remarks() {
+        let mut program = parse("+++[>++<-]>[<]>[-]").unwrap();
+        let report = optimize_with_config(&mut program, &OptimizeConfig::o1());
+
+        assert_eq!(
+            report.remarks(),
+            vec![remark("zero-loops", 15..18, RemarkType::ZeroLoop)]
+        );
+
+        let mut program = parse("+++[>++<-]>[<]>[-]").unwrap();
+        let report = optimize_with_config(&mut program, &OptimizeConfig::o2());
+        let remarks = report.remarks();
+
+        assert!(remarks.contains(&remark(
+            "count-loops",
+            3..10,
+            RemarkType::CountLoop { step: 1 }
+        )));
+        assert!(remarks.contains(&remark(
+            "constant-loops",
+            3..10,
+            RemarkType::ConstantLoop { iterations: 3 }
+        )));
+        assert!(remarks.contains(&remark(
+            "search-zero",
+            11..14,
+            RemarkType::SearchZero { step: -1 }
+        )));
+    }
+
+    #[test]
+    fn test_missed_remarks() {
+        let mut program = parse(",[-.-]").unwrap();
+        let report = optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        assert_eq!(
+            report.remarks(),
+            vec![remark(
+                "count-loops",
+                1..6,
+                RemarkType::MissedCountLoop("counter cell is read between its decrements")
+            )]
+        );
+        assert_eq!(
+            report.remarks()[0].remark_type.to_string(),
+            "Loop not converted to ILoop: counter cell is read between its decrements"
+        );
+    }
+
+    #[test]
+    fn test_dead_store_remarks() {
+        let mut program = parse("++,").unwrap();
+        let report = optimize_with_config(&mut program, &OptimizeConfig::o1());
+
+        assert_eq!(
+            report.remarks(),
+            vec![remark("dead-stores", 0..2, RemarkType::DeadStore)]
+        );
+    }
+
+    struct RemarkAll;
+
+    impl OptimizationPass for RemarkAll {
+        fn name(&self) -> &str {
+            "remark-all"
+        }
+
+        fn run(&self, ops: &mut Vec<Op>, context: &PassContext) -> bool {
+            for op in ops.iter().skip(1) {
+                context.remark(op.span.clone(), RemarkType::DeadStore);
+            }
+            false
+        }
+    }
+
+    #[test]
+    fn test_custom_pass_remarks() {
+        let mut manager = PassManager::new();
+        manager.add_pass(Box::new(RemarkAll));
+
+        let mut program = parse("+>").unwrap();
+        let report = manager.run(&mut program, &OptimizeConfig::o1());
+
+        assert_eq!(
+            report.iterations[0].passes[0].remarks,
+            vec![
+                remark("remark-all", 0..1, RemarkType::DeadStore),
+                remark("remark-all", 1..2, RemarkType::DeadStore)
+            ]
+        );
+    }
+}
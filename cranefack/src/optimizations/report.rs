@@ -0,0 +1,313 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::ir::ops::Op;
+use crate::optimizations::remarks::Remark;
+use crate::parser::Program;
+
+/// Number of ops, loops and conditions in a program
+///
+/// Same values as returned by [Program::get_statistics]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ProgramStatistics {
+    pub op_count: usize,
+    pub dloop_count: usize,
+    pub lloop_count: usize,
+    pub iloop_count: usize,
+    pub cloop_count: usize,
+    pub if_count: usize,
+}
+
+impl ProgramStatistics {
+    pub(crate) fn from_ops(ops: &[Op]) -> ProgramStatistics {
+        let (op_count, dloop_count, lloop_count, iloop_count, cloop_count, if_count) =
+            Program::get_ops_statistics(ops);
+
+        ProgramStatistics {
+            op_count,
+            dloop_count,
+            lloop_count,
+            iloop_count,
+            cloop_count,
+            if_count,
+        }
+    }
+
+    fn write_json(&self, output: &mut String) {
+        write!(
+            output,
+            "{{\"ops\":{},\"dloops\":{},\"lloops\":{},\"iloops\":{},\"cloops\":{},\"ifs\":{}}}",
+            self.op_count,
+            self.dloop_count,
+            self.lloop_count,
+            self.iloop_count,
+            self.cloop_count,
+            self.if_count
+        )
+        .unwrap();
+    }
+}
+
+/// Result of a single run of an optimization pass
+///
+/// The statistics and the duration are only collected with
+/// [OptimizeConfig::statistics](crate::OptimizeConfig::statistics).
+#[derive(Debug, Clone)]
+pub struct PassReport {
+    /// Name of the pass
+    pub name: String,
+
+    /// Statistics of the program before the pass
+    pub before: ProgramStatistics,
+
+    /// Statistics of the program after the pass
+    pub after: ProgramStatistics,
+
+    /// Time the pass took to run
+    pub duration: Duration,
+
+    /// The pass changed the program
+    pub progress: bool,
+
+    /// Remarks emitted by the pass
+    pub remarks: Vec<Remark>,
+}
+
+/// Passes run during one iteration of the pipeline
+#[derive(Debug, Clone, Default)]
+pub struct IterationReport {
+    /// Passes in the order they ran
+    pub passes: Vec<PassReport>,
+}
+
+impl IterationReport {
+    /// Check if any pass of the iteration changed the program
+    pub fn progress(&self) -> bool {
+        self.passes.iter().any(|pass| pass.progress)
+    }
+}
+
+/// Statistics of all passes run by the optimizer
+///
+/// ```
+/// # use std::error::Error;
+/// # fn main() -> Result<(), Box<dyn Error>> {
+///     use cranefack::{parse, optimize_with_config, OptimizeConfig};
+///
+///     let mut program = parse("+++[>++<-]>.")?;
+///     let config = OptimizeConfig {
+///         statistics: true,
+///         ..OptimizeConfig::o2()
+///     };
+///     let report = optimize_with_config(&mut program, &config);
+///
+///     for pass in report.iterations.iter().flat_map(|iteration| &iteration.passes) {
+///         if pass.progress {
+///             println!("{} took {:?}", pass.name, pass.duration);
+///         }
+///     }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct OptimizationReport {
+    /// Iterations over all passes of the pipeline
+    pub iterations: Vec<IterationReport>,
+}
+
+impl OptimizationReport {
+    /// Number of iterations over all passes
+    pub fn iteration_count(&self) -> usize {
+        self.iterations.len()
+    }
+
+    /// Total time of all passes
+    pub fn duration(&self) -> Duration {
+        self.iterations
+            .iter()
+            .flat_map(|iteration| &iteration.passes)
+            .map(|pass| pass.duration)
+            .sum()
+    }
+
+    /// Remarks of all passes without duplicates
+    ///
+    /// Remarks about missed optimizations are only taken from the last iteration
+    /// as earlier ones might be outdated by later changes of the program.
+    pub fn remarks(&self) -> Vec<Remark> {
+        let last = self.iterations.len().saturating_sub(1);
+        let mut remarks: Vec<Remark> = vec![];
+
+        for (index, iteration) in self.iterations.iter().enumerate() {
+            for remark in iteration.passes.iter().flat_map(|pass| &pass.remarks) {
+                if remark.remark_type.is_missed() && index != last {
+                    continue;
+                }
+
+                if !remarks.contains(remark) {
+                    remarks.push(remark.clone());
+                }
+            }
+        }
+
+        remarks
+    }
+
+    /// Serialize the report into a single line of JSON
+    ///
+    /// Durations are given in nanoseconds.
+    pub fn to_json(&self) -> String {
+        let mut output = String::new();
+
+        output.push_str("{\"iterations\":[");
+
+        for (index, iteration) in self.iterations.iter().enumerate() {
+            if index > 0 {
+                output.push(',');
+            }
+
+            write!(output, "{{\"iteration\":{},\"passes\":[", index + 1).unwrap();
+
+            for (index, pass) in iteration.passes.iter().enumerate() {
+                if index > 0 {
+                    output.push(',');
+                }
+
+                output.push_str("{\"name\":");
+                write_json_string(&mut output, &pass.name);
+                write!(
+                    output,
+                    ",\"progress\":{},\"duration_ns\":{},\"before\":",
+                    pass.progress,
+                    pass.duration.as_nanos()
+                )
+                .unwrap();
+                pass.before.write_json(&mut output);
+                output.push_str(",\"after\":");
+                pass.after.write_json(&mut output);
+
+                output.push_str(",\"remarks\":[");
+                for (index, remark) in pass.remarks.iter().enumerate() {
+                    if index > 0 {
+                        output.push(',');
+                    }
+
+                    write!(
+                        output,
+                        "{{\"span\":[{},{}],\"message\":",
+                        remark.span.start, remark.span.end
+                    )
+                    .unwrap();
+                    write_json_string(&mut output, &remark.remark_type.to_string());
+                    output.push('}');
+                }
+                output.push_str("]}");
+            }
+
+            output.push_str("]}");
+        }
+
+        write!(output, "],\"duration_ns\":{}}}", self.duration().as_nanos()).unwrap();
+
+        output
+    }
+}
+
+fn write_json_string(output: &mut String, value: &str) {
+    output.push('"');
+
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            '\r' => output.push_str("\\r"),
+            '\t' => output.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(output, "\\u{:04x}", c as u32).unwrap(),
+            c => output.push(c),
+        }
+    }
+
+    output.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{optimize_with_config, parse, OptimizeConfig, ProgramStatistics};
+
+    #[test]
+    fn test_report() {
+        let mut program = parse("+++[>++<-]>[-]").unwrap();
+        let parsed = ProgramStatistics::from_ops(&program.ops);
+
+        let config = OptimizeConfig {
+            statistics: true,
+            ..OptimizeConfig::o2()
+        };
+        let report = optimize_with_config(&mut program, &config);
+        let optimized = ProgramStatistics::from_ops(&program.ops);
+
+        assert!(report.iteration_count() > 1);
+        assert!(report.iterations[0].progress());
+        assert!(!report.iterations.last().unwrap().progress());
+
+        let passes: Vec<_> = report
+            .iterations
+            .iter()
+            .flat_map(|iteration| &iteration.passes)
+            .collect();
+
+        assert_eq!(passes.first().unwrap().before, parsed);
+        assert_eq!(passes.last().unwrap().after, optimized);
+
+        for pair in passes.windows(2) {
+            assert_eq!(pair[0].after, pair[1].before);
+        }
+
+        assert!(passes
+            .iter()
+            .any(|pass| pass.name == "count-loops" && pass.progress));
+    }
+
+    #[test]
+    fn test_report_without_statistics() {
+        let mut program = parse("+++[>++<-]>[-]").unwrap();
+        let report = optimize_with_config(&mut program, &OptimizeConfig::o2());
+
+        assert!(report.iterations[0].progress());
+        assert!(report
+            .iterations
+            .iter()
+            .flat_map(|iteration| &iteration.passes)
+            .all(|pass| pass.after == ProgramStatistics::default() && pass.duration.is_zero()));
+    }
+
+    #[test]
+    fn test_json() {
+        let mut program = parse("+[-]").unwrap();
+        let config = OptimizeConfig {
+            statistics: true,
+            ..OptimizeConfig::o1()
+        };
+        let report = optimize_with_config(&mut program, &config);
+
+        let json = report.to_json();
+
+        assert!(json.starts_with(
+            "{\"iterations\":[{\"iteration\":1,\"passes\":[{\"name\":\"dead-loops\",\"progress\":false,\"duration_ns\":"
+        ));
+        assert!(json.contains(
+            "\"before\":{\"ops\":3,\"dloops\":1,\"lloops\":0,\"iloops\":0,\"cloops\":0,\"ifs\":0}"
+        ));
+        assert!(json.contains("{\"name\":\"zero-loops\",\"progress\":true,"));
+        assert_eq!(
+            json.matches("\"iteration\":").count(),
+            report.iteration_count()
+        );
+        assert!(json.ends_with('}'));
+
+        let mut escaped = String::new();
+        super::write_json_string(&mut escaped, "a\"b\\c\n\u{1}");
+        assert_eq!(escaped, "\"a\\\"b\\\\c\\n\\u0001\"");
+    }
+}
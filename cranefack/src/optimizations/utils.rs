@@ -1,7 +1,9 @@
+use std::mem;
 use std::ops::Range;
 
-use crate::ir::ops::{Op, OpType};
+use crate::ir::ops::{CellSize, Op, OpType};
 use crate::ir::opt_info::Cell;
+use crate::ir::visitor::{walk, walk_mut, Visitor, VisitorMut};
 
 #[derive(Debug)]
 pub enum Change {
@@ -104,8 +106,8 @@ pub enum CellValue {
     Unknown,
     NonZero,
     Bool,
-    Value(u8),
-    Range(u8, u8),
+    Value(u32),
+    Range(u32, u32),
 }
 
 impl CellValue {
@@ -124,20 +126,24 @@ impl CellValue {
 }
 
 pub fn count_ops_recursive(ops: &[Op]) -> usize {
-    let mut count = ops.len();
+    struct OpCount(usize);
 
-    for op in ops {
-        if let Some(children) = op.op_type.get_children() {
-            count += count_ops_recursive(children);
+    impl Visitor for OpCount {
+        fn visit_ops(&mut self, ops: &[Op], _depth: usize) {
+            self.0 += ops.len();
         }
     }
 
-    count
+    let mut count = OpCount(0);
+    walk(&mut count, ops);
+
+    count.0
 }
 
 struct AccessIndices {
     zeroed: bool,
     offset: isize,
+    // Sorted to keep lookups fast in deeply nested loops with many known cells
     indices: Vec<isize>,
 }
 
@@ -174,56 +180,186 @@ impl AccessIndices {
     pub fn add(&mut self, offset: isize) {
         let offset = self.offset + offset;
 
-        if !self.indices.contains(&offset) {
-            self.indices.push(offset);
+        if let Err(index) = self.indices.binary_search(&offset) {
+            self.indices.insert(index, offset);
+        }
+    }
+}
+
+/// Run a pass over the ops and the bodies of all nested blocks
+///
+/// The pass sees the ops of a block before the bodies of the blocks nested in them.
+pub fn run_block_pass<F>(ops: &mut Vec<Op>, func: F) -> bool
+where
+    F: FnMut(&mut Vec<Op>) -> bool,
+{
+    struct BlockPass<F> {
+        func: F,
+        progress: bool,
+    }
+
+    impl<F> VisitorMut for BlockPass<F>
+    where
+        F: FnMut(&mut Vec<Op>) -> bool,
+    {
+        fn visit_ops_mut(&mut self, ops: &mut Vec<Op>, _depth: usize) {
+            self.progress |= (self.func)(ops);
+        }
+    }
+
+    let mut pass = BlockPass {
+        func,
+        progress: false,
+    };
+    walk_mut(&mut pass, ops);
+
+    pass.progress
+}
+
+/// Run a pass over the bodies of all nested blocks and the ops
+///
+/// The pass sees the ops of a block after the bodies of the blocks nested in them.
+pub fn run_block_pass_bottom_up<F>(ops: &mut Vec<Op>, func: F) -> bool
+where
+    F: FnMut(&mut Vec<Op>) -> bool,
+{
+    struct BlockPass<F> {
+        func: F,
+        progress: bool,
+    }
+
+    impl<F> VisitorMut for BlockPass<F>
+    where
+        F: FnMut(&mut Vec<Op>) -> bool,
+    {
+        fn leave_block_mut(&mut self, op: &mut Op, _depth: usize) {
+            if let Some(children) = op.op_type.get_children_mut() {
+                self.progress |= (self.func)(children);
+            }
+        }
+    }
+
+    let mut pass = BlockPass {
+        func,
+        progress: false,
+    };
+    walk_mut(&mut pass, ops);
+
+    pass.progress | (pass.func)(ops)
+}
+
+/// Ops of a block that is processed by [run_non_local_pass]
+struct NonLocalBlock {
+    ops: Vec<Op>,
+    index: usize,
+    access: AccessIndices,
+    inputs: Vec<(isize, CellValue)>,
+    // Known cells after the block are updated with its cell access once it is left
+    update_access: bool,
+}
+
+impl NonLocalBlock {
+    fn new(
+        ops: Vec<Op>,
+        zeroed: bool,
+        inputs: Vec<(isize, CellValue)>,
+        update_access: bool,
+    ) -> NonLocalBlock {
+        let mut access = AccessIndices::new(zeroed);
+
+        for (offset, _) in &inputs {
+            access.add(*offset)
+        }
+
+        NonLocalBlock {
+            ops,
+            index: 0,
+            access,
+            inputs,
+            update_access,
         }
     }
 }
 
+/// Run a pass over the ops and the bodies of all nested blocks with the known cell values at the
+/// start of each block
+///
+/// Nested blocks are processed with an explicit stack, so deeply nested programs don't overflow
+/// the stack.
 pub fn run_non_local_pass<F>(
     ops: &mut Vec<Op>,
     func: F,
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
 ) -> bool
 where
-    F: Fn(&mut Vec<Op>, bool, &[(isize, CellValue)], bool) -> bool + Copy,
+    F: Fn(&mut Vec<Op>, bool, &[(isize, CellValue)], bool, CellSize) -> bool + Copy,
 {
+    let mut inputs = inputs.to_vec();
+    inputs.sort_by_key(|(offset, _)| *offset);
+
     let mut progress = false;
 
-    progress |= func(ops, zeroed, inputs, wrapping_is_ub);
+    progress |= func(ops, zeroed, &inputs, wrapping_is_ub, cell_size);
 
-    let mut access = AccessIndices::new(zeroed);
+    let mut stack = vec![NonLocalBlock::new(mem::take(ops), zeroed, inputs, false)];
 
-    for (offset, _) in inputs {
-        access.add(*offset)
-    }
+    while let Some(block) = stack.last_mut() {
+        let i = block.index;
+
+        if i >= block.ops.len() {
+            let block = stack.pop().expect("Stack is not empty");
 
-    let mut i = 0;
+            if let Some(parent) = stack.last_mut() {
+                let op = &mut parent.ops[parent.index];
 
-    while i < ops.len() {
-        let op = &mut ops[i];
+                *op.op_type
+                    .get_children_mut()
+                    .expect("Loops must have children") = block.ops;
 
-        let is_loop = match &mut op.op_type {
+                if block.update_access {
+                    if let Some(info) = op.op_type.get_block_info() {
+                        if let Some(cell_access) = info.cell_access() {
+                            for cell in cell_access {
+                                parent.access.add(cell.offset);
+                            }
+                        }
+                    } else {
+                        unreachable!("Local loops must a block info");
+                    }
+                }
+
+                parent.index += 1;
+            } else {
+                *ops = block.ops;
+            }
+
+            continue;
+        }
+
+        let access = &mut block.access;
+
+        let is_loop = match &mut block.ops[i].op_type {
             OpType::Start => {
                 access.set_zeroed(true);
-                false
+                None
             }
             OpType::IncPtr(offset) => {
                 access.inc_offset(*offset);
-                false
+                None
             }
             OpType::DecPtr(offset) => {
                 access.dec_offset(*offset);
-                false
+                None
             }
             OpType::Set(offset, _)
             | OpType::Inc(offset, _)
             | OpType::Dec(offset, _)
             | OpType::GetChar(offset) => {
                 access.add(*offset);
-                false
+                None
             }
             OpType::Add(src_offset, dest_offset, _)
             | OpType::CAdd(src_offset, dest_offset, _)
@@ -233,7 +369,7 @@ where
             | OpType::Move(src_offset, dest_offset) => {
                 access.add(*src_offset);
                 access.add(*dest_offset);
-                false
+                None
             }
             OpType::NzAdd(_, offset, _)
             | OpType::NzCAdd(_, offset, _)
@@ -242,7 +378,7 @@ where
             | OpType::NzMul(_, offset, _)
             | OpType::Copy(_, offset) => {
                 access.add(*offset);
-                false
+                None
             }
             OpType::SearchZero(step, always) => {
                 access.clear();
@@ -252,55 +388,63 @@ where
                     access.add(-*step);
                 }
 
-                false
+                None
             }
-            OpType::DLoop(children, info) => {
+            OpType::DLoop(_, info) => {
                 if info.has_cell_access() {
-                    true
+                    Some(true)
                 } else {
                     access.clear();
-                    progress |= run_non_local_pass(children, func, false, &[], wrapping_is_ub);
-                    false
+                    Some(false)
                 }
             }
             OpType::LLoop(..)
             | OpType::ILoop(..)
             | OpType::CLoop(..)
             | OpType::TNz(..)
-            | OpType::DTNz(..) => true,
-            OpType::PutChar(_) | OpType::PutString(..) => {
+            | OpType::DTNz(..) => Some(true),
+            OpType::PutChar(_) | OpType::PutString(..) | OpType::Breakpoint => {
                 // Ignore
-                false
+                None
             }
         };
 
-        if is_loop {
-            let loop_inputs = get_loop_inputs(ops, &inputs, wrapping_is_ub, &mut access, i);
-
-            if let Some(children) = ops[i].op_type.get_children_mut() {
-                progress |= run_non_local_pass(
-                    children,
-                    func,
-                    access.zeroed(),
-                    &loop_inputs,
+        let (zeroed, loop_inputs, update_access) = match is_loop {
+            Some(true) => {
+                let loop_inputs = get_loop_inputs(
+                    &mut block.ops,
+                    &block.inputs.as_slice(),
                     wrapping_is_ub,
+                    cell_size,
+                    &mut block.access,
+                    i,
                 );
-            } else {
-                unreachable!("Local loops must have children");
-            }
 
-            if let Some(info) = ops[i].op_type.get_block_info() {
-                if let Some(cell_access) = info.cell_access() {
-                    for cell in cell_access {
-                        access.add(cell.offset);
-                    }
-                }
-            } else {
-                unreachable!("Local loops must a block info");
+                (block.access.zeroed(), loop_inputs, true)
             }
-        }
+            // Nothing is known about loops without cell access
+            Some(false) => (false, vec![], false),
+            None => {
+                block.index += 1;
+                continue;
+            }
+        };
 
-        i += 1
+        let children = if let Some(children) = block.ops[i].op_type.get_children_mut() {
+            children
+        } else {
+            unreachable!("Local loops must have children");
+        };
+
+        progress |= func(children, zeroed, &loop_inputs, wrapping_is_ub, cell_size);
+
+        let children = mem::take(children);
+        stack.push(NonLocalBlock::new(
+            children,
+            zeroed,
+            loop_inputs,
+            update_access,
+        ));
     }
 
     progress
@@ -310,12 +454,13 @@ fn get_loop_inputs(
     ops: &mut Vec<Op>,
     inputs: &&[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
     access: &mut AccessIndices,
     i: usize,
 ) -> Vec<(isize, CellValue)> {
     let is_d_loop = matches!(ops[i].op_type, OpType::DLoop(..));
 
-    let loop_inputs = if is_d_loop {
+    let mut loop_inputs = if is_d_loop {
         if let Some(info) = ops[i].op_type.get_block_info() {
             let mut loop_inputs = vec![];
             if let Some(cell_access) = info.cell_access() {
@@ -329,6 +474,7 @@ fn get_loop_inputs(
                             access.zeroed,
                             inputs,
                             wrapping_is_ub,
+                            cell_size,
                             true,
                         ),
                     ) {
@@ -342,7 +488,7 @@ fn get_loop_inputs(
                             } else {
                                 let min = v1.min(v2);
                                 let max = v1.max(v2);
-                                if min != 0 && max != 255 {
+                                if min != 0 && max != cell_size.max_value() {
                                     loop_inputs.push((cell.offset, CellValue::Range(min, max)));
                                 }
                             }
@@ -434,6 +580,7 @@ fn get_loop_inputs(
                             access.zeroed(),
                             inputs,
                             wrapping_is_ub,
+                            cell_size,
                             true,
                         ) == CellValue::Bool
                         {
@@ -454,6 +601,7 @@ fn get_loop_inputs(
                             access.zeroed(),
                             inputs,
                             wrapping_is_ub,
+                            cell_size,
                             true,
                         ),
                     )
@@ -464,6 +612,9 @@ fn get_loop_inputs(
         if !matches!(ops[i].op_type, OpType::TNz(..) | OpType::DTNz(..)) {
             if let Some(info) = ops[i].op_type.get_block_info() {
                 if let Some(cell_access) = info.cell_access() {
+                    // Cells accessed by the loop are unique so only the known inputs are searched
+                    let known = loop_inputs.len();
+
                     for cell in cell_access {
                         if cell.offset != 0 {
                             match &cell.value {
@@ -471,73 +622,65 @@ fn get_loop_inputs(
                                     //ignore
                                 }
                                 Cell::Write => {
-                                    let mut found = false;
-
-                                    for (offset, value) in loop_inputs.iter_mut() {
-                                        if *offset == cell.offset {
-                                            *value = CellValue::Unknown;
-                                            found = true;
-                                        }
-                                    }
-
-                                    if !found {
+                                    if let Ok(index) = loop_inputs[..known]
+                                        .binary_search_by_key(&cell.offset, |(offset, _)| *offset)
+                                    {
+                                        loop_inputs[index].1 = CellValue::Unknown;
+                                    } else {
                                         loop_inputs.push((cell.offset, CellValue::Unknown))
                                     }
                                 }
                                 Cell::Value(v) => {
-                                    let mut found = false;
-
-                                    for (offset, value) in loop_inputs.iter_mut() {
-                                        if *offset == cell.offset {
-                                            match value {
-                                                CellValue::Unknown => {
-                                                    // Keep as is
-                                                }
-                                                CellValue::Value(v2) => {
-                                                    if *v != *v2 {
-                                                        if *v != 0 && *v2 != 0 {
-                                                            *value = CellValue::NonZero;
-                                                        } else if (*v == 0 || *v == 1)
-                                                            && (*v2 == 0 || *v2 == 1)
+                                    if let Ok(index) = loop_inputs[..known]
+                                        .binary_search_by_key(&cell.offset, |(offset, _)| *offset)
+                                    {
+                                        let value = &mut loop_inputs[index].1;
+
+                                        match value {
+                                            CellValue::Unknown => {
+                                                // Keep as is
+                                            }
+                                            CellValue::Value(v2) => {
+                                                if *v != *v2 {
+                                                    if *v != 0 && *v2 != 0 {
+                                                        *value = CellValue::NonZero;
+                                                    } else if (*v == 0 || *v == 1)
+                                                        && (*v2 == 0 || *v2 == 1)
+                                                    {
+                                                        *value = CellValue::Bool;
+                                                    } else {
+                                                        let min = (*v).min(*v2);
+                                                        let max = (*v).max(*v2);
+                                                        if min != 0 && max != cell_size.max_value()
                                                         {
-                                                            *value = CellValue::Bool;
+                                                            *value = CellValue::Range(min, max);
                                                         } else {
-                                                            let min = (*v).min(*v2);
-                                                            let max = (*v).max(*v2);
-                                                            if min != 0 && max != 255 {
-                                                                *value = CellValue::Range(min, max);
-                                                            } else {
-                                                                *value = CellValue::Unknown;
-                                                            }
+                                                            *value = CellValue::Unknown;
                                                         }
                                                     }
                                                 }
-                                                CellValue::Range(start, end) => {
-                                                    let start = (*start).min(*v);
-                                                    let end = (*end).max(*v);
-                                                    if start == 0 && end == 255 {
-                                                        *value = CellValue::Unknown;
-                                                    } else {
-                                                        *value = CellValue::Range(start, end);
-                                                    }
+                                            }
+                                            CellValue::Range(start, end) => {
+                                                let start = (*start).min(*v);
+                                                let end = (*end).max(*v);
+                                                if start == 0 && end == cell_size.max_value() {
+                                                    *value = CellValue::Unknown;
+                                                } else {
+                                                    *value = CellValue::Range(start, end);
                                                 }
-                                                CellValue::Bool => {
-                                                    if *v != 0 && *v != 1 {
-                                                        *value = CellValue::Unknown;
-                                                    }
+                                            }
+                                            CellValue::Bool => {
+                                                if *v != 0 && *v != 1 {
+                                                    *value = CellValue::Unknown;
                                                 }
-                                                CellValue::NonZero => {
-                                                    if *v == 0 {
-                                                        *value = CellValue::Unknown;
-                                                    }
+                                            }
+                                            CellValue::NonZero => {
+                                                if *v == 0 {
+                                                    *value = CellValue::Unknown;
                                                 }
                                             }
-                                            found = true;
-                                            break;
                                         }
-                                    }
-
-                                    if !found {
+                                    } else {
                                         if *v == 0 && access.zeroed() {
                                             loop_inputs.push((cell.offset, CellValue::Value(*v)))
                                         } else {
@@ -546,57 +689,54 @@ fn get_loop_inputs(
                                     }
                                 }
                                 Cell::Range(s1, e1) => {
-                                    let mut found = false;
-
-                                    for (offset, value) in loop_inputs.iter_mut() {
-                                        if *offset == cell.offset {
-                                            match value {
-                                                CellValue::Unknown => {
-                                                    // Keep as is
-                                                }
-                                                CellValue::Value(v) => {
-                                                    let start = (*s1).min(*v);
-                                                    let end = (*e1).max(*v);
-                                                    if start == 0 && end == 255 {
-                                                        *value = CellValue::Unknown;
-                                                    } else {
-                                                        *value = CellValue::Range(start, end);
-                                                    }
+                                    if let Ok(index) = loop_inputs[..known]
+                                        .binary_search_by_key(&cell.offset, |(offset, _)| *offset)
+                                    {
+                                        let value = &mut loop_inputs[index].1;
+
+                                        match value {
+                                            CellValue::Unknown => {
+                                                // Keep as is
+                                            }
+                                            CellValue::Value(v) => {
+                                                let start = (*s1).min(*v);
+                                                let end = (*e1).max(*v);
+                                                if start == 0 && end == cell_size.max_value() {
+                                                    *value = CellValue::Unknown;
+                                                } else {
+                                                    *value = CellValue::Range(start, end);
                                                 }
-                                                CellValue::Range(start, end) => {
-                                                    let start = (*start).min(*s1);
-                                                    let end = (*end).max(*e1);
-                                                    if start == 0 && end == 255 {
-                                                        *value = CellValue::Unknown;
-                                                    } else {
-                                                        *value = CellValue::Range(start, end);
-                                                    }
+                                            }
+                                            CellValue::Range(start, end) => {
+                                                let start = (*start).min(*s1);
+                                                let end = (*end).max(*e1);
+                                                if start == 0 && end == cell_size.max_value() {
+                                                    *value = CellValue::Unknown;
+                                                } else {
+                                                    *value = CellValue::Range(start, end);
                                                 }
-                                                CellValue::Bool => {
-                                                    let start = (*s1).min(0);
-                                                    let end = (*e1).max(1);
-                                                    if start == 0 && end == 255 {
-                                                        *value = CellValue::Unknown;
-                                                    } else {
-                                                        *value = CellValue::Range(start, end);
-                                                    }
+                                            }
+                                            CellValue::Bool => {
+                                                let start = (*s1).min(0);
+                                                let end = (*e1).max(1);
+                                                if start == 0 && end == cell_size.max_value() {
+                                                    *value = CellValue::Unknown;
+                                                } else {
+                                                    *value = CellValue::Range(start, end);
                                                 }
-                                                CellValue::NonZero => {
-                                                    if *s1 > 0 {
-                                                        *value = CellValue::NonZero;
-                                                    } else {
-                                                        *value = CellValue::Unknown;
-                                                    }
+                                            }
+                                            CellValue::NonZero => {
+                                                if *s1 > 0 {
+                                                    *value = CellValue::NonZero;
+                                                } else {
+                                                    *value = CellValue::Unknown;
                                                 }
                                             }
-                                            found = true;
-                                            break;
                                         }
-                                    }
-                                    if !found {
+                                    } else {
                                         if access.zeroed() {
                                             let start = 0.min(*s1);
-                                            if start == 0 && *e1 == 255 {
+                                            if start == 0 && *e1 == cell_size.max_value() {
                                                 loop_inputs.push((cell.offset, CellValue::Unknown));
                                             } else {
                                                 loop_inputs.push((
@@ -610,79 +750,71 @@ fn get_loop_inputs(
                                     }
                                 }
                                 Cell::NonZero => {
-                                    let mut found = false;
-
-                                    for (offset, value) in loop_inputs.iter_mut() {
-                                        if *offset == cell.offset {
-                                            match value {
-                                                CellValue::Unknown => {
-                                                    // Keep as is
-                                                }
-                                                CellValue::Value(v2) => {
-                                                    if *v2 != 0 {
-                                                        *value = CellValue::NonZero;
-                                                    } else {
-                                                        *value = CellValue::Unknown;
-                                                    }
-                                                }
-                                                CellValue::Range(start, _) => {
-                                                    if *start > 0 {
-                                                        *value = CellValue::NonZero;
-                                                    } else {
-                                                        *value = CellValue::Unknown;
-                                                    }
-                                                }
-                                                CellValue::NonZero => {
+                                    if let Ok(index) = loop_inputs[..known]
+                                        .binary_search_by_key(&cell.offset, |(offset, _)| *offset)
+                                    {
+                                        let value = &mut loop_inputs[index].1;
+
+                                        match value {
+                                            CellValue::Unknown => {
+                                                // Keep as is
+                                            }
+                                            CellValue::Value(v2) => {
+                                                if *v2 != 0 {
                                                     *value = CellValue::NonZero;
+                                                } else {
+                                                    *value = CellValue::Unknown;
                                                 }
-                                                CellValue::Bool => {
+                                            }
+                                            CellValue::Range(start, _) => {
+                                                if *start > 0 {
+                                                    *value = CellValue::NonZero;
+                                                } else {
                                                     *value = CellValue::Unknown;
                                                 }
                                             }
-                                            found = true;
-                                            break;
+                                            CellValue::NonZero => {
+                                                *value = CellValue::NonZero;
+                                            }
+                                            CellValue::Bool => {
+                                                *value = CellValue::Unknown;
+                                            }
                                         }
-                                    }
-
-                                    if !found {
+                                    } else {
                                         loop_inputs.push((cell.offset, CellValue::Unknown))
                                     }
                                 }
                                 Cell::Bool => {
-                                    let mut found = false;
-
-                                    for (offset, value) in loop_inputs.iter_mut() {
-                                        if *offset == cell.offset {
-                                            match value {
-                                                CellValue::Unknown | CellValue::Bool => {
-                                                    // Keep as is
-                                                }
-                                                CellValue::Value(v2) => {
-                                                    if *v2 == 0 || *v2 == 1 {
-                                                        *value = CellValue::Bool;
-                                                    } else {
-                                                        *value = CellValue::Unknown;
-                                                    }
-                                                }
-                                                CellValue::Range(start, end) => {
-                                                    let start = (*start).min(0);
-                                                    let end = (*end).max(1);
-                                                    if start == 0 && end == 255 {
-                                                        *value = CellValue::Unknown;
-                                                    } else {
-                                                        *value = CellValue::Range(start, end);
-                                                    }
+                                    if let Ok(index) = loop_inputs[..known]
+                                        .binary_search_by_key(&cell.offset, |(offset, _)| *offset)
+                                    {
+                                        let value = &mut loop_inputs[index].1;
+
+                                        match value {
+                                            CellValue::Unknown | CellValue::Bool => {
+                                                // Keep as is
+                                            }
+                                            CellValue::Value(v2) => {
+                                                if *v2 == 0 || *v2 == 1 {
+                                                    *value = CellValue::Bool;
+                                                } else {
+                                                    *value = CellValue::Unknown;
                                                 }
-                                                CellValue::NonZero => {
+                                            }
+                                            CellValue::Range(start, end) => {
+                                                let start = (*start).min(0);
+                                                let end = (*end).max(1);
+                                                if start == 0 && end == cell_size.max_value() {
                                                     *value = CellValue::Unknown;
+                                                } else {
+                                                    *value = CellValue::Range(start, end);
                                                 }
                                             }
-                                            found = true;
-                                            break;
+                                            CellValue::NonZero => {
+                                                *value = CellValue::Unknown;
+                                            }
                                         }
-                                    }
-
-                                    if !found {
+                                    } else {
                                         loop_inputs.push((cell.offset, CellValue::Unknown))
                                     }
                                 }
@@ -724,9 +856,13 @@ fn get_loop_inputs(
 
         loop_inputs
     };
+
+    loop_inputs.sort_by_key(|(offset, _)| *offset);
+
     loop_inputs
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn find_heap_value(
     ops: &[Op],
     start_cell_offset: isize,
@@ -734,6 +870,7 @@ pub fn find_heap_value(
     zeroed: bool,
     inputs: &[(isize, CellValue)],
     wrapping_is_ub: bool,
+    cell_size: CellSize,
     follow: bool,
 ) -> CellValue {
     let mut cell_offset = start_cell_offset;
@@ -766,17 +903,18 @@ pub fn find_heap_value(
                             zeroed,
                             inputs,
                             wrapping_is_ub,
+                            cell_size,
                             true,
                         ) {
                             CellValue::Bool => {
-                                if *v > 0 && *v < 254 {
+                                if *v > 0 && *v < cell_size.max_value() - 1 {
                                     CellValue::NonZero
                                 } else {
                                     CellValue::Unknown
                                 }
                             }
                             CellValue::Range(start, end) => {
-                                if 255 - end >= *v {
+                                if cell_size.max_value() - end >= *v {
                                     CellValue::Range(start + *v, end + *v)
                                 } else {
                                     CellValue::Unknown
@@ -797,6 +935,7 @@ pub fn find_heap_value(
                             zeroed,
                             inputs,
                             wrapping_is_ub,
+                            cell_size,
                             true,
                         )
                     } else {
@@ -813,6 +952,7 @@ pub fn find_heap_value(
                         zeroed,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
                     let dest_value = find_heap_value(
@@ -822,6 +962,7 @@ pub fn find_heap_value(
                         zeroed,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
 
@@ -840,6 +981,7 @@ pub fn find_heap_value(
                         zeroed,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
                     let dest_value = find_heap_value(
@@ -849,6 +991,7 @@ pub fn find_heap_value(
                         zeroed,
                         inputs,
                         wrapping_is_ub,
+                        cell_size,
                         true,
                     );
 
@@ -860,11 +1003,19 @@ pub fn find_heap_value(
             }
             OpType::Dec(offset, v) | OpType::CSub(_, offset, v) | OpType::NzCSub(_, offset, v) => {
                 if *offset == cell_offset {
-                    match find_heap_value(ops, *offset, i - 1, zeroed, inputs, wrapping_is_ub, true)
-                    {
+                    match find_heap_value(
+                        ops,
+                        *offset,
+                        i - 1,
+                        zeroed,
+                        inputs,
+                        wrapping_is_ub,
+                        cell_size,
+                        true,
+                    ) {
                         CellValue::NonZero => {
                             if *v == 1 {
-                                return CellValue::Range(0, 254);
+                                return CellValue::Range(0, cell_size.max_value() - 1);
                             } else {
                             }
                         }
@@ -886,7 +1037,7 @@ pub fn find_heap_value(
                     return CellValue::Unknown;
                 }
             }
-            OpType::PutChar(..) => {
+            OpType::PutChar(..) | OpType::Breakpoint => {
                 // Ignore
             }
             OpType::DTNz(_, Some(offset), info) => {
@@ -906,6 +1057,12 @@ pub fn find_heap_value(
                     return CellValue::Unknown;
                 }
 
+                // The pointer only moves if the condition is true so neither the cell
+                // before nor inside of the conditional block is known to be the same
+                if *offset != 0 {
+                    return CellValue::Unknown;
+                }
+
                 match info.get_access_value(cell_offset) {
                     Some(Cell::Value(loop_value)) => {
                         let loop_inputs = inputs
@@ -920,6 +1077,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -934,7 +1092,7 @@ pub fn find_heap_value(
                                 } else {
                                     let start = (input_value).min(loop_value);
                                     let end = (input_value).max(loop_value);
-                                    if start == 0 && end == 255 {
+                                    if start == 0 && end == cell_size.max_value() {
                                         return CellValue::Unknown;
                                     } else {
                                         return CellValue::Range(start, end);
@@ -944,7 +1102,7 @@ pub fn find_heap_value(
                             CellValue::Range(start, end) => {
                                 let start = (start).min(loop_value);
                                 let end = (end).max(loop_value);
-                                if start == 0 && end == 255 {
+                                if start == 0 && end == cell_size.max_value() {
                                     return CellValue::Unknown;
                                 } else {
                                     return CellValue::Range(start, end);
@@ -963,7 +1121,7 @@ pub fn find_heap_value(
                                 } else {
                                     let start = 0.min(loop_value);
                                     let end = 1.max(loop_value);
-                                    if start == 0 && end == 255 {
+                                    if start == 0 && end == cell_size.max_value() {
                                         return CellValue::Unknown;
                                     } else {
                                         return CellValue::Range(start, end);
@@ -986,6 +1144,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -1021,6 +1180,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -1033,7 +1193,7 @@ pub fn find_heap_value(
                             CellValue::Range(start, end) => {
                                 let start = 0.min(start);
                                 let end = 1.max(end);
-                                if start == 0 && end == 255 {
+                                if start == 0 && end == cell_size.max_value() {
                                     return CellValue::Unknown;
                                 } else {
                                     return CellValue::Range(start, end);
@@ -1085,6 +1245,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -1099,7 +1260,7 @@ pub fn find_heap_value(
                                 } else {
                                     let start = loop_value.min(input_value);
                                     let end = loop_value.max(input_value);
-                                    if start == 0 && end == 255 {
+                                    if start == 0 && end == cell_size.max_value() {
                                         return CellValue::Unknown;
                                     } else {
                                         return CellValue::Range(start, end);
@@ -1109,7 +1270,7 @@ pub fn find_heap_value(
                             CellValue::Range(start, end) => {
                                 let start = (start).min(loop_value);
                                 let end = (end).max(loop_value);
-                                if start == 0 && end == 255 {
+                                if start == 0 && end == cell_size.max_value() {
                                     return CellValue::Unknown;
                                 } else {
                                     return CellValue::Range(start, end);
@@ -1145,6 +1306,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -1173,6 +1335,7 @@ pub fn find_heap_value(
                             zeroed,
                             &loop_inputs,
                             wrapping_is_ub,
+                            cell_size,
                             follow,
                         ) {
                             CellValue::Value(input_value) => {
@@ -1185,7 +1348,7 @@ pub fn find_heap_value(
                             CellValue::Range(start, end) => {
                                 let start = 0.min(start);
                                 let end = 1.max(end);
-                                if start == 0 && end == 255 {
+                                if start == 0 && end == cell_size.max_value() {
                                     return CellValue::Unknown;
                                 } else {
                                     return CellValue::Range(start, end);
@@ -1243,6 +1406,7 @@ pub fn find_heap_value(
                                     zeroed,
                                     &loop_inputs,
                                     wrapping_is_ub,
+                                    cell_size,
                                     follow,
                                 ) {
                                     CellValue::Value(v2) => {
@@ -1255,7 +1419,7 @@ pub fn find_heap_value(
                                         } else {
                                             let start = v.min(v2);
                                             let end = v.max(v2);
-                                            if start != 0 && end != 255 {
+                                            if start != 0 && end != cell_size.max_value() {
                                                 return CellValue::Range(start, end);
                                             }
                                         }
@@ -1263,7 +1427,7 @@ pub fn find_heap_value(
                                     CellValue::Range(start, end) => {
                                         let start = (start).min(v);
                                         let end = (end).max(v);
-                                        if start == 0 && end == 255 {
+                                        if start == 0 && end == cell_size.max_value() {
                                             return CellValue::Unknown;
                                         } else {
                                             return CellValue::Range(start, end);
@@ -1306,12 +1470,13 @@ pub fn find_heap_value(
                                     zeroed,
                                     &loop_inputs,
                                     wrapping_is_ub,
+                                    cell_size,
                                     follow,
                                 ) {
                                     CellValue::Value(v2) => {
                                         let start = (start).min(v2);
                                         let end = (end).max(v2);
-                                        if start == 0 && end == 255 {
+                                        if start == 0 && end == cell_size.max_value() {
                                             return CellValue::Unknown;
                                         } else {
                                             return CellValue::Range(start, end);
@@ -1320,7 +1485,7 @@ pub fn find_heap_value(
                                     CellValue::Range(start2, end2) => {
                                         let start = (start).min(start2);
                                         let end = (end).max(end2);
-                                        if start == 0 && end == 255 {
+                                        if start == 0 && end == cell_size.max_value() {
                                             return CellValue::Unknown;
                                         } else {
                                             return CellValue::Range(start, end);
@@ -1336,7 +1501,7 @@ pub fn find_heap_value(
                                     CellValue::Bool => {
                                         let start = (start).min(0);
                                         let end = (end).max(1);
-                                        if start == 0 && end == 255 {
+                                        if start == 0 && end == cell_size.max_value() {
                                             return CellValue::Unknown;
                                         } else {
                                             return CellValue::Range(start, end);
@@ -1364,6 +1529,7 @@ pub fn find_heap_value(
                                 zeroed,
                                 &loop_inputs,
                                 wrapping_is_ub,
+                                cell_size,
                                 follow,
                             ) {
                                 CellValue::NonZero => return CellValue::NonZero,
@@ -1392,6 +1558,7 @@ pub fn find_heap_value(
                                 zeroed,
                                 &loop_inputs,
                                 wrapping_is_ub,
+                                cell_size,
                                 follow,
                             ) {
                                 CellValue::Bool => {
@@ -1407,7 +1574,7 @@ pub fn find_heap_value(
                                 CellValue::Range(start, end) => {
                                     let start = 0.min(start);
                                     let end = 1.max(end);
-                                    if start == 0 && end == 255 {
+                                    if start == 0 && end == cell_size.max_value() {
                                         return CellValue::Unknown;
                                     } else {
                                         return CellValue::Range(start, end);
@@ -1456,10 +1623,9 @@ pub fn find_heap_value(
         i -= 1;
     }
 
-    for (offset, cell) in inputs {
-        if *offset == cell_offset {
-            return *cell;
-        }
+    // Inputs are sorted by offset
+    if let Ok(index) = inputs.binary_search_by_key(&cell_offset, |(offset, _)| *offset) {
+        return inputs[index].1;
     }
 
     if zeroed {
@@ -1473,7 +1639,7 @@ pub fn find_last_accessing_inc_dec(
     ops: &[Op],
     start_offset: isize,
     start_index: isize,
-) -> Option<(isize, i16)> {
+) -> Option<(isize, i64)> {
     let mut ptr_offset = start_offset;
 
     let mut i = start_index;
@@ -1486,12 +1652,12 @@ pub fn find_last_accessing_inc_dec(
             OpType::DecPtr(offset) => ptr_offset -= *offset as isize,
             OpType::Inc(offset, v) => {
                 if *offset == ptr_offset {
-                    return Some((i, *v as i16));
+                    return Some((i, *v as i64));
                 }
             }
             OpType::Dec(offset, v) => {
                 if *offset == ptr_offset {
-                    return Some((i, -(*v as i16)));
+                    return Some((i, -(*v as i64)));
                 }
             }
             OpType::Add(src_offset, dest_offset, _)
@@ -1520,7 +1686,8 @@ pub fn find_last_accessing_inc_dec(
             OpType::Start
             | OpType::SearchZero(_, _)
             | OpType::DLoop(_, _)
-            | OpType::DTNz(_, _, _) => {
+            | OpType::DTNz(_, _, _)
+            | OpType::Breakpoint => {
                 return None;
             }
             OpType::LLoop(.., info)
@@ -1554,6 +1721,7 @@ pub fn find_last_put_string(ops: &[Op], start_index: isize) -> Option<(isize, Ve
             }
             OpType::PutChar(_)
             | OpType::GetChar(_)
+            | OpType::Breakpoint
             | OpType::SearchZero(_, _)
             | OpType::DLoop(..)
             | OpType::DTNz(..)
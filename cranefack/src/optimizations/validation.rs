@@ -0,0 +1,664 @@
+use std::fmt::{Display, Formatter};
+use std::io::Cursor;
+
+use crate::errors::{RuntimeError, ValidationError};
+use crate::ir::ops::{drop_ops, Op};
+use crate::optimizations::{OptimizationPass, OptimizeConfig, PassContext};
+use crate::parser::Program;
+use crate::Interpreter;
+
+/// Upper limit of programs and inputs tried while minimizing a reproducer
+const MAX_MINIMIZE_ATTEMPTS: usize = 5000;
+
+/// Settings to check every optimization pass with [PassManager::run_checked](crate::PassManager::run_checked)
+#[derive(Debug, Clone)]
+pub struct CheckConfig {
+    /// Inputs the program is run with before and after each pass
+    pub inputs: Vec<Vec<u8>>,
+
+    /// Number of randomly generated inputs added to the supplied ones
+    pub random_inputs: usize,
+
+    /// Maximum length of the generated inputs
+    pub max_random_input_len: usize,
+
+    /// Seed for generating the random inputs
+    pub seed: u64,
+
+    /// Maximum number of steps of each run
+    ///
+    /// Inputs the program runs out of fuel for before a pass are skipped as they can't be compared.
+    pub fuel: u64,
+
+    /// Compare the final tape in addition to the output
+    pub compare_tape: bool,
+}
+
+impl Default for CheckConfig {
+    fn default() -> CheckConfig {
+        CheckConfig {
+            inputs: vec![],
+            random_inputs: 8,
+            max_random_input_len: 32,
+            seed: 0x2545_f491_4f6c_dd1d,
+            fuel: 1_000_000,
+            compare_tape: true,
+        }
+    }
+}
+
+impl CheckConfig {
+    /// Supplied inputs followed by the random ones
+    ///
+    /// Contains a single empty input if there would be no input at all.
+    pub(crate) fn get_inputs(&self) -> Vec<Vec<u8>> {
+        let mut inputs = self.inputs.clone();
+
+        // Xorshift is good enough to get some variety into the inputs
+        let mut state = self.seed.max(1);
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..self.random_inputs {
+            let len = next() as usize % (self.max_random_input_len + 1);
+            inputs.push((0..len).map(|_| next() as u8).collect());
+        }
+
+        if inputs.is_empty() {
+            inputs.push(vec![]);
+        }
+
+        inputs
+    }
+}
+
+/// Observable result of running a program with the [Interpreter]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    /// The program ran to the end
+    Finished {
+        /// Written output
+        output: Vec<u8>,
+
+        /// Position of the first non zero cell of the final tape
+        tape_start: isize,
+
+        /// Cells of the final tape from the first to the last non zero cell
+        tape: Vec<u32>,
+    },
+
+    /// The program failed with the runtime error message
+    Error(String),
+
+    /// The program ran out of fuel
+    OutOfFuel,
+}
+
+impl Display for ExecutionOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExecutionOutcome::Finished {
+                output,
+                tape_start,
+                tape,
+            } => write!(
+                f,
+                "Finished with output \"{}\" and tape {:?} starting at cell {}",
+                output.escape_ascii(),
+                tape,
+                tape_start
+            ),
+            ExecutionOutcome::Error(message) => write!(f, "Failed with error: {}", message),
+            ExecutionOutcome::OutOfFuel => write!(f, "Ran out of fuel"),
+        }
+    }
+}
+
+impl ExecutionOutcome {
+    /// Run ops with the runtime settings of the config
+    pub(crate) fn run(ops: &[Op], input: &[u8], fuel: u64, config: &OptimizeConfig) -> Self {
+        let program = Program { ops: ops.to_vec() };
+
+        let mut interpreter = Interpreter::new_with_config(Cursor::new(input), vec![], config);
+        interpreter.set_fuel(Some(fuel));
+
+        match interpreter.execute(&program) {
+            Ok(()) => {
+                let (tape_start, tape) = interpreter.get_tape();
+
+                ExecutionOutcome::Finished {
+                    output: std::mem::take(interpreter.output_mut()),
+                    tape_start,
+                    tape,
+                }
+            }
+            Err(RuntimeError::OutOfFuel { .. }) => ExecutionOutcome::OutOfFuel,
+            Err(error) => ExecutionOutcome::Error(error.to_string()),
+        }
+    }
+
+    fn is_finished(&self) -> bool {
+        matches!(self, ExecutionOutcome::Finished { .. })
+    }
+
+    /// Check if the outcome after a pass is the same as the expected one before it
+    fn matches(&self, actual: &ExecutionOutcome, compare_tape: bool) -> bool {
+        match (self, actual) {
+            (
+                ExecutionOutcome::Finished { output, .. },
+                ExecutionOutcome::Finished {
+                    output: actual_output,
+                    ..
+                },
+            ) if !compare_tape => output == actual_output,
+            _ => self == actual,
+        }
+    }
+}
+
+/// Checks the behavior of the program before and after each pass
+pub(crate) struct Validator<'a> {
+    config: &'a OptimizeConfig,
+    check: &'a CheckConfig,
+    inputs: Vec<Vec<u8>>,
+    expected: Vec<ExecutionOutcome>,
+}
+
+impl<'a> Validator<'a> {
+    pub(crate) fn new(ops: &[Op], config: &'a OptimizeConfig, check: &'a CheckConfig) -> Self {
+        let inputs = check.get_inputs();
+
+        let expected = inputs
+            .iter()
+            .map(|input| ExecutionOutcome::run(ops, input, check.fuel, config))
+            .collect();
+
+        Validator {
+            config,
+            check,
+            inputs,
+            expected,
+        }
+    }
+
+    /// Compare the ops after a pass with the outcomes before it
+    ///
+    /// On success the outcomes of the ops become the expected ones of the next pass.
+    pub(crate) fn validate(
+        &mut self,
+        pass: &dyn OptimizationPass,
+        context: &PassContext,
+        before: &[Op],
+        after: &[Op],
+    ) -> Result<(), Box<ValidationError>> {
+        let mut outcomes = Vec::with_capacity(self.inputs.len());
+
+        for (input, expected) in self.inputs.iter().zip(&self.expected) {
+            if !expected.is_finished() {
+                outcomes.push(expected.clone());
+                continue;
+            }
+
+            let actual = ExecutionOutcome::run(after, input, self.check.fuel, self.config);
+
+            if !expected.matches(&actual, self.check.compare_tape) {
+                let difference = Difference {
+                    program: Program {
+                        ops: before.to_vec(),
+                    },
+                    input: input.clone(),
+                    optimized: Program {
+                        ops: after.to_vec(),
+                    },
+                    expected: expected.clone(),
+                    actual,
+                };
+
+                return Err(Box::new(self.minimize(pass, context, difference)));
+            }
+
+            outcomes.push(actual);
+        }
+
+        self.expected = outcomes;
+
+        Ok(())
+    }
+
+    /// Run the pass on a copy of the ops and return the outcomes if they differ
+    fn find_difference(
+        &self,
+        pass: &dyn OptimizationPass,
+        context: &PassContext,
+        ops: Vec<Op>,
+        input: Vec<u8>,
+    ) -> Option<Difference> {
+        let program = Program { ops };
+        let expected = ExecutionOutcome::run(&program.ops, &input, self.check.fuel, self.config);
+
+        if !expected.is_finished() {
+            return None;
+        }
+
+        let mut optimized = program.clone();
+        pass.run(&mut optimized.ops, context);
+
+        let actual = ExecutionOutcome::run(&optimized.ops, &input, self.check.fuel, self.config);
+
+        if expected.matches(&actual, self.check.compare_tape) {
+            None
+        } else {
+            Some(Difference {
+                program,
+                input,
+                optimized,
+                expected,
+                actual,
+            })
+        }
+    }
+
+    /// Shrink program and input while the pass still changes the behavior in the same way
+    ///
+    /// Smaller candidates that fail for another reason, like a heap error instead of a wrong
+    /// output, would point at a different bug and are rejected.
+    fn minimize(
+        &self,
+        pass: &dyn OptimizationPass,
+        context: &PassContext,
+        mut difference: Difference,
+    ) -> ValidationError {
+        let mut attempts = 0;
+        let mismatch = difference.mismatch();
+
+        let mut try_candidate = |ops: Vec<Op>, input: Vec<u8>, difference: &mut Difference| {
+            attempts += 1;
+
+            if attempts > MAX_MINIMIZE_ATTEMPTS {
+                return false;
+            }
+
+            match self.find_difference(pass, context, ops, input) {
+                Some(smaller) if smaller.mismatch() == mismatch => {
+                    *difference = smaller;
+                    true
+                }
+                _ => false,
+            }
+        };
+
+        let mut progress = true;
+
+        while progress {
+            progress = false;
+
+            // Remove chunks of input bytes
+            let mut chunk = (difference.input.len() / 2).max(1);
+            while !difference.input.is_empty() {
+                let mut start = 0;
+                while start < difference.input.len() {
+                    let mut input = difference.input.clone();
+                    input.drain(start..(start + chunk).min(input.len()));
+
+                    if try_candidate(difference.program.ops.clone(), input, &mut difference) {
+                        progress = true;
+                    } else {
+                        start += chunk;
+                    }
+                }
+
+                if chunk == 1 {
+                    break;
+                }
+                chunk /= 2;
+            }
+
+            // Remove chunks of ops and unwrap blocks starting with the outermost ones
+            let mut paths = vec![vec![]];
+
+            while let Some(path) = paths.pop() {
+                // The start marker at the beginning of the program must stay
+                let first = if path.is_empty() { 1 } else { 0 };
+
+                let mut chunk = (get_block(&mut difference.program.ops, &path).len() / 2).max(1);
+                loop {
+                    let mut start = first;
+                    while start < get_block(&mut difference.program.ops, &path).len() {
+                        let mut ops = difference.program.ops.clone();
+                        let block = get_block(&mut ops, &path);
+                        let end = (start + chunk).min(block.len());
+                        drop_ops(block.drain(start..end).collect());
+
+                        if try_candidate(ops, difference.input.clone(), &mut difference) {
+                            progress = true;
+                        } else {
+                            start += chunk;
+                        }
+                    }
+
+                    if chunk == 1 {
+                        break;
+                    }
+                    chunk /= 2;
+                }
+
+                let mut index = first;
+                while index < get_block(&mut difference.program.ops, &path).len() {
+                    let mut ops = difference.program.ops.clone();
+                    let block = get_block(&mut ops, &path);
+
+                    if let Some(children) = block[index].op_type.get_children_mut() {
+                        let children = std::mem::take(children);
+                        block.splice(index..=index, children);
+
+                        if try_candidate(ops, difference.input.clone(), &mut difference) {
+                            progress = true;
+                            continue;
+                        }
+                    }
+
+                    index += 1;
+                }
+
+                for (index, op) in get_block(&mut difference.program.ops, &path)
+                    .iter_mut()
+                    .enumerate()
+                {
+                    if op.op_type.get_children_mut().is_some() {
+                        let mut child_path = path.clone();
+                        child_path.push(index);
+                        paths.push(child_path);
+                    }
+                }
+            }
+        }
+
+        ValidationError {
+            pass: pass.name().to_owned(),
+            iteration: context.iteration,
+            input: difference.input,
+            expected: difference.expected,
+            actual: difference.actual,
+            program: difference.program,
+            optimized: difference.optimized,
+        }
+    }
+}
+
+/// Program and input the behavior differs for before and after a pass
+struct Difference {
+    program: Program,
+    input: Vec<u8>,
+    optimized: Program,
+    expected: ExecutionOutcome,
+    actual: ExecutionOutcome,
+}
+
+impl Difference {
+    fn mismatch(&self) -> Mismatch {
+        match (&self.expected, &self.actual) {
+            (
+                ExecutionOutcome::Finished { output, .. },
+                ExecutionOutcome::Finished {
+                    output: actual_output,
+                    ..
+                },
+            ) => {
+                if output == actual_output {
+                    Mismatch::Tape
+                } else {
+                    Mismatch::Output
+                }
+            }
+            (_, ExecutionOutcome::Error(message)) => Mismatch::Error(message.clone()),
+            (_, _) => Mismatch::OutOfFuel,
+        }
+    }
+}
+
+/// Way the outcome after a pass differs from the one before it
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mismatch {
+    /// Both runs finished with a different output
+    Output,
+
+    /// Both runs finished with the same output but a different final tape
+    Tape,
+
+    /// The run after the pass failed with the error message
+    Error(String),
+
+    /// The run after the pass ran out of fuel
+    OutOfFuel,
+}
+
+/// Get the block of ops at a path of indices of nested blocks
+fn get_block<'a>(ops: &'a mut Vec<Op>, path: &[usize]) -> &'a mut Vec<Op> {
+    path.iter().fold(ops, |ops, index| {
+        ops[*index]
+            .op_type
+            .get_children_mut()
+            .expect("Path must point to blocks")
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ir::ops::{Op, OpType};
+    use crate::optimizations::{OptimizationPass, PassContext, PassManager};
+    use crate::{optimize_with_config, parse, CheckConfig, ExecutionOutcome, OptimizeConfig};
+
+    /// Pass with a bug turning every top level decrement into an increment
+    struct BrokenDec;
+
+    impl OptimizationPass for BrokenDec {
+        fn name(&self) -> &str {
+            "broken-dec"
+        }
+
+        fn run(&self, ops: &mut Vec<Op>, _context: &PassContext) -> bool {
+            let mut progress = false;
+
+            for op in ops.iter_mut() {
+                if let OpType::Dec(offset, value) = op.op_type {
+                    op.op_type = OpType::Inc(offset, value);
+                    progress = true;
+                }
+            }
+
+            progress
+        }
+    }
+
+    /// Pass dropping top level output and, like [BrokenDec], turning decrements into increments
+    struct BrokenOutput;
+
+    impl OptimizationPass for BrokenOutput {
+        fn name(&self) -> &str {
+            "broken-output"
+        }
+
+        fn run(&self, ops: &mut Vec<Op>, context: &PassContext) -> bool {
+            let len = ops.len();
+            ops.retain(|op| !matches!(op.op_type, OpType::PutChar(..)));
+
+            BrokenDec.run(ops, context) || ops.len() != len
+        }
+    }
+
+    /// Pass removing trailing increments that only change the final tape
+    struct RemoveTrailingInc;
+
+    impl OptimizationPass for RemoveTrailingInc {
+        fn name(&self) -> &str {
+            "remove-trailing-inc"
+        }
+
+        fn run(&self, ops: &mut Vec<Op>, _context: &PassContext) -> bool {
+            if matches!(ops.last().map(|op| &op.op_type), Some(OpType::Inc(..))) {
+                ops.pop();
+                true
+            } else {
+                false
+            }
+        }
+    }
+
+    #[test]
+    fn test_checked_pipeline() {
+        let sources = [
+            ",[->+<]>.",
+            ",>,<[->[->+>+<<]>>[-<<+>>]<<<]>>.",
+            "++++++++[>++++++++<-]>+.[-]<<,[>+>+<<-]>[<+>-]>.",
+            ",[.,]",
+        ];
+
+        for source in sources {
+            for config in [OptimizeConfig::o1(), OptimizeConfig::o3()] {
+                let mut expected = parse(source).unwrap();
+                optimize_with_config(&mut expected, &config);
+
+                let mut program = parse(source).unwrap();
+                PassManager::from_config(&config)
+                    .run_checked(&mut program, &config, &CheckConfig::default())
+                    .unwrap();
+
+                assert_eq!(program.ops, expected.ops);
+            }
+        }
+    }
+
+    #[test]
+    fn test_broken_pass() {
+        let config = OptimizeConfig::o1();
+
+        let mut manager = PassManager::from_config(&config);
+        manager.add_pass(Box::new(BrokenDec));
+
+        let mut program = parse(",>+++[<+>-]<.>>-").unwrap();
+
+        let error = manager
+            .run_checked(&mut program, &config, &CheckConfig::default())
+            .unwrap_err();
+
+        assert_eq!(error.pass, "broken-dec");
+        assert_eq!(error.iteration, 1);
+        assert!(error.input.is_empty());
+        assert_eq!(
+            error.program.ops,
+            vec![Op::start(), Op::dec_with_offset(15..16, 1, 1)]
+        );
+        assert_eq!(
+            error.optimized.ops,
+            vec![Op::start(), Op::inc_with_offset(15..16, 1, 1)]
+        );
+        assert_eq!(
+            error.expected,
+            ExecutionOutcome::Finished {
+                output: vec![],
+                tape_start: 1,
+                tape: vec![255],
+            }
+        );
+        assert_eq!(
+            error.actual,
+            ExecutionOutcome::Finished {
+                output: vec![],
+                tape_start: 1,
+                tape: vec![1],
+            }
+        );
+
+        assert_eq!(
+            error.to_string(),
+            "Optimization pass broken-dec changed the behavior of the program in iteration 1"
+        );
+
+        // The program is left in the state before the broken pass
+        assert_eq!(program.ops.last().unwrap().op_type, OpType::Dec(1, 1));
+        assert_ne!(program.ops[0].op_type, OpType::Start);
+    }
+
+    #[test]
+    fn test_minimize_same_mismatch() {
+        let config = OptimizeConfig::o1();
+
+        let mut manager = PassManager::new();
+        manager.add_pass(Box::new(BrokenOutput));
+
+        let mut program = parse("+.-").unwrap();
+
+        let error = manager
+            .run_checked(&mut program, &config, &CheckConfig::default())
+            .unwrap_err();
+
+        // Dropping the output would still leave a different tape, but that's not the original bug
+        assert!(error
+            .program
+            .ops
+            .iter()
+            .any(|op| matches!(op.op_type, OpType::PutChar(..))));
+
+        match (&error.expected, &error.actual) {
+            (
+                ExecutionOutcome::Finished { output, .. },
+                ExecutionOutcome::Finished {
+                    output: actual_output,
+                    ..
+                },
+            ) => assert_ne!(output, actual_output),
+            _ => panic!("Expected different output"),
+        }
+    }
+
+    #[test]
+    fn test_compare_tape() {
+        let config = OptimizeConfig::o1();
+
+        let mut manager = PassManager::new();
+        manager.add_pass(Box::new(RemoveTrailingInc));
+
+        let mut program = parse("+.+").unwrap();
+        assert!(manager
+            .run_checked(&mut program, &config, &CheckConfig::default())
+            .is_err());
+
+        let check = CheckConfig {
+            compare_tape: false,
+            ..CheckConfig::default()
+        };
+
+        let mut program = parse("+.+").unwrap();
+        manager.run_checked(&mut program, &config, &check).unwrap();
+
+        assert_eq!(program.ops, vec![Op::inc(0..1, 1), Op::put_char(1..2)]);
+    }
+
+    #[test]
+    fn test_random_inputs() {
+        let check = CheckConfig {
+            inputs: vec![b"x".to_vec()],
+            random_inputs: 4,
+            max_random_input_len: 3,
+            ..CheckConfig::default()
+        };
+
+        let inputs = check.get_inputs();
+
+        assert_eq!(inputs.len(), 5);
+        assert_eq!(inputs[0], b"x");
+        assert!(inputs.iter().all(|input| input.len() <= 3));
+        assert_eq!(inputs, check.get_inputs());
+
+        let check = CheckConfig {
+            random_inputs: 0,
+            ..CheckConfig::default()
+        };
+
+        assert_eq!(check.get_inputs(), vec![Vec::<u8>::new()]);
+    }
+}
@@ -1,133 +1,161 @@
 use std::error::Error;
-use std::io::Write;
+use std::io::{ErrorKind, Read, Write};
+use std::ops::Range;
 
 use crate::errors::ParserError;
-use crate::ir::ops::{Op, OpType};
+use crate::ir::ops::{drop_ops, Op, OpType};
 use crate::ir::opt_info::BlockInfo;
+use crate::ir::visitor::{walk, walk_mut, Visitor, VisitorMut};
 
 /// An executable program
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Program {
     pub ops: Vec<Op>,
 }
 
+impl Drop for Program {
+    fn drop(&mut self) {
+        drop_ops(std::mem::take(&mut self.ops));
+    }
+}
+
 impl Program {
+    /// Walk over all ops including the bodies of nested blocks
+    pub fn visit<V: Visitor + ?Sized>(&self, visitor: &mut V) {
+        walk(visitor, &self.ops);
+    }
+
+    /// Walk over all ops including the bodies of nested blocks with mutable access
+    pub fn visit_mut<V: VisitorMut + ?Sized>(&mut self, visitor: &mut V) {
+        walk_mut(visitor, &mut self.ops);
+    }
+
     pub fn get_statistics(&self) -> (usize, usize, usize, usize, usize, usize) {
-        self.get_ops_statistics(&self.ops)
+        Self::get_ops_statistics(&self.ops)
     }
 
-    fn get_ops_statistics(&self, ops: &[Op]) -> (usize, usize, usize, usize, usize, usize) {
-        let mut op_count = 0;
-        let mut dloop_count = 0;
-        let mut lloop_count = 0;
-        let mut iloop_count = 0;
-        let mut cloop_count = 0;
-        let mut if_count = 0;
+    /// Get the maximum depth of nested loops and conditions
+    pub fn get_loop_depth(&self) -> usize {
+        struct LoopDepth(usize);
 
-        for op in ops {
-            op_count += 1;
-            match &op.op_type {
-                OpType::DLoop(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    dloop_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
-                }
-                OpType::LLoop(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    lloop_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
-                }
-                OpType::ILoop(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    iloop_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
-                }
-                OpType::CLoop(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    cloop_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
-                }
-                OpType::TNz(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    if_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
-                }
-                OpType::DTNz(children, ..) => {
-                    let (ops, dloops, lloop, iloops, cloops, ifs) =
-                        self.get_ops_statistics(children);
-                    op_count += ops;
-                    if_count += 1;
-                    dloop_count += dloops;
-                    lloop_count += lloop;
-                    iloop_count += iloops;
-                    cloop_count += cloops;
-                    if_count += ifs;
+        impl Visitor for LoopDepth {
+            fn visit_ops(&mut self, _ops: &[Op], depth: usize) {
+                self.0 = self.0.max(depth);
+            }
+        }
+
+        let mut loop_depth = LoopDepth(0);
+        self.visit(&mut loop_depth);
+
+        loop_depth.0
+    }
+
+    /// Get line and column of a byte position in the source of the program
+    ///
+    /// Lines and columns start at 1 and columns are counted in chars.
+    /// Returns `None` if the position is beyond the end of the source or inside of a char.
+    pub fn get_line_column(source: &str, position: usize) -> Option<(usize, usize)> {
+        if !source.is_char_boundary(position) {
+            return None;
+        }
+
+        let before = &source[..position];
+        let line_start = before.rfind('\n').map(|index| index + 1).unwrap_or(0);
+
+        let line = before.matches('\n').count() + 1;
+        let column = before[line_start..].chars().count() + 1;
+
+        Some((line, column))
+    }
+
+    /// Get line and column of the start and the end of a span in the source of the program
+    ///
+    /// The end is the position after the last char of the span.
+    pub fn get_span_line_columns(
+        source: &str,
+        span: &Range<usize>,
+    ) -> Option<((usize, usize), (usize, usize))> {
+        Some((
+            Self::get_line_column(source, span.start)?,
+            Self::get_line_column(source, span.end)?,
+        ))
+    }
+
+    pub(crate) fn get_ops_statistics(ops: &[Op]) -> (usize, usize, usize, usize, usize, usize) {
+        #[derive(Default)]
+        struct Statistics {
+            op_count: usize,
+            dloop_count: usize,
+            lloop_count: usize,
+            iloop_count: usize,
+            cloop_count: usize,
+            if_count: usize,
+        }
+
+        impl Visitor for Statistics {
+            fn visit_op(&mut self, op: &Op, _depth: usize) -> bool {
+                self.op_count += 1;
+                match &op.op_type {
+                    OpType::DLoop(..) => self.dloop_count += 1,
+                    OpType::LLoop(..) => self.lloop_count += 1,
+                    OpType::ILoop(..) => self.iloop_count += 1,
+                    OpType::CLoop(..) => self.cloop_count += 1,
+                    OpType::TNz(..) | OpType::DTNz(..) => self.if_count += 1,
+                    _ => {}
                 }
-                _ => {}
+                true
             }
         }
 
+        let mut stats = Statistics::default();
+        walk(&mut stats, ops);
+
         (
-            op_count,
-            dloop_count,
-            lloop_count,
-            iloop_count,
-            cloop_count,
-            if_count,
+            stats.op_count,
+            stats.dloop_count,
+            stats.lloop_count,
+            stats.iloop_count,
+            stats.cloop_count,
+            stats.if_count,
         )
     }
 
     /// Dump program into a assembly like structure
     pub fn dump<W: Write>(&self, mut output: W, debug: bool) -> Result<(), Box<dyn Error>> {
-        self.dump_ops(&mut output, &self.ops, 0, debug)
+        self.dump_ops(&mut output, &self.ops, debug)
     }
 
     fn dump_ops<W: Write>(
         &self,
         output: &mut W,
         ops: &[Op],
-        indent: usize,
         debug: bool,
     ) -> Result<(), Box<dyn Error>> {
-        let mut ptr_offset = 0;
+        // Blocks are dumped with an explicit stack to support deeply nested programs
+        let mut stack = vec![(ops.iter(), 0)];
+
+        while let Some(indent) = stack.len().checked_sub(1) {
+            let (ops, ptr_offset) = stack.last_mut().unwrap();
+
+            let op = match ops.next() {
+                Some(op) => op,
+                None => {
+                    stack.pop();
+                    continue;
+                }
+            };
 
-        for op in ops {
             let mut pos = format!("0x{:x}..0x{:x}", op.span.start, op.span.end - 1);
 
             while pos.len() < 16 {
                 pos.push(' ');
             }
 
+            // Keep long spans separated so the dump can be parsed again
+            if !pos.ends_with(' ') {
+                pos.push(' ');
+            }
+
             write!(output, "{}", pos)?;
 
             if debug {
@@ -139,7 +167,7 @@ impl Program {
 
                 write!(output, "{}  ", offset)?;
                 if let Some(offset) = op.op_type.get_ptr_offset() {
-                    ptr_offset += offset;
+                    *ptr_offset += offset;
                 }
             }
 
@@ -227,7 +255,9 @@ impl Program {
                     write!(output, "PUT STRING \"")?;
 
                     for &v in array {
-                        if v.is_ascii_graphic() || v == b' ' {
+                        if v == b'\\' {
+                            write!(output, "\\\\")?;
+                        } else if v.is_ascii_graphic() || v == b' ' {
                             write!(output, "{}", v as char)?;
                         } else if v == b'\n' {
                             write!(output, "\\n")?;
@@ -236,23 +266,22 @@ impl Program {
                         } else if v == b'\t' {
                             write!(output, "\\t")?;
                         } else {
-                            write!(output, "\\0x{:x}", v)?;
+                            write!(output, "\\0x{:02x}", v)?;
                         }
                     }
 
                     writeln!(output, "\"")?
                 }
                 OpType::GetChar(offset) => writeln!(output, "GET offset: {}", offset)?,
+                OpType::Breakpoint => writeln!(output, "BREAKPOINT")?,
 
-                OpType::DLoop(children, info) => {
+                OpType::DLoop(_, info) => {
                     writeln!(output, "DLOOP info: {}", info.asm(debug))?;
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
-                OpType::LLoop(children, info) => {
+                OpType::LLoop(_, info) => {
                     writeln!(output, "LLOOP info: {}", info.asm(debug))?;
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
-                OpType::ILoop(children, step, increment, info) => {
+                OpType::ILoop(_, step, increment, info) => {
                     writeln!(
                         output,
                         "ILOOP step: {} increment: {:?} info: {}",
@@ -260,9 +289,8 @@ impl Program {
                         increment,
                         info.asm(debug)
                     )?;
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
-                OpType::CLoop(children, iterations, increment, info) => {
+                OpType::CLoop(_, iterations, increment, info) => {
                     writeln!(
                         output,
                         "CLOOP iterations: {} increment: {:?} info: {}",
@@ -270,13 +298,11 @@ impl Program {
                         increment,
                         info.asm(debug)
                     )?;
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
-                OpType::TNz(children, info) => {
+                OpType::TNz(_, info) => {
                     writeln!(output, "TNZ info: {}", info.asm(debug))?;
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
-                OpType::DTNz(children, end_offset, info) => {
+                OpType::DTNz(_, end_offset, info) => {
                     if let Some(end_offset) = end_offset {
                         writeln!(
                             output,
@@ -287,7 +313,6 @@ impl Program {
                     } else {
                         writeln!(output, "D_TNZ info: {}", info.asm(debug),)?;
                     }
-                    self.dump_ops(output, children, indent + 1, debug)?;
                 }
                 OpType::SearchZero(step, always) => {
                     if *always {
@@ -297,96 +322,1019 @@ impl Program {
                     }
                 }
             }
+
+            if let Some(children) = op.op_type.get_children() {
+                stack.push((children.iter(), 0));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+const DEFAULT_MAX_LOOP_DEPTH: usize = 1024;
+
+/// Syntax of the source
+///
+/// All dialects are translated into the same ops with spans covering the whole token.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Dialect {
+    /// Classic brainfuck with single char commands
+    #[default]
+    Brainfuck,
+
+    /// Ook! with commands made of pairs of `Ook.`, `Ook?` and `Ook!`
+    Ook,
+
+    /// Blub with commands made of pairs of `Blub.`, `Blub?` and `Blub!`
+    Blub,
+
+    /// Custom tokens substituting the brainfuck commands
+    Custom(TokenMap),
+}
+
+impl Dialect {
+    /// Get dialect from its name
+    pub fn from_name(name: &str) -> Option<Dialect> {
+        match name {
+            "brainfuck" => Some(Dialect::Brainfuck),
+            "ook" => Some(Dialect::Ook),
+            "blub" => Some(Dialect::Blub),
+            _ => None,
+        }
+    }
+
+    /// Split source into commands
+    ///
+    /// Each command is passed as the equivalent brainfuck char together with the span of its token.
+    fn tokenize<F>(&self, source: &[u8], mut command: F) -> Result<(), ParserError>
+    where
+        F: FnMut(Range<usize>, u8) -> Result<(), ParserError>,
+    {
+        match self {
+            Dialect::Brainfuck => {
+                for (pos, &byte) in source.iter().enumerate() {
+                    command(pos..pos + 1, byte)?;
+                }
+                Ok(())
+            }
+            Dialect::Ook => tokenize_word_pairs(source, b"Ook", command),
+            Dialect::Blub => tokenize_word_pairs(source, b"Blub", command),
+            Dialect::Custom(token_map) => token_map.tokenize(source, command),
+        }
+    }
+}
+
+/// Tokenize dialects like Ook! using pairs of a word followed by `.`, `?` or `!`
+///
+/// Everything between the words is ignored.
+fn tokenize_word_pairs<F>(source: &[u8], word: &[u8], mut command: F) -> Result<(), ParserError>
+where
+    F: FnMut(Range<usize>, u8) -> Result<(), ParserError>,
+{
+    let mut first: Option<(usize, u8)> = None;
+    let mut pos = 0;
+
+    while pos + word.len() < source.len() {
+        let punctuation = source[pos + word.len()];
+
+        if !source[pos..].starts_with(word) || !matches!(punctuation, b'.' | b'?' | b'!') {
+            pos += 1;
+            continue;
+        }
+
+        let end = pos + word.len() + 1;
+
+        match first.take() {
+            None => first = Some((pos, punctuation)),
+            Some((start, first_punctuation)) => {
+                let byte = match (first_punctuation, punctuation) {
+                    (b'.', b'?') => b'>',
+                    (b'?', b'.') => b'<',
+                    (b'.', b'.') => b'+',
+                    (b'!', b'!') => b'-',
+                    (b'!', b'.') => b'.',
+                    (b'.', b'!') => b',',
+                    (b'!', b'?') => b'[',
+                    (b'?', b'!') => b']',
+                    _ => return Err(ParserError::InvalidToken { span: start..end }),
+                };
+
+                command(start..end, byte)?;
+            }
+        }
+
+        pos = end;
+    }
+
+    if let Some((start, _)) = first {
+        return Err(ParserError::InvalidToken {
+            span: start..start + word.len() + 1,
+        });
+    }
+
+    Ok(())
+}
+
+/// Custom tokens mapped to brainfuck commands
+///
+/// Tokens can consist of multiple chars. The longest token matching at a position is used and
+/// everything that doesn't match a token is ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TokenMap {
+    tokens: Vec<(Vec<u8>, u8)>,
+}
+
+impl TokenMap {
+    pub fn new() -> TokenMap {
+        TokenMap { tokens: vec![] }
+    }
+
+    /// Create token map with substitutions for `><+-.,[]` in that order
+    pub fn from_substitutions(tokens: [&str; 8]) -> TokenMap {
+        let mut token_map = TokenMap::new();
+
+        for (token, &command) in tokens.iter().zip(b"><+-.,[]") {
+            token_map.insert(token, command as char);
+        }
+
+        token_map
+    }
+
+    /// Map token to a brainfuck command char
+    ///
+    /// An existing mapping of the token is replaced and empty tokens are ignored.
+    pub fn insert(&mut self, token: &str, command: char) {
+        if token.is_empty() || !command.is_ascii() {
+            return;
+        }
+
+        let token = token.as_bytes().to_vec();
+        self.tokens.retain(|(existing, _)| *existing != token);
+        self.tokens.push((token, command as u8));
+    }
+
+    fn tokenize<F>(&self, source: &[u8], mut command: F) -> Result<(), ParserError>
+    where
+        F: FnMut(Range<usize>, u8) -> Result<(), ParserError>,
+    {
+        let mut pos = 0;
+
+        while pos < source.len() {
+            let longest_match = self
+                .tokens
+                .iter()
+                .filter(|(token, _)| source[pos..].starts_with(token))
+                .max_by_key(|(token, _)| token.len());
+
+            match longest_match {
+                Some((token, byte)) => {
+                    command(pos..pos + token.len(), *byte)?;
+                    pos += token.len();
+                }
+                None => pos += 1,
+            }
         }
 
         Ok(())
     }
 }
 
-const MAX_LOOP_DEPTH: usize = 1024;
+/// Options for the dialect and optional extensions of the syntax
+///
+/// All extensions are disabled by default and the extension chars are treated as comments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParserOptions {
+    /// Syntax of the source
+    pub dialect: Dialect,
+
+    /// Parse `#` as a debug breakpoint reported by the interpreter
+    pub breakpoints: bool,
+
+    /// Treat everything after the first `!` as input of the program
+    ///
+    /// Only supported by the brainfuck dialect.
+    pub inline_input: bool,
+
+    /// Maximum depth of nested loops
+    ///
+    /// Defaults to 1024. Deeper nesting fails with [ParserError::LoopStackOverflow].
+    pub max_loop_depth: usize,
+}
+
+impl Default for ParserOptions {
+    fn default() -> Self {
+        ParserOptions {
+            dialect: Dialect::default(),
+            breakpoints: false,
+            inline_input: false,
+            max_loop_depth: DEFAULT_MAX_LOOP_DEPTH,
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Split source into the code and the inline input if enabled
+    pub fn split_source<'a>(&self, source: &'a [u8]) -> (&'a [u8], Option<&'a [u8]>) {
+        if self.inline_input && self.dialect == Dialect::Brainfuck {
+            split_inline_input(source)
+        } else {
+            (source, None)
+        }
+    }
+}
+
+struct Block {
+    start: usize,
+    ops: Vec<Op>,
+    last_loop: Option<(usize, usize)>,
+}
+
+impl Block {
+    fn new(start: usize) -> Block {
+        Block {
+            start,
+            ops: vec![],
+            last_loop: None,
+        }
+    }
+}
 
 struct Parser {
-    stack: Vec<(usize, Vec<Op>)>,
+    stack: Vec<Block>,
+    breakpoints: bool,
+    max_loop_depth: usize,
+    recover: bool,
+    errors: Vec<ParserError>,
 }
 
 impl Parser {
-    pub fn new() -> Parser {
+    pub fn new(options: &ParserOptions) -> Parser {
         Parser {
-            stack: vec![(1, vec![])],
+            stack: vec![Block::new(1)],
+            breakpoints: options.breakpoints,
+            max_loop_depth: options.max_loop_depth,
+            recover: false,
+            errors: vec![],
         }
     }
 
+    /// Collect bracket errors instead of stopping at the first one
+    pub fn with_recovery(mut self) -> Parser {
+        self.recover = true;
+        self
+    }
+
     pub fn depth(&self) -> usize {
         self.stack.len()
     }
 
     pub fn push_op(&mut self, op: Op) {
         let tos = self.stack.len() - 1;
-        self.stack[tos].1.push(op);
+        self.stack[tos].ops.push(op);
     }
 
     pub fn open_loop(&mut self, position: usize) -> Result<(), ParserError> {
-        if self.stack.len() >= MAX_LOOP_DEPTH {
+        // The root block on the stack doesn't count as a loop
+        if self.stack.len() > self.max_loop_depth {
             Err(ParserError::LoopStackOverflow {
                 position,
-                max_depth: MAX_LOOP_DEPTH,
+                max_depth: self.max_loop_depth,
             })
         } else {
-            self.stack.push((position, vec![]));
+            self.stack.push(Block::new(position));
             Ok(())
         }
     }
 
-    pub fn close_loop(&mut self, position: usize) -> Result<(), ParserError> {
+    pub fn close_loop(&mut self, span: Range<usize>) -> Result<(), ParserError> {
         let tos = self.stack.len() - 1;
 
         if tos > 0 {
-            let ops = self.stack.remove(tos);
+            let block = self.stack.remove(tos);
             self.push_op(Op::d_loop(
-                ops.0..position + 1,
-                ops.1,
+                block.start..span.end,
+                block.ops,
                 BlockInfo::new_empty(),
             ));
+            self.stack[tos - 1].last_loop = Some((block.start, span.start));
             Ok(())
         } else {
-            Err(ParserError::BadlyClosedLoop { position })
+            let error = ParserError::BadlyClosedLoop {
+                position: span.start,
+                likely_match: self.stack[0].last_loop.map(|(start, _)| start),
+            };
+
+            if self.recover {
+                // Skip the bracket and continue as if it was a comment
+                self.errors.push(error);
+                Ok(())
+            } else {
+                Err(error)
+            }
         }
     }
 
-    pub fn get_tos(&self) -> &(usize, Vec<Op>) {
+    pub fn get_tos(&self) -> &Block {
         &self.stack[self.stack.len() - 1]
     }
 
-    pub fn parse(&mut self, source: &str) -> Result<Program, ParserError> {
-        for (pos, char) in source.chars().enumerate() {
-            match char {
-                '>' => self.push_op(Op::inc_ptr(pos..pos + 1, 1)),
-                '<' => self.push_op(Op::dec_ptr(pos..pos + 1, 1)),
-                '+' => self.push_op(Op::inc(pos..pos + 1, 1)),
-                '-' => self.push_op(Op::dec(pos..pos + 1, 1)),
-                '.' => self.push_op(Op::put_char(pos..pos + 1)),
-                ',' => self.push_op(Op::get_char(pos..pos + 1)),
-                '[' => self.open_loop(pos)?,
-                ']' => self.close_loop(pos)?,
-                _ => {
-                    // Ignore comment char
-                }
+    pub fn parse_command(&mut self, span: Range<usize>, byte: u8) -> Result<(), ParserError> {
+        match byte {
+            b'>' => self.push_op(Op::inc_ptr(span, 1)),
+            b'<' => self.push_op(Op::dec_ptr(span, 1)),
+            b'+' => self.push_op(Op::inc(span, 1)),
+            b'-' => self.push_op(Op::dec(span, 1)),
+            b'.' => self.push_op(Op::put_char(span)),
+            b',' => self.push_op(Op::get_char(span)),
+            b'[' => self.open_loop(span.start)?,
+            b']' => self.close_loop(span)?,
+            b'#' if self.breakpoints => self.push_op(Op::breakpoint(span)),
+            _ => {
+                // Ignore comment byte
             }
         }
 
+        Ok(())
+    }
+
+    pub fn finish(&mut self) -> Result<Program, ParserError> {
         if self.depth() != 1 {
+            let tos = self.get_tos();
             return Err(ParserError::UnclosedLoop {
-                position: self.get_tos().0,
+                position: tos.start,
+                likely_match: tos.last_loop.map(|(_, end)| end),
             });
         }
 
         Ok(Program {
-            ops: self.stack.remove(0).1,
+            ops: self.stack.remove(0).ops,
         })
     }
+
+    /// Finish parsing and return all collected errors
+    ///
+    /// Every loop that is still open is reported, not just the innermost one.
+    pub fn finish_with_errors(mut self) -> Result<Program, Vec<ParserError>> {
+        for block in self.stack.drain(1..) {
+            self.errors.push(ParserError::UnclosedLoop {
+                position: block.start,
+                likely_match: block.last_loop.map(|(_, end)| end),
+            });
+        }
+
+        if self.errors.is_empty() {
+            Ok(Program {
+                ops: self.stack.remove(0).ops,
+            })
+        } else {
+            self.errors.sort_by_key(|error| match error {
+                ParserError::LoopStackOverflow { position, .. }
+                | ParserError::BadlyClosedLoop { position, .. }
+                | ParserError::UnclosedLoop { position, .. }
+                | ParserError::IoError { position, .. } => *position,
+                ParserError::InvalidToken { span } => span.start,
+            });
+            Err(self.errors)
+        }
+    }
 }
 
 /// Parse the input source file into an abstract representation
 pub fn parse(source: &str) -> Result<Program, ParserError> {
-    Parser::new().parse(source)
+    parse_bytes(source.as_bytes())
+}
+
+/// Parse source bytes into an abstract representation
+///
+/// All bytes that are not brainfuck commands are ignored, so the source doesn't need to be valid UTF-8.
+pub fn parse_bytes(source: &[u8]) -> Result<Program, ParserError> {
+    parse_bytes_with_options(source, &ParserOptions::default())
+}
+
+/// Parse source bytes in a dialect or with syntax extensions into an abstract representation
+///
+/// With [ParserOptions::inline_input] parsing stops at the first `!`.
+/// The input following it can be retrieved with [ParserOptions::split_source].
+pub fn parse_bytes_with_options(
+    source: &[u8],
+    options: &ParserOptions,
+) -> Result<Program, ParserError> {
+    let (source, _) = options.split_source(source);
+
+    let mut parser = Parser::new(options);
+
+    options
+        .dialect
+        .tokenize(source, |span, byte| parser.parse_command(span, byte))?;
+
+    parser.finish()
+}
+
+/// Parse source bytes and report all unmatched brackets at once
+///
+/// Unmatched `]` are skipped and every `[` that is still open at the end of the source is reported.
+/// Parsing still stops at errors that can't be recovered from like too deeply nested loops.
+pub fn parse_bytes_with_recovery(
+    source: &[u8],
+    options: &ParserOptions,
+) -> Result<Program, Vec<ParserError>> {
+    let (source, _) = options.split_source(source);
+    let mut parser = Parser::new(options).with_recovery();
+
+    if let Err(error) = options
+        .dialect
+        .tokenize(source, |span, byte| parser.parse_command(span, byte))
+    {
+        parser.errors.push(error);
+        return Err(parser.errors);
+    }
+
+    parser.finish_with_errors()
+}
+
+/// Split source at the first `!` into the code and the inline input following it
+///
+/// Returns `None` as input if the source doesn't contain a `!`.
+pub fn split_inline_input(source: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match source.iter().position(|&byte| byte == b'!') {
+        Some(index) => (&source[..index], Some(&source[index + 1..])),
+        None => (source, None),
+    }
+}
+
+/// Parse source read from a reader in a dialect or with syntax extensions
+///
+/// Brainfuck sources are parsed while reading without holding all of it in memory.
+/// Tokens of the other dialects can span multiple reads, so their sources are read completely first.
+///
+/// With [ParserOptions::inline_input] parsing stops at the first `!`.
+pub fn parse_reader<R: Read>(reader: R, options: &ParserOptions) -> Result<Program, ParserError> {
+    let mut parser = Parser::new(options);
+
+    tokenize_reader(reader, options, &mut parser)?;
+
+    parser.finish()
+}
+
+/// Parse source read from a reader and report all unmatched brackets at once
+///
+/// Same as [parse_bytes_with_recovery] but for a reader like [parse_reader].
+pub fn parse_reader_with_recovery<R: Read>(
+    reader: R,
+    options: &ParserOptions,
+) -> Result<Program, Vec<ParserError>> {
+    let mut parser = Parser::new(options).with_recovery();
+
+    if let Err(error) = tokenize_reader(reader, options, &mut parser) {
+        parser.errors.push(error);
+        return Err(parser.errors);
+    }
+
+    parser.finish_with_errors()
+}
+
+fn tokenize_reader<R: Read>(
+    reader: R,
+    options: &ParserOptions,
+    parser: &mut Parser,
+) -> Result<(), ParserError> {
+    if options.dialect == Dialect::Brainfuck {
+        read_chunks(reader, |position, chunk| {
+            for (index, &byte) in chunk.iter().enumerate() {
+                if options.inline_input && byte == b'!' {
+                    return Ok(false);
+                }
+
+                let pos = position + index;
+                parser.parse_command(pos..pos + 1, byte)?;
+            }
+
+            Ok(true)
+        })
+    } else {
+        let mut source = vec![];
+
+        read_chunks(reader, |_, chunk| {
+            source.extend_from_slice(chunk);
+            Ok(true)
+        })?;
+
+        options
+            .dialect
+            .tokenize(&source, |span, byte| parser.parse_command(span, byte))
+    }
+}
+
+/// Pass chunks of the reader with their position until the end or the callback returns false
+fn read_chunks<R, F>(mut reader: R, mut chunk: F) -> Result<(), ParserError>
+where
+    R: Read,
+    F: FnMut(usize, &[u8]) -> Result<bool, ParserError>,
+{
+    let mut buf = [0; 8 * 1024];
+    let mut position = 0;
+
+    loop {
+        let count = match reader.read(&mut buf) {
+            Ok(0) => return Ok(()),
+            Ok(count) => count,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(ParserError::IoError { position, error }),
+        };
+
+        if !chunk(position, &buf[..count])? {
+            return Ok(());
+        }
+
+        position += count;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{ErrorKind, Read};
+
+    use crate::errors::ParserError;
+    use crate::ir::ops::OpType;
+    use crate::parser::{
+        parse, parse_bytes, parse_bytes_with_options, parse_bytes_with_recovery, parse_reader,
+        parse_reader_with_recovery, split_inline_input, Dialect, ParserOptions, Program, TokenMap,
+    };
+
+    #[test]
+    fn test_byte_spans() {
+        let source = "Grüße → +\n[-] ✓.";
+
+        let program = parse(source).unwrap();
+
+        assert_eq!(program.ops.len(), 3);
+        assert_eq!(program.ops[0].span, 12..13);
+        assert_eq!(program.ops[1].span, 14..17);
+        assert_eq!(program.ops[2].span, 21..22);
+
+        assert_eq!(&source[program.ops[0].span.clone()], "+");
+        assert_eq!(&source[program.ops[1].span.clone()], "[-]");
+        assert_eq!(&source[program.ops[2].span.clone()], ".");
+    }
+
+    #[test]
+    fn test_byte_error_positions() {
+        match parse("ä]") {
+            Err(ParserError::BadlyClosedLoop { position, .. }) => assert_eq!(position, 2),
+            _ => panic!("Expected badly closed loop"),
+        }
+
+        match parse("→ [") {
+            Err(ParserError::UnclosedLoop { position, .. }) => assert_eq!(position, 4),
+            _ => panic!("Expected unclosed loop"),
+        }
+    }
+
+    #[test]
+    fn test_line_column() {
+        let source = "Grüße → +\n[-] ✓.";
+
+        assert_eq!(Program::get_line_column(source, 0), Some((1, 1)));
+        assert_eq!(Program::get_line_column(source, 12), Some((1, 9)));
+        assert_eq!(Program::get_line_column(source, 14), Some((2, 1)));
+        assert_eq!(Program::get_line_column(source, 21), Some((2, 6)));
+        assert_eq!(Program::get_line_column(source, 22), Some((2, 7)));
+        assert_eq!(Program::get_line_column(source, 3), None);
+        assert_eq!(Program::get_line_column(source, 23), None);
+
+        let program = parse(source).unwrap();
+
+        assert_eq!(
+            Program::get_span_line_columns(source, &program.ops[1].span),
+            Some(((2, 1), (2, 4)))
+        );
+    }
+
+    #[test]
+    fn test_parse_bytes() {
+        // Latin-1 encoded comment
+        let source = b"Gr\xfc\xdfe +[\xff-]\x00.";
+
+        let program = parse_bytes(source).unwrap();
+
+        assert_eq!(program.ops.len(), 3);
+        assert_eq!(program.ops[0].span, 6..7);
+        assert_eq!(program.ops[1].span, 7..11);
+        assert_eq!(program.ops[2].span, 12..13);
+    }
+
+    #[test]
+    fn test_parse_reader() {
+        struct ByteReader<'a> {
+            source: &'a [u8],
+        }
+
+        impl Read for ByteReader<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                match self.source.split_first() {
+                    Some((&byte, rest)) => {
+                        buf[0] = byte;
+                        self.source = rest;
+                        Ok(1)
+                    }
+                    None => Ok(0),
+                }
+            }
+        }
+
+        let source = include_bytes!("../../test_programs/bottles.bf");
+
+        let program = parse_reader(ByteReader { source }, &ParserOptions::default()).unwrap();
+        let expected = parse_bytes(source).unwrap();
+
+        let mut dump = Vec::new();
+        program.dump(&mut dump, false).unwrap();
+
+        let mut expected_dump = Vec::new();
+        expected.dump(&mut expected_dump, false).unwrap();
+
+        assert_eq!(dump, expected_dump);
+
+        match parse_reader(
+            ByteReader {
+                source: b"\xe4+\xe4]",
+            },
+            &ParserOptions::default(),
+        ) {
+            Err(ParserError::BadlyClosedLoop { position, .. }) => assert_eq!(position, 3),
+            _ => panic!("Expected badly closed loop"),
+        }
+
+        let options = ParserOptions {
+            dialect: Dialect::Ook,
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+        let source = b"Ook. Ook. Ook! Ook? Ook! Ook! Ook? Ook! Ook! Ook.";
+
+        let program = parse_reader(ByteReader { source }, &options).unwrap();
+        assert_eq!(
+            program.ops,
+            parse_bytes_with_options(source, &options).unwrap().ops
+        );
+
+        let options = ParserOptions {
+            inline_input: true,
+            max_loop_depth: 2,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_reader(ByteReader { source: b"+.!]" }, &options).unwrap();
+        assert_eq!(program.ops.len(), 2);
+
+        assert!(matches!(
+            parse_reader(ByteReader { source: b"[[[]]]" }, &options),
+            Err(ParserError::LoopStackOverflow { max_depth: 2, .. })
+        ));
+
+        match parse_reader_with_recovery(ByteReader { source: b"]+[" }, &options) {
+            Err(errors) => assert_eq!(errors.len(), 2),
+            _ => panic!("Expected errors"),
+        }
+    }
+
+    #[test]
+    fn test_parse_reader_error() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(ErrorKind::BrokenPipe.into())
+            }
+        }
+
+        assert!(matches!(
+            parse_reader(FailingReader, &ParserOptions::default()),
+            Err(ParserError::IoError { position: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let program = parse("+#.").unwrap();
+        assert_eq!(program.ops.len(), 2);
+
+        let options = ParserOptions {
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_bytes_with_options(b"+[#-]", &options).unwrap();
+
+        assert_eq!(program.ops.len(), 2);
+
+        match &program.ops[1].op_type {
+            OpType::DLoop(children, _) => {
+                assert_eq!(children[0].op_type, OpType::Breakpoint);
+                assert_eq!(children[0].span, 2..3);
+            }
+            _ => panic!("Expected loop"),
+        }
+    }
+
+    #[test]
+    fn test_inline_input() {
+        let source = b",[.,]!input!more";
+
+        assert_eq!(
+            split_inline_input(source),
+            (&b",[.,]"[..], Some(&b"input!more"[..]))
+        );
+        assert_eq!(split_inline_input(b"+."), (&b"+."[..], None));
+
+        let options = ParserOptions {
+            inline_input: true,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_bytes_with_options(source, &options).unwrap();
+        assert_eq!(program.ops.len(), 2);
+
+        // The input isn't parsed as code
+        let program = parse_bytes_with_options(b"+!]", &options).unwrap();
+        assert_eq!(program.ops.len(), 1);
+
+        assert!(matches!(
+            parse_bytes(b"+!]"),
+            Err(ParserError::BadlyClosedLoop { position: 2, .. })
+        ));
+    }
+
+    fn parse_dialect(source: &str, dialect: Dialect) -> Result<Program, ParserError> {
+        let options = ParserOptions {
+            dialect,
+            ..ParserOptions::default()
+        };
+
+        parse_bytes_with_options(source.as_bytes(), &options)
+    }
+
+    fn dump(program: &Program) -> String {
+        let mut dump = Vec::new();
+        program.dump(&mut dump, false).unwrap();
+
+        String::from_utf8(dump)
+            .unwrap()
+            .lines()
+            .map(|line| line[16..].trim().to_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_ook() {
+        let source =
+            "Ook. Ook? Ook. Ook.\nOok! Ook? Ook! Ook!\n  Ook? Ook! Ook! Ook. Ook. Ook! Ook? Ook.";
+
+        let program = parse_dialect(source, Dialect::Ook).unwrap();
+
+        assert_eq!(dump(&program), dump(&parse(">+[-].,<").unwrap()));
+
+        assert_eq!(program.ops[0].span, 0..9);
+        assert_eq!(program.ops[1].span, 10..19);
+        assert_eq!(program.ops[2].span, 20..51);
+        assert_eq!(&source[program.ops[2].span.clone()][..9], "Ook! Ook?");
+        assert_eq!(program.ops[3].span, 52..61);
+    }
+
+    #[test]
+    fn test_blub() {
+        let program = parse_dialect("Blub. Blub. Blub! Blub. # Blub", Dialect::Blub).unwrap();
+
+        assert_eq!(dump(&program), dump(&parse("+.").unwrap()));
+        assert_eq!(program.ops[1].span, 12..23);
+    }
+
+    #[test]
+    fn test_dialect_errors() {
+        match parse_dialect("Ook. Ook? Ook? Ook?", Dialect::Ook) {
+            Err(ParserError::InvalidToken { span }) => assert_eq!(span, 10..19),
+            _ => panic!("Expected invalid token"),
+        }
+
+        match parse_dialect("Ook. Ook. Ook.", Dialect::Ook) {
+            Err(ParserError::InvalidToken { span }) => assert_eq!(span, 10..14),
+            _ => panic!("Expected invalid token"),
+        }
+
+        match parse_dialect("Ook. Ook. Ook! Ook?", Dialect::Ook) {
+            Err(ParserError::UnclosedLoop { position, .. }) => assert_eq!(position, 10),
+            _ => panic!("Expected unclosed loop"),
+        }
+
+        match parse_dialect("Ook. Ook. Ook? Ook!", Dialect::Ook) {
+            Err(ParserError::BadlyClosedLoop { position, .. }) => assert_eq!(position, 10),
+            _ => panic!("Expected badly closed loop"),
+        }
+    }
+
+    #[test]
+    fn test_custom_dialect() {
+        let mut token_map =
+            TokenMap::from_substitutions(["→", "←", "inc", "dec", "out", "in", "(", ")"]);
+        token_map.insert("incinc", '#');
+
+        let source = "inc(→ incinc←dec)→out";
+
+        let program = parse_dialect(source, Dialect::Custom(token_map.clone())).unwrap();
+
+        assert_eq!(dump(&program), dump(&parse("+[><-]>.").unwrap()));
+        assert_eq!(program.ops[1].span, 3..21);
+
+        match &program.ops[1].op_type {
+            OpType::DLoop(children, _) => {
+                assert_eq!(children[0].span, 4..7);
+            }
+            _ => panic!("Expected loop"),
+        }
+
+        let options = ParserOptions {
+            dialect: Dialect::Custom(token_map),
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+
+        let expected = ParserOptions {
+            breakpoints: true,
+            ..ParserOptions::default()
+        };
+
+        assert_eq!(
+            dump(&program),
+            dump(&parse_bytes_with_options(b"+[>#<-]>.", &expected).unwrap())
+        );
+    }
+
+    fn bracket_errors(source: &str) -> Vec<(&'static str, usize, Option<usize>)> {
+        parse_bytes_with_recovery(source.as_bytes(), &ParserOptions::default())
+            .err()
+            .unwrap()
+            .into_iter()
+            .map(|error| match error {
+                ParserError::BadlyClosedLoop {
+                    position,
+                    likely_match,
+                } => ("]", position, likely_match),
+                ParserError::UnclosedLoop {
+                    position,
+                    likely_match,
+                } => ("[", position, likely_match),
+                error => panic!("Unexpected error {}", error),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recovery_reports_all_brackets() {
+        assert_eq!(
+            bracket_errors("+[-]]>[[-]<["),
+            vec![("]", 4, Some(1)), ("[", 6, Some(9)), ("[", 11, None)]
+        );
+
+        assert_eq!(
+            bracket_errors("]]>[[[-]"),
+            vec![
+                ("]", 0, None),
+                ("]", 1, None),
+                ("[", 3, None),
+                ("[", 4, Some(7))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_recovery_valid_source() {
+        let source = "+[->+<]>.";
+
+        let program =
+            parse_bytes_with_recovery(source.as_bytes(), &ParserOptions::default()).unwrap();
+
+        assert_eq!(dump(&program), dump(&parse(source).unwrap()));
+    }
+
+    #[test]
+    fn test_recovery_stops_at_invalid_token() {
+        let options = ParserOptions {
+            dialect: Dialect::Ook,
+            ..ParserOptions::default()
+        };
+
+        let errors = parse_bytes_with_recovery(b"Ook? Ook! Ook. Ook? Ook.", &options)
+            .err()
+            .unwrap();
+
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(
+            errors[0],
+            ParserError::BadlyClosedLoop { position: 0, .. }
+        ));
+        assert!(matches!(errors[1], ParserError::InvalidToken { .. }));
+    }
+
+    #[test]
+    fn test_unclosed_loop_likely_match() {
+        match parse("[[-]>") {
+            Err(ParserError::UnclosedLoop {
+                position,
+                likely_match,
+            }) => {
+                assert_eq!(position, 0);
+                assert_eq!(likely_match, Some(3));
+            }
+            _ => panic!("Expected unclosed loop"),
+        }
+    }
+
+    fn deeply_nested_source(depth: usize) -> String {
+        format!("+{}[-]{}A", "[>+".repeat(depth), "<-]".repeat(depth))
+    }
+
+    #[test]
+    fn test_max_loop_depth() {
+        let options = ParserOptions {
+            max_loop_depth: 3,
+            ..ParserOptions::default()
+        };
+
+        assert!(parse_bytes_with_options(b"[[[]]]", &options).is_ok());
+
+        match parse_bytes_with_options(b"[[[[]]]]", &options) {
+            Err(ParserError::LoopStackOverflow {
+                position,
+                max_depth,
+            }) => {
+                assert_eq!(position, 3);
+                assert_eq!(max_depth, 3);
+            }
+            _ => panic!("Expected loop stack overflow"),
+        }
+
+        let source = deeply_nested_source(5000);
+
+        assert!(matches!(
+            parse(&source),
+            Err(ParserError::LoopStackOverflow {
+                max_depth: 1024,
+                ..
+            })
+        ));
+
+        let options = ParserOptions {
+            max_loop_depth: 5000,
+            ..ParserOptions::default()
+        };
+
+        assert!(parse_bytes_with_options(source.as_bytes(), &options).is_err());
+
+        let options = ParserOptions {
+            max_loop_depth: 5001,
+            ..ParserOptions::default()
+        };
+
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+
+        assert_eq!(program.get_loop_depth(), 5001);
+    }
+
+    #[test]
+    fn test_dump_deeply_nested() {
+        let options = ParserOptions {
+            max_loop_depth: 10_000,
+            ..ParserOptions::default()
+        };
+
+        let source = deeply_nested_source(2000);
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+
+        let mut output = vec![];
+        program.dump(&mut output, false).unwrap();
+
+        assert_eq!(
+            String::from_utf8(output).unwrap().lines().count(),
+            program.get_statistics().0
+        );
+    }
+
+    #[test]
+    fn test_clone_compare_and_drop_deeply_nested() {
+        let options = ParserOptions {
+            max_loop_depth: 200_001,
+            ..ParserOptions::default()
+        };
+
+        let source = deeply_nested_source(200_000);
+        let program = parse_bytes_with_options(source.as_bytes(), &options).unwrap();
+        let copy = program.clone();
+
+        assert_eq!(program.ops, copy.ops);
+        assert_eq!(copy.get_loop_depth(), 200_001);
+    }
 }